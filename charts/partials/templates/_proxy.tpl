@@ -37,8 +37,14 @@ env:
   value: {{ternary "localhost.:8090" (printf "linkerd-policy.%s.svc.%s.:8090" .Release.Namespace .Values.clusterDomain) (eq (toString .Values.proxy.component) "linkerd-destination")}}
 - name: LINKERD2_PROXY_POLICY_WORKLOAD
   value: "$(_pod_ns):$(_pod_name)"
+{{- /*
+The proxy doesn't support the policy controller's `all-authenticated-or-probes` policy. Probes are
+permitted by the policies that the controller serves, so the proxy's fallback only requires
+authentication.
+*/}}
+{{- $defaultInboundPolicy := .Values.proxy.defaultInboundPolicy | default .Values.policyController.defaultAllowPolicy }}
 - name: LINKERD2_PROXY_INBOUND_DEFAULT_POLICY
-  value: {{.Values.proxy.defaultInboundPolicy | default .Values.policyController.defaultAllowPolicy}}
+  value: {{ternary "all-authenticated" $defaultInboundPolicy (eq $defaultInboundPolicy "all-authenticated-or-probes")}}
 - name: LINKERD2_PROXY_POLICY_CLUSTER_NETWORKS
   value: {{.Values.clusterNetworks | quote}}
 {{ if .Values.proxy.inboundConnectTimeout -}}
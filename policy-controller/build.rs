@@ -0,0 +1,48 @@
+//! Records the commit and time of the build so that `/version` can identify a running binary.
+
+use std::{
+    env,
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+fn main() {
+    // Container builds don't include the git repository, so the commit may be set explicitly.
+    let git_sha = env::var("LINKERD_POLICY_GIT_SHA")
+        .ok()
+        .filter(|s| !s.is_empty())
+        .or_else(git_sha)
+        .unwrap_or_else(|| "unknown".to_string());
+
+    // Honor `SOURCE_DATE_EPOCH` so that builds can be reproducible.
+    let timestamp = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default()
+        });
+
+    println!("cargo:rustc-env=LINKERD_POLICY_BUILD_GIT_SHA={}", git_sha);
+    println!(
+        "cargo:rustc-env=LINKERD_POLICY_BUILD_TIMESTAMP={}",
+        timestamp
+    );
+    println!("cargo:rerun-if-env-changed=LINKERD_POLICY_GIT_SHA");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+}
+
+fn git_sha() -> Option<String> {
+    let out = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    let sha = String::from_utf8(out.stdout).ok()?.trim().to_string();
+    Some(sha).filter(|s| !s.is_empty())
+}
@@ -8,7 +8,7 @@ pub use self::{identity_match::IdentityMatch, network_match::NetworkMatch};
 use anyhow::Result;
 use futures::prelude::*;
 pub use ipnet::{IpNet, Ipv4Net, Ipv6Net};
-use std::{collections::HashMap, hash::Hash, pin::Pin, time::Duration};
+use std::{collections::BTreeMap, hash::Hash, pin::Pin, time::Duration};
 
 /// Models inbound server configuration discovery.
 #[async_trait::async_trait]
@@ -25,7 +25,9 @@ pub type InboundServerStream = Pin<Box<dyn Stream<Item = InboundServer> + Send +
 pub struct InboundServer {
     pub name: String,
     pub protocol: ProxyProtocol,
-    pub authorizations: HashMap<String, ClientAuthorization>,
+    /// Authorizations by name. These are ordered so that identical policies are always encoded
+    /// identically.
+    pub authorizations: BTreeMap<String, ClientAuthorization>,
 }
 
 /// Describes how a proxy should handle inbound connections.
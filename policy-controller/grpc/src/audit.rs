@@ -0,0 +1,173 @@
+//! Audit records of the policies served to each client.
+//!
+//! A record is written for each discovery response, identifying the client, the workload and port
+//! that it discovered, and the server and authorizations that apply. Records are sent to a
+//! bounded channel so that a slow sink never delays responses; records that don't fit in the
+//! channel are dropped and counted.
+
+use crate::identity;
+use linkerd2_proxy_api::inbound as proto;
+use linkerd_policy_controller_metrics::Counter;
+use serde::Serialize;
+use std::{sync::Arc, time::SystemTime};
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt},
+    sync::mpsc,
+};
+use tonic::transport::Certificate;
+
+/// Sends audit records to the sink.
+#[derive(Clone, Debug)]
+pub struct AuditLog {
+    tx: mpsc::Sender<Record>,
+
+    /// Counts records that were dropped because the channel was full.
+    dropped: Counter,
+}
+
+/// Describes the policy served in a single discovery response.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Record {
+    /// The time at which the response was sent, in milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+
+    /// The client's mTLS identity, if it was authenticated.
+    pub client_identity: Option<String>,
+
+    /// The discovered workload, in the form `namespace:name`.
+    pub workload: String,
+
+    pub port: u16,
+
+    /// The name of the server that applies to the port, or of the default policy.
+    pub server: String,
+
+    /// The names of the authorizations that apply to the server.
+    pub authorizations: Vec<String>,
+}
+
+/// Creates an audit log that buffers up to `capacity` records, returning the log and the receiver
+/// from which records are written.
+pub fn channel(capacity: usize, dropped: Counter) -> (AuditLog, mpsc::Receiver<Record>) {
+    let (tx, rx) = mpsc::channel(capacity);
+    (AuditLog { tx, dropped }, rx)
+}
+
+/// Writes each record to `sink` as a line of JSON, until all audit logs are dropped.
+pub async fn write(
+    mut records: mpsc::Receiver<Record>,
+    mut sink: impl AsyncWrite + Unpin,
+) -> std::io::Result<()> {
+    while let Some(record) = records.recv().await {
+        let mut line = serde_json::to_vec(&record)?;
+        line.push(b'\n');
+        sink.write_all(&line).await?;
+        sink.flush().await?;
+    }
+    Ok(())
+}
+
+// === impl AuditLog ===
+
+impl AuditLog {
+    /// Records a response without waiting for the sink. If the channel is full, the record is
+    /// dropped.
+    pub(crate) fn record(&self, record: Record) {
+        if self.tx.try_send(record).is_err() {
+            self.dropped.inc();
+        }
+    }
+}
+
+// === impl Record ===
+
+impl Record {
+    pub(crate) fn new(
+        client_identity: Option<String>,
+        (ns, name, port): &(String, String, u16),
+        server: &proto::Server,
+    ) -> Self {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|t| t.as_millis() as u64)
+            .unwrap_or_default();
+        Self {
+            timestamp_ms,
+            client_identity,
+            workload: format!("{}:{}", ns, name),
+            port: *port,
+            server: server.labels.get("name").cloned().unwrap_or_default(),
+            authorizations: server
+                .authorizations
+                .iter()
+                .filter_map(|authz| authz.labels.get("name").cloned())
+                .collect(),
+        }
+    }
+
+    /// Returns the identity of the client's certificate, if the client was authenticated.
+    pub(crate) fn client_identity(peer_certs: Option<&Arc<Vec<Certificate>>>) -> Option<String> {
+        identity::client_identity(peer_certs?.first()?).map(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mk_server() -> proto::Server {
+        proto::Server {
+            labels: Some(("name".to_string(), "web-http".to_string()))
+                .into_iter()
+                .collect(),
+            authorizations: ["authz-0", "authz-1"]
+                .iter()
+                .map(|name| proto::Authz {
+                    labels: Some(("name".to_string(), name.to_string()))
+                        .into_iter()
+                        .collect(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn writes_records() {
+        let dropped = Counter::default();
+        let (log, rx) = channel(1, dropped.clone());
+        let target = ("ns-0".to_string(), "web-0".to_string(), 8080);
+        log.record(Record::new(
+            Some("web.ns-0.serviceaccount.identity.linkerd.cluster.local".to_string()),
+            &target,
+            &mk_server(),
+        ));
+
+        // The channel is full, so further records are dropped rather than waiting for the sink.
+        log.record(Record::new(None, &target, &mk_server()));
+        assert_eq!(dropped.get(), 1);
+        drop(log);
+
+        let mut out = Vec::new();
+        write(rx, &mut out).await.unwrap();
+        let lines = out.split(|b| *b == b'\n').filter(|l| !l.is_empty());
+        let records = lines
+            .map(|l| serde_json::from_slice::<serde_json::Value>(l).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!(
+            record["client_identity"],
+            "web.ns-0.serviceaccount.identity.linkerd.cluster.local"
+        );
+        assert_eq!(record["workload"], "ns-0:web-0");
+        assert_eq!(record["port"], 8080);
+        assert_eq!(record["server"], "web-http");
+        assert_eq!(
+            record["authorizations"],
+            serde_json::json!(["authz-0", "authz-1"])
+        );
+        assert!(record["timestamp_ms"].as_u64().unwrap() > 0);
+    }
+}
@@ -0,0 +1,37 @@
+use tokio::sync::watch;
+use tonic_health::{server::HealthReporter, ServingStatus};
+
+/// Reports `service`, and the server as a whole, as serving while `ready` is set and as not
+/// serving once `drain` is signaled.
+pub(crate) async fn report(
+    mut reporter: HealthReporter,
+    service: &'static str,
+    mut ready: watch::Receiver<bool>,
+    drain: drain::Watch,
+) {
+    let drained = drain.signaled();
+    tokio::pin!(drained);
+
+    // Readiness can't change once its sender is dropped, so only the drain is awaited from then on.
+    let mut ready_open = true;
+    loop {
+        let status = if *ready.borrow() {
+            ServingStatus::Serving
+        } else {
+            ServingStatus::NotServing
+        };
+        set(&mut reporter, service, status).await;
+
+        tokio::select! {
+            res = ready.changed(), if ready_open => ready_open = res.is_ok(),
+            _ = &mut drained => break,
+        }
+    }
+
+    set(&mut reporter, service, ServingStatus::NotServing).await;
+}
+
+async fn set(reporter: &mut HealthReporter, service: &'static str, status: ServingStatus) {
+    reporter.set_service_status(service, status).await;
+    reporter.set_service_status("", status).await;
+}
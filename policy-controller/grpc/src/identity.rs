@@ -0,0 +1,151 @@
+//! Authorizes discovery requests by the client's mTLS identity.
+//!
+//! Proxies are issued certificates with an identity of the form
+//! `<serviceaccount>.<namespace>.serviceaccount.identity.<control-plane-ns>.<trust-domain>`. When
+//! client identities are enforced, a client may only discover policies for workloads in its own
+//! namespace. The index doesn't track pods' service accounts, so workloads in the same namespace
+//! are not distinguished.
+
+use std::sync::Arc;
+use tonic::transport::Certificate;
+use x509_parser::{
+    certificate::X509Certificate,
+    extensions::{GeneralName, ParsedExtension},
+    traits::FromDer,
+};
+
+/// Fails with the reason for denial unless one of the client's certificates has an identity in the
+/// workload's namespace.
+pub(crate) fn authorize(
+    peer_certs: Option<Arc<Vec<Certificate>>>,
+    workload_ns: &str,
+) -> Result<(), String> {
+    let certs = peer_certs.ok_or_else(|| "client identity is required".to_string())?;
+
+    // The client's own certificate is first in its chain.
+    let ids = certs
+        .first()
+        .map(|c| dns_names(c.get_ref()))
+        .unwrap_or_default();
+    if ids
+        .iter()
+        .any(|id| identity_namespace(id) == Some(workload_ns))
+    {
+        return Ok(());
+    }
+
+    Err(format!(
+        "client identity may not discover workloads in namespace {}",
+        workload_ns
+    ))
+}
+
+/// Returns the proxy identity of a client certificate, if it has one.
+pub(crate) fn client_identity(cert: &Certificate) -> Option<&str> {
+    dns_names(cert.get_ref())
+        .into_iter()
+        .find(|id| identity_namespace(id).is_some())
+}
+
+/// Returns the namespace of a proxy identity, or `None` if `id` isn't a proxy identity.
+fn identity_namespace(id: &str) -> Option<&str> {
+    let mut labels = id.split('.');
+    let _sa = labels.next().filter(|l| !l.is_empty())?;
+    let ns = labels.next().filter(|l| !l.is_empty())?;
+    if labels.next()? != "serviceaccount" || labels.next()? != "identity" {
+        return None;
+    }
+    Some(ns)
+}
+
+/// Returns the DNS names in an X.509 certificate's subjectAltName extension.
+fn dns_names(cert: &[u8]) -> Vec<&str> {
+    let cert = match X509Certificate::from_der(cert) {
+        Ok((_, cert)) => cert,
+        Err(_) => return Vec::new(),
+    };
+    cert.extensions()
+        .iter()
+        .filter_map(|ext| match ext.parsed_extension() {
+            ParsedExtension::SubjectAlternativeName(sans) => Some(sans),
+            _ => None,
+        })
+        .flat_map(|sans| sans.general_names.iter())
+        .filter_map(|name| match name {
+            GeneralName::DNSName(name) => Some(*name),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A self-signed certificate for `web.ns-0.serviceaccount.identity.linkerd.cluster.local`.
+    const CLIENT_CERT: &[u8] = include_bytes!("../testdata/client.der");
+
+    fn client_certs() -> Option<Arc<Vec<Certificate>>> {
+        Some(Arc::new(vec![Certificate::from_pem(CLIENT_CERT)]))
+    }
+
+    #[test]
+    fn reads_dns_names() {
+        assert_eq!(
+            dns_names(CLIENT_CERT),
+            vec!["web.ns-0.serviceaccount.identity.linkerd.cluster.local"]
+        );
+        assert!(dns_names(b"not a certificate").is_empty());
+        assert!(
+            dns_names(&CLIENT_CERT[..CLIENT_CERT.len() / 2]).is_empty(),
+            "truncated"
+        );
+        assert!(
+            dns_names(&[0x30, 0x84, 0xff, 0xff, 0xff, 0xff]).is_empty(),
+            "the length must not exceed the input"
+        );
+    }
+
+    #[test]
+    fn reads_client_identities() {
+        let certs = client_certs().unwrap();
+        assert_eq!(
+            client_identity(&certs[0]),
+            Some("web.ns-0.serviceaccount.identity.linkerd.cluster.local")
+        );
+    }
+
+    #[test]
+    fn parses_identity_namespaces() {
+        assert_eq!(
+            identity_namespace("web.ns-0.serviceaccount.identity.linkerd.cluster.local"),
+            Some("ns-0")
+        );
+        for id in [
+            "",
+            "web.ns-0",
+            ".ns-0.serviceaccount.identity.linkerd.cluster.local",
+            "web.ns-0.sa.identity.linkerd.cluster.local",
+            "example.com",
+        ] {
+            assert_eq!(identity_namespace(id), None, "{:?}", id);
+        }
+    }
+
+    #[test]
+    fn authorizes_matching_identities() {
+        authorize(client_certs(), "ns-0").expect("identity must match its own namespace");
+    }
+
+    #[test]
+    fn denies_mismatched_identities() {
+        let reason = authorize(client_certs(), "ns-1").expect_err("namespace must not match");
+        assert_eq!(
+            reason,
+            "client identity may not discover workloads in namespace ns-1"
+        );
+
+        let reason = authorize(None, "ns-0").expect_err("identity must be required");
+        assert_eq!(reason, "client identity is required");
+    }
+}
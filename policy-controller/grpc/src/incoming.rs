@@ -0,0 +1,240 @@
+use futures::prelude::*;
+use linkerd_policy_controller_metrics::Gauge;
+use std::{
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::{TcpListener, TcpStream, UnixListener, UnixStream},
+    sync::mpsc,
+    time,
+};
+use tonic::transport::server::{Connected, TcpConnectInfo};
+use tracing::{debug, warn};
+
+/// Connection IDs are unique for the lifetime of the process.
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// A server-side connection that is closed if the client doesn't begin the HTTP/2 handshake
+/// before a timeout elapses.
+///
+/// The `active` gauge is incremented while the connection is open. Each connection is logged at
+/// `debug` level with a connection ID and the client's address when it's opened and closed. TLS is
+/// negotiated after the connection is accepted, so client identities are instead recorded on each
+/// request's span, alongside the client's address.
+pub(crate) struct Conn<I> {
+    io: I,
+
+    id: u64,
+
+    /// Unix domain socket clients have no meaningful address.
+    client: Option<SocketAddr>,
+
+    /// Cleared once any data has been read from the client.
+    connect_timeout: Option<Pin<Box<time::Sleep>>>,
+
+    /// When set, the connection is closed forcibly once this elapses, regardless of its state.
+    deadline: Option<Pin<Box<time::Sleep>>>,
+
+    active: Gauge,
+}
+
+/// How long to wait before accepting another connection after the listener fails, e.g. because the
+/// process is out of file descriptors.
+const ACCEPT_BACKOFF: time::Duration = time::Duration::from_millis(500);
+
+/// Accepts connections from `listener`.
+///
+/// Accept errors are logged rather than returned, since returning an error would stop the server.
+/// Such failures usually persist for a while, so accepting is paused briefly after each one.
+pub(crate) fn tcp(
+    listener: TcpListener,
+    connect_timeout: Option<time::Duration>,
+    active: Gauge,
+) -> impl Stream<Item = io::Result<Conn<TcpStream>>> {
+    async_stream::stream! {
+        loop {
+            match listener.accept().await {
+                Ok((socket, client)) => {
+                    let conn = Conn::new(socket, Some(client), connect_timeout, active.clone());
+                    yield Ok::<_, io::Error>(conn)
+                }
+                Err(error) => {
+                    warn!(%error, "Failed to accept connection");
+                    time::sleep(ACCEPT_BACKOFF).await;
+                }
+            }
+        }
+    }
+}
+
+/// Accepts connections from a Unix domain socket `listener`.
+///
+/// Accept errors are logged rather than returned, since returning an error would stop the server.
+/// As with TCP listeners, accepting is paused briefly after each failure.
+pub(crate) fn unix(
+    listener: UnixListener,
+    connect_timeout: Option<time::Duration>,
+    active: Gauge,
+) -> impl Stream<Item = io::Result<Conn<UnixStream>>> {
+    async_stream::stream! {
+        loop {
+            match listener.accept().await {
+                Ok((socket, _)) => {
+                    let conn = Conn::new(socket, None, connect_timeout, active.clone());
+                    yield Ok::<_, io::Error>(conn)
+                }
+                Err(error) => {
+                    warn!(%error, "Failed to accept connection");
+                    time::sleep(ACCEPT_BACKOFF).await;
+                }
+            }
+        }
+    }
+}
+
+/// Serves connections accepted on a shared listener, until the sender is dropped.
+pub(crate) fn shared(
+    mut connections: mpsc::Receiver<TcpStream>,
+    connect_timeout: Option<time::Duration>,
+    active: Gauge,
+) -> impl Stream<Item = io::Result<Conn<TcpStream>>> {
+    async_stream::stream! {
+        while let Some(socket) = connections.recv().await {
+            let client = socket.peer_addr().ok();
+            yield Ok::<_, io::Error>(Conn::new(socket, client, connect_timeout, active.clone()))
+        }
+    }
+}
+
+// === impl Conn ===
+
+impl<I> Conn<I> {
+    fn new(
+        io: I,
+        client: Option<SocketAddr>,
+        connect_timeout: Option<time::Duration>,
+        active: Gauge,
+    ) -> Self {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        active.inc();
+        debug!(
+            conn.id = id,
+            client.addr = client.map(tracing::field::display),
+            "Connection opened"
+        );
+        Self {
+            io,
+            id,
+            client,
+            connect_timeout: connect_timeout.map(|t| Box::pin(time::sleep(t))),
+            deadline: None,
+            active,
+        }
+    }
+
+    /// Closes the connection once `deadline` elapses, e.g. when a client doesn't close its
+    /// connection after the server has asked it to.
+    pub(crate) fn with_deadline(mut self, deadline: Option<time::Duration>) -> Self {
+        self.deadline = deadline.map(|t| Box::pin(time::sleep(t)));
+        self
+    }
+}
+
+impl<I> Drop for Conn<I> {
+    fn drop(&mut self) {
+        self.active.dec();
+        debug!(
+            conn.id = self.id,
+            client.addr = self.client.map(tracing::field::display),
+            "Connection closed"
+        );
+    }
+}
+
+impl Connected for Conn<TcpStream> {
+    type ConnectInfo = TcpConnectInfo;
+
+    fn connect_info(&self) -> Self::ConnectInfo {
+        self.io.connect_info()
+    }
+}
+
+/// Unix domain socket clients have no meaningful address.
+impl Connected for Conn<UnixStream> {
+    type ConnectInfo = ();
+
+    fn connect_info(&self) -> Self::ConnectInfo {}
+}
+
+impl<I: AsyncRead + Unpin> AsyncRead for Conn<I> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        if let Some(deadline) = this.deadline.as_mut() {
+            if deadline.as_mut().poll(cx).is_ready() {
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "connection exceeded its maximum age",
+                )));
+            }
+        }
+
+        let filled = buf.filled().len();
+        let poll = Pin::new(&mut this.io).poll_read(cx, buf);
+
+        if let Some(timeout) = this.connect_timeout.as_mut() {
+            match poll {
+                Poll::Ready(Ok(())) if buf.filled().len() > filled => {
+                    this.connect_timeout = None;
+                }
+                Poll::Pending if timeout.as_mut().poll(cx).is_ready() => {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "client did not begin the HTTP/2 handshake",
+                    )));
+                }
+                _ => {}
+            }
+        }
+
+        poll
+    }
+}
+
+impl<I: AsyncWrite + Unpin> AsyncWrite for Conn<I> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.io).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.io).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.io.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_shutdown(cx)
+    }
+}
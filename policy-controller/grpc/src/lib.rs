@@ -10,14 +10,244 @@ use linkerd_policy_controller_core::{
     ClientAuthentication, ClientAuthorization, DiscoverInboundServer, IdentityMatch, InboundServer,
     InboundServerStream, IpNet, NetworkMatch, ProxyProtocol,
 };
-use std::sync::Arc;
-use tracing::trace;
+use linkerd_policy_controller_metrics::Gauge;
+use std::{io, num::NonZeroUsize, sync::Arc, time::Duration};
+use tokio::{
+    net::{TcpListener, TcpStream, UnixListener},
+    sync::{mpsc, watch, Semaphore, SemaphorePermit},
+};
+use tonic::transport::NamedService;
+use tracing::{debug, trace, warn};
+
+pub mod audit;
+mod health;
+mod identity;
+mod incoming;
+mod request_id;
+mod version;
 
 #[derive(Clone, Debug)]
 pub struct Server<T> {
     discover: T,
     drain: drain::Watch,
-    cluster_networks: Arc<[IpNet]>,
+
+    /// The cluster's networks, which may be updated as the server runs.
+    cluster_networks: watch::Receiver<Arc<[IpNet]>>,
+
+    /// When set, bounds the number of lookups processed concurrently.
+    requests: Option<Arc<Semaphore>>,
+
+    /// Updates received within this window are coalesced on each watch. Zero disables coalescing.
+    update_debounce: Duration,
+
+    /// Networks of this family are omitted from responses.
+    omit_family: Option<IpFamily>,
+
+    /// When set, clients may only discover workloads in the namespace of their mTLS identity.
+    enforce_client_identity: bool,
+
+    /// When set, each response is recorded in the audit log.
+    audit: Option<audit::AuditLog>,
+
+    /// When set, responses' repeated fields are sorted and lookups carry a content hash.
+    stable_encoding: bool,
+
+    /// When set, responses are delayed by this amount.
+    inject_latency: Option<Duration>,
+}
+
+/// An IP address family.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IpFamily {
+    V4,
+    V6,
+}
+
+/// Configures the connections accepted by a [`Server`].
+#[derive(Clone, Debug, Default)]
+pub struct ServerConfig {
+    /// When set, HTTP/2 pings are sent to clients at this interval.
+    ///
+    /// Proxies send their own keepalive pings on control plane connections, so this is mostly
+    /// useful to detect clients that disappear without closing their connections.
+    pub keepalive_interval: Option<Duration>,
+
+    /// The amount of time to wait for a keepalive ping to be acknowledged before the connection
+    /// is closed. Only used when a `keepalive_interval` is set.
+    pub keepalive_timeout: Option<Duration>,
+
+    /// When set, connections are closed if the client doesn't begin the HTTP/2 handshake within
+    /// this amount of time.
+    pub connect_timeout: Option<Duration>,
+
+    /// When set, connections are served over TLS. Otherwise, the server accepts plaintext
+    /// connections.
+    pub tls: Option<TlsConfig>,
+
+    /// When set, each connection is closed gracefully (i.e. with an HTTP/2 GOAWAY) once it has been
+    /// open for this amount of time, so that clients reconnect and their connections are
+    /// rebalanced across replicas.
+    pub max_connection_age: Option<Duration>,
+
+    /// When set, connections that remain open this long after their maximum age (e.g. because
+    /// a watch is still streaming) are closed forcibly. Only used when a `max_connection_age` is
+    /// set.
+    pub max_connection_age_grace: Option<Duration>,
+
+    /// When set, limits the number of concurrent HTTP/2 streams on each connection.
+    pub max_concurrent_streams: Option<u32>,
+
+    /// When set, overrides the default HTTP/2 initial window size of each stream.
+    pub initial_stream_window_size: Option<u32>,
+
+    /// When set, overrides the default HTTP/2 initial window size of each connection.
+    pub initial_connection_window_size: Option<u32>,
+
+    /// When set, limits the number of lookups processed concurrently across all connections.
+    /// Requests beyond this limit wait for capacity rather than failing.
+    ///
+    /// Unlike a concurrency limit on the server's service, which would hold capacity for as long
+    /// as each watch streams, watches only hold capacity while they're established.
+    pub max_concurrent_requests: Option<NonZeroUsize>,
+
+    /// After a watch sends an update, further updates received within this window are collapsed
+    /// so that only the most recent is sent. Zero disables coalescing.
+    pub update_debounce: Duration,
+
+    /// When set, identifies the controller's build in each response's
+    /// `l5d-policy-controller-version` header.
+    pub version: Option<String>,
+
+    /// Tracks the number of open connections.
+    pub connections: Gauge,
+
+    /// When set, networks of this address family are omitted from authorizations, so that proxies
+    /// in a single-stack cluster aren't configured with networks they can't use.
+    pub omit_family: Option<IpFamily>,
+
+    /// When set, discovery requests are denied unless the client's mTLS identity is in the
+    /// namespace of the requested workload. This requires that clients are authenticated, i.e.
+    /// that the TLS configuration sets a `client_ca`.
+    pub enforce_client_identity: bool,
+
+    /// When set, a record of each discovery response is sent to this audit log.
+    pub audit: Option<audit::AuditLog>,
+
+    /// When set, responses' repeated fields are sorted so that equivalent policies are encoded
+    /// identically, and `GetPort` responses carry an `l5d-policy-hash` header with a hash of the
+    /// encoded response. Watch updates are sent as messages, which can't carry metadata, but their
+    /// encodings are also stable so that clients may hash them.
+    pub stable_encoding: bool,
+
+    /// When set, each lookup response and watch update is delayed by this amount, so that clients'
+    /// resilience to a slow controller can be tested. This must never be set in production.
+    pub inject_latency: Option<Duration>,
+}
+
+/// A bound socket on which a [`Server`] accepts connections.
+#[derive(Debug)]
+pub enum Listener {
+    Tcp(TcpListener),
+
+    /// A Unix domain socket, so that the server is only reachable from the local host.
+    Unix(UnixListener),
+
+    /// Connections accepted on a listener that is shared with another server, e.g. when the admin
+    /// and gRPC servers are served on a single port. The server stops once the sender is dropped.
+    Shared(mpsc::Receiver<TcpStream>),
+}
+
+/// PEM-encoded credentials used to serve TLS.
+#[derive(Clone)]
+pub struct TlsConfig {
+    pub cert: Vec<u8>,
+    pub key: Vec<u8>,
+
+    /// When set, clients must present a certificate issued by one of these roots.
+    pub client_ca: Option<Vec<u8>>,
+}
+
+/// Why a request's target can't be served.
+#[derive(Debug)]
+enum TargetError {
+    /// The target isn't a valid workload and port.
+    Invalid(String),
+
+    /// The client's identity may not discover the target.
+    Denied(String),
+}
+
+// === impl Listener ===
+
+impl From<TcpListener> for Listener {
+    fn from(listener: TcpListener) -> Self {
+        Self::Tcp(listener)
+    }
+}
+
+impl From<UnixListener> for Listener {
+    fn from(listener: UnixListener) -> Self {
+        Self::Unix(listener)
+    }
+}
+
+impl From<mpsc::Receiver<TcpStream>> for Listener {
+    fn from(connections: mpsc::Receiver<TcpStream>) -> Self {
+        Self::Shared(connections)
+    }
+}
+
+// === impl TlsConfig ===
+
+impl TlsConfig {
+    /// Builds a TLS acceptor from the credentials, so that invalid credentials are reported before
+    /// the server is started.
+    pub fn validate(&self) -> Result<(), tonic::transport::Error> {
+        tonic::transport::Server::builder()
+            .tls_config(self.server_config())
+            .map(|_| ())
+    }
+
+    fn server_config(&self) -> tonic::transport::ServerTlsConfig {
+        let tls = tonic::transport::ServerTlsConfig::new().identity(
+            tonic::transport::Identity::from_pem(self.cert.clone(), self.key.clone()),
+        );
+        match self.client_ca.clone() {
+            Some(ca) => tls.client_ca_root(tonic::transport::Certificate::from_pem(ca)),
+            None => tls,
+        }
+    }
+}
+
+impl std::fmt::Debug for TlsConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Omit the private key.
+        f.debug_struct("TlsConfig")
+            .field("client_auth", &self.client_ca.is_some())
+            .finish()
+    }
+}
+
+// === impl TargetError ===
+
+impl From<TargetError> for tonic::Status {
+    fn from(error: TargetError) -> Self {
+        match error {
+            TargetError::Invalid(msg) => Self::invalid_argument(msg),
+            TargetError::Denied(msg) => Self::permission_denied(msg),
+        }
+    }
+}
+
+// === impl IpFamily ===
+
+impl IpFamily {
+    fn of(net: &IpNet) -> Self {
+        match net {
+            IpNet::V4(_) => Self::V4,
+            IpNet::V6(_) => Self::V6,
+        }
+    }
 }
 
 // === impl Server ===
@@ -27,38 +257,259 @@ where
     T: DiscoverInboundServer<(String, String, u16)> + Send + Sync + 'static,
 {
     pub fn new(discover: T, cluster_networks: Vec<IpNet>, drain: drain::Watch) -> Self {
+        let (_, cluster_networks) = watch::channel(cluster_networks.into());
         Self {
             discover,
             drain,
-            cluster_networks: cluster_networks.into(),
+            cluster_networks,
+            requests: None,
+            update_debounce: Duration::ZERO,
+            omit_family: None,
+            enforce_client_identity: false,
+            audit: None,
+            stable_encoding: false,
+            inject_latency: None,
+        }
+    }
+
+    /// Updates responses' cluster networks as `networks` changes, e.g. as a file is reloaded.
+    ///
+    /// Active watches resend their latest update when the networks change.
+    pub fn with_cluster_networks(mut self, networks: watch::Receiver<Arc<[IpNet]>>) -> Self {
+        self.cluster_networks = networks;
+        self
+    }
+
+    fn with_request_limit(mut self, limit: Option<NonZeroUsize>) -> Self {
+        self.requests = limit.map(|n| Arc::new(Semaphore::new(n.get())));
+        self
+    }
+
+    fn with_update_debounce(mut self, debounce: Duration) -> Self {
+        self.update_debounce = debounce;
+        self
+    }
+
+    fn with_omit_family(mut self, family: Option<IpFamily>) -> Self {
+        self.omit_family = family;
+        self
+    }
+
+    fn with_client_identity_enforced(mut self, enforce: bool) -> Self {
+        self.enforce_client_identity = enforce;
+        self
+    }
+
+    fn with_audit_log(mut self, audit: Option<audit::AuditLog>) -> Self {
+        self.audit = audit;
+        self
+    }
+
+    fn with_stable_encoding(mut self, stable: bool) -> Self {
+        self.stable_encoding = stable;
+        self
+    }
+
+    fn with_injected_latency(mut self, latency: Option<Duration>) -> Self {
+        self.inject_latency = latency;
+        self
+    }
+
+    /// Returns the client's identity, if responses are audited.
+    fn audited_identity(&self, req: &tonic::Request<proto::PortSpec>) -> Option<String> {
+        self.audit.as_ref()?;
+        audit::Record::client_identity(req.peer_certs().as_ref())
+    }
+
+    /// Waits for capacity to process a request, if requests are limited.
+    async fn acquire(&self) -> Option<SemaphorePermit<'_>> {
+        match self.requests.as_ref() {
+            Some(requests) => Some(
+                requests
+                    .acquire()
+                    .await
+                    .expect("semaphore must not be closed"),
+            ),
+            None => None,
         }
     }
 
+    /// Serves the policy discovery service alongside the standard `grpc.health.v1.Health`
+    /// service.
+    ///
+    /// The policy service is reported as serving once `ready` is set (i.e. once the index has
+    /// synced), and as not serving once the server begins draining.
+    ///
+    /// Each request is logged with the ID from its `l5d-request-id` header (or a generated ID),
+    /// which is echoed back in the response's headers and trailers. When a version is configured,
+    /// it's set on each response's `l5d-policy-controller-version` header.
+    ///
+    /// When a maximum connection age is configured, each connection is served independently so that
+    /// it can be closed gracefully once it ages, without affecting other connections.
     pub async fn serve(
         self,
-        addr: std::net::SocketAddr,
+        listener: impl Into<Listener>,
+        config: ServerConfig,
+        ready: watch::Receiver<bool>,
         shutdown: impl std::future::Future<Output = ()>,
-    ) -> Result<(), tonic::transport::Error> {
-        tonic::transport::Server::builder()
-            .add_service(InboundServerPoliciesServer::new(self))
-            .serve_with_shutdown(addr, shutdown)
-            .await
+    ) -> Result<(), tonic::transport::Error>
+    where
+        T: Clone,
+    {
+        let server = self
+            .with_request_limit(config.max_concurrent_requests)
+            .with_update_debounce(config.update_debounce)
+            .with_omit_family(config.omit_family)
+            .with_client_identity_enforced(config.enforce_client_identity)
+            .with_audit_log(config.audit)
+            .with_stable_encoding(config.stable_encoding)
+            .with_injected_latency(config.inject_latency);
+
+        let (reporter, health) = tonic_health::server::health_reporter();
+        tokio::spawn(health::report(
+            reporter,
+            <InboundServerPoliciesServer<Self> as NamedService>::NAME,
+            ready,
+            server.drain.clone(),
+        ));
+
+        let mut builder = tonic::transport::Server::builder();
+        if let Some(tls) = config.tls.as_ref() {
+            builder = builder.tls_config(tls.server_config())?;
+        }
+
+        let mut builder = builder
+            .layer(request_id::RequestIdLayer::default())
+            .layer(version::VersionLayer::new(config.version.as_deref()))
+            .http2_keepalive_interval(config.keepalive_interval)
+            .http2_keepalive_timeout(config.keepalive_timeout)
+            .max_concurrent_streams(config.max_concurrent_streams)
+            .initial_stream_window_size(config.initial_stream_window_size)
+            .initial_connection_window_size(config.initial_connection_window_size);
+
+        // The listener types differ, so each is served by the same code.
+        let max_connection_age = config.max_connection_age;
+        let deadline = max_connection_age
+            .zip(config.max_connection_age_grace)
+            .map(|(age, grace)| age + grace);
+        macro_rules! serve {
+            ($incoming:expr) => {
+                match max_connection_age {
+                    None => {
+                        builder
+                            .add_service(health)
+                            .add_service(InboundServerPoliciesServer::new(server))
+                            .serve_with_incoming_shutdown(Box::pin($incoming), shutdown)
+                            .await
+                    }
+                    Some(age) => {
+                        let mut incoming = Box::pin($incoming);
+                        tokio::pin!(shutdown);
+                        loop {
+                            let conn = tokio::select! {
+                                conn = incoming.next() => match conn {
+                                    Some(Ok(conn)) => conn.with_deadline(deadline),
+                                    Some(Err(error)) => {
+                                        warn!(%error, "Failed to accept connection");
+                                        tokio::time::sleep(ACCEPT_BACKOFF).await;
+                                        continue;
+                                    }
+                                    None => return Ok(()),
+                                },
+                                () = &mut shutdown => return Ok(()),
+                            };
+
+                            // Each connection is served until it ages or the server drains. The
+                            // task holds a drain handle so that draining waits for it to close.
+                            let router = builder
+                                .clone()
+                                .add_service(health.clone())
+                                .add_service(InboundServerPoliciesServer::new(server.clone()));
+                            let drain = server.drain.clone();
+                            tokio::spawn(async move {
+                                let close = {
+                                    let drained = drain.clone().signaled();
+                                    async move {
+                                        tokio::select! {
+                                            _ = tokio::time::sleep(age) => {}
+                                            _ = drained => {}
+                                        }
+                                    }
+                                };
+                                // The connection's stream never ends, since the server stops
+                                // serving once its incoming stream completes.
+                                let incoming = stream::once(future::ready(Ok::<_, io::Error>(conn)))
+                                    .chain(stream::pending());
+                                if let Err(error) = router
+                                    .serve_with_incoming_shutdown(Box::pin(incoming), close)
+                                    .await
+                                {
+                                    debug!(%error, "Connection failed");
+                                }
+                                drop(drain);
+                            });
+                        }
+                    }
+                }
+            };
+        }
+
+        match listener.into() {
+            Listener::Tcp(listener) => {
+                serve!(incoming::tcp(
+                    listener,
+                    config.connect_timeout,
+                    config.connections
+                ))
+            }
+            Listener::Unix(listener) => {
+                serve!(incoming::unix(
+                    listener,
+                    config.connect_timeout,
+                    config.connections
+                ))
+            }
+            Listener::Shared(connections) => {
+                serve!(incoming::shared(
+                    connections,
+                    config.connect_timeout,
+                    config.connections
+                ))
+            }
+        }
+    }
+
+    /// Validates the requested target and, when client identities are enforced, authorizes the
+    /// client to discover it.
+    ///
+    /// The target workload is only known once the request message is read, so this is checked by
+    /// each handler rather than by an interceptor.
+    fn authorize_target(
+        &self,
+        req: tonic::Request<proto::PortSpec>,
+    ) -> Result<(String, String, u16), TargetError> {
+        let peer_certs = req.peer_certs();
+        let target = self.check_target(req.into_inner())?;
+        if self.enforce_client_identity {
+            identity::authorize(peer_certs, &target.0).map_err(TargetError::Denied)?;
+        }
+        Ok(target)
     }
 
     fn check_target(
         &self,
         proto::PortSpec { workload, port }: proto::PortSpec,
-    ) -> Result<(String, String, u16), tonic::Status> {
+    ) -> Result<(String, String, u16), TargetError> {
         // Parse a workload name in the form namespace:name.
         let (ns, name) = match workload.split_once(':') {
             None => {
-                return Err(tonic::Status::invalid_argument(format!(
+                return Err(TargetError::Invalid(format!(
                     "Invalid workload: {}",
                     workload
                 )));
             }
             Some((ns, pod)) if ns.is_empty() || pod.is_empty() => {
-                return Err(tonic::Status::invalid_argument(format!(
+                return Err(TargetError::Invalid(format!(
                     "Invalid workload: {}",
                     workload
                 )));
@@ -68,11 +519,8 @@ where
 
         // Ensure that the port is in the valid range.
         let port = {
-            if port == 0 || port > std::u16::MAX as u32 {
-                return Err(tonic::Status::invalid_argument(format!(
-                    "Invalid port: {}",
-                    port
-                )));
+            if port == 0 || port > u16::MAX as u32 {
+                return Err(TargetError::Invalid(format!("Invalid port: {}", port)));
             }
             port as u16
         };
@@ -90,18 +538,39 @@ where
         &self,
         req: tonic::Request<proto::PortSpec>,
     ) -> Result<tonic::Response<proto::Server>, tonic::Status> {
-        let target = self.check_target(req.into_inner())?;
+        let client_identity = self.audited_identity(&req);
+        let target = self.authorize_target(req)?;
+        let _permit = self.acquire().await;
 
         // Lookup the configuration for an inbound port. If the pod hasn't (yet)
         // been indexed, return a Not Found error.
         let s = self
             .discover
-            .get_inbound_server(target)
+            .get_inbound_server(target.clone())
             .await
             .map_err(|e| tonic::Status::internal(format!("lookup failed: {}", e)))?
             .ok_or_else(|| tonic::Status::not_found("unknown server"))?;
+        if let Some(latency) = self.inject_latency {
+            tokio::time::sleep(latency).await;
+        }
 
-        Ok(tonic::Response::new(to_server(&s, &*self.cluster_networks)))
+        let cluster_networks = self.cluster_networks.borrow().clone();
+        let mut server = to_server(&s, &cluster_networks, self.omit_family);
+        if let Some(audit) = self.audit.as_ref() {
+            audit.record(audit::Record::new(client_identity, &target, &server));
+        }
+        if !self.stable_encoding {
+            return Ok(tonic::Response::new(server));
+        }
+
+        sort_server(&mut server);
+        let hash = content_hash(&server);
+        let mut rsp = tonic::Response::new(server);
+        rsp.metadata_mut().insert(
+            HASH_HEADER,
+            hash.parse().expect("hex must be a valid header value"),
+        );
+        Ok(rsp)
     }
 
     type WatchPortStream = BoxWatchStream;
@@ -110,56 +579,140 @@ where
         &self,
         req: tonic::Request<proto::PortSpec>,
     ) -> Result<tonic::Response<BoxWatchStream>, tonic::Status> {
-        let target = self.check_target(req.into_inner())?;
+        let client_identity = self.audited_identity(&req);
+        let target = self.authorize_target(req)?;
+        // The permit is only held while the watch is established, not for the life of the stream.
+        let _permit = self.acquire().await;
         let drain = self.drain.clone();
         let rx = self
             .discover
-            .watch_inbound_server(target)
+            .watch_inbound_server(target.clone())
             .await
             .map_err(|e| tonic::Status::internal(format!("lookup failed: {}", e)))?
             .ok_or_else(|| tonic::Status::not_found("unknown server"))?;
-        Ok(tonic::Response::new(response_stream(
+        let updates = response_stream(
             drain,
             rx,
             self.cluster_networks.clone(),
-        )))
+            self.omit_family,
+            self.update_debounce,
+            self.inject_latency,
+        );
+        let updates: BoxWatchStream = if self.stable_encoding {
+            Box::pin(updates.map_ok(|mut server| {
+                sort_server(&mut server);
+                server
+            }))
+        } else {
+            updates
+        };
+
+        // Each update sent on the stream is a response, so each is audited.
+        let updates: BoxWatchStream = match self.audit.clone() {
+            Some(audit) => Box::pin(updates.inspect(move |res| {
+                if let Ok(server) = res {
+                    audit.record(audit::Record::new(client_identity.clone(), &target, server));
+                }
+            })),
+            None => updates,
+        };
+        Ok(tonic::Response::new(updates))
     }
 }
 
+/// How long to wait before accepting another connection after the listener fails.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(500);
+
+/// The header on which a response's content hash is set, when encodings are stable.
+const HASH_HEADER: &str = "l5d-policy-hash";
+
 type BoxWatchStream =
     std::pin::Pin<Box<dyn Stream<Item = Result<proto::Server, tonic::Status>> + Send + Sync>>;
 
+/// Streams a port's server updates to the client.
+///
+/// The first update is sent immediately. Each later update is held for the `debounce` window, and
+/// any updates received in the meantime replace it, so that a burst of changes is sent to the
+/// proxy as a single update reflecting the latest state. When latency is injected, each update is
+/// further delayed by it.
+///
+/// When the cluster networks change, the latest update is resent so that its authorizations
+/// reflect the new networks.
 fn response_stream(
     drain: drain::Watch,
     mut rx: InboundServerStream,
-    cluster_networks: Arc<[IpNet]>,
+    mut cluster_networks: watch::Receiver<Arc<[IpNet]>>,
+    omit_family: Option<IpFamily>,
+    debounce: Duration,
+    latency: Option<Duration>,
 ) -> BoxWatchStream {
     Box::pin(async_stream::try_stream! {
         tokio::pin! {
             let shutdown = drain.signaled();
         }
 
+        let mut window = Duration::ZERO;
+        let mut sent = None;
         loop {
-            tokio::select! {
+            let mut latest = tokio::select! {
                 // When the port is updated with a new server, update the server watch.
                 res = rx.next() => match res {
-                    Some(s) => {
-                        yield to_server(&s, &*cluster_networks);
-                    }
+                    Some(s) => s,
                     None => return,
                 },
 
+                // When the cluster networks change, resend the latest update.
+                Ok(()) = cluster_networks.changed(), if sent.is_some() => {
+                    sent.take().expect("an update must have been sent")
+                },
+
                 // If the server starts shutting down, close the stream so that it doesn't hold the
                 // server open.
                 _ = (&mut shutdown) => {
                     return;
                 }
+            };
+
+            let mut closed = false;
+            if !window.is_zero() {
+                let deadline = tokio::time::sleep(window);
+                tokio::pin!(deadline);
+                loop {
+                    tokio::select! {
+                        res = rx.next() => match res {
+                            Some(s) => latest = s,
+                            None => {
+                                closed = true;
+                                break;
+                            }
+                        },
+                        _ = (&mut deadline) => break,
+                        _ = (&mut shutdown) => {
+                            return;
+                        }
+                    }
+                }
             }
+
+            if let Some(latency) = latency {
+                tokio::time::sleep(latency).await;
+            }
+            let networks = cluster_networks.borrow().clone();
+            yield to_server(&latest, &*networks, omit_family);
+            if closed {
+                return;
+            }
+            sent = Some(latest);
+            window = debounce;
         }
     })
 }
 
-fn to_server(srv: &InboundServer, cluster_networks: &[IpNet]) -> proto::Server {
+fn to_server(
+    srv: &InboundServer,
+    cluster_networks: &[IpNet],
+    omit_family: Option<IpFamily>,
+) -> proto::Server {
     // Convert the protocol object into a protobuf response.
     let protocol = proto::ProxyProtocol {
         kind: match srv.protocol {
@@ -190,7 +743,7 @@ fn to_server(srv: &InboundServer, cluster_networks: &[IpNet]) -> proto::Server {
     let authorizations = srv
         .authorizations
         .iter()
-        .map(|(n, c)| to_authz(n, c, cluster_networks))
+        .map(|(n, c)| to_authz(n, c, cluster_networks, omit_family))
         .collect();
     trace!(?authorizations);
 
@@ -207,6 +760,50 @@ fn to_server(srv: &InboundServer, cluster_networks: &[IpNet]) -> proto::Server {
     }
 }
 
+/// Sorts a response's repeated fields so that equivalent policies are encoded identically.
+///
+/// Authorizations are already ordered by name and labels have a single entry, so only networks and
+/// client identities need to be sorted. Their order has no meaning to the proxy.
+fn sort_server(server: &mut proto::Server) {
+    use proto::authn::{permit_mesh_tls::Clients, Permit, PermitMeshTls};
+
+    fn sort<M: prost::Message>(items: &mut [M]) {
+        items.sort_by_cached_key(|m| m.encode_to_vec());
+    }
+
+    for authz in server.authorizations.iter_mut() {
+        for net in authz.networks.iter_mut() {
+            sort(&mut net.except);
+        }
+        sort(&mut authz.networks);
+
+        let permit = authz
+            .authentication
+            .as_mut()
+            .and_then(|a| a.permit.as_mut());
+        if let Some(Permit::MeshTls(PermitMeshTls {
+            clients: Some(Clients::Identities(clients)),
+        })) = permit
+        {
+            sort(&mut clients.identities);
+            sort(&mut clients.suffixes);
+        }
+    }
+}
+
+/// Hashes a response's encoding with 64-bit FNV-1a, formatted as hex.
+fn content_hash(server: &proto::Server) -> String {
+    use prost::Message;
+
+    let hash = server
+        .encode_to_vec()
+        .iter()
+        .fold(0xcbf29ce484222325u64, |h, b| {
+            (h ^ *b as u64).wrapping_mul(0x100000001b3)
+        });
+    format!("{:016x}", hash)
+}
+
 fn to_authz(
     name: impl ToString,
     ClientAuthorization {
@@ -214,10 +811,13 @@ fn to_authz(
         authentication,
     }: &ClientAuthorization,
     cluster_networks: &[IpNet],
+    omit_family: Option<IpFamily>,
 ) -> proto::Authz {
+    let include = |net: &IpNet| omit_family != Some(IpFamily::of(net));
     let networks = if networks.is_empty() {
         cluster_networks
             .iter()
+            .filter(|n| include(n))
             .map(|n| proto::Network {
                 net: Some((*n).into()),
                 except: vec![],
@@ -226,9 +826,15 @@ fn to_authz(
     } else {
         networks
             .iter()
+            .filter(|NetworkMatch { net, .. }| include(net))
             .map(|NetworkMatch { net, except }| proto::Network {
                 net: Some((*net).into()),
-                except: except.iter().cloned().map(Into::into).collect(),
+                except: except
+                    .iter()
+                    .filter(|n| include(n))
+                    .cloned()
+                    .map(Into::into)
+                    .collect(),
             })
             .collect()
     };
@@ -292,3 +898,703 @@ fn to_authz(
         authentication: Some(authn),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use tonic_health::proto::{
+        health_check_response::ServingStatus, health_client::HealthClient, HealthCheckRequest,
+    };
+
+    /// A discovery implementation that never finds any servers.
+    #[derive(Clone)]
+    struct NotFound;
+
+    #[async_trait::async_trait]
+    impl DiscoverInboundServer<(String, String, u16)> for NotFound {
+        async fn get_inbound_server(
+            &self,
+            _: (String, String, u16),
+        ) -> anyhow::Result<Option<InboundServer>> {
+            Ok(None)
+        }
+
+        async fn watch_inbound_server(
+            &self,
+            _: (String, String, u16),
+        ) -> anyhow::Result<Option<InboundServerStream>> {
+            Ok(None)
+        }
+    }
+
+    #[tokio::test]
+    async fn health_reflects_readiness() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (drain_tx, drain_rx) = drain::channel();
+        let (ready_tx, ready_rx) = watch::channel(false);
+        let server = Server::new(NotFound, vec![], drain_rx);
+        tokio::spawn(server.serve(
+            listener,
+            ServerConfig::default(),
+            ready_rx,
+            future::pending(),
+        ));
+
+        let channel = tonic::transport::Endpoint::from_shared(format!("http://{}", addr))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let mut client = HealthClient::new(channel);
+        let policy = <InboundServerPoliciesServer<Server<NotFound>> as NamedService>::NAME;
+
+        await_status(&mut client, policy, ServingStatus::NotServing).await;
+        await_status(&mut client, "", ServingStatus::NotServing).await;
+
+        ready_tx.send(true).unwrap();
+        await_status(&mut client, policy, ServingStatus::Serving).await;
+        await_status(&mut client, "", ServingStatus::Serving).await;
+
+        // The server continues to serve while draining, since its shutdown never fires.
+        tokio::spawn(drain_tx.drain());
+        await_status(&mut client, policy, ServingStatus::NotServing).await;
+    }
+
+    #[tokio::test]
+    async fn counts_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (_drain_tx, drain_rx) = drain::channel();
+        let (_ready_tx, ready_rx) = watch::channel(true);
+        let connections = Gauge::default();
+        let config = ServerConfig {
+            connections: connections.clone(),
+            ..Default::default()
+        };
+        let server = Server::new(NotFound, vec![], drain_rx);
+        tokio::spawn(server.serve(listener, config, ready_rx, future::pending()));
+
+        let channel = tonic::transport::Endpoint::from_shared(format!("http://{}", addr))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let mut client = HealthClient::new(channel);
+        await_status(&mut client, "", ServingStatus::Serving).await;
+        assert_eq!(connections.get(), 1);
+
+        // The gauge is decremented once the client disconnects.
+        drop(client);
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while connections.get() != 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("connection must be closed");
+    }
+
+    #[tokio::test]
+    async fn closes_aged_connections() {
+        use linkerd2_proxy_api::inbound::inbound_server_policies_client::InboundServerPoliciesClient;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (_drain_tx, drain_rx) = drain::channel();
+        let (_ready_tx, ready_rx) = watch::channel(true);
+        let connections = Gauge::default();
+        let config = ServerConfig {
+            max_connection_age: Some(Duration::from_millis(100)),
+            max_connection_age_grace: Some(Duration::from_millis(200)),
+            connections: connections.clone(),
+            ..Default::default()
+        };
+        let server = Server::new(Static(mk_server("srv-0")), vec![], drain_rx);
+        tokio::spawn(server.serve(listener, config, ready_rx, future::pending()));
+
+        let socket = TcpStream::connect(addr).await.unwrap();
+        let (client, conn) = h2::client::handshake(socket).await.unwrap();
+        let conn = tokio::spawn(conn);
+
+        // The server sends a GOAWAY once the connection ages, after which the connection closes
+        // without error and no new streams may be opened.
+        tokio::time::timeout(Duration::from_secs(1), conn)
+            .await
+            .expect("connection must be closed")
+            .unwrap()
+            .expect("connection must be closed gracefully");
+        let error = client.ready().await.expect_err("connection must be closed");
+        assert_eq!(error.reason(), Some(h2::Reason::NO_ERROR));
+        let closed = || {
+            let connections = connections.clone();
+            tokio::time::timeout(Duration::from_secs(1), async move {
+                while connections.get() != 0 {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            })
+        };
+        closed().await.expect("connection must be closed");
+
+        // A connection with an in-flight watch isn't closed when it ages, since the watch never
+        // completes, so it's closed forcibly once the grace period elapses.
+        let channel = tonic::transport::Endpoint::from_shared(format!("http://{}", addr))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let mut client = InboundServerPoliciesClient::new(channel);
+        let mut updates = client
+            .watch_port(proto::PortSpec {
+                workload: "ns-0:pod-0".to_string(),
+                port: 8080,
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        updates
+            .message()
+            .await
+            .unwrap()
+            .expect("must receive an update");
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert_eq!(
+            connections.get(),
+            1,
+            "the watch must outlive the maximum age"
+        );
+        let res = tokio::time::timeout(Duration::from_secs(1), updates.message())
+            .await
+            .expect("the watch must be closed after the grace period");
+        assert!(!matches!(res, Ok(Some(_))), "{:?}", res);
+        closed().await.expect("connection must be closed");
+    }
+
+    /// Captures formatted logs.
+    #[derive(Clone, Default)]
+    struct Logs(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Logs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Logs {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    #[tokio::test]
+    async fn logs_connections() {
+        let logs = Logs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_writer(move || writer.clone())
+            .finish();
+        // The test's runtime runs all tasks on this thread, so the server's logs are captured.
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (_drain_tx, drain_rx) = drain::channel();
+        let (_ready_tx, ready_rx) = watch::channel(true);
+        let server = Server::new(NotFound, vec![], drain_rx);
+        tokio::spawn(server.serve(
+            listener,
+            ServerConfig::default(),
+            ready_rx,
+            future::pending(),
+        ));
+
+        let channel = tonic::transport::Endpoint::from_shared(format!("http://{}", addr))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let mut client = HealthClient::new(channel);
+        await_status(&mut client, "", ServingStatus::Serving).await;
+        let opened = logs
+            .contents()
+            .lines()
+            .find(|l| l.contains("Connection opened"))
+            .expect("connection must be logged when opened")
+            .to_string();
+        assert!(opened.contains("conn.id="), "{}", opened);
+        assert!(opened.contains("client.addr=127.0.0.1:"), "{}", opened);
+
+        drop(client);
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while !logs.contents().contains("Connection closed") {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("connection must be logged when closed");
+
+        // Both logs identify the same connection.
+        let id = opened.split("conn.id=").nth(1).unwrap().split(' ').next();
+        let contents = logs.contents();
+        let closed = contents
+            .lines()
+            .find(|l| l.contains("Connection closed"))
+            .unwrap();
+        assert_eq!(
+            closed.split("conn.id=").nth(1).unwrap().split(' ').next(),
+            id
+        );
+    }
+
+    /// A discovery implementation that waits for `release` permits before completing lookups.
+    #[derive(Clone)]
+    struct Blocking {
+        started: Arc<std::sync::atomic::AtomicUsize>,
+        release: Arc<Semaphore>,
+    }
+
+    #[async_trait::async_trait]
+    impl DiscoverInboundServer<(String, String, u16)> for Blocking {
+        async fn get_inbound_server(
+            &self,
+            _: (String, String, u16),
+        ) -> anyhow::Result<Option<InboundServer>> {
+            self.started
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            self.release.acquire().await?.forget();
+            Ok(None)
+        }
+
+        async fn watch_inbound_server(
+            &self,
+            _: (String, String, u16),
+        ) -> anyhow::Result<Option<InboundServerStream>> {
+            Ok(None)
+        }
+    }
+
+    #[tokio::test]
+    async fn limits_concurrent_requests() {
+        let discover = Blocking {
+            started: Default::default(),
+            release: Arc::new(Semaphore::new(0)),
+        };
+        let (_drain_tx, drain_rx) = drain::channel();
+        let server = Server::new(discover.clone(), vec![], drain_rx)
+            .with_request_limit(NonZeroUsize::new(1));
+        let started = || discover.started.load(std::sync::atomic::Ordering::SeqCst);
+        let get = |server: Server<Blocking>| {
+            tokio::spawn(async move {
+                let req = tonic::Request::new(proto::PortSpec {
+                    workload: "ns:pod".to_string(),
+                    port: 8080,
+                });
+                server.get_port(req).await
+            })
+        };
+
+        let first = get(server.clone());
+        while started() == 0 {
+            tokio::task::yield_now().await;
+        }
+        let second = get(server.clone());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(started(), 1, "the second request must wait for capacity");
+
+        // Once the first request completes, the second is processed.
+        discover.release.add_permits(1);
+        assert!(first.await.unwrap().is_err(), "server must not be found");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(started(), 2);
+        discover.release.add_permits(1);
+        assert!(second.await.unwrap().is_err(), "server must not be found");
+    }
+
+    #[tokio::test]
+    async fn enforces_client_identity() {
+        let (_drain_tx, drain_rx) = drain::channel();
+        let server = Server::new(NotFound, vec![], drain_rx);
+        let req = || {
+            tonic::Request::new(proto::PortSpec {
+                workload: "ns:pod".to_string(),
+                port: 8080,
+            })
+        };
+
+        let status = server.get_port(req()).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+
+        // Without an mTLS identity, the client may not discover any workload.
+        let server = server.with_client_identity_enforced(true);
+        let status = server.get_port(req()).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+        let status = server.watch_port(req()).await.err().unwrap();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+    }
+
+    /// A discovery implementation that serves the same server for every port.
+    #[derive(Clone)]
+    struct Static(InboundServer);
+
+    #[async_trait::async_trait]
+    impl DiscoverInboundServer<(String, String, u16)> for Static {
+        async fn get_inbound_server(
+            &self,
+            _: (String, String, u16),
+        ) -> anyhow::Result<Option<InboundServer>> {
+            Ok(Some(self.0.clone()))
+        }
+
+        async fn watch_inbound_server(
+            &self,
+            _: (String, String, u16),
+        ) -> anyhow::Result<Option<InboundServerStream>> {
+            let srv = self.0.clone();
+            Ok(Some(Box::pin(
+                stream::once(future::ready(srv)).chain(stream::pending()),
+            )))
+        }
+    }
+
+    #[tokio::test]
+    async fn audits_responses() {
+        let (_drain_tx, drain_rx) = drain::channel();
+        let (log, mut records) = audit::channel(10, Default::default());
+        let server =
+            Server::new(Static(mk_server("srv-0")), vec![], drain_rx).with_audit_log(Some(log));
+        let req = || {
+            tonic::Request::new(proto::PortSpec {
+                workload: "ns-0:pod-0".to_string(),
+                port: 8080,
+            })
+        };
+
+        server.get_port(req()).await.unwrap();
+        let record = records.try_recv().expect("response must be audited");
+        assert_eq!(record.workload, "ns-0:pod-0");
+        assert_eq!(record.port, 8080);
+        assert_eq!(record.server, "srv-0");
+        assert_eq!(record.client_identity, None);
+
+        let mut updates = server.watch_port(req()).await.unwrap().into_inner();
+        assert!(
+            records.try_recv().is_err(),
+            "must not audit before a response is sent"
+        );
+        updates.next().await.unwrap().unwrap();
+        let record = records.try_recv().expect("update must be audited");
+        assert_eq!(record.server, "srv-0");
+    }
+
+    #[tokio::test]
+    async fn injects_latency() {
+        let (_drain_tx, drain_rx) = drain::channel();
+        let latency = Duration::from_millis(100);
+        let server = Server::new(Static(mk_server("srv-0")), vec![], drain_rx)
+            .with_injected_latency(Some(latency));
+        let req = || {
+            tonic::Request::new(proto::PortSpec {
+                workload: "ns-0:pod-0".to_string(),
+                port: 8080,
+            })
+        };
+
+        let start = tokio::time::Instant::now();
+        server.get_port(req()).await.unwrap();
+        let elapsed = start.elapsed();
+        assert!(
+            latency <= elapsed && elapsed < latency * 5,
+            "lookup must be delayed by about {:?}; took {:?}",
+            latency,
+            elapsed
+        );
+
+        let start = tokio::time::Instant::now();
+        let mut updates = server.watch_port(req()).await.unwrap().into_inner();
+        updates.next().await.unwrap().unwrap();
+        let elapsed = start.elapsed();
+        assert!(
+            latency <= elapsed && elapsed < latency * 5,
+            "update must be delayed by about {:?}; took {:?}",
+            latency,
+            elapsed
+        );
+    }
+
+    fn mk_server(name: &str) -> InboundServer {
+        InboundServer {
+            name: name.to_string(),
+            protocol: ProxyProtocol::Opaque,
+            authorizations: Default::default(),
+        }
+    }
+
+    #[test]
+    fn deny_has_no_authorizations() {
+        let srv = InboundServer {
+            name: "default:deny".to_string(),
+            protocol: ProxyProtocol::Detect {
+                timeout: Duration::from_secs(10),
+            },
+            authorizations: Default::default(),
+        };
+        let networks = ["10.0.0.0/8".parse::<IpNet>().unwrap()];
+        let proto = to_server(&srv, &networks, None);
+        assert!(
+            proto.authorizations.is_empty(),
+            "the proxy must reject all connections"
+        );
+        assert_eq!(proto.labels["name"], "default:deny");
+    }
+
+    #[test]
+    fn omits_address_family() {
+        let srv = InboundServer {
+            name: "srv-0".to_string(),
+            protocol: ProxyProtocol::Opaque,
+            authorizations: vec![
+                (
+                    "default".to_string(),
+                    ClientAuthorization {
+                        authentication: ClientAuthentication::Unauthenticated,
+                        networks: vec![],
+                    },
+                ),
+                (
+                    "authz-0".to_string(),
+                    ClientAuthorization {
+                        authentication: ClientAuthentication::Unauthenticated,
+                        networks: vec![
+                            NetworkMatch {
+                                net: "10.0.0.0/8".parse().unwrap(),
+                                except: vec!["10.1.0.0/16".parse().unwrap()],
+                            },
+                            "fd00::/8".parse::<IpNet>().unwrap().into(),
+                        ],
+                    },
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        let cluster_networks = [
+            "10.0.0.0/8".parse::<IpNet>().unwrap(),
+            "fd00::/8".parse::<IpNet>().unwrap(),
+        ];
+        // Returns the networks of each authorization, by name.
+        let nets = |family| {
+            to_server(&srv, &cluster_networks, family)
+                .authorizations
+                .into_iter()
+                .map(|authz| (authz.labels["name"].clone(), authz.networks))
+                .collect::<HashMap<_, _>>()
+        };
+        let net = |s: &str| -> Option<linkerd2_proxy_api::net::IpNetwork> {
+            Some(s.parse::<IpNet>().unwrap().into())
+        };
+
+        let all = nets(None);
+        assert_eq!(all["default"].len(), 2);
+        assert_eq!(all["authz-0"].len(), 2);
+
+        let v4 = nets(Some(IpFamily::V6));
+        assert_eq!(v4["default"].len(), 1);
+        assert_eq!(v4["default"][0].net, net("10.0.0.0/8"));
+        assert_eq!(v4["authz-0"].len(), 1);
+        assert_eq!(v4["authz-0"][0].net, net("10.0.0.0/8"));
+        assert_eq!(v4["authz-0"][0].except.len(), 1);
+
+        let v6 = nets(Some(IpFamily::V4));
+        assert_eq!(v6["default"].len(), 1);
+        assert_eq!(v6["default"][0].net, net("fd00::/8"));
+        assert_eq!(v6["authz-0"].len(), 1);
+        assert_eq!(v6["authz-0"][0].net, net("fd00::/8"));
+    }
+
+    #[test]
+    fn stable_encodings_are_identical() {
+        use prost::Message;
+
+        // Builds a server whose networks and identities are in the given order.
+        let mk = |nets: &[&str], ids: &[&str]| InboundServer {
+            name: "srv-0".to_string(),
+            protocol: ProxyProtocol::Http1,
+            authorizations: Some((
+                "authz-0".to_string(),
+                ClientAuthorization {
+                    networks: nets
+                        .iter()
+                        .map(|n| n.parse::<IpNet>().unwrap().into())
+                        .collect(),
+                    authentication: ClientAuthentication::TlsAuthenticated(
+                        ids.iter()
+                            .map(|id| IdentityMatch::Name(id.to_string()))
+                            .collect(),
+                    ),
+                },
+            ))
+            .into_iter()
+            .collect(),
+        };
+        let encode = |srv: &InboundServer| {
+            let mut server = to_server(srv, &[], None);
+            sort_server(&mut server);
+            server
+        };
+
+        let a = encode(&mk(&["10.0.0.0/8", "fd00::/8"], &["a.ns-0", "b.ns-0"]));
+        let b = encode(&mk(&["fd00::/8", "10.0.0.0/8"], &["b.ns-0", "a.ns-0"]));
+        assert_eq!(a.encode_to_vec(), b.encode_to_vec());
+        assert_eq!(content_hash(&a), content_hash(&b));
+
+        let c = encode(&mk(&["10.0.0.0/8"], &["a.ns-0", "b.ns-0"]));
+        assert_ne!(content_hash(&a), content_hash(&c));
+    }
+
+    #[tokio::test]
+    async fn coalesces_rapid_updates() {
+        let (_drain_tx, drain_rx) = drain::channel();
+        let debounce = Duration::from_millis(50);
+
+        // The initial update is followed by a burst of updates that are all ready immediately.
+        let updates = (0..=10).map(|i| mk_server(&format!("srv-{}", i)));
+        let rx = Box::pin(
+            futures::stream::iter(updates.collect::<Vec<_>>()).chain(futures::stream::pending()),
+        );
+        let (_, networks) = watch::channel(Arc::from(vec![]));
+        let mut stream = response_stream(drain_rx, rx, networks, None, debounce, None);
+
+        let name = |srv: proto::Server| srv.labels["name"].clone();
+        let initial = stream.next().await.unwrap().unwrap();
+        assert_eq!(
+            name(initial),
+            "srv-0",
+            "the first update must not be delayed"
+        );
+        let update = stream.next().await.unwrap().unwrap();
+        assert_eq!(name(update), "srv-10", "only the last update must be sent");
+        assert!(
+            tokio::time::timeout(debounce * 4, stream.next())
+                .await
+                .is_err(),
+            "the burst must produce a single update"
+        );
+    }
+
+    #[tokio::test]
+    async fn resends_updates_when_cluster_networks_change() {
+        let (_drain_tx, drain_rx) = drain::channel();
+        let srv = InboundServer {
+            authorizations: Some((
+                "default".to_string(),
+                ClientAuthorization {
+                    authentication: ClientAuthentication::Unauthenticated,
+                    networks: vec![],
+                },
+            ))
+            .into_iter()
+            .collect(),
+            ..mk_server("srv-0")
+        };
+        let rx = Box::pin(futures::stream::iter(Some(srv)).chain(futures::stream::pending()));
+        let net = |s: &str| s.parse::<IpNet>().unwrap();
+        let (networks_tx, networks_rx) = watch::channel(Arc::from(vec![net("10.0.0.0/8")]));
+        let mut stream = response_stream(drain_rx, rx, networks_rx, None, Duration::ZERO, None);
+
+        let nets = |srv: proto::Server| {
+            srv.authorizations[0]
+                .networks
+                .iter()
+                .map(|n| n.net.clone().unwrap())
+                .collect::<Vec<_>>()
+        };
+        let initial = stream.next().await.unwrap().unwrap();
+        assert_eq!(nets(initial), vec![net("10.0.0.0/8").into()]);
+
+        networks_tx
+            .send(Arc::from(vec![net("10.0.0.0/8"), net("fd00::/8")]))
+            .unwrap();
+        let update = tokio::time::timeout(Duration::from_secs(1), stream.next())
+            .await
+            .expect("the update must be resent")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            nets(update),
+            vec![net("10.0.0.0/8").into(), net("fd00::/8").into()]
+        );
+    }
+
+    #[tokio::test]
+    async fn serves_unix_socket() {
+        use linkerd2_proxy_api::inbound::inbound_server_policies_client::InboundServerPoliciesClient;
+
+        let path = std::env::temp_dir().join(format!("policy-{:016x}.sock", rand::random::<u64>()));
+        let listener = UnixListener::bind(&path).unwrap();
+        let (_drain_tx, drain_rx) = drain::channel();
+        let (_ready_tx, ready_rx) = watch::channel(true);
+        let server = Server::new(NotFound, vec![], drain_rx);
+        tokio::spawn(server.serve(
+            listener,
+            ServerConfig::default(),
+            ready_rx,
+            future::pending(),
+        ));
+
+        // The URI is ignored by the connector.
+        let channel = {
+            let path = path.clone();
+            tonic::transport::Endpoint::from_static("http://localhost")
+                .connect_with_connector(tower::service_fn(move |_: http::Uri| {
+                    tokio::net::UnixStream::connect(path.clone())
+                }))
+                .await
+                .unwrap()
+        };
+        let mut client = InboundServerPoliciesClient::new(channel);
+        let status = client
+            .get_port(proto::PortSpec {
+                workload: "ns:pod".to_string(),
+                port: 8080,
+            })
+            .await
+            .expect_err("server must not be found");
+        assert_eq!(status.code(), tonic::Code::NotFound);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    /// Polls the health service until `service` reports the expected status, since health is
+    /// reported asynchronously.
+    async fn await_status(
+        client: &mut HealthClient<tonic::transport::Channel>,
+        service: &str,
+        expected: ServingStatus,
+    ) {
+        let poll = async {
+            loop {
+                let req = HealthCheckRequest {
+                    service: service.to_string(),
+                };
+                let status = client.check(req).await.unwrap().into_inner().status;
+                if status == expected as i32 {
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        if tokio::time::timeout(Duration::from_secs(1), poll)
+            .await
+            .is_err()
+        {
+            panic!("{:?} must report {:?}", service, expected);
+        }
+    }
+}
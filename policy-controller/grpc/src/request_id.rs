@@ -0,0 +1,202 @@
+use futures::prelude::*;
+use http::header::{HeaderMap, HeaderName, HeaderValue};
+use std::{
+    net::SocketAddr,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tonic::transport::server::{TcpConnectInfo, TlsConnectInfo};
+use tracing::{field, info_span, Instrument};
+
+/// The header used to correlate a request across the proxy and the controller.
+pub(crate) const HEADER: &str = "l5d-request-id";
+
+/// Assigns each request an ID, taken from the client's `l5d-request-id` header or generated if the
+/// client didn't set one.
+///
+/// The request is processed in a span that records the ID along with the client's address and, when
+/// the client authenticated via TLS, its identity. The ID is echoed back to the client in both the
+/// response headers and trailers.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RequestIdLayer(());
+
+#[derive(Clone, Debug)]
+pub(crate) struct RequestId<S> {
+    inner: S,
+}
+
+/// A response body that adds the request ID to the body's trailers.
+///
+/// The request's span is entered while the body is polled so that streaming responses are logged
+/// with the request ID.
+#[derive(Debug)]
+pub(crate) struct WithRequestId<B> {
+    inner: B,
+    id: HeaderValue,
+    span: tracing::Span,
+}
+
+type ResponseFuture<R, E> = Pin<Box<dyn Future<Output = Result<R, E>> + Send + 'static>>;
+
+// === impl RequestIdLayer ===
+
+impl<S> tower::Layer<S> for RequestIdLayer {
+    type Service = RequestId<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestId { inner }
+    }
+}
+
+// === impl RequestId ===
+
+impl<S, ReqB, RspB> tower::Service<http::Request<ReqB>> for RequestId<S>
+where
+    S: tower::Service<http::Request<ReqB>, Response = http::Response<RspB>>,
+    S::Future: Send + 'static,
+{
+    type Response = http::Response<WithRequestId<RspB>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: http::Request<ReqB>) -> Self::Future {
+        let name = HeaderName::from_static(HEADER);
+        let id = match req.headers().get(&name).filter(|v| is_valid(v)) {
+            Some(id) => id.clone(),
+            None => {
+                let id = generate();
+                // Set the generated ID on the request so that it's visible to the service.
+                req.headers_mut().insert(name.clone(), id.clone());
+                id
+            }
+        };
+
+        let (client_addr, client_id) = client(req.extensions());
+        let span = info_span!(
+            "request",
+            id = id.to_str().unwrap_or_default(),
+            path = req.uri().path(),
+            client.addr = client_addr.map(field::display),
+            client.id = client_id.as_deref(),
+        );
+        let rsp = self.inner.call(req).instrument(span.clone());
+        Box::pin(async move {
+            let mut rsp = rsp.await?;
+            rsp.headers_mut().insert(name, id.clone());
+            Ok(rsp.map(|inner| WithRequestId { inner, id, span }))
+        })
+    }
+}
+
+/// Returns the address of the client that sent a request and, when it authenticated via TLS, its
+/// proxy identity.
+fn client(extensions: &http::Extensions) -> (Option<SocketAddr>, Option<String>) {
+    if let Some(tls) = extensions.get::<TlsConnectInfo<TcpConnectInfo>>() {
+        let id = tls
+            .peer_certs()
+            .and_then(|certs| Some(crate::identity::client_identity(certs.first()?)?.to_string()));
+        return (tls.get_ref().remote_addr(), id);
+    }
+    let addr = extensions
+        .get::<TcpConnectInfo>()
+        .and_then(TcpConnectInfo::remote_addr);
+    (addr, None)
+}
+
+/// Only IDs that can be logged and echoed safely are accepted from clients.
+fn is_valid(id: &HeaderValue) -> bool {
+    match id.to_str() {
+        Ok(id) => !id.is_empty() && id.len() <= 128,
+        Err(_) => false,
+    }
+}
+
+fn generate() -> HeaderValue {
+    let id = format!("{:016x}", rand::random::<u64>());
+    HeaderValue::from_str(&id).expect("hex must be a valid header value")
+}
+
+// === impl WithRequestId ===
+
+impl<B> http_body::Body for WithRequestId<B>
+where
+    B: http_body::Body + Unpin,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = &mut *self;
+        let _enter = this.span.enter();
+        Pin::new(&mut this.inner).poll_data(cx)
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        let this = &mut *self;
+        let _enter = this.span.enter();
+        let mut trailers =
+            futures::ready!(Pin::new(&mut this.inner).poll_trailers(cx))?.unwrap_or_default();
+        trailers.insert(HeaderName::from_static(HEADER), this.id.clone());
+        Poll::Ready(Ok(Some(trailers)))
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body::Body as _;
+    use tower::{Layer, Service, ServiceExt};
+
+    fn mk_svc() -> impl Service<
+        http::Request<()>,
+        Response = http::Response<WithRequestId<http_body::Full<bytes::Bytes>>>,
+        Error = std::convert::Infallible,
+    > {
+        RequestIdLayer::default().layer(tower::service_fn(|req: http::Request<()>| async move {
+            // Echo the request's ID in the body so that tests can check what the service saw.
+            let id = req.headers()[HEADER].to_str().unwrap().to_string();
+            Ok::<_, std::convert::Infallible>(http::Response::new(http_body::Full::from(id)))
+        }))
+    }
+
+    #[tokio::test]
+    async fn echoes_client_id() {
+        let req = http::Request::builder()
+            .header(HEADER, "abc123")
+            .body(())
+            .unwrap();
+        let mut rsp = mk_svc().oneshot(req).await.unwrap();
+        assert_eq!(rsp.headers()[HEADER], "abc123");
+
+        let body = rsp.body_mut().data().await.unwrap().unwrap();
+        assert_eq!(&body[..], b"abc123");
+        let trailers = rsp.body_mut().trailers().await.unwrap().unwrap();
+        assert_eq!(trailers[HEADER], "abc123");
+    }
+
+    #[tokio::test]
+    async fn generates_missing_id() {
+        let mut rsp = mk_svc().oneshot(http::Request::new(())).await.unwrap();
+        let id = rsp.headers()[HEADER].clone();
+        assert_eq!(id.len(), 16);
+
+        let body = rsp.body_mut().data().await.unwrap().unwrap();
+        assert_eq!(&body[..], id.as_bytes(), "the service must see the ID");
+        let trailers = rsp.body_mut().trailers().await.unwrap().unwrap();
+        assert_eq!(trailers[HEADER], id);
+    }
+}
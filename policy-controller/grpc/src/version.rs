@@ -0,0 +1,98 @@
+use futures::prelude::*;
+use http::header::{HeaderName, HeaderValue};
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// The header that identifies the controller's build in responses.
+pub(crate) const HEADER: &str = "l5d-policy-controller-version";
+
+/// Sets the controller's version on each response's headers, if a valid version is configured.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct VersionLayer(Option<HeaderValue>);
+
+#[derive(Clone, Debug)]
+pub(crate) struct Version<S> {
+    inner: S,
+    version: Option<HeaderValue>,
+}
+
+type ResponseFuture<R, E> = Pin<Box<dyn Future<Output = Result<R, E>> + Send + 'static>>;
+
+// === impl VersionLayer ===
+
+impl VersionLayer {
+    /// Versions that aren't valid header values are ignored.
+    pub(crate) fn new(version: Option<&str>) -> Self {
+        Self(version.and_then(|v| HeaderValue::from_str(v).ok()))
+    }
+}
+
+impl<S> tower::Layer<S> for VersionLayer {
+    type Service = Version<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Version {
+            inner,
+            version: self.0.clone(),
+        }
+    }
+}
+
+// === impl Version ===
+
+impl<S, ReqB, RspB> tower::Service<http::Request<ReqB>> for Version<S>
+where
+    S: tower::Service<http::Request<ReqB>, Response = http::Response<RspB>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: http::Request<ReqB>) -> Self::Future {
+        let version = self.version.clone();
+        let rsp = self.inner.call(req);
+        Box::pin(async move {
+            let mut rsp = rsp.await?;
+            if let Some(version) = version {
+                rsp.headers_mut()
+                    .insert(HeaderName::from_static(HEADER), version);
+            }
+            Ok(rsp)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower::{Layer, ServiceExt};
+
+    async fn get(layer: VersionLayer) -> http::Response<()> {
+        let svc = layer.layer(tower::service_fn(|_: http::Request<()>| async move {
+            Ok::<_, std::convert::Infallible>(http::Response::new(()))
+        }));
+        svc.oneshot(http::Request::new(())).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn sets_version() {
+        let rsp = get(VersionLayer::new(Some("0.1.0+abc123"))).await;
+        assert_eq!(rsp.headers()[HEADER], "0.1.0+abc123");
+    }
+
+    #[tokio::test]
+    async fn omits_unset_version() {
+        let rsp = get(VersionLayer::new(None)).await;
+        assert!(rsp.headers().get(HEADER).is_none());
+
+        let rsp = get(VersionLayer::new(Some("invalid\n"))).await;
+        assert!(rsp.headers().get(HEADER).is_none());
+    }
+}
@@ -0,0 +1,134 @@
+/// Selects which policy resource kinds are watched.
+///
+/// Servers are always watched, since policies can't be applied without them. When
+/// ServerAuthorizations aren't watched, servers have no authorizations, so deployments that only
+/// use servers (e.g. to configure protocols) avoid an unused watch.
+///
+/// ClusterServers are only watched when they're listed, since they're cluster-scoped and the
+/// controller may not be permitted to watch them.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct WatchKinds {
+    pub server_authorizations: bool,
+    pub cluster_servers: bool,
+}
+
+/// Indicates that a list of watched kinds could not be parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidWatchKinds(String);
+
+// === impl WatchKinds ===
+
+impl WatchKinds {
+    /// The names of the kinds that may be watched.
+    pub const NAMES: [&'static str; 3] = ["Server", "ServerAuthorization", "ClusterServer"];
+}
+
+/// All namespaced kinds are watched by default.
+impl Default for WatchKinds {
+    fn default() -> Self {
+        Self {
+            server_authorizations: true,
+            cluster_servers: false,
+        }
+    }
+}
+
+/// Parses a comma-separated list of kinds, ignoring case.
+impl std::str::FromStr for WatchKinds {
+    type Err = InvalidWatchKinds;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut server = false;
+        let mut kinds = Self {
+            server_authorizations: false,
+            cluster_servers: false,
+        };
+        for kind in s.split(',').map(str::trim) {
+            match kind.to_ascii_lowercase().as_str() {
+                "server" => server = true,
+                "serverauthorization" => kinds.server_authorizations = true,
+                "clusterserver" => kinds.cluster_servers = true,
+                _ => {
+                    return Err(InvalidWatchKinds(format!(
+                        "unsupported kind {:?}; expected one of: {}",
+                        kind,
+                        Self::NAMES.join(", ")
+                    )))
+                }
+            }
+        }
+        if !server {
+            return Err(InvalidWatchKinds("Server must be watched".to_string()));
+        }
+        Ok(kinds)
+    }
+}
+
+impl std::fmt::Display for WatchKinds {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        "Server".fmt(f)?;
+        if self.server_authorizations {
+            ",ServerAuthorization".fmt(f)?;
+        }
+        if self.cluster_servers {
+            ",ClusterServer".fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+// === impl InvalidWatchKinds ===
+
+impl std::fmt::Display for InvalidWatchKinds {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid watch kinds: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidWatchKinds {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_kinds() {
+        assert_eq!(
+            "Server,ServerAuthorization".parse::<WatchKinds>().unwrap(),
+            WatchKinds::default()
+        );
+        assert_eq!(
+            " server ".parse::<WatchKinds>().unwrap(),
+            WatchKinds {
+                server_authorizations: false,
+                cluster_servers: false,
+            }
+        );
+        assert_eq!(
+            "Server,ClusterServer".parse::<WatchKinds>().unwrap(),
+            WatchKinds {
+                server_authorizations: false,
+                cluster_servers: true,
+            }
+        );
+        for kinds in [
+            WatchKinds::default(),
+            "Server".parse().unwrap(),
+            "Server,ServerAuthorization,ClusterServer".parse().unwrap(),
+        ] {
+            assert_eq!(kinds.to_string().parse::<WatchKinds>().unwrap(), kinds);
+        }
+    }
+
+    #[test]
+    fn requires_servers() {
+        for invalid in [
+            "",
+            "ServerAuthorization",
+            "ClusterServer",
+            "Server,HTTPRoute",
+        ] {
+            assert!(invalid.parse::<WatchKinds>().is_err(), "{:?}", invalid);
+        }
+    }
+}
@@ -19,6 +19,10 @@ pub struct Expression {
     values: Option<BTreeSet<String>>,
 }
 
+/// Indicates that a label selector string could not be parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidSelector(String);
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
 pub enum Operator {
     In,
@@ -52,6 +56,12 @@ impl Selector {
         }
     }
 
+    /// Indicates whether this selector selects all resources.
+    pub fn is_empty(&self) -> bool {
+        self.match_labels.iter().all(|l| l.is_empty())
+            && self.match_expressions.iter().all(|e| e.is_empty())
+    }
+
     pub fn matches(&self, labels: &Labels) -> bool {
         for expr in self.match_expressions.iter().flatten() {
             if !expr.matches(labels.as_ref()) {
@@ -71,6 +81,23 @@ impl Selector {
     }
 }
 
+/// Parses a selector in the syntax accepted by the Kubernetes API (e.g. by
+/// `kubectl get -l`): a comma-separated list of `key=value`, `key==value`, `key!=value`,
+/// `key in (a,b)`, `key notin (a,b)`, `key`, or `!key` requirements.
+impl std::str::FromStr for Selector {
+    type Err = InvalidSelector;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        split_requirements(s)
+            .map(|r| Expression::parse(r.trim()))
+            .collect::<Result<Expressions, _>>()
+            .map(Self::from_expressions)
+    }
+}
+
 impl std::iter::FromIterator<(String, String)> for Selector {
     fn from_iter<T: IntoIterator<Item = (String, String)>>(iter: T) -> Self {
         Self::from_map(iter.into_iter().collect())
@@ -157,7 +184,7 @@ impl Expression {
                 None => false,
             },
             (Operator::NotIn, key, Some(values)) => match labels.get(key) {
-                Some(v) => values.contains(v),
+                Some(v) => !values.contains(v),
                 None => true,
             },
             (Operator::Exists, key, None) => labels.contains_key(key),
@@ -168,6 +195,136 @@ impl Expression {
             }
         }
     }
+
+    fn parse(req: &str) -> Result<Self, InvalidSelector> {
+        let invalid = || InvalidSelector(req.to_string());
+
+        if let Some(key) = req.strip_prefix('!') {
+            return Ok(Self {
+                key: parse_key(key.trim()).ok_or_else(invalid)?,
+                operator: Operator::DoesNotExist,
+                values: None,
+            });
+        }
+
+        if let Some((lhs, set)) = req.split_once('(') {
+            let (key, op) = {
+                let mut words = lhs.split_whitespace();
+                match (words.next(), words.next(), words.next()) {
+                    (Some(key), Some(op), None) => (key, op),
+                    _ => return Err(invalid()),
+                }
+            };
+            let operator = match op {
+                "in" => Operator::In,
+                "notin" => Operator::NotIn,
+                _ => return Err(invalid()),
+            };
+            let values = set
+                .trim_end()
+                .strip_suffix(')')
+                .ok_or_else(invalid)?
+                .split(',')
+                .map(|v| parse_value(v.trim()).ok_or_else(invalid))
+                .collect::<Result<BTreeSet<_>, _>>()?;
+            return Ok(Self {
+                key: parse_key(key).ok_or_else(invalid)?,
+                operator,
+                values: Some(values),
+            });
+        }
+
+        let (key, operator, value) = if let Some((k, v)) = req.split_once("!=") {
+            (k, Operator::NotIn, Some(v))
+        } else if let Some((k, v)) = req.split_once("==") {
+            (k, Operator::In, Some(v))
+        } else if let Some((k, v)) = req.split_once('=') {
+            (k, Operator::In, Some(v))
+        } else {
+            (req, Operator::Exists, None)
+        };
+        let values = match value {
+            Some(v) => Some(
+                Some(parse_value(v.trim()).ok_or_else(invalid)?)
+                    .into_iter()
+                    .collect(),
+            ),
+            None => None,
+        };
+        Ok(Self {
+            key: parse_key(key.trim()).ok_or_else(invalid)?,
+            operator,
+            values,
+        })
+    }
+}
+
+// === InvalidSelector ===
+
+impl std::fmt::Display for InvalidSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid label selector requirement: {:?}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidSelector {}
+
+/// Splits a selector on the commas that separate requirements, i.e. ignoring commas within
+/// value sets.
+fn split_requirements(s: &str) -> impl Iterator<Item = &str> {
+    let mut depth = 0i32;
+    s.split(move |c: char| {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => {}
+        }
+        c == ',' && depth == 0
+    })
+}
+
+/// Parses a label key: a name, optionally prefixed by a DNS subdomain and a `/`.
+fn parse_key(key: &str) -> Option<String> {
+    let name = match key.split_once('/') {
+        Some((prefix, name)) => {
+            let valid_prefix = !prefix.is_empty()
+                && prefix.len() <= 253
+                && prefix.split('.').all(|l| {
+                    !l.is_empty()
+                        && l.starts_with(|c: char| c.is_ascii_alphanumeric())
+                        && l.ends_with(|c: char| c.is_ascii_alphanumeric())
+                        && l.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                });
+            if !valid_prefix {
+                return None;
+            }
+            name
+        }
+        None => key,
+    };
+    if name.is_empty() || !is_label_value(name) {
+        return None;
+    }
+    Some(key.to_string())
+}
+
+/// Parses a label value, which may be empty.
+fn parse_value(value: &str) -> Option<String> {
+    if value.is_empty() || is_label_value(value) {
+        Some(value.to_string())
+    } else {
+        None
+    }
+}
+
+/// Label names and non-empty values are at most 63 characters, beginning and ending with an
+/// alphanumeric character, and otherwise consisting of alphanumerics, `-`, `_`, and `.`.
+fn is_label_value(s: &str) -> bool {
+    s.len() <= 63
+        && s.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && s.ends_with(|c: char| c.is_ascii_alphanumeric())
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
 }
 
 #[cfg(test)]
@@ -205,4 +362,83 @@ mod tests {
             assert_eq!(selector.matches(labels), *matches, "{}", msg);
         }
     }
+
+    #[test]
+    fn test_parse() {
+        let labels = |pairs: &[(&'static str, &'static str)]| Labels::from_iter(pairs.to_vec());
+        for (selector, matches, rejects) in &[
+            ("", labels(&[]), labels(&[])),
+            (
+                "app=web",
+                labels(&[("app", "web")]),
+                labels(&[("app", "db")]),
+            ),
+            ("app==web", labels(&[("app", "web")]), labels(&[])),
+            ("app!=web", labels(&[]), labels(&[("app", "web")])),
+            (
+                "example.com/team",
+                labels(&[("example.com/team", "a")]),
+                labels(&[]),
+            ),
+            ("!legacy", labels(&[]), labels(&[("legacy", "true")])),
+            (
+                "tier in (frontend, backend)",
+                labels(&[("tier", "backend")]),
+                labels(&[("tier", "cache")]),
+            ),
+            (
+                "tier notin (cache)",
+                labels(&[("tier", "backend")]),
+                labels(&[("tier", "cache")]),
+            ),
+            (
+                "app=web, tier in (a,b), !legacy",
+                labels(&[("app", "web"), ("tier", "a")]),
+                labels(&[("app", "web"), ("tier", "a"), ("legacy", "")]),
+            ),
+        ] {
+            let sel = selector.parse::<Selector>().expect(selector);
+            assert!(
+                sel.matches(matches),
+                "{:?} must match {:?}",
+                selector,
+                matches
+            );
+            if !rejects.as_ref().is_empty() || !sel.is_empty() {
+                assert!(
+                    !sel.matches(rejects),
+                    "{:?} must not match {:?}",
+                    selector,
+                    rejects
+                );
+            }
+        }
+
+        for invalid in &[
+            "app=web,",
+            "=web",
+            "app=-web",
+            "app in web",
+            "app in (web",
+            "app within (web)",
+            "-app",
+            "/app",
+            "example..com/app",
+            "app=web=db",
+        ] {
+            assert!(
+                invalid.parse::<Selector>().is_err(),
+                "{:?} must not parse",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_is_empty() {
+        assert!(Selector::default().is_empty());
+        assert!(Selector::from_map(Map::default()).is_empty());
+        assert!(Selector::from_expressions(Expressions::default()).is_empty());
+        assert!(!Selector::from_iter(Some(("foo", "bar"))).is_empty());
+    }
 }
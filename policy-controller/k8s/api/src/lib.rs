@@ -1,30 +1,51 @@
 #![deny(warnings, rust_2018_idioms)]
 #![forbid(unsafe_code)]
 
+mod kinds;
 pub mod labels;
 pub mod policy;
 mod watch;
 
 pub use self::{
+    kinds::{InvalidWatchKinds, WatchKinds},
     labels::Labels,
     watch::{Event, Watch},
 };
-pub use k8s_openapi::api::{
-    self,
-    core::v1::{Namespace, Node, NodeSpec, Pod, PodSpec, PodStatus},
+pub use k8s_openapi::{
+    api::{
+        self,
+        admissionregistration::v1::ValidatingWebhookConfiguration,
+        authorization::v1::{
+            ResourceAttributes, SelfSubjectAccessReview, SelfSubjectAccessReviewSpec,
+        },
+        coordination::v1::{Lease, LeaseSpec},
+        core::v1::{Namespace, Node, NodeSpec, Pod, PodSpec, PodStatus},
+    },
+    apimachinery::pkg::{
+        apis::meta::v1::{MicroTime, Time},
+        util::intstr::IntOrString,
+    },
+    chrono,
 };
 pub use kube::api::{ObjectMeta, ResourceExt};
 use kube::{
     api::{Api, ListParams},
     runtime::watcher,
 };
+use serde::de::DeserializeOwned;
 use tracing::info_span;
 
+/// Selects the pods injected by Linkerd's proxy injector, which are the only pods that can have
+/// inbound policy.
+pub const DEFAULT_MESHED_WORKLOAD_SELECTOR: &str = "linkerd.io/control-plane-ns";
+
 /// Resource watches.
 pub struct ResourceWatches {
+    pub namespaces_rx: Watch<Namespace>,
     pub pods_rx: Watch<Pod>,
     pub servers_rx: Watch<policy::Server>,
     pub authorizations_rx: Watch<policy::ServerAuthorization>,
+    pub cluster_servers_rx: Watch<policy::ClusterServer>,
 }
 
 // === impl ResourceWatches ===
@@ -34,24 +55,208 @@ impl ResourceWatches {
     ///
     /// Must be less than 295 or Kubernetes throws an error.
     const DEFAULT_TIMEOUT_SECS: u32 = 290;
-}
 
-impl From<kube::Client> for ResourceWatches {
-    fn from(client: kube::Client) -> Self {
+    /// Watches resources in the given namespace or, if no namespace is provided, in all
+    /// namespaces.
+    ///
+    /// When a `policy_selector` is provided, only `Server`, `ServerAuthorization`, and
+    /// `ClusterServer` resources with matching labels are watched. Kinds that aren't in `kinds`
+    /// aren't watched at all; they are indexed as if none exist. Only pods matching
+    /// `meshed_selector` are watched.
+    pub fn new(
+        client: kube::Client,
+        namespace: Option<&str>,
+        policy_selector: Option<&str>,
+        meshed_selector: &str,
+        kinds: WatchKinds,
+    ) -> Self {
         let params = ListParams::default().timeout(Self::DEFAULT_TIMEOUT_SECS);
+        let policy_params = policy_params(params.clone(), policy_selector);
 
         // We only need to watch pods that are injected with a Linkerd sidecar because these are the
         // only pods that can have inbound policy. We avoid indexing information about uninjected
         // pods.
-        let pod_params = params.clone().labels("linkerd.io/control-plane-ns");
+        let pod_params = params.clone().labels(meshed_selector);
+
+        // Namespaces are cluster-scoped, so they can't be watched with namespace-scoped RBAC. When
+        // the controller is limited to a namespace, no namespaces are indexed and namespace
+        // annotations are ignored.
+        let namespaces_rx: Watch<Namespace> = match namespace {
+            Some(_) => Watch::unwatched(),
+            None => resyncable(Api::all(client.clone()), params),
+        };
+        let authorizations_rx = if kinds.server_authorizations {
+            resyncable(api(&client, namespace), policy_params.clone())
+        } else {
+            Watch::unwatched()
+        };
+        // Like namespaces, cluster servers can't be watched when the controller is limited to a
+        // namespace.
+        let cluster_servers_rx: Watch<policy::ClusterServer> = match namespace {
+            None if kinds.cluster_servers => {
+                resyncable(Api::all(client.clone()), policy_params.clone())
+            }
+            _ => Watch::unwatched(),
+        };
 
         Self {
-            pods_rx: Watch::from(watcher(Api::all(client.clone()), pod_params))
-                .instrument(info_span!("pods")),
-            servers_rx: Watch::from(watcher(Api::all(client.clone()), params.clone()))
+            namespaces_rx: namespaces_rx.instrument(info_span!("namespaces")),
+            pods_rx: resyncable(api(&client, namespace), pod_params).instrument(info_span!("pods")),
+            servers_rx: resyncable(api(&client, namespace), policy_params)
                 .instrument(info_span!("servers")),
-            authorizations_rx: Watch::from(watcher(Api::all(client), params))
-                .instrument(info_span!("serverauthorizations")),
+            authorizations_rx: authorizations_rx.instrument(info_span!("serverauthorizations")),
+            cluster_servers_rx: cluster_servers_rx.instrument(info_span!("clusterservers")),
         }
     }
 }
+
+impl From<kube::Client> for ResourceWatches {
+    fn from(client: kube::Client) -> Self {
+        Self::new(
+            client,
+            None,
+            None,
+            DEFAULT_MESHED_WORKLOAD_SELECTOR,
+            WatchKinds::default(),
+        )
+    }
+}
+
+/// Restricts the params used to watch policy resources to those matching `selector`, if set.
+fn policy_params(params: ListParams, selector: Option<&str>) -> ListParams {
+    match selector {
+        Some(selector) => params.labels(selector),
+        None => params,
+    }
+}
+
+/// Watches the resources selected by `api` and `params`, restarting the watch (and re-listing
+/// resources) when the watch is resynced.
+///
+/// The params request watch bookmarks, so the watcher tracks a recent resource version even when
+/// the watched resources are rarely updated: when a watch times out or its connection is lost, the
+/// watch resumes from that version rather than re-listing all resources. Resources are only
+/// re-listed once the version expires (i.e. `410 Gone`).
+fn resyncable<K>(api: Api<K>, params: ListParams) -> Watch<K>
+where
+    K: kube::Resource + Clone + DeserializeOwned + std::fmt::Debug + Send + Sync + 'static,
+{
+    Watch::resyncable(move || watcher(api.clone(), params.clone()))
+}
+
+fn api<K>(client: &kube::Client, namespace: Option<&str>) -> Api<K>
+where
+    K: kube::Resource,
+    K::DynamicType: Default,
+{
+    match namespace {
+        Some(ns) => Api::namespaced(client.clone(), ns),
+        None => Api::all(client.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        convert::Infallible,
+        sync::{Arc, Mutex},
+    };
+
+    #[test]
+    fn policy_params_select_labels() {
+        let params = ListParams::default().timeout(ResourceWatches::DEFAULT_TIMEOUT_SECS);
+        assert_eq!(policy_params(params.clone(), None).label_selector, None);
+
+        let params = policy_params(params, Some("policy.example.com/scope=meshed"));
+        assert_eq!(
+            params.label_selector.as_deref(),
+            Some("policy.example.com/scope=meshed")
+        );
+        assert_eq!(params.timeout, Some(ResourceWatches::DEFAULT_TIMEOUT_SECS));
+    }
+
+    #[tokio::test]
+    async fn resumes_from_bookmarks() {
+        // Stands in for the API server, recording the query of each pods request. The resources are
+        // listed at version 1; the first watch is bookmarked at version 5 before it ends, and the
+        // next watch adds a pod.
+        let queries = Arc::new(Mutex::new(Vec::new()));
+        let client = kube::Client::new(
+            hyper::service::service_fn({
+                let queries = queries.clone();
+                move |req: hyper::Request<hyper::Body>| {
+                    assert_eq!(req.uri().path(), "/api/v1/namespaces/ns-0/pods");
+                    let query = req.uri().query().unwrap_or_default().to_string();
+                    let body = if !query.contains("watch=true") {
+                        serde_json::json!({
+                            "apiVersion": "v1",
+                            "kind": "PodList",
+                            "metadata": { "resourceVersion": "1" },
+                            "items": [],
+                        })
+                        .to_string()
+                    } else if query.contains("resourceVersion=1") {
+                        serde_json::json!({
+                            "type": "BOOKMARK",
+                            "object": {
+                                "apiVersion": "v1",
+                                "kind": "Pod",
+                                "metadata": { "resourceVersion": "5" },
+                            },
+                        })
+                        .to_string()
+                    } else {
+                        serde_json::json!({
+                            "type": "ADDED",
+                            "object": {
+                                "apiVersion": "v1",
+                                "kind": "Pod",
+                                "metadata": {
+                                    "name": "pod-0",
+                                    "namespace": "ns-0",
+                                    "resourceVersion": "6",
+                                },
+                            },
+                        })
+                        .to_string()
+                    };
+                    queries.lock().unwrap().push(query);
+                    let rsp = hyper::Response::new(hyper::Body::from(body));
+                    async move { Ok::<_, Infallible>(rsp) }
+                }
+            }),
+            "default",
+        );
+
+        let mut watches = ResourceWatches::new(
+            client,
+            Some("ns-0"),
+            None,
+            DEFAULT_MESHED_WORKLOAD_SELECTOR,
+            WatchKinds::default(),
+        );
+        assert!(
+            matches!(watches.pods_rx.recv().await, Ok(Event::Restarted(pods)) if pods.is_empty())
+        );
+        // The watch reconnects after the bookmark without re-listing pods.
+        assert!(matches!(
+            watches.pods_rx.recv().await,
+            Ok(Event::Applied(pod)) if pod.name() == "pod-0"
+        ));
+
+        let queries = queries.lock().unwrap();
+        assert_eq!(
+            queries.iter().filter(|q| !q.contains("watch=true")).count(),
+            1,
+            "pods must only be listed once: {:?}",
+            *queries
+        );
+        assert!(
+            queries[1].contains("allowWatchBookmarks=true"),
+            "{}",
+            queries[1]
+        );
+        assert!(queries[2].contains("resourceVersion=5"), "{}", queries[2]);
+    }
+}
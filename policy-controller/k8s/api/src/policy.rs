@@ -1,5 +1,7 @@
 pub mod authz;
+pub mod cluster_server;
 pub mod server;
 
 pub use self::authz::{ServerAuthorization, ServerAuthorizationSpec};
+pub use self::cluster_server::{ClusterServer, ClusterServerSpec};
 pub use self::server::{Server, ServerSpec};
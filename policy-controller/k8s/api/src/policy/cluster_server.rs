@@ -0,0 +1,21 @@
+use super::super::labels;
+use super::server::{Port, ProxyProtocol};
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Describes a server interface exposed by a set of pods in any namespace.
+///
+/// A `Server` that selects the same pod port takes precedence over a `ClusterServer`.
+#[derive(Clone, Debug, PartialEq, Eq, CustomResource, Deserialize, Serialize, JsonSchema)]
+#[kube(
+    group = "policy.linkerd.io",
+    version = "v1beta1",
+    kind = "ClusterServer"
+)]
+#[serde(rename_all = "camelCase")]
+pub struct ClusterServerSpec {
+    pub pod_selector: labels::Selector,
+    pub port: Port,
+    pub proxy_protocol: Option<ProxyProtocol>,
+}
@@ -26,10 +26,12 @@ pub enum Port {
     Name(String),
 }
 
+/// Configures how the proxy handles inbound connections on a server's port.
+///
+/// Unrecognized values (e.g. from a newer version of the CRD) are treated as `unknown`, so that
+/// the proxy falls back to protocol detection.
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
 pub enum ProxyProtocol {
-    #[serde(rename = "unknown")]
-    Unknown,
     #[serde(rename = "HTTP/1")]
     Http1,
     #[serde(rename = "HTTP/2")]
@@ -40,4 +42,32 @@ pub enum ProxyProtocol {
     Opaque,
     #[serde(rename = "TLS")]
     Tls,
+    #[serde(rename = "unknown", other)]
+    Unknown,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_proxy_protocol() {
+        for (value, protocol) in [
+            ("unknown", ProxyProtocol::Unknown),
+            ("HTTP/1", ProxyProtocol::Http1),
+            ("HTTP/2", ProxyProtocol::Http2),
+            ("gRPC", ProxyProtocol::Grpc),
+            ("opaque", ProxyProtocol::Opaque),
+            ("TLS", ProxyProtocol::Tls),
+            ("HTTP/3", ProxyProtocol::Unknown),
+            ("", ProxyProtocol::Unknown),
+        ] {
+            assert_eq!(
+                serde_json::from_value::<ProxyProtocol>(serde_json::json!(value)).unwrap(),
+                protocol,
+                "{:?}",
+                value
+            );
+        }
+    }
 }
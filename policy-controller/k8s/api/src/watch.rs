@@ -1,16 +1,31 @@
 use futures::prelude::*;
-pub use kube::runtime::watcher::{Event, Result};
-use std::pin::Pin;
+pub use kube::runtime::watcher::{Error, Event, Result};
+use std::{pin::Pin, sync::Arc};
 use tokio::time;
-use tracing::{info, Instrument};
+use tracing::{error, info, Instrument};
 
 /// Wraps an event stream that never terminates.
+///
+/// Transient errors are retried with a jittered exponential backoff. Errors that can't be resolved
+/// by retrying (i.e. when the controller isn't authorized to watch a resource) are returned.
 pub struct Watch<T> {
     initialized: bool,
     span: tracing::Span,
-    rx: Pin<Box<dyn Stream<Item = Result<Event<T>>> + Send + 'static>>,
+    rx: EventStream<T>,
+
+    /// Creates a new event stream to resync the watch, if the watch supports it.
+    mk: Option<Arc<dyn Fn() -> EventStream<T> + Send + Sync + 'static>>,
+
+    /// The number of consecutive failures since the last successful event.
+    failures: u32,
+
+    /// Set while backing off after a failure, so that the backoff is honored even if a call to
+    /// `recv` is canceled.
+    backoff: Option<Pin<Box<time::Sleep>>>,
 }
 
+type EventStream<T> = Pin<Box<dyn Stream<Item = Result<Event<T>>> + Send + 'static>>;
+
 // === impl Watch ===
 
 impl<T, W> From<W> for Watch<T>
@@ -23,28 +38,88 @@ where
 }
 
 impl<T> Watch<T> {
-    pub fn new(rx: Pin<Box<dyn Stream<Item = Result<Event<T>>> + Send + 'static>>) -> Watch<T> {
+    const MIN_BACKOFF: time::Duration = time::Duration::from_secs(1);
+    const MAX_BACKOFF: time::Duration = time::Duration::from_secs(30);
+
+    pub fn new(rx: EventStream<T>) -> Watch<T> {
         Self {
             rx,
+            mk: None,
             initialized: false,
             span: tracing::Span::current(),
+            failures: 0,
+            backoff: None,
         }
     }
 
+    /// Creates a watch that can be resynced by calling `mk` to start a new event stream, which is
+    /// expected to begin by listing all resources.
+    pub fn resyncable<W>(mk: impl Fn() -> W + Send + Sync + 'static) -> Watch<T>
+    where
+        W: Stream<Item = Result<Event<T>>> + Send + 'static,
+    {
+        let mk = Arc::new(move || mk().boxed());
+        let mut watch = Self::new(mk());
+        watch.mk = Some(mk);
+        watch
+    }
+
+    /// Creates a watch for resources that aren't watched, which lists no resources and is never
+    /// updated.
+    pub fn unwatched() -> Watch<T>
+    where
+        T: Send + 'static,
+    {
+        Self::listed(vec![])
+    }
+
+    /// Creates a watch that lists a fixed set of resources (e.g. loaded from a file) and is never
+    /// updated.
+    pub fn listed(resources: Vec<T>) -> Watch<T>
+    where
+        T: Send + 'static,
+    {
+        Self::from(
+            stream::once(future::ready(Ok(Event::Restarted(resources)))).chain(stream::pending()),
+        )
+    }
+
     pub fn instrument(mut self, span: tracing::Span) -> Self {
         self.span = span;
         self
     }
 
+    /// Indicates whether the watch has received a complete list of resources.
     pub fn is_initialized(&self) -> bool {
         self.initialized
     }
 
+    /// Replaces the event stream so that all resources are listed again, discarding any pending
+    /// backoff. This has no effect if the watch was not created with [`Watch::resyncable`].
+    ///
+    /// The watch remains initialized while the resources are re-listed.
+    pub fn resync(&mut self) {
+        if let Some(mk) = self.mk.as_ref() {
+            info!(parent: &self.span, "Resyncing");
+            self.rx = mk();
+            self.backoff = None;
+            self.failures = 0;
+        }
+    }
+
     /// Receive the next event in the stream.
     ///
-    /// If the stream fails, log the error and sleep for 1s before polling for a reset event.
-    pub async fn recv(&mut self) -> Event<T> {
+    /// If the stream fails, log the error and back off before polling for a reset event. When the
+    /// watch's resource version has expired (i.e. `410 Gone`), the underlying watcher re-lists
+    /// resources, so the stream is polled again immediately.
+    pub async fn recv(&mut self) -> Result<Event<T>> {
         loop {
+            if let Some(backoff) = self.backoff.as_mut() {
+                backoff.await;
+                self.backoff = None;
+                info!(parent: &self.span, "Restarting");
+            }
+
             let ev = self
                 .rx
                 .next()
@@ -54,20 +129,136 @@ impl<T> Watch<T> {
 
             match ev {
                 Ok(ev) => {
-                    self.initialized = true;
-                    return ev;
-                }
-                Err(error) => {
-                    info!(parent: &self.span, %error, "Failed");
-
-                    // TODO(ver) this backoff may not be fully honored if this call to `recv` is
-                    // canceled. Instead, we should track the backoff on the `Watch` and poll it
-                    // before the inner stream. We probably need to use pin-project-lite for this,
-                    // though.
-                    time::sleep(time::Duration::from_secs(1)).await;
-                    info!(parent: &self.span, "Restarting");
+                    // The watch is only initialized once the full set of resources has been
+                    // listed, rather than when an individual resource is updated.
+                    if let Event::Restarted(ref resources) = ev {
+                        // Log the number of listed resources so that operators can confirm that
+                        // label selectors are effective.
+                        info!(parent: &self.span, resources = resources.len(), "Listed");
+                        self.initialized = true;
+                    }
+                    self.failures = 0;
+                    return Ok(ev);
                 }
+                Err(error) => match error_code(&error) {
+                    Some(401) | Some(403) => {
+                        error!(parent: &self.span, %error, "Not authorized to watch resources");
+                        return Err(error);
+                    }
+                    Some(410) => {
+                        info!(parent: &self.span, %error, "Resource version expired; re-listing");
+                    }
+                    _ => {
+                        info!(parent: &self.span, %error, "Failed");
+                        let delay = self.next_backoff();
+                        self.backoff = Some(Box::pin(time::sleep(delay)));
+                    }
+                },
             }
         }
     }
+
+    /// Doubles the backoff (from `MIN_BACKOFF` up to `MAX_BACKOFF`) for each consecutive failure,
+    /// jittering it so that watches don't retry in lockstep.
+    fn next_backoff(&mut self) -> time::Duration {
+        let exp = 2u32.saturating_pow(self.failures);
+        self.failures = self.failures.saturating_add(1);
+        let ceiling = Self::MIN_BACKOFF.saturating_mul(exp).min(Self::MAX_BACKOFF);
+        ceiling / 2 + (ceiling / 2).mul_f64(rand::random::<f64>())
+    }
+}
+
+/// Returns the HTTP status code of an API server error, if there is one.
+fn error_code(error: &Error) -> Option<u16> {
+    match error {
+        Error::InitialListFailed(kube::Error::Api(rsp))
+        | Error::WatchStartFailed(kube::Error::Api(rsp))
+        | Error::WatchFailed(kube::Error::Api(rsp))
+        | Error::WatchError(rsp) => Some(rsp.code),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kube::core::ErrorResponse;
+
+    fn api_error(code: u16) -> Error {
+        Error::WatchError(ErrorResponse {
+            status: "Failure".to_string(),
+            message: "test".to_string(),
+            reason: "test".to_string(),
+            code,
+        })
+    }
+
+    #[tokio::test]
+    async fn relists_when_gone() {
+        let events = vec![
+            Ok(Event::Restarted(vec![1])),
+            Err(api_error(410)),
+            Ok(Event::Restarted(vec![2])),
+        ];
+        let mut watch = Watch::from(stream::iter(events).chain(stream::pending()));
+
+        assert!(matches!(watch.recv().await, Ok(Event::Restarted(v)) if v == [1]));
+        // The expired watch is not surfaced and does not incur a backoff.
+        let ev = time::timeout(time::Duration::from_millis(100), watch.recv())
+            .await
+            .expect("must not back off");
+        assert!(matches!(ev, Ok(Event::Restarted(v)) if v == [2]));
+        assert!(watch.is_initialized());
+    }
+
+    #[tokio::test]
+    async fn fails_when_forbidden() {
+        let events = vec![Err::<Event<()>, _>(api_error(403))];
+        let mut watch = Watch::from(stream::iter(events).chain(stream::pending()));
+        assert!(watch.recv().await.is_err());
+        assert!(!watch.is_initialized());
+    }
+
+    #[tokio::test]
+    async fn resyncs() {
+        let lists = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let mut watch = Watch::resyncable({
+            let lists = lists.clone();
+            move || {
+                let n = lists.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                stream::iter(Some(Ok(Event::Restarted(vec![n])))).chain(stream::pending())
+            }
+        });
+
+        assert!(matches!(watch.recv().await, Ok(Event::Restarted(v)) if v == [0]));
+        watch.resync();
+        assert!(matches!(watch.recv().await, Ok(Event::Restarted(v)) if v == [1]));
+        assert!(watch.is_initialized());
+    }
+
+    #[tokio::test]
+    async fn unwatched_lists_nothing() {
+        let mut watch = Watch::<()>::unwatched();
+        assert!(matches!(watch.recv().await, Ok(Event::Restarted(v)) if v.is_empty()));
+        assert!(watch.is_initialized());
+        assert!(time::timeout(time::Duration::from_millis(10), watch.recv())
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn backoff_is_bounded() {
+        let mut watch = Watch::<()>::from(stream::pending());
+        for failures in 0..10 {
+            let ceiling = Watch::<()>::MIN_BACKOFF
+                .saturating_mul(2u32.pow(failures))
+                .min(Watch::<()>::MAX_BACKOFF);
+            let backoff = watch.next_backoff();
+            assert!(
+                ceiling / 2 <= backoff && backoff <= ceiling,
+                "{:?}",
+                backoff
+            );
+        }
+    }
 }
@@ -8,8 +8,9 @@ use linkerd_policy_controller_k8s_api::{
     policy::{self, authz::MeshTls},
     ResourceExt,
 };
+use linkerd_policy_controller_metrics::Counter;
 use std::collections::{hash_map::Entry as HashEntry, HashMap, HashSet};
-use tracing::{debug, instrument, trace};
+use tracing::{debug, instrument, trace, warn};
 
 /// Indexes `ServerAuthorization` resources within a namespace.
 #[derive(Debug, Default)]
@@ -24,6 +25,10 @@ struct Authz {
 
     /// The current authorization policy to apply.
     clients: ClientAuthorization,
+
+    /// Indicates whether the client networks were configured on the resource rather than
+    /// defaulting to the cluster networks.
+    explicit_networks: bool,
 }
 
 // === impl Index ===
@@ -42,7 +47,12 @@ impl Index {
             .namespaces
             .get_or_default(authz.namespace().expect("namespace required"));
 
-        ns.authzs.apply(authz, &mut ns.servers, &self.cluster_info)
+        ns.authzs.apply(
+            authz,
+            &mut ns.servers,
+            &self.cluster_info,
+            &self.authz_overlaps,
+        )
     }
 
     #[instrument(
@@ -103,6 +113,39 @@ impl Index {
 // === impl AuthzIndex ===
 
 impl AuthzIndex {
+    /// Describes each authorization, by name, for debugging.
+    pub(crate) fn dump(&self) -> serde_json::Value {
+        self.index
+            .iter()
+            .map(|(name, authz)| {
+                let servers = match &authz.servers {
+                    ServerSelector::Name(n) => serde_json::json!({ "name": n }),
+                    ServerSelector::Selector(s) => {
+                        serde_json::json!({ "selector": format!("{:?}", s) })
+                    }
+                };
+                let networks = authz
+                    .clients
+                    .networks
+                    .iter()
+                    .map(|NetworkMatch { net, except }| {
+                        serde_json::json!({
+                            "net": net.to_string(),
+                            "except": except.iter().map(ToString::to_string).collect::<Vec<_>>(),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                let authz = serde_json::json!({
+                    "servers": servers,
+                    "networks": networks,
+                    "authentication": format!("{:?}", authz.clients.authentication),
+                });
+                (name.clone(), authz)
+            })
+            .collect::<serde_json::Map<_, _>>()
+            .into()
+    }
+
     /// Enumerates authorizations in this namespace matching either the given server name or its
     /// labels.
     pub(crate) fn filter_for_server(
@@ -137,11 +180,12 @@ impl AuthzIndex {
         authz: policy::ServerAuthorization,
         servers: &mut SrvIndex,
         cluster: &ClusterInfo,
+        overlaps: &Counter,
     ) -> Result<()> {
         let name = authz.name();
         let authz = mk_authz(authz, cluster)?;
 
-        match self.index.entry(name) {
+        match self.index.entry(name.clone()) {
             HashEntry::Vacant(entry) => {
                 servers.add_authz(entry.key(), &authz.servers, authz.clients.clone());
                 entry.insert(authz);
@@ -149,16 +193,53 @@ impl AuthzIndex {
 
             HashEntry::Occupied(mut entry) => {
                 // If the authorization changed materially, then update it in all servers.
-                if entry.get() != &authz {
-                    servers.add_authz(entry.key(), &authz.servers, authz.clients.clone());
-                    entry.insert(authz);
+                if entry.get() == &authz {
+                    return Ok(());
                 }
+                servers.add_authz(entry.key(), &authz.servers, authz.clients.clone());
+                entry.insert(authz);
             }
         }
 
+        self.check_overlaps(&name, servers, overlaps);
         Ok(())
     }
 
+    /// Warns about (and counts) other authorizations on the same servers whose client networks
+    /// overlap those of the named authorization.
+    ///
+    /// Servers authorize the union of their authorizations, so overlaps don't change the policy;
+    /// but it becomes unclear which authorization is responsible for admitting a client. Networks
+    /// that default to the cluster networks are not considered.
+    fn check_overlaps(&self, name: &str, servers: &SrvIndex, overlaps: &Counter) {
+        let authz = match self.index.get(name) {
+            Some(authz) if authz.explicit_networks => authz,
+            _ => return,
+        };
+
+        for (server, peers) in servers.authz_peers(name) {
+            for peer in peers {
+                let other = match self.index.get(peer) {
+                    Some(other) if other.explicit_networks => other,
+                    _ => continue,
+                };
+                if let Some((net, other_net)) =
+                    overlapping_networks(&authz.clients.networks, &other.clients.networks)
+                {
+                    warn!(
+                        %server,
+                        authz = %name,
+                        %net,
+                        other = %peer,
+                        %other_net,
+                        "Authorizations have overlapping client networks; clients are authorized by either",
+                    );
+                    overlaps.inc();
+                }
+            }
+        }
+    }
+
     fn delete(&mut self, name: &str) {
         self.index.remove(name);
         debug!("Removed authz");
@@ -178,7 +259,8 @@ fn mk_authz(srv: policy::authz::ServerAuthorization, cluster: &ClusterInfo) -> R
         }
     };
 
-    let networks = if let Some(nets) = spec.client.networks {
+    let explicit_networks = spec.client.networks.iter().any(|n| !n.is_empty());
+    let networks = if let Some(nets) = spec.client.networks.filter(|n| !n.is_empty()) {
         nets.into_iter()
             .map(|policy::authz::Network { cidr, except }| {
                 let net = cidr.parse::<IpNet>()?;
@@ -192,7 +274,9 @@ fn mk_authz(srv: policy::authz::ServerAuthorization, cluster: &ClusterInfo) -> R
             })
             .collect::<Result<Vec<NetworkMatch>>>()?
     } else {
-        // If no networks are specified, the cluster networks are used as the default.
+        // If no networks are specified, the cluster networks are used as the default. An empty
+        // list is treated the same way so that the authorization is rebuilt if the cluster
+        // networks change.
         cluster
             .networks
             .iter()
@@ -217,9 +301,20 @@ fn mk_authz(srv: policy::authz::ServerAuthorization, cluster: &ClusterInfo) -> R
             networks,
             authentication,
         },
+        explicit_networks,
     })
 }
 
+/// Finds a pair of overlapping networks, one from each list. Exceptions are not considered.
+fn overlapping_networks<'n>(
+    a: &'n [NetworkMatch],
+    b: &'n [NetworkMatch],
+) -> Option<(&'n IpNet, &'n IpNet)> {
+    a.iter()
+        .flat_map(|a| b.iter().map(move |b| (&a.net, &b.net)))
+        .find(|&(a, b)| a.contains(b) || b.contains(a))
+}
+
 fn mk_mtls_authn(
     metadata: &k8s::ObjectMeta,
     mtls: MeshTls,
@@ -268,3 +363,115 @@ fn mk_mtls_authn(
 
     Ok(ClientAuthentication::TlsAuthenticated(identities))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mk_nets(nets: &[&str]) -> Vec<NetworkMatch> {
+        nets.iter()
+            .map(|n| n.parse::<IpNet>().unwrap().into())
+            .collect()
+    }
+
+    #[test]
+    fn disjoint_networks() {
+        let a = mk_nets(&["192.0.2.0/24", "2001:db8::/64"]);
+        let b = mk_nets(&["198.51.100.0/24", "2001:db8:1::/64"]);
+        assert_eq!(overlapping_networks(&a, &b), None);
+        assert_eq!(overlapping_networks(&b, &a), None);
+
+        // Networks of different families never overlap.
+        let v4 = mk_nets(&["0.0.0.0/0"]);
+        let v6 = mk_nets(&["::/0"]);
+        assert_eq!(overlapping_networks(&v4, &v6), None);
+    }
+
+    #[test]
+    fn overlapping_networks_are_found() {
+        let a = mk_nets(&["198.51.100.0/24", "192.0.2.0/24"]);
+        let b = mk_nets(&["192.0.2.128/25"]);
+        let expected = (&a[1].net, &b[0].net);
+        assert_eq!(overlapping_networks(&a, &b), Some(expected));
+        assert_eq!(
+            overlapping_networks(&b, &a),
+            Some((expected.1, expected.0)),
+            "overlaps must be found in either order"
+        );
+    }
+
+    fn mk_cluster() -> ClusterInfo {
+        ClusterInfo {
+            networks: vec![],
+            control_plane_ns: "linkerd".to_string(),
+            identity_domain: "cluster.example.com".to_string(),
+        }
+    }
+
+    fn mk_service_accounts(refs: &[(Option<&str>, &str)]) -> MeshTls {
+        MeshTls {
+            service_accounts: Some(
+                refs.iter()
+                    .map(|(ns, name)| policy::authz::ServiceAccountRef {
+                        namespace: ns.map(Into::into),
+                        name: name.to_string(),
+                    })
+                    .collect(),
+            ),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn service_accounts_in_same_namespace() {
+        let metadata = k8s::ObjectMeta {
+            namespace: Some("ns-0".to_string()),
+            name: Some("authz-0".to_string()),
+            ..Default::default()
+        };
+        let authn = mk_mtls_authn(
+            &metadata,
+            mk_service_accounts(&[(None, "frontend")]),
+            &mk_cluster(),
+        )
+        .unwrap();
+        assert_eq!(
+            authn,
+            ClientAuthentication::TlsAuthenticated(vec![IdentityMatch::Name(
+                "frontend.ns-0.serviceaccount.identity.linkerd.cluster.example.com".to_string()
+            )])
+        );
+    }
+
+    #[test]
+    fn service_accounts_in_other_namespaces() {
+        let metadata = k8s::ObjectMeta {
+            namespace: Some("ns-0".to_string()),
+            name: Some("authz-0".to_string()),
+            ..Default::default()
+        };
+        let authn = mk_mtls_authn(
+            &metadata,
+            mk_service_accounts(&[(Some("ns-1"), "frontend"), (None, "backend")]),
+            &mk_cluster(),
+        )
+        .unwrap();
+        assert_eq!(
+            authn,
+            ClientAuthentication::TlsAuthenticated(vec![
+                IdentityMatch::Name(
+                    "frontend.ns-1.serviceaccount.identity.linkerd.cluster.example.com".to_string()
+                ),
+                IdentityMatch::Name(
+                    "backend.ns-0.serviceaccount.identity.linkerd.cluster.example.com".to_string()
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn identical_networks_overlap() {
+        let a = mk_nets(&["192.0.2.0/24"]);
+        assert_eq!(overlapping_networks(&a, &a), Some((&a[0].net, &a[0].net)));
+    }
+}
@@ -1,11 +1,11 @@
-use crate::{ServerRx, ServerTx};
+use crate::{detect::DetectTimeouts, ServerRx, ServerTx};
 use anyhow::{anyhow, Error, Result};
 use linkerd_policy_controller_core::{
     ClientAuthentication, ClientAuthorization, IdentityMatch, InboundServer, IpNet, NetworkMatch,
     ProxyProtocol,
 };
 use linkerd_policy_controller_k8s_api as k8s;
-use std::{collections::HashMap, hash::Hash};
+use std::{collections::HashMap, hash::Hash, net::IpAddr};
 use tokio::{sync::watch, time};
 
 /// Indicates the default behavior to apply when no Server is found for a port.
@@ -20,7 +20,22 @@ pub enum DefaultPolicy {
     },
 
     /// Indicates that all traffic is denied unless explicitly permitted by an authorization policy.
+    ///
+    /// This applies to meshed clients as well: a client's identity does not grant it access without
+    /// an authorization. The only exception is for ports targeted by the pod's probes, which admit
+    /// unauthenticated connections from the pod's node so that the kubelet's health checks continue
+    /// to succeed (unless the port requires identity). The proxy's own admin server is not subject
+    /// to inbound policy, so its probes are unaffected.
     Deny,
+
+    /// Indicates that authenticated traffic is permitted from all networks, as well as the
+    /// kubelet's unauthenticated probes.
+    ///
+    /// Like `Deny`, probes are admitted from the pod's node to the ports targeted by its probes,
+    /// so that authenticated-only workloads don't need authorizations for their health checks.
+    /// Probes are admitted by their source rather than their path, since policies don't
+    /// distinguish requests on a connection.
+    AllAuthenticatedOrProbes,
 }
 
 /// Describes the default behavior for a pod-port to apply when no Server is found for a port.
@@ -28,17 +43,21 @@ pub enum DefaultPolicy {
 pub(crate) struct PortDefaults {
     pub authenticated: bool,
     pub opaque: bool,
+
+    /// Set with the address of the pod's node when the port is targeted by one of the pod's probes.
+    pub kubelet: Option<IpAddr>,
 }
 
-/// Holds the watches for all default policies. These watches are initiated lazily and are never
-/// updated. `DefaultPolicyWatches` creates a watch as needed and holding its sender so that the
-/// receiver never observes the sender closing (or updating at all).
+/// Holds the watches for all default policies. These watches are initiated lazily and are only
+/// updated when the cluster networks change. `DefaultPolicyWatches` creates a watch as needed and
+/// holding its sender so that the receiver never observes the sender closing.
 #[derive(Debug)]
 pub(crate) struct DefaultPolicyWatches {
     cluster_nets: Vec<IpNet>,
-    detect_timeout: time::Duration,
+    pub(crate) detect_timeouts: DetectTimeouts,
 
-    watches: HashMap<(DefaultPolicy, PortDefaults), (ServerTx, ServerRx)>,
+    /// Watches by default policy, port configuration, and the port's protocol detection timeout.
+    watches: HashMap<(DefaultPolicy, PortDefaults, time::Duration), (ServerTx, ServerRx)>,
 }
 
 // === impl DefaultPolicy ===
@@ -46,6 +65,16 @@ pub(crate) struct DefaultPolicyWatches {
 impl DefaultPolicy {
     pub const ANNOTATION: &'static str = "config.linkerd.io/default-inbound-policy";
 
+    /// The canonical names of all default policies.
+    pub const NAMES: [&'static str; 6] = [
+        "all-authenticated",
+        "all-authenticated-or-probes",
+        "all-unauthenticated",
+        "cluster-authenticated",
+        "cluster-unauthenticated",
+        "deny",
+    ];
+
     pub fn from_annotation(meta: &k8s::ObjectMeta) -> Result<Option<Self>> {
         if let Some(ann) = meta.annotations.as_ref() {
             if let Some(v) = ann.get(Self::ANNOTATION) {
@@ -61,8 +90,9 @@ impl DefaultPolicy {
 impl std::str::FromStr for DefaultPolicy {
     type Err = Error;
 
+    /// Parses a default policy name, ignoring case and treating `_` like `-`.
     fn from_str(s: &str) -> Result<Self> {
-        match s {
+        match s.to_ascii_lowercase().replace('_', "-").as_str() {
             "all-authenticated" => Ok(Self::Allow {
                 authenticated_only: true,
                 cluster_only: false,
@@ -79,8 +109,13 @@ impl std::str::FromStr for DefaultPolicy {
                 authenticated_only: false,
                 cluster_only: true,
             }),
+            "all-authenticated-or-probes" => Ok(Self::AllAuthenticatedOrProbes),
             "deny" => Ok(Self::Deny),
-            s => Err(anyhow!("invalid mode: {:?}", s)),
+            _ => Err(anyhow!(
+                "invalid mode: {:?}; expected one of: {}",
+                s,
+                Self::NAMES.join(", ")
+            )),
         }
     }
 }
@@ -105,6 +140,7 @@ impl std::fmt::Display for DefaultPolicy {
                 cluster_only: true,
             } => "cluster-unauthenticated".fmt(f),
             Self::Deny => "deny".fmt(f),
+            Self::AllAuthenticatedOrProbes => "all-authenticated-or-probes".fmt(f),
         }
     }
 }
@@ -117,24 +153,35 @@ impl DefaultPolicyWatches {
     /// These receivers are never updated. The senders are moved into a background task so that
     /// the receivers continue to be live. The returned background task completes once all receivers
     /// are dropped.
-    pub(crate) fn new(cluster_nets: Vec<IpNet>, detect_timeout: time::Duration) -> Self {
+    pub(crate) fn new(cluster_nets: Vec<IpNet>, detect_timeouts: DetectTimeouts) -> Self {
         Self {
             cluster_nets,
-            detect_timeout,
+            detect_timeouts,
             watches: HashMap::default(),
         }
     }
 
-    /// Obtains a watch for a default policy. This watch never updates.
+    /// Obtains a watch for a default policy on the given port. This watch is only updated if the
+    /// cluster networks change.
     ///
     /// If a watch for this policy does not already exist, one is created.
-    pub fn watch(&mut self, default: DefaultPolicy, config: PortDefaults) -> ServerRx {
+    pub fn watch(&mut self, default: DefaultPolicy, port: u16, config: PortDefaults) -> ServerRx {
         use std::collections::hash_map::Entry;
-        match self.watches.entry((default, config)) {
+
+        // Probes are only handled specially when unauthenticated traffic is denied by default, so
+        // the kubelet's address is dropped otherwise to avoid creating a watch for each node.
+        let config = match default {
+            DefaultPolicy::Deny | DefaultPolicy::AllAuthenticatedOrProbes => config,
+            DefaultPolicy::Allow { .. } => PortDefaults {
+                kubelet: None,
+                ..config
+            },
+        };
+        let detect_timeout = self.detect_timeouts.get(port);
+        match self.watches.entry((default, config, detect_timeout)) {
             Entry::Occupied(entry) => entry.get().1.clone(),
             Entry::Vacant(entry) => {
-                let server =
-                    Self::mk_server(default, config, &self.cluster_nets, self.detect_timeout);
+                let server = Self::mk_server(default, config, &self.cluster_nets, detect_timeout);
                 let (tx, rx) = watch::channel(server);
                 entry.insert((tx, rx.clone()));
                 rx
@@ -142,6 +189,17 @@ impl DefaultPolicyWatches {
         }
     }
 
+    /// Updates all default policies to use the given cluster networks.
+    pub(crate) fn set_cluster_networks(&mut self, cluster_nets: Vec<IpNet>) {
+        self.cluster_nets = cluster_nets;
+        for ((default, config, detect_timeout), (tx, _)) in self.watches.iter() {
+            let server = Self::mk_server(*default, *config, &self.cluster_nets, *detect_timeout);
+            if *tx.borrow() != server {
+                let _ = tx.send(server);
+            }
+        }
+    }
+
     fn mk_server(
         default: DefaultPolicy,
         port: PortDefaults,
@@ -173,15 +231,50 @@ impl DefaultPolicyWatches {
             DefaultPolicy::Deny => InboundServer {
                 name: "default:deny".to_string(),
                 protocol,
-                authorizations: Default::default(),
+                authorizations: Self::mk_probes(port).into_iter().collect(),
             },
+
+            DefaultPolicy::AllAuthenticatedOrProbes => {
+                let name = Self::name(default, port);
+                let nets = [IpNet::V4(Default::default()), IpNet::V6(Default::default())];
+                let authn =
+                    ClientAuthentication::TlsAuthenticated(vec![IdentityMatch::Suffix(vec![])]);
+                let mut server = Self::mk_policy(name, protocol, nets, authn);
+                server.authorizations.extend(Self::mk_probes(port));
+                server
+            }
         }
     }
 
+    /// Authorizes the kubelet's unauthenticated probes from the pod's node, if the port is probed.
+    ///
+    /// The kubelet can't be authenticated, so probes to ports that require identity are denied like
+    /// any other unauthenticated connection.
+    fn mk_probes(port: PortDefaults) -> Option<(String, ClientAuthorization)> {
+        port.kubelet.filter(|_| !port.authenticated).map(|ip| {
+            let net = IpNet::from(ip);
+            let authz = ClientAuthorization {
+                networks: vec![NetworkMatch {
+                    net,
+                    except: vec![],
+                }],
+                authentication: ClientAuthentication::Unauthenticated,
+            };
+            ("default:probes".to_string(), authz)
+        })
+    }
+
     fn name(default: DefaultPolicy, port: PortDefaults) -> String {
         // Update the default to account for whether the port requires authentication:
         let default = match default {
             DefaultPolicy::Deny => DefaultPolicy::Deny,
+            // Probes to ports that require identity are denied, so all traffic must be
+            // authenticated.
+            DefaultPolicy::AllAuthenticatedOrProbes if port.authenticated => DefaultPolicy::Allow {
+                cluster_only: false,
+                authenticated_only: true,
+            },
+            DefaultPolicy::AllAuthenticatedOrProbes => DefaultPolicy::AllAuthenticatedOrProbes,
             DefaultPolicy::Allow {
                 cluster_only,
                 authenticated_only,
@@ -234,6 +327,7 @@ mod test {
     fn test_parse_displayed() {
         for default in [
             DefaultPolicy::Deny,
+            DefaultPolicy::AllAuthenticatedOrProbes,
             DefaultPolicy::Allow {
                 authenticated_only: true,
                 cluster_only: false,
@@ -259,4 +353,43 @@ mod test {
             );
         }
     }
+
+    #[test]
+    fn test_parse_spellings() {
+        for name in DefaultPolicy::NAMES {
+            let expected = name.parse::<DefaultPolicy>().unwrap();
+            assert_eq!(expected.to_string(), name);
+
+            let title = name
+                .split('-')
+                .map(|w| w[..1].to_uppercase() + &w[1..])
+                .collect::<Vec<_>>()
+                .join("-");
+            for spelling in [
+                name.to_string(),
+                name.to_uppercase(),
+                name.replace('-', "_"),
+                name.replace('-', "_").to_uppercase(),
+                title.clone(),
+                title.replace('-', "_"),
+            ] {
+                assert_eq!(
+                    spelling.parse::<DefaultPolicy>().unwrap(),
+                    expected,
+                    "failed to parse {:?}",
+                    spelling
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        for invalid in ["", "allow", "all-authenticated-only", "all unauthenticated"] {
+            let err = invalid.parse::<DefaultPolicy>().unwrap_err().to_string();
+            for name in DefaultPolicy::NAMES {
+                assert!(err.contains(name), "{:?} does not list {}", err, name);
+            }
+        }
+    }
 }
@@ -0,0 +1,44 @@
+use linkerd_policy_controller_k8s_api::policy;
+use std::collections::HashMap;
+use tokio::time;
+
+/// The protocol detection timeouts to use for ports that don't configure a protocol.
+///
+/// A single timeout rarely suits every port: ports that serve opaque protocols gain nothing from
+/// waiting on detection, while slow HTTP clients may need longer. Overrides set the timeout for
+/// specific ports; all other ports use the default.
+#[derive(Clone, Debug)]
+pub(crate) struct DetectTimeouts {
+    default: time::Duration,
+    overrides: HashMap<u16, time::Duration>,
+}
+
+// === impl DetectTimeouts ===
+
+impl DetectTimeouts {
+    pub(crate) fn new(default: time::Duration) -> Self {
+        Self {
+            default,
+            overrides: HashMap::default(),
+        }
+    }
+
+    pub(crate) fn set_overrides(&mut self, overrides: HashMap<u16, time::Duration>) {
+        self.overrides = overrides;
+    }
+
+    /// Returns the timeout for a pod's port.
+    pub(crate) fn get(&self, port: u16) -> time::Duration {
+        self.overrides.get(&port).copied().unwrap_or(self.default)
+    }
+
+    /// Returns the timeout for a server that selects `port`.
+    ///
+    /// Overrides are set by port number, so servers that select ports by name use the default.
+    pub(crate) fn server(&self, port: &policy::server::Port) -> time::Duration {
+        match port {
+            policy::server::Port::Number(port) => self.get(*port),
+            policy::server::Port::Name(_) => self.default,
+        }
+    }
+}
@@ -0,0 +1,76 @@
+//! Counts the corrections made when resources are re-listed.
+//!
+//! Watches may rarely miss events, leaving the index stale until the resources are listed again.
+//! When a watch re-lists its resources (e.g. when the index is periodically resynced), the index is
+//! reset to the listed resources. Resources that were added, removed, or changed relative to what
+//! the index had observed are counted as corrections so that drift is visible.
+
+use linkerd_policy_controller_k8s_api::{self as k8s, ResourceExt};
+use linkerd_policy_controller_metrics::{Counter, Family};
+use std::collections::HashMap;
+use tracing::info;
+
+#[derive(Debug)]
+pub(crate) struct Drift {
+    /// The version of each observed resource, by kind and then by namespace and name. A kind is
+    /// absent until its resources are first listed.
+    versions: HashMap<&'static str, Versions>,
+
+    pub(crate) corrections: Family<Counter>,
+}
+
+/// The version of each resource of a kind, by namespace and name.
+type Versions = HashMap<(Option<String>, String), Option<String>>;
+
+// === impl Drift ===
+
+impl Default for Drift {
+    fn default() -> Self {
+        Self {
+            versions: HashMap::default(),
+            corrections: Family::new(&["kind"]),
+        }
+    }
+}
+
+impl Drift {
+    /// Tracks the resource versions in a watch event, counting corrections when resources of the
+    /// given kind are re-listed. The first list of each kind isn't a correction.
+    pub(crate) fn observe<K: ResourceExt>(&mut self, kind: &'static str, event: &k8s::Event<K>) {
+        match event {
+            k8s::Event::Applied(resource) => {
+                if let Some(versions) = self.versions.get_mut(kind) {
+                    versions.insert(key(resource), resource.resource_version());
+                }
+            }
+            k8s::Event::Deleted(resource) => {
+                if let Some(versions) = self.versions.get_mut(kind) {
+                    versions.remove(&key(resource));
+                }
+            }
+            k8s::Event::Restarted(resources) => {
+                let listed = resources
+                    .iter()
+                    .map(|r| (key(r), r.resource_version()))
+                    .collect::<Versions>();
+                if let Some(prior) = self.versions.get(kind) {
+                    let changed = listed
+                        .iter()
+                        .filter(|(k, v)| prior.get(*k) != Some(*v))
+                        .count();
+                    let removed = prior.keys().filter(|k| !listed.contains_key(*k)).count();
+                    let corrections = changed + removed;
+                    if corrections > 0 {
+                        info!(%kind, corrections, "Corrected resources that drifted from the API server");
+                        self.corrections.get(&[kind]).add(corrections as u64);
+                    }
+                }
+                self.versions.insert(kind, listed);
+            }
+        }
+    }
+}
+
+fn key<K: ResourceExt>(resource: &K) -> (Option<String>, String) {
+    (resource.namespace(), resource.name())
+}
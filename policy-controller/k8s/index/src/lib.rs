@@ -8,6 +8,8 @@
 //! - Each `Pod` enumerate its ports. We maintain an index of each pod's ports, linked to `Server`
 //!   objects.
 //! - Each `Server` selects over pods in the same namespace.
+//! - Each `ClusterServer` selects over pods in all namespaces. A `Server` that selects the same
+//!   pod-port takes precedence over a `ClusterServer`.
 //! - Each `ServerAuthorization` selects over `Server` instances in the same namespace.  When a
 //!   `ServerAuthorization` is updated, we find all of the `Server` instances it selects and update
 //!   their authorizations and publishes these updates on the server's broadcast channel.
@@ -22,31 +24,47 @@
 //!
 //! The Pod, Server, and ServerAuthorization indices are all scoped within a namespace index, as
 //! these resources cannot reference resources in other namespaces. This scoping helps to narrow the
-//! search space when processing updates and linking resources.
+//! search space when processing updates and linking resources. Each `ClusterServer` is indexed in
+//! every namespace, so that it's linked against the authorizations and pods in each namespace.
 
 #![deny(warnings, rust_2018_idioms)]
 #![forbid(unsafe_code)]
 
 mod authz;
 mod defaults;
+mod detect;
+mod drift;
 mod lookup;
 mod namespace;
 mod pod;
 mod server;
+mod server_metrics;
+mod snapshot;
 #[cfg(test)]
 mod tests;
 
-pub use self::{defaults::DefaultPolicy, lookup::Reader};
+pub use self::{
+    defaults::DefaultPolicy,
+    lookup::Reader,
+    namespace::NamespaceLabels,
+    snapshot::{Snapshot, SnapshotHandle},
+};
 use self::{
     defaults::DefaultPolicyWatches,
+    detect::DetectTimeouts,
     namespace::{Namespace, NamespaceIndex},
     server::SrvIndex,
 };
 use anyhow::Context;
 use linkerd_policy_controller_core::{InboundServer, IpNet};
 use linkerd_policy_controller_k8s_api::{self as k8s};
-use tokio::{sync::watch, time};
-use tracing::{debug, warn};
+use linkerd_policy_controller_metrics::{Counter, Family, Gauge, Registry};
+use std::{collections::HashMap, sync::Arc};
+use tokio::{
+    sync::{mpsc, oneshot, watch, Notify},
+    time,
+};
+use tracing::{debug, info, warn};
 
 /// Watches a server's configuration for server/authorization changes.
 type ServerRx = watch::Receiver<InboundServer>;
@@ -75,12 +93,19 @@ pub struct ClusterInfo {
     pub identity_domain: String,
 }
 
+/// Requests a description of the index's state from the indexing task, for debugging.
+#[derive(Clone, Debug)]
+pub struct DumpHandle(mpsc::Sender<oneshot::Sender<serde_json::Value>>);
+
 /// Holds all indexing state. Owned and updated by a single task that processes watch events,
 /// publishing results to the shared lookup map for quick lookups in the API server.
 pub struct Index {
     /// Holds per-namespace pod/server/authorization indexes.
     namespaces: NamespaceIndex,
 
+    /// Shared with callers that match namespaces' labels.
+    namespace_labels: NamespaceLabels,
+
     cluster_info: ClusterInfo,
 
     /// Holds watches for the cluster's default-allow policies. These watches are never updated but
@@ -90,6 +115,67 @@ pub struct Index {
 
     /// A handle that supports updates to the lookup index.
     lookups: lookup::Writer,
+
+    /// Notified to restart all watches, re-listing all resources.
+    resync: Arc<Notify>,
+
+    /// When set, updates the cluster networks as they change.
+    cluster_networks: Option<watch::Receiver<Arc<[IpNet]>>>,
+
+    /// Shared with callers that describe the index's state.
+    dump_handle: DumpHandle,
+
+    /// Receives requests from `DumpHandle`s. Taken when the index is run.
+    dump_rx: Option<mpsc::Receiver<oneshot::Sender<serde_json::Value>>>,
+
+    /// Counts authorizations indexed with client networks that overlap another authorization's.
+    authz_overlaps: Counter,
+
+    /// Counts watch events that failed to be processed, including those that panicked.
+    processing_errors: Counter,
+
+    /// The highest resource version observed by each kind of resource's watch.
+    resource_versions_observed: Family<Gauge>,
+
+    /// The resource version of the last event applied to the index, by kind of resource.
+    resource_versions_applied: Family<Gauge>,
+
+    /// The difference between the observed and applied resource versions, by kind of resource.
+    resource_version_lag: Family<Gauge>,
+
+    /// Counts the corrections made when resources are re-listed.
+    drift: drift::Drift,
+
+    /// When set, the index is not ready while any indexed pod address is outside of the cluster
+    /// networks.
+    strict_network_check: bool,
+
+    /// The number of indexed pod addresses outside of the cluster networks. Only updated when the
+    /// strict network check is enabled.
+    uncovered_pod_ips: Gauge,
+
+    /// Describes each indexed server.
+    server_metrics: server_metrics::ServerMetrics,
+
+    /// How long watch events may fail to be processed before the index is no longer ready.
+    unready_grace_period: time::Duration,
+
+    /// Set when a watch event fails to be processed and cleared once an event is processed
+    /// successfully.
+    failing_since: Option<time::Instant>,
+
+    /// When set, only pods matching this selector are indexed. Otherwise, all watched pods are
+    /// assumed to be meshed.
+    meshed_selector: Option<k8s::labels::Selector>,
+
+    /// When set, records the resources observed by the index's watches so they can be snapshotted.
+    recorder: Option<snapshot::Recorder>,
+
+    /// Shared with callers that snapshot the index's resources.
+    snapshot_handle: SnapshotHandle,
+
+    /// Receives requests from `SnapshotHandle`s. Taken when the index is run.
+    snapshot_rx: Option<mpsc::Receiver<oneshot::Sender<Snapshot>>>,
 }
 
 #[derive(Debug)]
@@ -98,29 +184,176 @@ struct Errors(Vec<anyhow::Error>);
 // === impl Index ===
 
 impl Index {
+    /// How long processing may fail before the index is no longer ready, unless configured
+    /// otherwise.
+    pub const DEFAULT_UNREADY_GRACE_PERIOD: time::Duration = time::Duration::from_secs(5);
+
     pub fn new(
         cluster_info: ClusterInfo,
         default_policy: DefaultPolicy,
         detect_timeout: time::Duration,
     ) -> (lookup::Reader, Self) {
         // Create a common set of receivers for all supported default policies.
+        let detect_timeouts = DetectTimeouts::new(detect_timeout);
         let default_policy_watches =
-            DefaultPolicyWatches::new(cluster_info.networks.clone(), detect_timeout);
+            DefaultPolicyWatches::new(cluster_info.networks.clone(), detect_timeouts.clone());
 
         // Provide the cluster-wide default-allow policy to the namespace index so that it may be
         // used when a workload-level annotation is not set.
-        let namespaces = NamespaceIndex::new(default_policy);
+        let namespaces = NamespaceIndex::new(default_policy, detect_timeouts);
 
         let (writer, reader) = lookup::pair();
+        let (dump_tx, dump_rx) = mpsc::channel(1);
+        let (snapshot_tx, snapshot_rx) = mpsc::channel(1);
         let idx = Self {
             lookups: writer,
             namespaces,
+            namespace_labels: NamespaceLabels::default(),
             cluster_info,
             default_policy_watches,
+            resync: Arc::new(Notify::new()),
+            cluster_networks: None,
+            dump_handle: DumpHandle(dump_tx),
+            dump_rx: Some(dump_rx),
+            authz_overlaps: Counter::default(),
+            processing_errors: Counter::default(),
+            resource_versions_observed: Family::new(&["kind"]),
+            resource_versions_applied: Family::new(&["kind"]),
+            resource_version_lag: Family::new(&["kind"]),
+            drift: drift::Drift::default(),
+            strict_network_check: false,
+            uncovered_pod_ips: Gauge::default(),
+            server_metrics: server_metrics::ServerMetrics::default(),
+            unready_grace_period: Self::DEFAULT_UNREADY_GRACE_PERIOD,
+            failing_since: None,
+            meshed_selector: None,
+            recorder: None,
+            snapshot_handle: SnapshotHandle(snapshot_tx),
+            snapshot_rx: Some(snapshot_rx),
         };
         (reader, idx)
     }
 
+    /// Returns a handle that, when notified, causes the index to re-list all resources. The index
+    /// remains ready while resources are re-listed.
+    pub fn resync_handle(&self) -> Arc<Notify> {
+        self.resync.clone()
+    }
+
+    /// Returns a handle that describes the index's state as JSON, keyed by namespace and resource
+    /// name.
+    pub fn dump_handle(&self) -> DumpHandle {
+        self.dump_handle.clone()
+    }
+
+    /// Returns a handle that reads the labels of indexed namespaces. Namespaces are only indexed
+    /// when they're watched, i.e. when the index isn't limited to a single namespace.
+    pub fn namespace_labels_handle(&self) -> NamespaceLabels {
+        self.namespace_labels.clone()
+    }
+
+    /// Updates the index's cluster networks as `networks` changes, e.g. as a file is reloaded.
+    ///
+    /// When the networks change, default policies are updated in place and authorizations are
+    /// re-listed so that those that default to the cluster networks are rebuilt.
+    pub fn watch_cluster_networks(&mut self, networks: watch::Receiver<Arc<[IpNet]>>) {
+        self.cluster_networks = Some(networks);
+    }
+
+    /// Withholds readiness while any indexed pod address is outside of the cluster networks, since
+    /// this usually means the cluster networks are misconfigured and traffic from those pods would
+    /// be treated as coming from outside the cluster.
+    ///
+    /// The check is evaluated once the index has synced and as it's updated, so the index becomes
+    /// ready once the uncovered pods are removed or the cluster networks are updated to cover them.
+    pub fn set_strict_network_check(&mut self, strict: bool) {
+        self.strict_network_check = strict;
+    }
+
+    /// Only indexes pods that match `selector`, e.g. when meshed pods are identified by a label
+    /// other than the one set by Linkerd's proxy injector. Pods that stop matching the selector are
+    /// removed from the index, so they are no longer discoverable.
+    pub fn set_meshed_workload_selector(&mut self, selector: k8s::labels::Selector) {
+        self.meshed_selector = Some(selector);
+    }
+
+    /// Withholds readiness once watch events have failed to be processed, without an event being
+    /// processed successfully since, for `grace_period`. Brief failures don't affect readiness, so
+    /// the controller isn't removed from rotation by transient errors. `Duration::ZERO` withholds
+    /// readiness as soon as an event fails.
+    pub fn set_unready_grace_period(&mut self, grace_period: time::Duration) {
+        self.unready_grace_period = grace_period;
+    }
+
+    /// Limits the number of servers that are described individually by the per-server metrics.
+    /// Servers beyond the limit are described in aggregate.
+    pub fn set_server_metrics_limit(&mut self, limit: usize) {
+        self.server_metrics.set_limit(limit);
+    }
+
+    /// Overrides the protocol detection timeout for the given ports, which otherwise use the
+    /// index's default timeout. Ports with a configured protocol (via a `Server` or the pod's
+    /// opaque-ports annotation) don't detect their protocol, so overrides don't apply to them.
+    ///
+    /// Overrides must be set before any resources are indexed.
+    pub fn set_detect_timeout_overrides(&mut self, overrides: HashMap<u16, time::Duration>) {
+        self.namespaces
+            .detect_timeouts
+            .set_overrides(overrides.clone());
+        self.default_policy_watches
+            .detect_timeouts
+            .set_overrides(overrides);
+    }
+
+    /// Registers the index's metrics.
+    pub fn register_metrics(&mut self, metrics: &Registry) {
+        self.authz_overlaps = metrics.register(
+            "index_authorization_network_overlaps_total",
+            "Total authorizations indexed with client networks that overlap those of another authorization on the same server.",
+            Counter::default(),
+        );
+        self.processing_errors = metrics.register(
+            "index_processing_errors_total",
+            "Total watch events that the index failed to process, including those that panicked.",
+            Counter::default(),
+        );
+        self.resource_versions_observed = metrics.register(
+            "index_resource_version_observed",
+            "The highest resource version observed by the watch for each kind of resource.",
+            Family::new(&["kind"]),
+        );
+        self.resource_versions_applied = metrics.register(
+            "index_resource_version_applied",
+            "The resource version of the last event applied to the index for each kind of resource.",
+            Family::new(&["kind"]),
+        );
+        self.resource_version_lag = metrics.register(
+            "index_resource_version_lag",
+            "The difference between the observed and applied resource versions for each kind of resource.",
+            Family::new(&["kind"]),
+        );
+        self.drift.corrections = metrics.register(
+            "index_resync_corrections_total",
+            "Total resources that were added, removed, or changed when resources were re-listed, by kind of resource.",
+            Family::new(&["kind"]),
+        );
+        self.uncovered_pod_ips = metrics.register(
+            "index_uncovered_pod_ips",
+            "The number of indexed pod addresses outside of the cluster networks, when the strict network check is enabled.",
+            Gauge::default(),
+        );
+        self.server_metrics.authorizations = metrics.register(
+            "index_server_authorizations",
+            "The number of authorizations that apply to each server, by namespace and server name. Servers beyond the limit are aggregated into a single `other` series.",
+            Family::new(&["namespace", "server"]),
+        );
+        self.server_metrics.default_protocol = metrics.register(
+            "index_server_default_protocol",
+            "Whether each server uses the default (detected) protocol, by namespace and server name. Servers beyond the limit are aggregated into a single `other` series.",
+            Family::new(&["namespace", "server"]),
+        );
+    }
+
     /// Drives indexing for all resource types.
     ///
     /// This is all driven on a single task, so it's not necessary for any of the indexing logic to
@@ -128,64 +361,335 @@ impl Index {
     ///
     /// All updates are atomically published to the shared `lookups` map after indexing occurs; but
     /// the indexing task is solely responsible for mutating it.
+    ///
+    /// Watch failures are retried, so this only returns if a resource can't be watched at all
+    /// (e.g. because the controller isn't authorized to watch it).
     pub async fn run(
         mut self,
         resources: impl Into<k8s::ResourceWatches>,
         ready_tx: watch::Sender<bool>,
-    ) {
+    ) -> anyhow::Result<()> {
         let k8s::ResourceWatches {
+            mut namespaces_rx,
             mut pods_rx,
             mut servers_rx,
             mut authorizations_rx,
+            mut cluster_servers_rx,
         } = resources.into();
 
+        let resync = self.resync.clone();
+        let mut cluster_networks = self.cluster_networks.take();
+        let mut dump_rx = self.dump_rx.take().expect("index must only be run once");
+        let mut snapshot_rx = self
+            .snapshot_rx
+            .take()
+            .expect("index must only be run once");
         let mut initialized = false;
         loop {
+            let unready_at = self
+                .failing_since
+                .filter(|_| *ready_tx.borrow())
+                .map(|since| since + self.unready_grace_period);
             let res = tokio::select! {
+                // While the index is ready but processing is failing, readiness is withheld once
+                // the grace period elapses, even if no further events are received.
+                _ = time::sleep_until(unready_at.unwrap_or_else(time::Instant::now)), if unready_at.is_some() => Ok(()),
+
+                _ = resync.notified() => {
+                    namespaces_rx.resync();
+                    pods_rx.resync();
+                    servers_rx.resync();
+                    authorizations_rx.resync();
+                    cluster_servers_rx.resync();
+                    Ok(())
+                },
+
+                // Describe the index's state for debugging.
+                Some(tx) = dump_rx.recv() => {
+                    let _ = tx.send(self.dump());
+                    Ok(())
+                },
+
+                // Snapshot the resources observed by the index.
+                Some(tx) = snapshot_rx.recv() => {
+                    let _ = tx.send(self.recorder.as_ref().map(|r| r.snapshot()).unwrap_or_default());
+                    Ok(())
+                },
+
+                // Rebuild policies that depend on the cluster networks. If the sender is dropped,
+                // the networks can no longer change.
+                res = changed(cluster_networks.as_mut()) => {
+                    match res {
+                        Some(networks) => {
+                            self.set_cluster_networks(networks.to_vec());
+                            authorizations_rx.resync();
+                        }
+                        None => cluster_networks = None,
+                    }
+                    Ok(())
+                },
+
+                // Track namespaces' default policies.
+                up = namespaces_rx.recv() => {
+                    let ev = self.record("namespaces", up.context("watching namespaces")?, |r| &mut r.namespaces);
+                    self.process("namespaces", move |idx| match ev {
+                        k8s::Event::Applied(ns) => {
+                            idx.apply_namespace(ns);
+                            Ok(())
+                        }
+                        k8s::Event::Deleted(ns) => {
+                            idx.delete_namespace(ns);
+                            Ok(())
+                        }
+                        k8s::Event::Restarted(nss) => {
+                            idx.reset_namespaces(nss);
+                            Ok(())
+                        }
+                    })
+                },
+
                 // Track pods against the appropriate server.
-                up = pods_rx.recv() => match up {
-                    k8s::Event::Applied(pod) => self.apply_pod(pod).context("applying a pod"),
-                    k8s::Event::Deleted(pod) => self.delete_pod(pod).context("deleting a pod"),
-                    k8s::Event::Restarted(pods) => self.reset_pods(pods).context("resetting pods"),
+                up = pods_rx.recv() => {
+                    let ev = self.record("pods", up.context("watching pods")?, |r| &mut r.pods);
+                    self.process("pods", move |idx| match ev {
+                        k8s::Event::Applied(pod) => idx.apply_pod(pod).context("applying a pod"),
+                        k8s::Event::Deleted(pod) => idx.delete_pod(pod).context("deleting a pod"),
+                        k8s::Event::Restarted(pods) => idx.reset_pods(pods).context("resetting pods"),
+                    })
                 },
 
                 // Track servers and link them with pods.
-                up = servers_rx.recv() => match up {
-                    k8s::Event::Applied(srv) => {
-                        self.apply_server(srv);
-                        Ok(())
-                    }
-                    k8s::Event::Deleted(srv) => self.delete_server(srv).context("deleting a server"),
-                    k8s::Event::Restarted(srvs) => self.reset_servers(srvs).context("resetting servers"),
+                up = servers_rx.recv() => {
+                    let ev = self.record("servers", up.context("watching servers")?, |r| &mut r.servers);
+                    self.process("servers", move |idx| match ev {
+                        k8s::Event::Applied(srv) => {
+                            idx.apply_server(srv);
+                            Ok(())
+                        }
+                        k8s::Event::Deleted(srv) => idx.delete_server(srv).context("deleting a server"),
+                        k8s::Event::Restarted(srvs) => idx.reset_servers(srvs).context("resetting servers"),
+                    })
                 },
 
                 // Track authorizations and update relevant servers.
-                up = authorizations_rx.recv() => match up {
-                    k8s::Event::Applied(authz) => self.apply_authz(authz).context("applying an authorization"),
-                    k8s::Event::Deleted(authz) => {
-                        self.delete_authz(authz);
-                        Ok(())
-                    }
-                    k8s::Event::Restarted(authzs) => self.reset_authzs(authzs).context("resetting authorizations"),
+                up = authorizations_rx.recv() => {
+                    let ev = self.record("authorizations", up.context("watching authorizations")?, |r| &mut r.authorizations);
+                    self.process("authorizations", move |idx| match ev {
+                        k8s::Event::Applied(authz) => idx.apply_authz(authz).context("applying an authorization"),
+                        k8s::Event::Deleted(authz) => {
+                            idx.delete_authz(authz);
+                            Ok(())
+                        }
+                        k8s::Event::Restarted(authzs) => idx.reset_authzs(authzs).context("resetting authorizations"),
+                    })
+                },
+
+                // Track cluster servers and link them with pods in all namespaces.
+                up = cluster_servers_rx.recv() => {
+                    let ev = self.record("cluster_servers", up.context("watching cluster servers")?, |r| &mut r.cluster_servers);
+                    self.process("cluster_servers", move |idx| match ev {
+                        k8s::Event::Applied(srv) => {
+                            idx.apply_cluster_server(srv);
+                            Ok(())
+                        }
+                        k8s::Event::Deleted(srv) => idx.delete_cluster_server(srv).context("deleting a cluster server"),
+                        k8s::Event::Restarted(srvs) => idx.reset_cluster_servers(srvs).context("resetting cluster servers"),
+                    })
                 },
             };
 
             if let Err(error) = res {
+                self.processing_errors.inc();
                 warn!(?error);
             }
+            self.update_server_metrics();
 
             // Notify the readiness watch once all watches have updated.
             if !initialized
+                && namespaces_rx.is_initialized()
                 && pods_rx.is_initialized()
                 && servers_rx.is_initialized()
                 && authorizations_rx.is_initialized()
+                && cluster_servers_rx.is_initialized()
             {
-                let _ = ready_tx.send(true);
                 initialized = true;
-                debug!("Ready");
             }
+
+            // Once synced, readiness is withheld while processing has failed for longer than the
+            // grace period and, in strict mode, while the cluster networks don't cover all pods.
+            if initialized {
+                let processing = self.check_processing();
+                let covered = !self.strict_network_check || self.check_pod_networks();
+                let ready = processing && covered;
+                if *ready_tx.borrow() != ready {
+                    let _ = ready_tx.send(ready);
+                    if ready {
+                        debug!("Ready");
+                    } else if !processing {
+                        let grace_period = self.unready_grace_period;
+                        warn!(
+                            ?grace_period,
+                            "Watch events have failed to be processed; not ready"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns false once watch events have failed to be processed, without recovering, for the
+    /// unready grace period.
+    fn check_processing(&self) -> bool {
+        match self.failing_since {
+            Some(since) => since.elapsed() < self.unready_grace_period,
+            None => true,
         }
     }
+
+    /// Returns true if all indexed pod addresses are within the cluster networks.
+    ///
+    /// Each uncovered address is logged when the number of uncovered addresses changes, so that
+    /// the index's updates don't repeat the warnings.
+    fn check_pod_networks(&self) -> bool {
+        let uncovered = self
+            .namespaces
+            .iter()
+            .flat_map(|(ns, idx)| {
+                idx.pods
+                    .uncovered_ips(&self.cluster_info.networks)
+                    .map(move |(pod, ip)| (ns.as_str(), pod, ip))
+            })
+            .collect::<Vec<_>>();
+        if uncovered.len() as i64 != self.uncovered_pod_ips.get() {
+            for (ns, pod, ip) in &uncovered {
+                warn!(%ns, %pod, %ip, "Pod IP is not within the cluster networks");
+            }
+            self.uncovered_pod_ips.set(uncovered.len() as i64);
+        }
+        uncovered.is_empty()
+    }
+
+    /// Updates the per-server metrics to describe the indexed servers.
+    pub(crate) fn update_server_metrics(&mut self) {
+        let servers = self.namespaces.iter().flat_map(|(ns, idx)| {
+            idx.servers
+                .iter_stats()
+                .map(move |(srv, authzs, detect)| (ns.as_str(), srv, authzs, detect))
+        });
+        self.server_metrics.update(servers);
+    }
+
+    /// Processes a watch event for the given kind of resource.
+    ///
+    /// A panic while processing the event (e.g. because a resource is missing a field that the
+    /// index expects) is returned as an error so that the index continues to process other
+    /// resources. The resource that caused the panic may not be fully indexed.
+    ///
+    /// The event's resource version is considered applied once it's processed, even if processing
+    /// failed.
+    fn process(
+        &mut self,
+        kind: &'static str,
+        f: impl FnOnce(&mut Self) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let res = match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| f(self))) {
+            Ok(res) => res,
+            Err(panic) => {
+                let msg = panic
+                    .downcast_ref::<&str>()
+                    .map(|s| s.to_string())
+                    .or_else(|| panic.downcast_ref::<String>().cloned())
+                    .unwrap_or_default();
+                Err(anyhow::anyhow!(
+                    "panicked while processing {}: {}",
+                    kind,
+                    msg
+                ))
+            }
+        };
+
+        let observed = self.resource_versions_observed.get(&[kind]).get();
+        self.resource_versions_applied.get(&[kind]).set(observed);
+        self.resource_version_lag.get(&[kind]).set(0);
+
+        // Track how long processing has been failing, so that brief failures don't affect
+        // readiness.
+        if res.is_ok() {
+            self.failing_since = None;
+        } else if self.failing_since.is_none() {
+            self.failing_since = Some(time::Instant::now());
+        }
+        res
+    }
+
+    /// Records a watch event so that it's reflected in snapshots, if snapshots are enabled, tracks
+    /// the highest resource version observed for the given kind of resource, and counts the
+    /// corrections made when resources are re-listed.
+    ///
+    /// Resource versions are opaque to clients, but the API server uses etcd revisions, so they're
+    /// compared numerically. Versions that aren't numeric are ignored.
+    fn record<K: k8s::ResourceExt + Clone>(
+        &mut self,
+        kind: &'static str,
+        event: k8s::Event<K>,
+        store: impl FnOnce(&mut snapshot::Recorder) -> &mut snapshot::Store<K>,
+    ) -> k8s::Event<K> {
+        let version = match &event {
+            k8s::Event::Applied(r) | k8s::Event::Deleted(r) => resource_version(r),
+            k8s::Event::Restarted(rs) => rs.iter().filter_map(resource_version).max(),
+        };
+        if let Some(version) = version {
+            let observed = self.resource_versions_observed.get(&[kind]);
+            if version > observed.get() {
+                observed.set(version);
+            }
+            let applied = self.resource_versions_applied.get(&[kind]).get();
+            self.resource_version_lag
+                .get(&[kind])
+                .set(observed.get() - applied);
+        }
+
+        if let Some(recorder) = self.recorder.as_mut() {
+            store(recorder).update(&event);
+        }
+        self.drift.observe(kind, &event);
+        event
+    }
+
+    pub(crate) fn set_cluster_networks(&mut self, networks: Vec<IpNet>) {
+        info!(?networks, "Updating cluster networks");
+        self.default_policy_watches
+            .set_cluster_networks(networks.clone());
+        self.cluster_info.networks = networks;
+    }
+}
+
+/// Returns a resource's version, if it's numeric.
+fn resource_version<K: k8s::ResourceExt>(resource: &K) -> Option<i64> {
+    resource.resource_version()?.parse().ok()
+}
+
+/// Waits for `rx` to change, returning its new value, or `None` if its sender was dropped. Never
+/// completes if there is no `rx`.
+async fn changed<T: Clone>(rx: Option<&mut watch::Receiver<T>>) -> Option<T> {
+    match rx {
+        Some(rx) => match rx.changed().await {
+            Ok(()) => Some(rx.borrow().clone()),
+            Err(_) => None,
+        },
+        None => futures::future::pending().await,
+    }
+}
+
+// === impl DumpHandle ===
+
+impl DumpHandle {
+    /// Describes the index's state, or returns `None` if the index is no longer running.
+    pub async fn dump(&self) -> Option<serde_json::Value> {
+        let (tx, rx) = oneshot::channel();
+        self.0.send(tx).await.ok()?;
+        rx.await.ok()
+    }
 }
 
 // === impl Errors ===
@@ -8,6 +8,10 @@ use std::{collections::HashMap, sync::Arc};
 pub(crate) struct Writer(ByNs);
 
 // Supports lookups in a shared map of pod-ports.
+//
+// Server selectors are resolved by the indexing task as resources change, so each lookup is only a
+// map access that returns the pod-port's watch. The watch is updated in place as servers and
+// authorizations change, so lookups never need to be cached or invalidated.
 #[derive(Clone, Debug)]
 pub struct Reader(ByNs);
 
@@ -99,14 +103,14 @@ impl DiscoverInboundServer<(String, String, u16)> for Reader {
         &self,
         (ns, pod, port): (String, String, u16),
     ) -> Result<Option<InboundServer>> {
-        Ok(self.lookup(&*ns, &*pod, port).map(|rx| rx.get()))
+        Ok(self.lookup(&ns, &pod, port).map(|rx| rx.get()))
     }
 
     async fn watch_inbound_server(
         &self,
         (ns, pod, port): (String, String, u16),
     ) -> Result<Option<InboundServerStream>> {
-        Ok(self.lookup(&*ns, &*pod, port).map(|rx| rx.into_stream()))
+        Ok(self.lookup(&ns, &pod, port).map(|rx| rx.into_stream()))
     }
 }
 
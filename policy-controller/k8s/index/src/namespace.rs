@@ -1,5 +1,14 @@
-use crate::{authz::AuthzIndex, pod::PodIndex, server::SrvIndex, DefaultPolicy};
-use std::collections::HashMap;
+use crate::{
+    authz::AuthzIndex, detect::DetectTimeouts, pod::PodIndex, server::SrvIndex, DefaultPolicy,
+    Index,
+};
+use dashmap::DashMap;
+use linkerd_policy_controller_k8s_api::{self as k8s, labels::Labels, policy, ResourceExt};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+use tracing::{debug, instrument, warn};
 
 #[derive(Debug)]
 pub(crate) struct NamespaceIndex {
@@ -7,11 +16,26 @@ pub(crate) struct NamespaceIndex {
 
     // The global default-allow policy.
     default_policy: DefaultPolicy,
+
+    /// Default-allow policies set by `Namespace` annotations, overriding the global default.
+    overrides: HashMap<String, DefaultPolicy>,
+
+    /// Cluster servers, which are indexed in every namespace as it's created.
+    pub cluster_servers: HashMap<String, policy::ClusterServer>,
+
+    /// The protocol detection timeouts to use for servers that don't configure a protocol.
+    pub detect_timeouts: DetectTimeouts,
 }
 
+/// Shares the labels of the indexed namespaces, e.g. so that the admission webhook can match
+/// namespace selectors without querying the API server.
+#[derive(Clone, Debug, Default)]
+pub struct NamespaceLabels(Arc<DashMap<String, Labels>>);
+
 #[derive(Debug)]
 pub(crate) struct Namespace {
-    /// Holds the global default-allow policy, which may be overridden per-workload.
+    /// Holds the namespace's default-allow policy (from its annotation or, if it's not annotated,
+    /// the global default), which may be overridden per-workload.
     pub default_policy: DefaultPolicy,
 
     pub pods: PodIndex,
@@ -22,24 +46,156 @@ pub(crate) struct Namespace {
 // === impl Namespaces ===
 
 impl NamespaceIndex {
-    pub fn new(default_policy: DefaultPolicy) -> Self {
+    pub fn new(default_policy: DefaultPolicy, detect_timeouts: DetectTimeouts) -> Self {
         Self {
             default_policy,
             index: HashMap::default(),
+            overrides: HashMap::default(),
+            cluster_servers: HashMap::default(),
+            detect_timeouts,
         }
     }
 
     pub fn get_or_default(&mut self, name: impl Into<String>) -> &mut Namespace {
-        let default_policy = self.default_policy;
-        self.index.entry(name.into()).or_insert_with(|| Namespace {
-            default_policy,
-            pods: PodIndex::default(),
-            servers: SrvIndex::default(),
-            authzs: AuthzIndex::default(),
+        let name = name.into();
+        let default_policy = self.default_policy(&name);
+        let cluster_servers = &self.cluster_servers;
+        let detect_timeouts = &self.detect_timeouts;
+        self.index.entry(name).or_insert_with(|| {
+            // A new namespace has no authorizations, so its cluster servers have none until
+            // authorizations are indexed in it.
+            let authzs = AuthzIndex::default();
+            let mut servers = SrvIndex::default();
+            for srv in cluster_servers.values() {
+                let detect_timeout = detect_timeouts.server(&srv.spec.port);
+                servers.apply_cluster(srv, &authzs, detect_timeout);
+            }
+            Namespace {
+                default_policy,
+                pods: PodIndex::default(),
+                servers,
+                authzs,
+            }
         })
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = (&String, &Namespace)> + ExactSizeIterator {
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = (&String, &Namespace)> {
         self.index.iter()
     }
+
+    /// Returns the default-allow policy for workloads in the named namespace.
+    fn default_policy(&self, name: &str) -> DefaultPolicy {
+        self.overrides
+            .get(name)
+            .copied()
+            .unwrap_or(self.default_policy)
+    }
+}
+
+// === impl NamespaceLabels ===
+
+impl NamespaceLabels {
+    /// Returns the labels of the named namespace, if it's indexed.
+    pub fn get(&self, name: &str) -> Option<Labels> {
+        self.0.get(name).map(|labels| labels.clone())
+    }
+}
+
+impl FromIterator<(String, Labels)> for NamespaceLabels {
+    fn from_iter<T: IntoIterator<Item = (String, Labels)>>(iter: T) -> Self {
+        Self(Arc::new(iter.into_iter().collect()))
+    }
+}
+
+// === impl Index ===
+
+impl Index {
+    /// Describes the index's state, keyed by namespace and resource name, for debugging.
+    pub(crate) fn dump(&self) -> serde_json::Value {
+        self.namespaces
+            .iter()
+            .map(|(name, ns)| {
+                let ns = serde_json::json!({
+                    "default_policy": ns.default_policy.to_string(),
+                    "pods": ns.pods.dump(),
+                    "servers": ns.servers.dump(),
+                    "cluster_servers": ns.servers.dump_cluster(),
+                    "authorizations": ns.authzs.dump(),
+                });
+                (name.clone(), ns)
+            })
+            .collect::<serde_json::Map<_, _>>()
+            .into()
+    }
+
+    /// Updates the namespace's default-allow policy from its annotation.
+    ///
+    /// If the annotation is invalid, a warning is logged and the global default applies.
+    #[instrument(skip(self, ns), fields(name = %ns.name()))]
+    pub(crate) fn apply_namespace(&mut self, ns: k8s::Namespace) {
+        let name = ns.name();
+        self.namespace_labels
+            .0
+            .insert(name.clone(), ns.metadata.labels.clone().into());
+        match DefaultPolicy::from_annotation(&ns.metadata) {
+            Ok(Some(policy)) => {
+                self.namespaces.overrides.insert(name.clone(), policy);
+            }
+            Ok(None) => {
+                self.namespaces.overrides.remove(&name);
+            }
+            Err(error) => {
+                warn!(%error, "Invalid default-allow annotation; using the global default");
+                self.namespaces.overrides.remove(&name);
+            }
+        }
+        self.update_namespace_policy(&name);
+    }
+
+    /// Restores the global default-allow policy for a namespace.
+    #[instrument(skip(self, ns), fields(name = %ns.name()))]
+    pub(crate) fn delete_namespace(&mut self, ns: k8s::Namespace) {
+        let name = ns.name();
+        self.namespace_labels.0.remove(&name);
+        self.namespaces.overrides.remove(&name);
+        self.update_namespace_policy(&name);
+    }
+
+    #[instrument(skip(self, nss))]
+    pub(crate) fn reset_namespaces(&mut self, nss: Vec<k8s::Namespace>) {
+        let mut prior = self
+            .namespaces
+            .overrides
+            .keys()
+            .cloned()
+            .collect::<HashSet<_>>();
+
+        let names = nss.iter().map(|ns| ns.name()).collect::<HashSet<_>>();
+        self.namespace_labels
+            .0
+            .retain(|name, _| names.contains(name));
+
+        for ns in nss.into_iter() {
+            prior.remove(ns.name().as_str());
+            self.apply_namespace(ns);
+        }
+
+        for name in prior.into_iter() {
+            self.namespaces.overrides.remove(&name);
+            self.update_namespace_policy(&name);
+        }
+    }
+
+    /// Updates the pods in a namespace if its default-allow policy has changed.
+    fn update_namespace_policy(&mut self, name: &str) {
+        let default_policy = self.namespaces.default_policy(name);
+        if let Some(ns) = self.namespaces.index.get_mut(name) {
+            if ns.default_policy != default_policy {
+                debug!(%default_policy, "Updating the namespace's default policy");
+                ns.default_policy = default_policy;
+                ns.pods
+                    .set_default_policy(default_policy, &mut self.default_policy_watches);
+            }
+        }
+    }
 }
@@ -3,10 +3,14 @@ use crate::{
     PodServerTx, ServerRx, SrvIndex,
 };
 use anyhow::{anyhow, bail, Context, Result};
+use linkerd_policy_controller_core::IpNet;
 use linkerd_policy_controller_k8s_api::{self as k8s, policy, ResourceExt};
-use std::collections::{hash_map::Entry as HashEntry, HashMap, HashSet};
+use std::{
+    collections::{hash_map::Entry as HashEntry, BTreeSet, HashMap, HashSet},
+    net::IpAddr,
+};
 use tokio::sync::watch;
-use tracing::{debug, instrument, trace};
+use tracing::{debug, instrument, trace, warn};
 
 /// Indexes pod state (within a namespace).
 #[derive(Debug, Default)]
@@ -25,6 +29,20 @@ struct Pod {
 
     /// The workload's default allow behavior (to apply when no `Server` references a port).
     default_policy: DefaultPolicy,
+
+    /// Indicates that the default policy was set by the pod's own annotation, so it isn't changed
+    /// when the namespace's default policy changes.
+    default_policy_annotated: bool,
+
+    /// Ports targeted by the pod's liveness, readiness, or startup probes.
+    probe_ports: HashSet<u16>,
+
+    /// The address of the pod's node, from which the kubelet probes the pod. This isn't known
+    /// until the pod is scheduled.
+    host_ip: Option<IpAddr>,
+
+    /// The pod's addresses. These aren't known until the pod is scheduled.
+    pod_ips: Vec<IpAddr>,
 }
 
 /// An index of all ports in a pod spec to the
@@ -52,11 +70,19 @@ struct PodAnnotations {
 struct Port {
     default_policy_rx: ServerRx,
 
+    /// The port's annotated configuration, used to obtain a new default policy watch when the
+    /// pod's default policy changes.
+    config: PortDefaults,
+
     /// Set with the name of the `Server` resource that currently selects this port, if one exists.
     ///
     /// When this is `None`, a default policy currently applies.
     server_name: Option<String>,
 
+    /// Indicates that `server_name` refers to a `ClusterServer` rather than a `Server` in the
+    /// pod's namespace.
+    cluster_server: bool,
+
     /// Updated with a server update receiver as servers select/deselect this port.
     server_tx: PodServerTx,
 }
@@ -73,6 +99,23 @@ impl Index {
         )
     )]
     pub(crate) fn apply_pod(&mut self, pod: k8s::Pod) -> Result<()> {
+        if let Some(selector) = self.meshed_selector.as_ref() {
+            if !selector.matches(&k8s::Labels::from(pod.metadata.labels.clone())) {
+                // The pod may have been indexed before its labels changed.
+                let ns_name = pod.namespace().expect("namespace must be set");
+                let pod_name = pod.name();
+                let indexed = matches!(
+                    self.namespaces.index.get(&ns_name),
+                    Some(ns) if ns.pods.index.contains_key(&pod_name)
+                );
+                if indexed {
+                    return self.rm_pod(&ns_name, &pod_name);
+                }
+                debug!("Ignoring unmeshed pod");
+                return Ok(());
+            }
+        }
+
         let Namespace {
             default_policy,
             ref mut pods,
@@ -148,7 +191,7 @@ impl Index {
             .remove(pod)
             .ok_or_else(|| anyhow!("pod {} doesn't exist", pod))?;
 
-        self.lookups.unset(&ns, &pod)?;
+        self.lookups.unset(ns, pod)?;
 
         debug!("Removed pod");
 
@@ -159,23 +202,122 @@ impl Index {
 // === impl PodIndex ===
 
 impl PodIndex {
+    /// Describes each pod, by name, for debugging: its resolved default policy and, for each port,
+    /// the server that selects it or the default policy that applies to it.
+    pub(crate) fn dump(&self) -> serde_json::Value {
+        self.index
+            .iter()
+            .map(|(name, pod)| {
+                let ports = pod
+                    .ports
+                    .by_port
+                    .iter()
+                    .map(|(p, port)| {
+                        let port = serde_json::json!({
+                            "server": port.server_name,
+                            "cluster_server": port.cluster_server,
+                            "default": port.default_policy_rx.borrow().name,
+                        });
+                        (p.to_string(), port)
+                    })
+                    .collect::<serde_json::Map<_, _>>();
+                let pod = serde_json::json!({
+                    "default_policy": pod.default_policy.to_string(),
+                    "ports": ports,
+                });
+                (name.clone(), pod)
+            })
+            .collect::<serde_json::Map<_, _>>()
+            .into()
+    }
+
+    /// Returns the name and address of each pod address that isn't contained by any of
+    /// `networks`.
+    pub(crate) fn uncovered_ips<'p>(
+        &'p self,
+        networks: &'p [IpNet],
+    ) -> impl Iterator<Item = (&'p str, IpAddr)> + 'p {
+        self.index.iter().flat_map(move |(name, pod)| {
+            pod.pod_ips
+                .iter()
+                .filter(move |ip| !networks.iter().any(|net| net.contains(*ip)))
+                .map(move |ip| (name.as_str(), *ip))
+        })
+    }
+
     pub(crate) fn link_servers(&mut self, servers: &SrvIndex) {
         for pod in self.index.values_mut() {
-            pod.link_servers(servers)
+            pod.link_servers(servers);
+        }
+        self.warn_port_name_conflicts(servers);
+    }
+
+    /// Warns when a server selects pods by a port name that the pods map to different port
+    /// numbers.
+    ///
+    /// Each pod's ports are linked independently, so the server applies to whichever port each
+    /// pod names, but this is usually a sign that the server selects more pods than intended.
+    fn warn_port_name_conflicts(&self, servers: &SrvIndex) {
+        for (server, name, ports) in self.port_name_conflicts(servers) {
+            warn!(
+                %server,
+                port = %name,
+                ?ports,
+                "Server port name refers to different ports on the pods it selects"
+            );
+        }
+    }
+
+    /// Returns each server port name that resolves to different port numbers across the selected
+    /// pods, with all of the port numbers it resolves to.
+    pub(crate) fn port_name_conflicts<'s>(
+        &self,
+        servers: &'s SrvIndex,
+    ) -> Vec<(&'s str, &'s str, BTreeSet<u16>)> {
+        let mut resolved = HashMap::<(&str, &str), BTreeSet<u16>>::new();
+        for pod in self.index.values() {
+            for (server, port, _) in servers.iter_matching_pod(pod.labels.clone()) {
+                if let policy::server::Port::Name(name) = port {
+                    if let Some(ports) = pod.ports.by_name.get(name) {
+                        resolved
+                            .entry((server, name.as_str()))
+                            .or_default()
+                            .extend(ports);
+                    }
+                }
+            }
         }
+
+        let mut conflicts = resolved
+            .into_iter()
+            .filter(|(_, ports)| ports.len() > 1)
+            .map(|((server, name), ports)| (server, name, ports))
+            .collect::<Vec<_>>();
+        conflicts.sort();
+        conflicts
     }
 
-    pub(crate) fn reset_server(&mut self, name: &str) {
+    /// Updates pods that aren't annotated with a default policy to use `default_policy`,
+    /// reverting their unselected ports to the new default.
+    pub(crate) fn set_default_policy(
+        &mut self,
+        default_policy: DefaultPolicy,
+        default_policy_watches: &mut DefaultPolicyWatches,
+    ) {
         for (pod_name, pod) in self.index.iter_mut() {
+            if pod.default_policy_annotated || pod.default_policy == default_policy {
+                continue;
+            }
+            pod.default_policy = default_policy;
+
             for (p, port) in pod.ports.by_port.iter_mut() {
-                if port.server_name.as_deref() == Some(name) {
-                    debug!(pod = %pod_name, port = %p, "Removing server from pod");
-                    port.server_name = None;
+                port.default_policy_rx =
+                    default_policy_watches.watch(default_policy, *p, port.config);
+                if port.server_name.is_none() {
+                    debug!(pod = %pod_name, port = %p, "Updating default policy");
                     port.server_tx
                         .send(port.default_policy_rx.clone())
                         .expect("pod config receiver must still be held");
-                } else {
-                    trace!(pod = %pod_name, port = %p, server = ?port.server_name, "Server does not match");
                 }
             }
         }
@@ -199,15 +341,18 @@ impl PodIndex {
                 // Check the pod for a default-allow annotation. If it's set, use it; otherwise use
                 // the default policy from the namespace or cluster. We retain this value (and not
                 // only the policy) so that we can more conveniently de-duplicate changes
-                let default_policy = match DefaultPolicy::from_annotation(&pod.metadata) {
-                    Ok(allow) => allow.unwrap_or(default_policy),
+                let annotated = match DefaultPolicy::from_annotation(&pod.metadata) {
+                    Ok(allow) => allow,
                     Err(error) => {
                         tracing::info!(%error, "failed to parse default-allow annotation");
-                        default_policy
+                        None
                     }
                 };
+                let default_policy = annotated.unwrap_or(default_policy);
 
                 let pod_annotations = PodAnnotations::from_annotations(&pod.metadata);
+                let probe_ports = probe_ports(&spec);
+                let host_ip = host_ip(pod.status.as_ref());
 
                 // Read the pod's ports and extract:
                 // - `ServerTx`s to be linkerd against the server index; and
@@ -215,6 +360,8 @@ impl PodIndex {
                 let (ports, pod_lookups) = Self::extract_ports(
                     spec,
                     &pod_annotations,
+                    &probe_ports,
+                    host_ip,
                     default_policy,
                     default_policy_watches,
                 );
@@ -223,10 +370,14 @@ impl PodIndex {
                 // created. Immediately link the pod's ports to the server index.
                 let mut pod = Pod {
                     default_policy,
+                    default_policy_annotated: annotated.is_some(),
                     labels: pod.metadata.labels.into(),
                     ports,
+                    probe_ports,
+                    host_ip,
+                    pod_ips: pod_ips(pod.status.as_ref()),
                 };
-                pod.link_servers(servers);
+                let by_name = pod.link_servers(servers);
 
                 // The pod has been linked against servers and is registered for subsequent updates,
                 // so make it discoverable to API clients.
@@ -236,6 +387,11 @@ impl PodIndex {
 
                 pod_entry.insert(pod);
 
+                // Only pods selected by a port name can introduce a conflicting mapping.
+                if by_name {
+                    self.warn_port_name_conflicts(servers);
+                }
+
                 Ok(())
             }
 
@@ -249,12 +405,26 @@ impl PodIndex {
                 // labels have changed, then we relink servers to pods in case label selections have
                 // changed.
                 let p = entry.get_mut();
+                let mut by_name = false;
                 if p.labels != pod.metadata.labels {
                     p.labels = pod.metadata.labels.into();
-                    p.link_servers(servers);
+                    by_name = p.link_servers(servers);
+                }
+
+                // The pod's node is only known once it's scheduled, so probed ports are updated as
+                // its host IP is set.
+                let host_ip = host_ip(pod.status.as_ref());
+                if p.host_ip != host_ip {
+                    p.set_host_ip(host_ip, default_policy_watches);
                 }
+                p.pod_ips = pod_ips(pod.status.as_ref());
 
-                // Note that the default-allow annotation may not be changed at runtime.
+                if by_name {
+                    self.warn_port_name_conflicts(servers);
+                }
+
+                // Note that the default-allow annotation may not be changed at runtime, though the
+                // namespace's default policy may be.
                 Ok(())
             }
         }
@@ -264,6 +434,8 @@ impl PodIndex {
     fn extract_ports(
         spec: k8s::PodSpec,
         annotations: &PodAnnotations,
+        probe_ports: &HashSet<u16>,
+        host_ip: Option<IpAddr>,
         default_policy: DefaultPolicy,
         default_policy_watches: &mut DefaultPolicyWatches,
     ) -> (PodPorts, HashMap<u16, lookup::Rx>) {
@@ -279,12 +451,18 @@ impl PodIndex {
                         continue;
                     }
 
-                    let config = annotations.get_config(port);
-                    let default_policy_rx = default_policy_watches.watch(default_policy, config);
+                    let mut config = annotations.get_config(port);
+                    if probe_ports.contains(&port) {
+                        config.kubelet = host_ip;
+                    }
+                    let default_policy_rx =
+                        default_policy_watches.watch(default_policy, port, config);
                     let (server_tx, rx) = watch::channel(default_policy_rx.clone());
                     let pod_port = Port {
                         default_policy_rx,
+                        config,
                         server_name: None,
+                        cluster_server: false,
                         server_tx,
                     };
 
@@ -303,21 +481,114 @@ impl PodIndex {
     }
 }
 
+/// Returns the ports targeted by the HTTP and TCP probes of all containers in `spec`.
+///
+/// Named probe ports refer to the probed container's own ports.
+fn probe_ports(spec: &k8s::PodSpec) -> HashSet<u16> {
+    let mut ports = HashSet::new();
+    for container in spec.containers.iter() {
+        let probes = [
+            &container.liveness_probe,
+            &container.readiness_probe,
+            &container.startup_probe,
+        ];
+        for probe in probes.into_iter().flatten() {
+            let targets = [
+                probe.http_get.as_ref().map(|h| &h.port),
+                probe.tcp_socket.as_ref().map(|t| &t.port),
+            ];
+            for target in targets.into_iter().flatten() {
+                let port = match target {
+                    k8s::IntOrString::Int(port) => u16::try_from(*port).ok(),
+                    k8s::IntOrString::String(name) => container
+                        .ports
+                        .iter()
+                        .flatten()
+                        .find(|p| p.name.as_deref() == Some(name.as_str()))
+                        .and_then(|p| u16::try_from(p.container_port).ok()),
+                };
+                if let Some(port) = port {
+                    ports.insert(port);
+                }
+            }
+        }
+    }
+    ports
+}
+
+fn host_ip(status: Option<&k8s::PodStatus>) -> Option<IpAddr> {
+    status?.host_ip.as_deref()?.parse().ok()
+}
+
+/// Reads a pod's addresses from its status, falling back to the singular `podIP` when `podIPs` is
+/// not set. Invalid addresses are ignored.
+fn pod_ips(status: Option<&k8s::PodStatus>) -> Vec<IpAddr> {
+    let status = match status {
+        Some(status) => status,
+        None => return vec![],
+    };
+    let ips = status
+        .pod_ips
+        .iter()
+        .flatten()
+        .filter_map(|ip| ip.ip.as_deref())
+        .collect::<Vec<_>>();
+    let ips = if ips.is_empty() {
+        status.pod_ip.as_deref().into_iter().collect()
+    } else {
+        ips
+    };
+    ips.into_iter().filter_map(|ip| ip.parse().ok()).collect()
+}
+
 // === impl Pod ===
 
 impl Pod {
     /// Links this pod to servers (by label selector).
     ///
-    ///
-    fn link_servers(&mut self, servers: &SrvIndex) {
+    /// Servers that select a port by name are linked to whichever ports the pod gives that name.
+    /// A server in the pod's namespace takes precedence over a cluster server that selects the same
+    /// port. Returns true if any of the pod's ports were selected by name.
+    fn link_servers(&mut self, servers: &SrvIndex) -> bool {
         let mut remaining_ports = self.ports.by_port.keys().copied().collect::<HashSet<u16>>();
-
-        // Get all servers that match this pod.
-        let matching = servers.iter_matching_pod(self.labels.clone());
-        for (name, port_match, rx) in matching {
+        let mut linked = HashMap::<u16, (&str, bool)>::new();
+        let mut by_name = false;
+
+        // Get all servers that match this pod, linking namespaced servers before cluster servers.
+        let matching = servers
+            .iter_matching_pod(self.labels.clone())
+            .map(|(name, port, rx)| (name, port, rx, false))
+            .chain(
+                servers
+                    .iter_cluster_matching_pod(self.labels.clone())
+                    .map(|(name, port, rx)| (name, port, rx, true)),
+            );
+        for (name, port_match, rx, cluster) in matching {
             // Get all pod ports that match this server.
-            for p in self.ports.collect_port(port_match).into_iter() {
-                self.link_server_port(p, name, rx);
+            let ports = self.ports.collect_port(port_match);
+            if let policy::server::Port::Name(port_name) = port_match {
+                if ports.is_empty() {
+                    debug!(server = %name, port = %port_name, "Pod has no port with the server's port name");
+                }
+                by_name |= !ports.is_empty();
+            }
+            for p in ports.into_iter() {
+                // If multiple servers of the same kind select this port, we panic if the
+                // controller is running in debug mode. In release mode, we log a warning and
+                // ignore the conflicting server.
+                //
+                // TODO these cases should be prevented with a validating admission controller.
+                if let Some((sn, sn_cluster)) = linked.get(&p) {
+                    if *sn_cluster == cluster {
+                        debug_assert!(false, "Pod port must not match multiple servers");
+                        tracing::warn!("Pod port matches multiple servers: {} and {}", sn, name);
+                    } else {
+                        debug!(server = %sn, cluster_server = %name, port = %p, "Server takes precedence over cluster server");
+                    }
+                    continue;
+                }
+                linked.insert(p, (name, cluster));
+                self.link_server_port(p, name, cluster, rx);
                 remaining_ports.remove(&p);
             }
         }
@@ -326,38 +597,57 @@ impl Pod {
         for p in remaining_ports.into_iter() {
             let port = self.ports.by_port.get_mut(&p).unwrap();
             port.server_name = None;
+            port.cluster_server = false;
             port.server_tx
                 .send(port.default_policy_rx.clone())
                 .expect("pod config receiver must still be held");
         }
+
+        by_name
+    }
+
+    /// Updates the default policies of the pod's probed ports to reflect the address of its node.
+    fn set_host_ip(
+        &mut self,
+        host_ip: Option<IpAddr>,
+        default_policy_watches: &mut DefaultPolicyWatches,
+    ) {
+        self.host_ip = host_ip;
+        for p in self.probe_ports.iter() {
+            let port = match self.ports.by_port.get_mut(p) {
+                Some(port) => port,
+                None => continue,
+            };
+            port.config.kubelet = host_ip;
+            port.default_policy_rx =
+                default_policy_watches.watch(self.default_policy, *p, port.config);
+            if port.server_name.is_none() {
+                debug!(port = %p, ?host_ip, "Updating probe policy");
+                port.server_tx
+                    .send(port.default_policy_rx.clone())
+                    .expect("pod config receiver must still be held");
+            }
+        }
     }
 
-    fn link_server_port(&mut self, port: u16, name: &str, rx: &ServerRx) {
+    fn link_server_port(&mut self, port: u16, name: &str, cluster: bool, rx: &ServerRx) {
         let port = match self.ports.by_port.get_mut(&port) {
             Some(p) => p,
             None => return,
         };
 
-        // Either this port is using a default allow policy, and the server name is unset, or
-        // multiple servers select this pod. If there's a conflict, we panic if the controller is
-        // running in debug mode. In release mode, we log a warning and ignore the conflicting
-        // server.
-        //
-        // TODO these cases should be prevented with a validating admission controller.
-        if let Some(sn) = port.server_name.as_ref() {
-            if sn != name {
-                debug_assert!(false, "Pod port must not match multiple servers");
-                tracing::warn!("Pod port matches multiple servers: {} and {}", sn, name);
-            }
-            // If the name matched there's no use in proceeding with a redundant update
+        // If the port is already linked to this server there's no use in proceeding with a
+        // redundant update.
+        if port.server_name.as_deref() == Some(name) && port.cluster_server == cluster {
             return;
         }
         port.server_name = Some(name.to_string());
+        port.cluster_server = cluster;
 
         port.server_tx
             .send(rx.clone())
             .expect("pod config receiver must be set");
-        debug!(server = %name, "Pod server updated");
+        debug!(server = %name, %cluster, "Pod server updated");
     }
 }
 
@@ -431,6 +721,7 @@ impl PodAnnotations {
         PortDefaults {
             authenticated: self.require_id.contains(&port),
             opaque: self.opaque.contains(&port),
+            kubelet: None,
         }
     }
 }
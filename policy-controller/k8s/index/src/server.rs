@@ -3,7 +3,7 @@ use anyhow::{anyhow, bail, Result};
 use linkerd_policy_controller_core::{ClientAuthorization, InboundServer, ProxyProtocol};
 use linkerd_policy_controller_k8s_api::{self as k8s, policy, ResourceExt};
 use std::{
-    collections::{hash_map::Entry as HashEntry, HashMap, HashSet},
+    collections::{hash_map::Entry as HashEntry, BTreeMap, HashMap, HashSet},
     sync::Arc,
 };
 use tokio::{sync::watch, time};
@@ -13,6 +13,10 @@ use tracing::{debug, instrument, trace};
 #[derive(Debug, Default)]
 pub(crate) struct SrvIndex {
     index: HashMap<String, Server>,
+
+    /// Holds the namespace's instance of each `ClusterServer`, so that it's linked against the
+    /// namespace's authorizations and pods.
+    cluster: HashMap<String, Server>,
 }
 
 /// The state of a `Server` instance and its authorizations.
@@ -31,7 +35,7 @@ struct Server {
     protocol: ProxyProtocol,
 
     /// Holds a copy of all authorization policies matching this server.
-    authorizations: HashMap<String, ClientAuthorization>,
+    authorizations: BTreeMap<String, ClientAuthorization>,
 
     /// Shares the server's state with pod-ports.
     rx: ServerRx,
@@ -60,6 +64,7 @@ impl Index {
     )]
     pub(crate) fn apply_server(&mut self, srv: policy::Server) {
         let ns_name = srv.namespace().expect("namespace must be set");
+        let detect_timeout = self.namespaces.detect_timeouts.server(&srv.spec.port);
         let Namespace {
             ref mut pods,
             ref mut authzs,
@@ -67,7 +72,7 @@ impl Index {
             default_policy: _,
         } = self.namespaces.get_or_default(ns_name);
 
-        servers.apply(srv, authzs);
+        servers.apply(srv, authzs, detect_timeout);
 
         // If we've updated the server->pod selection, then we need to re-index
         // all pods and servers.
@@ -96,8 +101,9 @@ impl Index {
             bail!("removing non-existent server {}", srv_name);
         }
 
-        // Reset the server config for all pods that were using this server.
-        ns.pods.reset_server(srv_name);
+        // Relink the pods that were using this server, since a cluster server may select their
+        // ports in its place.
+        ns.pods.link_servers(&ns.servers);
 
         debug!("Removed server");
         Ok(())
@@ -135,14 +141,106 @@ impl Index {
 
         Errors::ok_if_empty(errors)
     }
+
+    /// Builds a `ClusterServer` in every namespace, linking it against each namespace's
+    /// authorizations and pod ports.
+    #[instrument(skip(self, srv), fields(name = %srv.name()))]
+    pub(crate) fn apply_cluster_server(&mut self, srv: policy::ClusterServer) {
+        let detect_timeout = self.namespaces.detect_timeouts.server(&srv.spec.port);
+        for ns in self.namespaces.index.values_mut() {
+            ns.servers.apply_cluster(&srv, &ns.authzs, detect_timeout);
+            ns.pods.link_servers(&ns.servers);
+        }
+        self.namespaces.cluster_servers.insert(srv.name(), srv);
+    }
+
+    #[instrument(skip(self, srv), fields(name = %srv.name()))]
+    pub(crate) fn delete_cluster_server(&mut self, srv: policy::ClusterServer) -> Result<()> {
+        self.rm_cluster_server(srv.name().as_str())
+    }
+
+    fn rm_cluster_server(&mut self, srv_name: &str) -> Result<()> {
+        if self.namespaces.cluster_servers.remove(srv_name).is_none() {
+            bail!("removing non-existent cluster server {}", srv_name);
+        }
+
+        for ns in self.namespaces.index.values_mut() {
+            if ns.servers.cluster.remove(srv_name).is_some() {
+                ns.pods.link_servers(&ns.servers);
+            }
+        }
+
+        debug!("Removed cluster server");
+        Ok(())
+    }
+
+    #[instrument(skip(self, srvs))]
+    pub(crate) fn reset_cluster_servers(&mut self, srvs: Vec<policy::ClusterServer>) -> Result<()> {
+        let mut prior_servers = self
+            .namespaces
+            .cluster_servers
+            .keys()
+            .cloned()
+            .collect::<HashSet<_>>();
+
+        for srv in srvs.into_iter() {
+            prior_servers.remove(srv.name().as_str());
+            self.apply_cluster_server(srv);
+        }
+
+        let mut errors = vec![];
+        for srv_name in prior_servers.into_iter() {
+            if let Err(e) = self.rm_cluster_server(&srv_name) {
+                errors.push(e);
+            }
+        }
+
+        Errors::ok_if_empty(errors)
+    }
 }
 
 // === impl SrvIndex ===
 
 impl SrvIndex {
-    /// Adds an authorization to servers matching `selector`.
+    /// Describes each server, by name, for debugging.
+    pub(crate) fn dump(&self) -> serde_json::Value {
+        Self::dump_servers(&self.index)
+    }
+
+    /// Describes the namespace's instance of each cluster server, by name, for debugging.
+    pub(crate) fn dump_cluster(&self) -> serde_json::Value {
+        Self::dump_servers(&self.cluster)
+    }
+
+    fn dump_servers(index: &HashMap<String, Server>) -> serde_json::Value {
+        index
+            .iter()
+            .map(|(name, srv)| {
+                let authzs = srv.authorizations.keys().cloned().collect::<Vec<_>>();
+                let srv = serde_json::json!({
+                    "port": srv.port,
+                    "pod_selector": format!("{:?}", srv.pod_selector),
+                    "protocol": format!("{:?}", srv.protocol),
+                    "authorizations": authzs,
+                });
+                (name.clone(), srv)
+            })
+            .collect::<serde_json::Map<_, _>>()
+            .into()
+    }
+
+    /// Iterates over each server's name, number of authorizations, and whether it uses the default
+    /// (detected) protocol. Cluster servers aren't included.
+    pub(crate) fn iter_stats(&self) -> impl Iterator<Item = (&str, usize, bool)> {
+        self.index.iter().map(|(name, srv)| {
+            let detect = matches!(srv.protocol, ProxyProtocol::Detect { .. });
+            (name.as_str(), srv.authorizations.len(), detect)
+        })
+    }
+
+    /// Adds an authorization to servers (including cluster servers) matching `selector`.
     pub fn add_authz(&mut self, name: &str, selector: &ServerSelector, authz: ClientAuthorization) {
-        for (srv_name, srv) in self.index.iter_mut() {
+        for (srv_name, srv) in self.index.iter_mut().chain(self.cluster.iter_mut()) {
             if selector.selects(srv_name, &srv.labels) {
                 debug!(server = %srv_name, authz = %name, "Adding authz to server");
                 srv.insert_authz(name.to_string(), authz.clone());
@@ -153,9 +251,31 @@ impl SrvIndex {
         }
     }
 
+    /// Iterates over the servers that hold the named authorization, with the sorted names of each
+    /// server's other authorizations.
+    pub(crate) fn authz_peers<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> impl Iterator<Item = (&'a str, Vec<&'a str>)> + 'a {
+        self.index
+            .iter()
+            .chain(self.cluster.iter())
+            .filter(move |(_, srv)| srv.authorizations.contains_key(name))
+            .map(move |(srv_name, srv)| {
+                let mut peers = srv
+                    .authorizations
+                    .keys()
+                    .map(String::as_str)
+                    .filter(|n| *n != name)
+                    .collect::<Vec<_>>();
+                peers.sort_unstable();
+                (srv_name.as_str(), peers)
+            })
+    }
+
     /// Removes an authorization by `name`.
     pub fn remove_authz(&mut self, name: &str) {
-        for srv in self.index.values_mut() {
+        for srv in self.index.values_mut().chain(self.cluster.values_mut()) {
             srv.remove_authz(name);
         }
     }
@@ -165,7 +285,22 @@ impl SrvIndex {
         &self,
         pod_labels: k8s::Labels,
     ) -> impl Iterator<Item = (&str, &policy::server::Port, &ServerRx)> {
-        self.index.iter().filter_map(move |(srv_name, server)| {
+        Self::matching_pod(&self.index, pod_labels)
+    }
+
+    /// Iterates over cluster servers that select the given `pod_labels`.
+    pub fn iter_cluster_matching_pod(
+        &self,
+        pod_labels: k8s::Labels,
+    ) -> impl Iterator<Item = (&str, &policy::server::Port, &ServerRx)> {
+        Self::matching_pod(&self.cluster, pod_labels)
+    }
+
+    fn matching_pod(
+        index: &HashMap<String, Server>,
+        pod_labels: k8s::Labels,
+    ) -> impl Iterator<Item = (&str, &policy::server::Port, &ServerRx)> {
+        index.iter().filter_map(move |(srv_name, server)| {
             let matches = server.pod_selector.matches(&pod_labels);
             trace!(server = %srv_name, %matches);
             if matches {
@@ -177,19 +312,56 @@ impl SrvIndex {
     }
 
     /// Update the index with a server instance.
-    fn apply(&mut self, srv: policy::Server, ns_authzs: &AuthzIndex) {
+    fn apply(
+        &mut self,
+        srv: policy::Server,
+        ns_authzs: &AuthzIndex,
+        detect_timeout: time::Duration,
+    ) {
         trace!(?srv, "Applying server");
+        Self::apply_to(&mut self.index, srv, ns_authzs, detect_timeout)
+    }
+
+    /// Update the index with the namespace's instance of a cluster server.
+    pub(crate) fn apply_cluster(
+        &mut self,
+        srv: &policy::ClusterServer,
+        ns_authzs: &AuthzIndex,
+        detect_timeout: time::Duration,
+    ) {
+        trace!(?srv, "Applying cluster server");
+        let srv = policy::Server {
+            metadata: k8s::ObjectMeta {
+                name: srv.metadata.name.clone(),
+                labels: srv.metadata.labels.clone(),
+                ..Default::default()
+            },
+            spec: policy::ServerSpec {
+                pod_selector: srv.spec.pod_selector.clone(),
+                port: srv.spec.port.clone(),
+                proxy_protocol: srv.spec.proxy_protocol.clone(),
+            },
+        };
+        Self::apply_to(&mut self.cluster, srv, ns_authzs, detect_timeout)
+    }
+
+    fn apply_to(
+        index: &mut HashMap<String, Server>,
+        srv: policy::Server,
+        ns_authzs: &AuthzIndex,
+        detect_timeout: time::Duration,
+    ) {
         let srv_name = srv.name();
         let port = srv.spec.port;
-        let protocol = Self::mk_protocol(srv.spec.proxy_protocol.as_ref());
+        let protocol = Self::mk_protocol(srv.spec.proxy_protocol.as_ref(), detect_timeout);
 
-        match self.index.entry(srv_name) {
+        match index.entry(srv_name) {
             HashEntry::Vacant(entry) => {
                 let labels = k8s::Labels::from(srv.metadata.labels);
                 let authzs = ns_authzs
                     .filter_for_server(entry.key(), labels.clone())
                     .map(|(n, a)| (n, a.clone()))
-                    .collect::<HashMap<_, _>>();
+                    .collect::<BTreeMap<_, _>>();
                 debug!(authzs = ?authzs.keys());
                 let (tx, rx) = watch::channel(InboundServer {
                     name: entry.key().clone(),
@@ -237,7 +409,7 @@ impl SrvIndex {
                         let authzs = ns_authzs
                             .filter_for_server(entry.key(), labels.clone())
                             .map(|(n, a)| (n, a.clone()))
-                            .collect::<HashMap<_, _>>();
+                            .collect::<BTreeMap<_, _>>();
                         debug!(authzs = ?authzs.keys());
                         config.authorizations = authzs.clone();
                         entry.get_mut().labels = labels;
@@ -266,10 +438,13 @@ impl SrvIndex {
         }
     }
 
-    fn mk_protocol(p: Option<&policy::server::ProxyProtocol>) -> ProxyProtocol {
+    fn mk_protocol(
+        p: Option<&policy::server::ProxyProtocol>,
+        detect_timeout: time::Duration,
+    ) -> ProxyProtocol {
         match p {
             Some(policy::server::ProxyProtocol::Unknown) | None => ProxyProtocol::Detect {
-                timeout: time::Duration::from_secs(10),
+                timeout: detect_timeout,
             },
             Some(policy::server::ProxyProtocol::Http1) => ProxyProtocol::Http1,
             Some(policy::server::ProxyProtocol::Http2) => ProxyProtocol::Http2,
@@ -320,6 +495,8 @@ mod tests {
     use linkerd_policy_controller_core::ClientAuthentication;
     use linkerd_policy_controller_k8s_api::policy::server::{Port, ProxyProtocol};
 
+    const DETECT_TIMEOUT: time::Duration = time::Duration::from_secs(10);
+
     fn mk_server(
         ns: impl Into<String>,
         name: impl Into<String>,
@@ -365,14 +542,14 @@ mod tests {
             let srv = mk_server("ns-0", "srv-0", Port::Number(9999));
             with_proxy_protocol(srv, ProxyProtocol::Opaque)
         };
-        idx.apply(srv.clone(), &AuthzIndex::default());
+        idx.apply(srv.clone(), &AuthzIndex::default(), DETECT_TIMEOUT);
 
         srv.spec.proxy_protocol = Some(ProxyProtocol::Tls);
-        idx.apply(srv.clone(), &AuthzIndex::default());
+        idx.apply(srv.clone(), &AuthzIndex::default(), DETECT_TIMEOUT);
 
         let Server { protocol, .. } = idx.index.get("srv-0").unwrap();
         assert_eq!(
-            SrvIndex::mk_protocol(srv.spec.proxy_protocol.as_ref()),
+            SrvIndex::mk_protocol(srv.spec.proxy_protocol.as_ref(), DETECT_TIMEOUT),
             protocol.to_owned()
         );
     }
@@ -386,12 +563,12 @@ mod tests {
             let srv = mk_server("ns-0", "srv-0", Port::Number(9999));
             with_srv_labels(srv, labels)
         };
-        idx.apply(srv.clone(), &AuthzIndex::default());
+        idx.apply(srv.clone(), &AuthzIndex::default(), DETECT_TIMEOUT);
 
         let mut new_labels = HashMap::new();
         new_labels.insert("not-foo", "not-bar");
         let srv = with_srv_labels(srv, new_labels);
-        idx.apply(srv.clone(), &AuthzIndex::default());
+        idx.apply(srv.clone(), &AuthzIndex::default(), DETECT_TIMEOUT);
 
         let Server { labels, .. } = idx.index.get("srv-0").unwrap();
         assert_eq!(&k8s::Labels::from(srv.metadata.labels), labels);
@@ -402,7 +579,7 @@ mod tests {
         let mut idx = {
             let mut idx = SrvIndex::default();
             let srv = mk_server("ns-0", "srv-0", Port::Number(9999));
-            idx.apply(srv, &AuthzIndex::default());
+            idx.apply(srv, &AuthzIndex::default(), DETECT_TIMEOUT);
             idx
         };
         idx.add_authz(
@@ -416,7 +593,7 @@ mod tests {
 
         let srv = idx.index.get("srv-0").unwrap();
         assert!(
-            srv.authorizations.get("authz-test").is_some(),
+            srv.authorizations.contains_key("authz-test"),
             "expected {} to be Some(...) got None",
             "authz-test"
         );
@@ -427,7 +604,7 @@ mod tests {
         let mut idx = {
             let mut idx = SrvIndex::default();
             let srv = mk_server("ns-0", "srv-0", Port::Number(9999));
-            idx.apply(srv, &AuthzIndex::default());
+            idx.apply(srv, &AuthzIndex::default(), DETECT_TIMEOUT);
             idx
         };
         idx.add_authz(
@@ -441,7 +618,7 @@ mod tests {
         idx.remove_authz("authz-test");
         let srv = idx.index.get("srv-0").unwrap();
         assert!(
-            srv.authorizations.get("authz-test").is_none(),
+            !srv.authorizations.contains_key("authz-test"),
             "expected {} to be None, got: {:?}",
             "authz-test",
             srv.authorizations.get("authz-test"),
@@ -0,0 +1,94 @@
+//! Describes each indexed `Server` with gauges labeled by the server's namespace and name.
+//!
+//! Each server reports the number of authorizations that apply to it and whether it uses the
+//! default (detected) protocol. To bound the metrics' cardinality, only the first servers (ordered
+//! by namespace and name) up to a limit are reported individually; the remaining servers are
+//! aggregated into a single series labeled `namespace="other",server="other"`.
+
+use linkerd_policy_controller_metrics::{Family, Gauge};
+use std::collections::BTreeMap;
+
+/// The label value of the series that aggregates servers beyond the limit.
+const OTHER: &str = "other";
+
+#[derive(Debug)]
+pub(crate) struct ServerMetrics {
+    pub(crate) authorizations: Family<Gauge>,
+    pub(crate) default_protocol: Family<Gauge>,
+
+    /// The maximum number of servers reported individually.
+    limit: usize,
+
+    /// The values reported for each exported series, by namespace and server name, so that the
+    /// series of removed servers are removed.
+    exported: BTreeMap<(String, String), Values>,
+}
+
+/// The values of a server's series.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+struct Values {
+    authorizations: i64,
+    default_protocol: i64,
+}
+
+// === impl ServerMetrics ===
+
+impl ServerMetrics {
+    /// The number of servers reported individually, unless configured otherwise.
+    pub(crate) const DEFAULT_LIMIT: usize = 1000;
+
+    pub(crate) fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+    }
+
+    /// Updates the gauges to describe `servers`, given as each server's namespace, name, number of
+    /// authorizations, and whether it uses the default protocol.
+    pub(crate) fn update<'s>(
+        &mut self,
+        servers: impl IntoIterator<Item = (&'s str, &'s str, usize, bool)>,
+    ) {
+        let mut servers = servers.into_iter().collect::<Vec<_>>();
+        servers.sort_unstable_by_key(|(ns, name, _, _)| (*ns, *name));
+
+        let mut values = BTreeMap::<(String, String), Values>::new();
+        for (i, (ns, name, authorizations, default_protocol)) in servers.into_iter().enumerate() {
+            let key = if i < self.limit {
+                (ns.to_string(), name.to_string())
+            } else {
+                (OTHER.to_string(), OTHER.to_string())
+            };
+            let v = values.entry(key).or_default();
+            v.authorizations += authorizations as i64;
+            v.default_protocol += default_protocol as i64;
+        }
+
+        for key in self.exported.keys() {
+            if !values.contains_key(key) {
+                let (ns, name) = (key.0.as_str(), key.1.as_str());
+                self.authorizations.remove(&[ns, name]);
+                self.default_protocol.remove(&[ns, name]);
+            }
+        }
+        for (key, v) in &values {
+            if self.exported.get(key) != Some(v) {
+                let (ns, name) = (key.0.as_str(), key.1.as_str());
+                self.authorizations.get(&[ns, name]).set(v.authorizations);
+                self.default_protocol
+                    .get(&[ns, name])
+                    .set(v.default_protocol);
+            }
+        }
+        self.exported = values;
+    }
+}
+
+impl Default for ServerMetrics {
+    fn default() -> Self {
+        Self {
+            authorizations: Family::new(&["namespace", "server"]),
+            default_protocol: Family::new(&["namespace", "server"]),
+            limit: Self::DEFAULT_LIMIT,
+            exported: BTreeMap::default(),
+        }
+    }
+}
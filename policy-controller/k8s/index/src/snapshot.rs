@@ -0,0 +1,271 @@
+use crate::{Errors, Index};
+use anyhow::{Context, Result};
+use linkerd_policy_controller_k8s_api::{self as k8s, policy, ResourceExt};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, info, warn};
+
+/// The resources observed by the index, persisted so that a restarted controller can serve
+/// policies before its watches have synced.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Snapshot {
+    #[serde(default)]
+    pub namespaces: Vec<k8s::Namespace>,
+    #[serde(default)]
+    pub pods: Vec<k8s::Pod>,
+    #[serde(default)]
+    pub servers: Vec<policy::Server>,
+    #[serde(default)]
+    pub authorizations: Vec<policy::ServerAuthorization>,
+    #[serde(default)]
+    pub cluster_servers: Vec<policy::ClusterServer>,
+}
+
+/// Requests a snapshot of the resources observed by the indexing task.
+#[derive(Clone, Debug)]
+pub struct SnapshotHandle(pub(crate) mpsc::Sender<oneshot::Sender<Snapshot>>);
+
+/// Tracks the latest version of each resource observed by the index's watches.
+#[derive(Debug, Default)]
+pub(crate) struct Recorder {
+    pub namespaces: Store<k8s::Namespace>,
+    pub pods: Store<k8s::Pod>,
+    pub servers: Store<policy::Server>,
+    pub authorizations: Store<policy::ServerAuthorization>,
+    pub cluster_servers: Store<policy::ClusterServer>,
+}
+
+/// Holds resources of a single kind by namespace and name.
+#[derive(Debug)]
+pub(crate) struct Store<K>(BTreeMap<(Option<String>, String), K>);
+
+// === impl Snapshot ===
+
+impl Snapshot {
+    /// Reads a snapshot from `path`, returning `None` if the file doesn't exist.
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        let bytes = match std::fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => {
+                return Err(error).with_context(|| format!("failed to read {}", path.display()))
+            }
+        };
+        let snapshot = serde_json::from_slice(&bytes)
+            .with_context(|| format!("failed to parse {}", path.display()))?;
+        Ok(Some(snapshot))
+    }
+
+    /// Writes the snapshot to `path`.
+    ///
+    /// The snapshot is written to a temporary file that replaces `path`, so a partially-written
+    /// snapshot is never loaded.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("tmp");
+        let bytes = serde_json::to_vec(self).context("failed to serialize snapshot")?;
+        std::fs::write(&tmp, bytes)
+            .with_context(|| format!("failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, path)
+            .with_context(|| format!("failed to replace {}", path.display()))?;
+        Ok(())
+    }
+
+    /// The total number of resources in the snapshot.
+    pub fn len(&self) -> usize {
+        self.namespaces.len()
+            + self.pods.len()
+            + self.servers.len()
+            + self.authorizations.len()
+            + self.cluster_servers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// === impl SnapshotHandle ===
+
+impl SnapshotHandle {
+    /// Snapshots the index's resources, or returns `None` if the index is no longer running.
+    pub async fn snapshot(&self) -> Option<Snapshot> {
+        let (tx, rx) = oneshot::channel();
+        self.0.send(tx).await.ok()?;
+        rx.await.ok()
+    }
+}
+
+// === impl Recorder ===
+
+impl Recorder {
+    pub(crate) fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            namespaces: self.namespaces.values(),
+            pods: self.pods.values(),
+            servers: self.servers.values(),
+            authorizations: self.authorizations.values(),
+            cluster_servers: self.cluster_servers.values(),
+        }
+    }
+}
+
+// === impl Store ===
+
+impl<K> Default for Store<K> {
+    fn default() -> Self {
+        Self(BTreeMap::new())
+    }
+}
+
+impl<K: ResourceExt + Clone> Store<K> {
+    pub(crate) fn update(&mut self, event: &k8s::Event<K>) {
+        match event {
+            k8s::Event::Applied(obj) => {
+                self.0.insert((obj.namespace(), obj.name()), obj.clone());
+            }
+            k8s::Event::Deleted(obj) => {
+                self.0.remove(&(obj.namespace(), obj.name()));
+            }
+            k8s::Event::Restarted(objs) => {
+                self.0 = objs
+                    .iter()
+                    .map(|obj| ((obj.namespace(), obj.name()), obj.clone()))
+                    .collect();
+            }
+        }
+    }
+
+    fn values(&self) -> Vec<K> {
+        self.0.values().cloned().collect()
+    }
+}
+
+// === impl Index ===
+
+impl Index {
+    /// Returns a handle that snapshots the resources observed by the index.
+    ///
+    /// Resources are only recorded once a handle has been obtained, so this must be called before
+    /// the index is run.
+    pub fn snapshot_handle(&mut self) -> SnapshotHandle {
+        self.recorder.get_or_insert_with(Default::default);
+        self.snapshot_handle.clone()
+    }
+
+    /// Seeds the index with the resources from a prior snapshot, so that lookups can be served
+    /// before the index's watches have synced.
+    ///
+    /// This does not mark the index as ready. Once each watch syncs, the resources it lists
+    /// replace those from the snapshot, so stale resources are updated or removed.
+    pub fn seed(&mut self, snapshot: Snapshot) {
+        info!(resources = snapshot.len(), "Seeding index from snapshot");
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder
+                .namespaces
+                .update(&k8s::Event::Restarted(snapshot.namespaces.clone()));
+            recorder
+                .pods
+                .update(&k8s::Event::Restarted(snapshot.pods.clone()));
+            recorder
+                .servers
+                .update(&k8s::Event::Restarted(snapshot.servers.clone()));
+            recorder
+                .authorizations
+                .update(&k8s::Event::Restarted(snapshot.authorizations.clone()));
+            recorder
+                .cluster_servers
+                .update(&k8s::Event::Restarted(snapshot.cluster_servers.clone()));
+        }
+
+        self.reset_namespaces(snapshot.namespaces);
+        let mut errors = vec![];
+        if let Err(error) = self.reset_pods(snapshot.pods) {
+            errors.push(error);
+        }
+        if let Err(error) = self.reset_servers(snapshot.servers) {
+            errors.push(error);
+        }
+        if let Err(error) = self.reset_cluster_servers(snapshot.cluster_servers) {
+            errors.push(error);
+        }
+        if let Err(error) = self.reset_authzs(snapshot.authorizations) {
+            errors.push(error);
+        }
+        match Errors::ok_if_empty(errors) {
+            Ok(()) => debug!("Seeded index"),
+            Err(error) => warn!(?error, "Failed to seed index from snapshot"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut recorder = Recorder::default();
+        recorder.pods.update(&k8s::Event::Restarted(vec![
+            mk_pod("ns-0", "pod-0"),
+            mk_pod("ns-0", "pod-1"),
+        ]));
+        recorder
+            .pods
+            .update(&k8s::Event::Deleted(mk_pod("ns-0", "pod-1")));
+        recorder
+            .pods
+            .update(&k8s::Event::Applied(mk_pod("ns-1", "pod-0")));
+        let snapshot = recorder.snapshot();
+        assert_eq!(snapshot.len(), 2);
+
+        let path = std::env::temp_dir().join(format!(
+            "policy-snapshot-{}-round-trip.json",
+            std::process::id()
+        ));
+        assert!(Snapshot::load(&path).unwrap().is_none(), "must not exist");
+        snapshot.write(&path).unwrap();
+        let loaded = Snapshot::load(&path).unwrap().expect("snapshot must exist");
+        std::fs::remove_file(&path).unwrap();
+
+        let names = loaded
+            .pods
+            .iter()
+            .map(|p| (p.namespace().unwrap(), p.name()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                ("ns-0".to_string(), "pod-0".to_string()),
+                ("ns-1".to_string(), "pod-0".to_string())
+            ]
+        );
+        assert!(loaded.namespaces.is_empty());
+        assert!(loaded.servers.is_empty());
+        assert!(loaded.authorizations.is_empty());
+        assert!(loaded.cluster_servers.is_empty());
+    }
+
+    #[test]
+    fn load_invalid() {
+        let path = std::env::temp_dir().join(format!(
+            "policy-snapshot-{}-invalid.json",
+            std::process::id()
+        ));
+        std::fs::write(&path, "not json").unwrap();
+        let res = Snapshot::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(res.is_err());
+    }
+
+    fn mk_pod(ns: &str, name: &str) -> k8s::Pod {
+        k8s::Pod {
+            metadata: k8s::ObjectMeta {
+                namespace: Some(ns.to_string()),
+                name: Some(name.to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+}
@@ -5,8 +5,9 @@ use linkerd_policy_controller_core::{
     NetworkMatch, ProxyProtocol,
 };
 use linkerd_policy_controller_k8s_api::{policy::server::Port, ResourceExt};
-use std::{collections::HashMap, net::IpAddr, str::FromStr};
-use tokio::time;
+use linkerd_policy_controller_metrics::Registry;
+use std::{collections::BTreeMap, net::IpAddr, str::FromStr};
+use tokio::{sync::mpsc, time};
 
 /// Creates a pod, then a server, then an authorization--then deletes these resources in the reverse
 /// order--checking the server watch is updated at each step.
@@ -136,63 +137,1173 @@ async fn incrementally_configure_server() {
     );
 }
 
+/// Checks that readiness is only signaled once every watch has completed its initial sync.
+#[tokio::test]
+async fn ready_after_initial_sync() {
+    let cluster = ClusterInfo {
+        networks: vec![IpNet::from_str("192.0.2.0/24").unwrap()],
+        control_plane_ns: "linkerd".to_string(),
+        identity_domain: "cluster.example.com".into(),
+    };
+    let (_lookup_rx, idx) = Index::new(
+        cluster,
+        DefaultPolicy::Allow {
+            authenticated_only: false,
+            cluster_only: true,
+        },
+        time::Duration::from_secs(1),
+    );
+
+    let (namespaces_tx, namespaces_rx) = mpsc::unbounded_channel();
+    let (pods_tx, pods_rx) = mpsc::unbounded_channel();
+    let (servers_tx, servers_rx) = mpsc::unbounded_channel();
+    let (authzs_tx, authzs_rx) = mpsc::unbounded_channel();
+    let (ready_tx, mut ready_rx) = watch::channel(false);
+    tokio::spawn(idx.run(
+        k8s::ResourceWatches {
+            namespaces_rx: mk_watch(namespaces_rx),
+            pods_rx: mk_watch(pods_rx),
+            servers_rx: mk_watch(servers_rx),
+            authorizations_rx: mk_watch(authzs_rx),
+            cluster_servers_rx: k8s::Watch::unwatched(),
+        },
+        ready_tx,
+    ));
+
+    // Individual updates don't constitute a sync, nor does syncing only some resource kinds.
+    authzs_tx
+        .send(k8s::Event::Applied(mk_authz(
+            "ns-0",
+            "authz-0",
+            "srv-0",
+            Default::default(),
+        )))
+        .unwrap();
+    namespaces_tx.send(k8s::Event::Restarted(vec![])).unwrap();
+    pods_tx.send(k8s::Event::Restarted(vec![])).unwrap();
+    servers_tx
+        .send(k8s::Event::Restarted(vec![mk_server(
+            "ns-0",
+            "srv-0",
+            Port::Number(2222),
+            None,
+            None,
+        )]))
+        .unwrap();
+    assert!(
+        time::timeout(time::Duration::from_millis(100), ready_rx.changed())
+            .await
+            .is_err(),
+        "must not be ready before authorizations are synced"
+    );
+    assert!(!*ready_rx.borrow());
+
+    authzs_tx.send(k8s::Event::Restarted(vec![])).unwrap();
+    time::timeout(time::Duration::from_secs(1), ready_rx.changed())
+        .await
+        .expect("must become ready after all resources are synced")
+        .unwrap();
+    assert!(*ready_rx.borrow());
+}
+
+/// Checks that the index syncs and serves policies when ServerAuthorizations aren't watched.
+#[tokio::test]
+async fn indexes_without_authorizations() {
+    let cluster = ClusterInfo {
+        networks: vec![IpNet::from_str("192.0.2.0/24").unwrap()],
+        control_plane_ns: "linkerd".to_string(),
+        identity_domain: "cluster.example.com".into(),
+    };
+    let (lookup_rx, idx) = Index::new(cluster, DefaultPolicy::Deny, time::Duration::from_secs(1));
+
+    let (namespaces_tx, namespaces_rx) = mpsc::unbounded_channel();
+    let (pods_tx, pods_rx) = mpsc::unbounded_channel();
+    let (servers_tx, servers_rx) = mpsc::unbounded_channel();
+    let (ready_tx, mut ready_rx) = watch::channel(false);
+    tokio::spawn(idx.run(
+        k8s::ResourceWatches {
+            namespaces_rx: mk_watch(namespaces_rx),
+            pods_rx: mk_watch(pods_rx),
+            servers_rx: mk_watch(servers_rx),
+            authorizations_rx: k8s::Watch::unwatched(),
+            cluster_servers_rx: k8s::Watch::unwatched(),
+        },
+        ready_tx,
+    ));
+
+    namespaces_tx.send(k8s::Event::Restarted(vec![])).unwrap();
+    pods_tx
+        .send(k8s::Event::Restarted(vec![mk_pod(
+            "ns-0",
+            "pod-0",
+            "node-0",
+            "192.0.2.2".parse().unwrap(),
+            Some(("container-0", vec![2222])),
+        )]))
+        .unwrap();
+    servers_tx
+        .send(k8s::Event::Restarted(vec![mk_server(
+            "ns-0",
+            "srv-0",
+            Port::Number(2222),
+            None,
+            None,
+        )]))
+        .unwrap();
+    time::timeout(time::Duration::from_secs(1), ready_rx.changed())
+        .await
+        .expect("must become ready without authorizations")
+        .unwrap();
+
+    let port2222 = lookup_rx.lookup("ns-0", "pod-0", 2222).unwrap();
+    assert_eq!(port2222.get().name, "srv-0");
+    assert!(port2222.get().authorizations.is_empty());
+}
+
+/// Checks that, with the strict network check, the index is not ready while a pod's IP is outside
+/// of the cluster networks.
+#[tokio::test]
+async fn strict_network_check_withholds_readiness() {
+    let cluster = ClusterInfo {
+        networks: vec![IpNet::from_str("192.0.2.0/24").unwrap()],
+        control_plane_ns: "linkerd".to_string(),
+        identity_domain: "cluster.example.com".into(),
+    };
+    let (_lookup_rx, mut idx) = Index::new(
+        cluster,
+        DefaultPolicy::Allow {
+            authenticated_only: false,
+            cluster_only: true,
+        },
+        time::Duration::from_secs(1),
+    );
+    let metrics = Registry::default();
+    idx.register_metrics(&metrics);
+    idx.set_strict_network_check(true);
+
+    let (namespaces_tx, namespaces_rx) = mpsc::unbounded_channel();
+    let (pods_tx, pods_rx) = mpsc::unbounded_channel();
+    let (servers_tx, servers_rx) = mpsc::unbounded_channel();
+    let (authzs_tx, authzs_rx) = mpsc::unbounded_channel();
+    let (ready_tx, mut ready_rx) = watch::channel(false);
+    tokio::spawn(idx.run(
+        k8s::ResourceWatches {
+            namespaces_rx: mk_watch(namespaces_rx),
+            pods_rx: mk_watch(pods_rx),
+            servers_rx: mk_watch(servers_rx),
+            authorizations_rx: mk_watch(authzs_rx),
+            cluster_servers_rx: k8s::Watch::unwatched(),
+        },
+        ready_tx,
+    ));
+
+    let uncovered = mk_pod(
+        "ns-0",
+        "pod-1",
+        "node-0",
+        IpAddr::from([198, 51, 100, 1]),
+        Some(("container-0", vec![8080])),
+    );
+    namespaces_tx.send(k8s::Event::Restarted(vec![])).unwrap();
+    pods_tx
+        .send(k8s::Event::Restarted(vec![
+            mk_pod(
+                "ns-0",
+                "pod-0",
+                "node-0",
+                IpAddr::from([192, 0, 2, 2]),
+                Some(("container-0", vec![8080])),
+            ),
+            uncovered.clone(),
+        ]))
+        .unwrap();
+    servers_tx.send(k8s::Event::Restarted(vec![])).unwrap();
+    authzs_tx.send(k8s::Event::Restarted(vec![])).unwrap();
+    assert!(
+        time::timeout(time::Duration::from_millis(100), ready_rx.changed())
+            .await
+            .is_err(),
+        "must not be ready while a pod IP is uncovered"
+    );
+    assert!(!*ready_rx.borrow());
+    assert!(metrics.encode().contains("index_uncovered_pod_ips 1"));
+
+    pods_tx.send(k8s::Event::Deleted(uncovered)).unwrap();
+    time::timeout(time::Duration::from_secs(1), ready_rx.changed())
+        .await
+        .expect("must become ready once all pod IPs are covered")
+        .unwrap();
+    assert!(*ready_rx.borrow());
+    assert!(metrics.encode().contains("index_uncovered_pod_ips 0"));
+}
+
+/// Checks that the index's state is described by resource name within each namespace.
+#[tokio::test]
+async fn dumps_index_state() {
+    let cluster = ClusterInfo {
+        networks: vec![IpNet::from_str("192.0.2.0/24").unwrap()],
+        control_plane_ns: "linkerd".to_string(),
+        identity_domain: "cluster.example.com".into(),
+    };
+    let (_lookup_rx, idx) = Index::new(
+        cluster,
+        DefaultPolicy::Allow {
+            authenticated_only: false,
+            cluster_only: true,
+        },
+        time::Duration::from_secs(1),
+    );
+    let dumps = idx.dump_handle();
+
+    let (namespaces_tx, namespaces_rx) = mpsc::unbounded_channel();
+    let (pods_tx, pods_rx) = mpsc::unbounded_channel();
+    let (servers_tx, servers_rx) = mpsc::unbounded_channel();
+    let (authzs_tx, authzs_rx) = mpsc::unbounded_channel();
+    let (ready_tx, mut ready_rx) = watch::channel(false);
+    tokio::spawn(idx.run(
+        k8s::ResourceWatches {
+            namespaces_rx: mk_watch(namespaces_rx),
+            pods_rx: mk_watch(pods_rx),
+            servers_rx: mk_watch(servers_rx),
+            authorizations_rx: mk_watch(authzs_rx),
+            cluster_servers_rx: k8s::Watch::unwatched(),
+        },
+        ready_tx,
+    ));
+
+    namespaces_tx
+        .send(k8s::Event::Restarted(vec![mk_namespace(
+            "ns-0",
+            Some("all-authenticated"),
+        )]))
+        .unwrap();
+    pods_tx
+        .send(k8s::Event::Restarted(vec![mk_pod(
+            "ns-0",
+            "pod-0",
+            "node-0",
+            "192.0.2.2".parse().unwrap(),
+            Some(("container-0", vec![2222, 9999])),
+        )]))
+        .unwrap();
+    servers_tx
+        .send(k8s::Event::Restarted(vec![mk_server(
+            "ns-0",
+            "srv-0",
+            Port::Number(2222),
+            None,
+            None,
+        )]))
+        .unwrap();
+    authzs_tx
+        .send(k8s::Event::Restarted(vec![mk_authz(
+            "ns-0",
+            "authz-0",
+            "srv-0",
+            k8s::policy::authz::Client {
+                unauthenticated: true,
+                ..Default::default()
+            },
+        )]))
+        .unwrap();
+    time::timeout(time::Duration::from_secs(1), ready_rx.changed())
+        .await
+        .expect("must become ready after all resources are synced")
+        .unwrap();
+
+    let dump = dumps.dump().await.expect("index must be running");
+    let ns = &dump["ns-0"];
+    assert_eq!(ns["default_policy"], "all-authenticated");
+
+    let pod = &ns["pods"]["pod-0"];
+    assert_eq!(pod["default_policy"], "all-authenticated");
+    assert_eq!(pod["ports"]["2222"]["server"], "srv-0");
+    assert_eq!(pod["ports"]["9999"]["server"], serde_json::Value::Null);
+    assert_eq!(pod["ports"]["9999"]["default"], "default:all-authenticated");
+
+    let srv = &ns["servers"]["srv-0"];
+    assert_eq!(srv["port"], 2222);
+    assert_eq!(srv["authorizations"], serde_json::json!(["authz-0"]));
+
+    let authz = &ns["authorizations"]["authz-0"];
+    assert_eq!(authz["servers"]["name"], "srv-0");
+    assert_eq!(authz["networks"][0]["net"], "192.0.2.0/24");
+    assert_eq!(authz["authentication"], "Unauthenticated");
+}
+
+/// Checks that namespaces' labels are shared as namespaces are applied, deleted, and re-listed.
+#[test]
+fn shares_namespace_labels() {
+    let cluster = ClusterInfo {
+        networks: vec![IpNet::from_str("192.0.2.0/24").unwrap()],
+        control_plane_ns: "linkerd".to_string(),
+        identity_domain: "cluster.example.com".into(),
+    };
+    let (_lookup_rx, mut idx) = Index::new(
+        cluster,
+        DefaultPolicy::Allow {
+            authenticated_only: false,
+            cluster_only: true,
+        },
+        time::Duration::from_secs(1),
+    );
+    let labels = idx.namespace_labels_handle();
+    let mk_ns = |name: &str, env: &str| {
+        let mut ns = mk_namespace(name, None);
+        ns.metadata.labels = Some(
+            Some(("env".to_string(), env.to_string()))
+                .into_iter()
+                .collect(),
+        );
+        ns
+    };
+    let env = |name: &str| {
+        labels
+            .get(name)
+            .and_then(|labels| labels.as_ref().get("env").cloned())
+    };
+
+    idx.apply_namespace(mk_ns("ns-0", "prod"));
+    assert_eq!(env("ns-0").as_deref(), Some("prod"));
+    assert_eq!(env("ns-1"), None);
+
+    idx.apply_namespace(mk_ns("ns-0", "dev"));
+    assert_eq!(env("ns-0").as_deref(), Some("dev"));
+
+    idx.delete_namespace(mk_ns("ns-0", "dev"));
+    assert!(labels.get("ns-0").is_none());
+
+    // Namespaces that aren't re-listed are removed.
+    idx.apply_namespace(mk_ns("ns-0", "prod"));
+    idx.reset_namespaces(vec![mk_ns("ns-1", "prod")]);
+    assert!(labels.get("ns-0").is_none());
+    assert_eq!(env("ns-1").as_deref(), Some("prod"));
+}
+
+/// Checks that an index seeded from a snapshot serves the snapshot's policies until its watches
+/// sync, at which point stale resources are replaced.
+#[tokio::test]
+async fn seeded_snapshot_reconciled() {
+    let cluster = ClusterInfo {
+        networks: vec![IpNet::from_str("192.0.2.0/24").unwrap()],
+        control_plane_ns: "linkerd".to_string(),
+        identity_domain: "cluster.example.com".into(),
+    };
+    let default = DefaultPolicy::Allow {
+        authenticated_only: false,
+        cluster_only: true,
+    };
+    let (lookup_rx, mut idx) = Index::new(cluster, default, time::Duration::from_secs(1));
+    let snapshots = idx.snapshot_handle();
+    idx.seed(Snapshot {
+        pods: vec![
+            mk_pod(
+                "ns-0",
+                "pod-0",
+                "node-0",
+                "192.0.2.2".parse().unwrap(),
+                Some(("container-0", vec![2222])),
+            ),
+            mk_pod(
+                "ns-0",
+                "pod-1",
+                "node-0",
+                "192.0.2.3".parse().unwrap(),
+                Some(("container-0", vec![2222])),
+            ),
+        ],
+        servers: vec![mk_server("ns-0", "srv-0", Port::Number(2222), None, None)],
+        ..Default::default()
+    });
+
+    // The snapshot's policies are served before the index is synced.
+    let port2222 = lookup_rx.lookup("ns-0", "pod-0", 2222).unwrap();
+    assert_eq!(port2222.get().name, "srv-0");
+    assert!(lookup_rx.lookup("ns-0", "pod-1", 2222).is_some());
+
+    let (namespaces_tx, namespaces_rx) = mpsc::unbounded_channel();
+    let (pods_tx, pods_rx) = mpsc::unbounded_channel();
+    let (servers_tx, servers_rx) = mpsc::unbounded_channel();
+    let (authzs_tx, authzs_rx) = mpsc::unbounded_channel();
+    let (ready_tx, mut ready_rx) = watch::channel(false);
+    tokio::spawn(idx.run(
+        k8s::ResourceWatches {
+            namespaces_rx: mk_watch(namespaces_rx),
+            pods_rx: mk_watch(pods_rx),
+            servers_rx: mk_watch(servers_rx),
+            authorizations_rx: mk_watch(authzs_rx),
+            cluster_servers_rx: k8s::Watch::unwatched(),
+        },
+        ready_tx,
+    ));
+
+    // The server and one of the pods were deleted while the controller was down.
+    namespaces_tx.send(k8s::Event::Restarted(vec![])).unwrap();
+    pods_tx
+        .send(k8s::Event::Restarted(vec![mk_pod(
+            "ns-0",
+            "pod-0",
+            "node-0",
+            "192.0.2.2".parse().unwrap(),
+            Some(("container-0", vec![2222])),
+        )]))
+        .unwrap();
+    servers_tx.send(k8s::Event::Restarted(vec![])).unwrap();
+    authzs_tx.send(k8s::Event::Restarted(vec![])).unwrap();
+    time::timeout(time::Duration::from_secs(1), ready_rx.changed())
+        .await
+        .expect("must become ready after all resources are synced")
+        .unwrap();
+
+    assert_eq!(port2222.get().name, format!("default:{}", default));
+    assert!(lookup_rx.lookup("ns-0", "pod-1", 2222).is_none());
+
+    // Snapshots reflect the synced resources, not the seeded ones.
+    let snapshot = snapshots.snapshot().await.expect("index must be running");
+    assert_eq!(snapshot.pods.len(), 1);
+    assert_eq!(snapshot.pods[0].name(), "pod-0");
+    assert!(snapshot.servers.is_empty());
+}
+
+/// Checks that a resource that panics the index is skipped and that later resources are indexed.
+#[tokio::test]
+async fn survives_processing_panics() {
+    let cluster = ClusterInfo {
+        networks: vec![IpNet::from_str("192.0.2.0/24").unwrap()],
+        control_plane_ns: "linkerd".to_string(),
+        identity_domain: "cluster.example.com".into(),
+    };
+    let default = DefaultPolicy::Allow {
+        authenticated_only: false,
+        cluster_only: true,
+    };
+    let (lookup_rx, mut idx) = Index::new(cluster, default, time::Duration::from_secs(1));
+    let metrics = Registry::default();
+    idx.register_metrics(&metrics);
+
+    let (namespaces_tx, namespaces_rx) = mpsc::unbounded_channel();
+    let (pods_tx, pods_rx) = mpsc::unbounded_channel();
+    let (servers_tx, servers_rx) = mpsc::unbounded_channel();
+    let (authzs_tx, authzs_rx) = mpsc::unbounded_channel();
+    let (ready_tx, _ready_rx) = watch::channel(false);
+    tokio::spawn(idx.run(
+        k8s::ResourceWatches {
+            namespaces_rx: mk_watch(namespaces_rx),
+            pods_rx: mk_watch(pods_rx),
+            servers_rx: mk_watch(servers_rx),
+            authorizations_rx: mk_watch(authzs_rx),
+            cluster_servers_rx: k8s::Watch::unwatched(),
+        },
+        ready_tx,
+    ));
+    namespaces_tx.send(k8s::Event::Restarted(vec![])).unwrap();
+    servers_tx.send(k8s::Event::Restarted(vec![])).unwrap();
+    authzs_tx.send(k8s::Event::Restarted(vec![])).unwrap();
+
+    // The index expects pods to have a namespace.
+    let mut malformed = mk_pod(
+        "ns-0",
+        "pod-0",
+        "node-0",
+        "192.0.2.2".parse().unwrap(),
+        Some(("container-0", vec![2222])),
+    );
+    malformed.metadata.namespace = None;
+    pods_tx.send(k8s::Event::Applied(malformed)).unwrap();
+    pods_tx
+        .send(k8s::Event::Applied(mk_pod(
+            "ns-0",
+            "pod-1",
+            "node-0",
+            "192.0.2.3".parse().unwrap(),
+            Some(("container-0", vec![2222])),
+        )))
+        .unwrap();
+
+    time::timeout(time::Duration::from_secs(1), async {
+        while lookup_rx.lookup("ns-0", "pod-1", 2222).is_none() {
+            time::sleep(time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("pods must be indexed after a panic");
+    assert!(metrics.encode().contains("index_processing_errors_total 1"));
+}
+
+/// Checks that brief processing failures don't affect readiness, but failures that persist for the
+/// unready grace period do.
+#[tokio::test]
+async fn unready_grace_period() {
+    let cluster = ClusterInfo {
+        networks: vec![IpNet::from_str("192.0.2.0/24").unwrap()],
+        control_plane_ns: "linkerd".to_string(),
+        identity_domain: "cluster.example.com".into(),
+    };
+    let (lookup_rx, mut idx) =
+        Index::new(cluster, DefaultPolicy::Deny, time::Duration::from_secs(1));
+    let grace_period = time::Duration::from_millis(100);
+    idx.set_unready_grace_period(grace_period);
+
+    let (namespaces_tx, namespaces_rx) = mpsc::unbounded_channel();
+    let (pods_tx, pods_rx) = mpsc::unbounded_channel();
+    let (servers_tx, servers_rx) = mpsc::unbounded_channel();
+    let (authzs_tx, authzs_rx) = mpsc::unbounded_channel();
+    let (ready_tx, mut ready_rx) = watch::channel(false);
+    tokio::spawn(idx.run(
+        k8s::ResourceWatches {
+            namespaces_rx: mk_watch(namespaces_rx),
+            pods_rx: mk_watch(pods_rx),
+            servers_rx: mk_watch(servers_rx),
+            authorizations_rx: mk_watch(authzs_rx),
+            cluster_servers_rx: k8s::Watch::unwatched(),
+        },
+        ready_tx,
+    ));
+    namespaces_tx.send(k8s::Event::Restarted(vec![])).unwrap();
+    pods_tx.send(k8s::Event::Restarted(vec![])).unwrap();
+    servers_tx.send(k8s::Event::Restarted(vec![])).unwrap();
+    authzs_tx.send(k8s::Event::Restarted(vec![])).unwrap();
+    time::timeout(time::Duration::from_secs(1), ready_rx.changed())
+        .await
+        .expect("must become ready after all resources are synced")
+        .unwrap();
+
+    // The index expects pods to have a namespace.
+    let malformed = || {
+        let mut pod = mk_pod(
+            "ns-0",
+            "pod-0",
+            "node-0",
+            "192.0.2.2".parse().unwrap(),
+            Some(("container-0", vec![2222])),
+        );
+        pod.metadata.namespace = None;
+        pod
+    };
+    let apply_pod = |name: &str, ip: &str| {
+        pods_tx
+            .send(k8s::Event::Applied(mk_pod(
+                "ns-0",
+                name,
+                "node-0",
+                ip.parse().unwrap(),
+                Some(("container-0", vec![2222])),
+            )))
+            .unwrap();
+    };
+    let indexed = |name: &'static str| {
+        let lookup_rx = lookup_rx.clone();
+        async move {
+            time::timeout(time::Duration::from_secs(1), async {
+                while lookup_rx.lookup("ns-0", name, 2222).is_none() {
+                    time::sleep(time::Duration::from_millis(10)).await;
+                }
+            })
+            .await
+            .expect("pod must be indexed")
+        }
+    };
+
+    // A failure followed by a successful update doesn't affect readiness, even after the grace
+    // period elapses.
+    pods_tx.send(k8s::Event::Applied(malformed())).unwrap();
+    apply_pod("pod-1", "192.0.2.3");
+    indexed("pod-1").await;
+    time::sleep(grace_period * 2).await;
+    apply_pod("pod-2", "192.0.2.4");
+    indexed("pod-2").await;
+    assert!(*ready_rx.borrow());
+
+    // Failures that persist for the grace period withhold readiness.
+    pods_tx.send(k8s::Event::Applied(malformed())).unwrap();
+    time::sleep(grace_period * 2).await;
+    pods_tx.send(k8s::Event::Applied(malformed())).unwrap();
+    time::timeout(time::Duration::from_secs(1), ready_rx.changed())
+        .await
+        .expect("must not be ready while processing fails")
+        .unwrap();
+    assert!(!*ready_rx.borrow());
+
+    // The index is ready again once an update succeeds.
+    apply_pod("pod-3", "192.0.2.5");
+    time::timeout(time::Duration::from_secs(1), ready_rx.changed())
+        .await
+        .expect("must become ready once processing succeeds")
+        .unwrap();
+    assert!(*ready_rx.borrow());
+
+    // A failure withholds readiness once the grace period elapses, even if no further events are
+    // received.
+    pods_tx.send(k8s::Event::Applied(malformed())).unwrap();
+    time::timeout(time::Duration::from_secs(1), ready_rx.changed())
+        .await
+        .expect("must not be ready once the grace period elapses")
+        .unwrap();
+    assert!(!*ready_rx.borrow());
+}
+
+#[test]
+fn server_update_deselects_pod() {
+    let cluster_net = IpNet::from_str("192.0.2.0/24").unwrap();
+    let cluster = ClusterInfo {
+        networks: vec![cluster_net],
+        control_plane_ns: "linkerd".to_string(),
+        identity_domain: "cluster.example.com".into(),
+    };
+    let pod_net = IpNet::from_str("192.0.2.2/28").unwrap();
+    let detect_timeout = time::Duration::from_secs(1);
+    let default = DefaultPolicy::Allow {
+        authenticated_only: false,
+        cluster_only: true,
+    };
+    let (lookup_rx, mut idx) = Index::new(cluster, default, detect_timeout);
+
+    let p = mk_pod(
+        "ns-0",
+        "pod-0",
+        "node-0",
+        pod_net.hosts().next().unwrap(),
+        Some(("container-0", vec![2222])),
+    );
+    idx.apply_pod(p).unwrap();
+
+    let srv = {
+        let mut srv = mk_server("ns-0", "srv-0", Port::Number(2222), None, None);
+        srv.spec.proxy_protocol = Some(k8s::policy::server::ProxyProtocol::Http2);
+        srv
+    };
+    idx.apply_server(srv.clone());
+
+    // The default policy applies for all exposed ports.
+    let port2222 = lookup_rx.lookup("ns-0", "pod-0", 2222).unwrap();
+    assert_eq!(
+        port2222.get(),
+        InboundServer {
+            name: "srv-0".into(),
+            protocol: ProxyProtocol::Http2,
+            authorizations: Default::default(),
+        }
+    );
+
+    idx.apply_server({
+        let mut srv = srv;
+        srv.spec.pod_selector = Some(("label", "value")).into_iter().collect();
+        srv
+    });
+    assert_eq!(
+        port2222.get(),
+        InboundServer {
+            name: format!("default:{}", default),
+            authorizations: mk_default_policy(default, cluster_net),
+            protocol: ProxyProtocol::Detect {
+                timeout: detect_timeout,
+            },
+        }
+    );
+}
+
+/// Checks that a server that selects a port by name applies to whichever port each pod gives that
+/// name, and that pods without a port of that name retain their default policy.
+#[test]
+fn server_named_port() {
+    let cluster_net = IpNet::from_str("192.0.2.0/24").unwrap();
+    let cluster = ClusterInfo {
+        networks: vec![cluster_net],
+        control_plane_ns: "linkerd".to_string(),
+        identity_domain: "cluster.example.com".into(),
+    };
+    let pod_net = IpNet::from_str("192.0.2.2/28").unwrap();
+    let detect_timeout = time::Duration::from_secs(1);
+    let default = DefaultPolicy::Allow {
+        authenticated_only: false,
+        cluster_only: true,
+    };
+    let (lookup_rx, mut idx) = Index::new(cluster, default, detect_timeout);
+
+    let mut hosts = pod_net.hosts();
+    idx.apply_pod(mk_named_port_pod(
+        "pod-0",
+        hosts.next().unwrap(),
+        8080,
+        Some("http"),
+    ))
+    .unwrap();
+    idx.apply_pod(mk_named_port_pod(
+        "pod-1",
+        hosts.next().unwrap(),
+        8080,
+        None,
+    ))
+    .unwrap();
+
+    // The server is applied after the first pods and before the last, so that named ports are
+    // resolved as both servers and pods are indexed.
+    idx.apply_server(mk_server(
+        "ns-0",
+        "srv-0",
+        Port::Name("http".into()),
+        None,
+        None,
+    ));
+    idx.apply_pod(mk_named_port_pod(
+        "pod-2",
+        hosts.next().unwrap(),
+        8080,
+        Some("http"),
+    ))
+    .unwrap();
+
+    for pod in &["pod-0", "pod-2"] {
+        assert_eq!(
+            lookup_rx.lookup("ns-0", pod, 8080).unwrap().get().name,
+            "srv-0",
+            "{}",
+            pod
+        );
+    }
+    assert_eq!(
+        lookup_rx.lookup("ns-0", "pod-1", 8080).unwrap().get(),
+        InboundServer {
+            name: format!("default:{}", default),
+            authorizations: mk_default_policy(default, cluster_net),
+            protocol: ProxyProtocol::Detect {
+                timeout: detect_timeout,
+            },
+        }
+    );
+
+    let ns = idx.namespaces.index.get("ns-0").unwrap();
+    assert!(ns.pods.port_name_conflicts(&ns.servers).is_empty());
+}
+
+/// Checks that a server that selects a port by name is linked to each pod's own port when the pods
+/// give that name to different ports, and that the conflicting mapping is reported.
+#[test]
+fn server_named_port_conflict() {
+    let cluster_net = IpNet::from_str("192.0.2.0/24").unwrap();
+    let cluster = ClusterInfo {
+        networks: vec![cluster_net],
+        control_plane_ns: "linkerd".to_string(),
+        identity_domain: "cluster.example.com".into(),
+    };
+    let pod_net = IpNet::from_str("192.0.2.2/28").unwrap();
+    let detect_timeout = time::Duration::from_secs(1);
+    let default = DefaultPolicy::Allow {
+        authenticated_only: false,
+        cluster_only: true,
+    };
+    let (lookup_rx, mut idx) = Index::new(cluster, default, detect_timeout);
+
+    let mut hosts = pod_net.hosts();
+    idx.apply_pod(mk_named_port_pod(
+        "pod-0",
+        hosts.next().unwrap(),
+        8080,
+        Some("http"),
+    ))
+    .unwrap();
+    idx.apply_pod(mk_named_port_pod(
+        "pod-1",
+        hosts.next().unwrap(),
+        9090,
+        Some("http"),
+    ))
+    .unwrap();
+    idx.apply_server(mk_server(
+        "ns-0",
+        "srv-0",
+        Port::Name("http".into()),
+        None,
+        None,
+    ));
+
+    assert_eq!(
+        lookup_rx.lookup("ns-0", "pod-0", 8080).unwrap().get().name,
+        "srv-0"
+    );
+    assert_eq!(
+        lookup_rx.lookup("ns-0", "pod-1", 9090).unwrap().get().name,
+        "srv-0"
+    );
+
+    let ns = idx.namespaces.index.get("ns-0").unwrap();
+    assert_eq!(
+        ns.pods.port_name_conflicts(&ns.servers),
+        vec![("srv-0", "http", vec![8080, 9090].into_iter().collect())]
+    );
+}
+
+/// Checks that a server's protocol hint is served for its port, and that detection is used when the
+/// hint is unset or unrecognized.
+#[test]
+fn server_protocol_hints() {
+    let cluster_net = IpNet::from_str("192.0.2.0/24").unwrap();
+    let cluster = ClusterInfo {
+        networks: vec![cluster_net],
+        control_plane_ns: "linkerd".to_string(),
+        identity_domain: "cluster.example.com".into(),
+    };
+    let pod_net = IpNet::from_str("192.0.2.2/28").unwrap();
+    let detect_timeout = time::Duration::from_secs(1);
+    let default = DefaultPolicy::Allow {
+        authenticated_only: false,
+        cluster_only: true,
+    };
+    let (lookup_rx, mut idx) = Index::new(cluster, default, detect_timeout);
+
+    let p = mk_pod(
+        "ns-0",
+        "pod-0",
+        "node-0",
+        pod_net.hosts().next().unwrap(),
+        Some(("container-0", vec![2222])),
+    );
+    idx.apply_pod(p).unwrap();
+    let port2222 = lookup_rx.lookup("ns-0", "pod-0", 2222).unwrap();
+
+    let detect = ProxyProtocol::Detect {
+        timeout: detect_timeout,
+    };
+    for (hint, protocol) in [
+        (None, detect.clone()),
+        (Some("unknown"), detect.clone()),
+        (Some("HTTP/1"), ProxyProtocol::Http1),
+        (Some("HTTP/2"), ProxyProtocol::Http2),
+        (Some("gRPC"), ProxyProtocol::Grpc),
+        (Some("opaque"), ProxyProtocol::Opaque),
+        (Some("TLS"), ProxyProtocol::Tls),
+        (Some("HTTP/3"), detect),
+    ] {
+        let srv = serde_json::from_value::<k8s::policy::Server>(serde_json::json!({
+            "apiVersion": "policy.linkerd.io/v1beta1",
+            "kind": "Server",
+            "metadata": { "namespace": "ns-0", "name": "srv-0" },
+            "spec": {
+                "podSelector": {},
+                "port": 2222,
+                "proxyProtocol": hint,
+            },
+        }))
+        .expect("server must deserialize");
+        idx.apply_server(srv);
+        assert_eq!(port2222.get().name, "srv-0");
+        assert_eq!(port2222.get().protocol, protocol, "{:?}", hint);
+    }
+}
+
+/// Checks that a cluster server applies to matching pods in all namespaces, including namespaces
+/// that are indexed after the cluster server, and that authorizations only apply to the cluster
+/// server in their own namespace.
 #[test]
-fn server_update_deselects_pod() {
-    let cluster_net = IpNet::from_str("192.0.2.0/24").unwrap();
+fn cluster_server_selects_all_namespaces() {
     let cluster = ClusterInfo {
-        networks: vec![cluster_net],
+        networks: vec![IpNet::from_str("192.0.2.0/24").unwrap()],
         control_plane_ns: "linkerd".to_string(),
         identity_domain: "cluster.example.com".into(),
     };
-    let pod_net = IpNet::from_str("192.0.2.2/28").unwrap();
-    let detect_timeout = time::Duration::from_secs(1);
-    let default = DefaultPolicy::Allow {
-        authenticated_only: false,
-        cluster_only: true,
-    };
-    let (lookup_rx, mut idx) = Index::new(cluster, default, detect_timeout);
+    let (lookup_rx, mut idx) =
+        Index::new(cluster, DefaultPolicy::Deny, time::Duration::from_secs(1));
 
-    let p = mk_pod(
-        "ns-0",
+    let mut hosts = IpNet::from_str("192.0.2.2/28").unwrap().hosts();
+    for ns in ["ns-0", "ns-1"] {
+        let pod = mk_pod(
+            ns,
+            "pod-0",
+            "node-0",
+            hosts.next().unwrap(),
+            Some(("container-0", vec![2222, 9999])),
+        );
+        idx.apply_pod(pod).unwrap();
+    }
+    idx.apply_cluster_server(mk_cluster_server("csrv-0", Port::Number(2222)));
+
+    let pod = mk_pod(
+        "ns-2",
         "pod-0",
         "node-0",
-        pod_net.hosts().next().unwrap(),
+        hosts.next().unwrap(),
         Some(("container-0", vec![2222])),
     );
-    idx.apply_pod(p).unwrap();
+    idx.apply_pod(pod).unwrap();
+
+    for ns in ["ns-0", "ns-1", "ns-2"] {
+        assert_eq!(
+            lookup_rx.lookup(ns, "pod-0", 2222).unwrap().get().name,
+            "csrv-0",
+            "{}",
+            ns
+        );
+    }
+    assert_eq!(
+        lookup_rx.lookup("ns-0", "pod-0", 9999).unwrap().get().name,
+        "default:deny"
+    );
+
+    idx.apply_authz(mk_authz(
+        "ns-0",
+        "authz-0",
+        "csrv-0",
+        k8s::policy::authz::Client {
+            unauthenticated: true,
+            ..Default::default()
+        },
+    ))
+    .unwrap();
+    assert_eq!(
+        lookup_rx
+            .lookup("ns-0", "pod-0", 2222)
+            .unwrap()
+            .get()
+            .authorizations
+            .len(),
+        1
+    );
+    assert!(lookup_rx
+        .lookup("ns-1", "pod-0", 2222)
+        .unwrap()
+        .get()
+        .authorizations
+        .is_empty());
+
+    idx.delete_cluster_server(mk_cluster_server("csrv-0", Port::Number(2222)))
+        .unwrap();
+    for ns in ["ns-0", "ns-1", "ns-2"] {
+        assert_eq!(
+            lookup_rx.lookup(ns, "pod-0", 2222).unwrap().get().name,
+            "default:deny",
+            "{}",
+            ns
+        );
+    }
+    assert!(idx
+        .delete_cluster_server(mk_cluster_server("csrv-0", Port::Number(2222)))
+        .is_err());
+}
+
+/// Checks that a server in a pod's namespace takes precedence over a cluster server that selects
+/// the same port, regardless of the order in which they're indexed or whether they share a name.
+#[test]
+fn server_takes_precedence_over_cluster_server() {
+    let cluster = ClusterInfo {
+        networks: vec![IpNet::from_str("192.0.2.0/24").unwrap()],
+        control_plane_ns: "linkerd".to_string(),
+        identity_domain: "cluster.example.com".into(),
+    };
+    let (lookup_rx, mut idx) =
+        Index::new(cluster, DefaultPolicy::Deny, time::Duration::from_secs(1));
+
+    let mut hosts = IpNet::from_str("192.0.2.2/28").unwrap().hosts();
+    for ns in ["ns-0", "ns-1"] {
+        let pod = mk_pod(
+            ns,
+            "pod-0",
+            "node-0",
+            hosts.next().unwrap(),
+            Some(("container-0", vec![2222])),
+        );
+        idx.apply_pod(pod).unwrap();
+    }
+    let ns0 = lookup_rx.lookup("ns-0", "pod-0", 2222).unwrap();
+    let ns1 = lookup_rx.lookup("ns-1", "pod-0", 2222).unwrap();
+
+    // A server applied after the cluster server replaces it, only in the server's namespace.
+    let csrv = {
+        let mut srv = mk_cluster_server("shared", Port::Number(2222));
+        srv.spec.proxy_protocol = Some(k8s::policy::server::ProxyProtocol::Opaque);
+        srv
+    };
+    idx.apply_cluster_server(csrv.clone());
+    idx.apply_server(mk_server("ns-0", "srv-0", Port::Number(2222), None, None));
+    assert_eq!(ns0.get().name, "srv-0");
+    assert_eq!(ns1.get().name, "shared");
+
+    // Re-applying the cluster server doesn't displace the server.
+    idx.apply_cluster_server(csrv.clone());
+    assert_eq!(ns0.get().name, "srv-0");
 
+    // When the server is deleted, the cluster server applies in its place.
+    idx.delete_server(mk_server("ns-0", "srv-0", Port::Number(2222), None, None))
+        .unwrap();
+    assert_eq!(ns0.get().name, "shared");
+    assert_eq!(ns0.get().protocol, ProxyProtocol::Opaque);
+
+    // A server that shares the cluster server's name still takes precedence.
     let srv = {
-        let mut srv = mk_server("ns-0", "srv-0", Port::Number(2222), None, None);
-        srv.spec.proxy_protocol = Some(k8s::policy::server::ProxyProtocol::Http2);
+        let mut srv = mk_server("ns-0", "shared", Port::Number(2222), None, None);
+        srv.spec.proxy_protocol = Some(k8s::policy::server::ProxyProtocol::Http1);
         srv
     };
     idx.apply_server(srv.clone());
+    assert_eq!(ns0.get().protocol, ProxyProtocol::Http1);
+    assert_eq!(ns1.get().protocol, ProxyProtocol::Opaque);
 
-    // The default policy applies for all exposed ports.
-    let port2222 = lookup_rx.lookup("ns-0", "pod-0", 2222).unwrap();
-    assert_eq!(
-        port2222.get(),
-        InboundServer {
-            name: "srv-0".into(),
-            protocol: ProxyProtocol::Http2,
-            authorizations: Default::default(),
+    // Deleting the cluster server leaves the server in place.
+    idx.delete_cluster_server(csrv).unwrap();
+    assert_eq!(ns0.get().protocol, ProxyProtocol::Http1);
+    assert_eq!(ns1.get().name, "default:deny");
+
+    idx.delete_server(srv).unwrap();
+    assert_eq!(ns0.get().name, "default:deny");
+}
+
+/// Checks that a server's authorizations are ordered by name, regardless of the order in which
+/// they're indexed, so that identical policies are served identically by all replicas.
+#[test]
+fn authz_order_is_stable() {
+    let cluster_net = IpNet::from_str("192.0.2.0/24").unwrap();
+    let pod_net = IpNet::from_str("192.0.2.2/28").unwrap();
+    let default = DefaultPolicy::Allow {
+        authenticated_only: false,
+        cluster_only: true,
+    };
+    let index_in_order = |names: &[&str]| {
+        let cluster = ClusterInfo {
+            networks: vec![cluster_net],
+            control_plane_ns: "linkerd".to_string(),
+            identity_domain: "cluster.example.com".into(),
+        };
+        let (lookup_rx, mut idx) = Index::new(cluster, default, time::Duration::from_secs(1));
+        idx.apply_pod(mk_pod(
+            "ns-0",
+            "pod-0",
+            "node-0",
+            pod_net.hosts().next().unwrap(),
+            Some(("container-0", vec![2222])),
+        ))
+        .unwrap();
+        idx.apply_server(mk_server("ns-0", "srv-0", Port::Number(2222), None, None));
+        for name in names {
+            let client = k8s::policy::authz::Client {
+                unauthenticated: true,
+                ..Default::default()
+            };
+            idx.apply_authz(mk_authz("ns-0", *name, "srv-0", client))
+                .unwrap();
         }
+        lookup_rx.lookup("ns-0", "pod-0", 2222).unwrap().get()
+    };
+
+    let expected = index_in_order(&["authz-0", "authz-1", "authz-2"]);
+    assert_eq!(
+        expected.authorizations.keys().collect::<Vec<_>>(),
+        vec!["authz-0", "authz-1", "authz-2"]
     );
+    for order in [
+        ["authz-2", "authz-0", "authz-1"],
+        ["authz-1", "authz-2", "authz-0"],
+        ["authz-2", "authz-1", "authz-0"],
+    ] {
+        let srv = index_in_order(&order);
+        assert_eq!(
+            srv.authorizations.keys().collect::<Vec<_>>(),
+            expected.authorizations.keys().collect::<Vec<_>>(),
+            "{:?}",
+            order
+        );
+        assert_eq!(srv, expected, "{:?}", order);
+    }
+}
 
-    idx.apply_server({
-        let mut srv = srv;
-        srv.spec.pod_selector = Some(("label", "value")).into_iter().collect();
-        srv
-    });
+/// Checks that authorizations with overlapping client networks on the same server are counted,
+/// while both still apply.
+#[test]
+fn authz_overlapping_networks() {
+    let cluster = ClusterInfo {
+        networks: vec![IpNet::from_str("192.0.2.0/24").unwrap()],
+        control_plane_ns: "linkerd".to_string(),
+        identity_domain: "cluster.example.com".into(),
+    };
+    let default = DefaultPolicy::Allow {
+        authenticated_only: false,
+        cluster_only: true,
+    };
+    let (_lookup_rx, mut idx) = Index::new(cluster, default, time::Duration::from_secs(1));
+    idx.apply_server(mk_server("ns-0", "srv-0", Port::Number(2222), None, None));
+
+    let mk_client = |nets: &[&str]| k8s::policy::authz::Client {
+        networks: Some(
+            nets.iter()
+                .map(|cidr| k8s::policy::authz::Network {
+                    cidr: cidr.to_string(),
+                    except: None,
+                })
+                .collect(),
+        ),
+        unauthenticated: true,
+        ..Default::default()
+    };
+
+    // Authorizations that default to the cluster networks are not considered overlapping.
+    let unset = k8s::policy::authz::Client {
+        unauthenticated: true,
+        ..Default::default()
+    };
+    idx.apply_authz(mk_authz("ns-0", "authz-0", "srv-0", unset.clone()))
+        .unwrap();
+    idx.apply_authz(mk_authz("ns-0", "authz-1", "srv-0", unset))
+        .unwrap();
+    assert_eq!(idx.authz_overlaps.get(), 0);
+
+    idx.apply_authz(mk_authz(
+        "ns-0",
+        "authz-2",
+        "srv-0",
+        mk_client(&["10.0.0.0/16"]),
+    ))
+    .unwrap();
+    idx.apply_authz(mk_authz(
+        "ns-0",
+        "authz-3",
+        "srv-0",
+        mk_client(&["10.1.0.0/16"]),
+    ))
+    .unwrap();
+    assert_eq!(idx.authz_overlaps.get(), 0, "disjoint networks");
+
+    idx.apply_authz(mk_authz(
+        "ns-0",
+        "authz-4",
+        "srv-0",
+        mk_client(&["10.0.1.0/24"]),
+    ))
+    .unwrap();
+    assert_eq!(idx.authz_overlaps.get(), 1, "overlapping networks");
+
+    idx.apply_authz(mk_authz(
+        "ns-0",
+        "authz-5",
+        "srv-0",
+        mk_client(&["10.1.0.0/16"]),
+    ))
+    .unwrap();
+    assert_eq!(idx.authz_overlaps.get(), 2, "identical networks");
+
+    // Re-applying an unchanged authorization isn't counted again.
+    idx.apply_authz(mk_authz(
+        "ns-0",
+        "authz-5",
+        "srv-0",
+        mk_client(&["10.1.0.0/16"]),
+    ))
+    .unwrap();
+    assert_eq!(idx.authz_overlaps.get(), 2);
+
+    // Authorizations on other servers are not considered.
+    idx.apply_server(mk_server("ns-0", "srv-1", Port::Number(9999), None, None));
+    idx.apply_authz(mk_authz(
+        "ns-0",
+        "authz-6",
+        "srv-1",
+        mk_client(&["10.0.0.0/8"]),
+    ))
+    .unwrap();
+    assert_eq!(idx.authz_overlaps.get(), 2);
+
+    // Both overlapping authorizations still apply to the server.
+    let srv = &idx.namespaces.index["ns-0"].servers.dump()["srv-0"];
     assert_eq!(
-        port2222.get(),
-        InboundServer {
-            name: format!("default:{}", default),
-            authorizations: mk_default_policy(default, cluster_net),
-            protocol: ProxyProtocol::Detect {
-                timeout: detect_timeout,
-            },
-        }
+        srv["authorizations"],
+        serde_json::json!(["authz-0", "authz-1", "authz-2", "authz-3", "authz-4", "authz-5"])
     );
 }
 
@@ -238,6 +1349,47 @@ fn default_policy_global() {
     }
 }
 
+/// Tests that default policies are updated in place when the cluster networks change.
+#[test]
+fn default_policy_cluster_networks_updated() {
+    let cluster_net = IpNet::from_str("192.0.2.0/24").unwrap();
+    let cluster = ClusterInfo {
+        networks: vec![cluster_net],
+        control_plane_ns: "linkerd".to_string(),
+        identity_domain: "cluster.example.com".into(),
+    };
+    let default = DefaultPolicy::Allow {
+        authenticated_only: false,
+        cluster_only: true,
+    };
+    let detect_timeout = time::Duration::from_secs(1);
+    let (lookup_rx, mut idx) = Index::new(cluster, default, detect_timeout);
+
+    let p = mk_pod(
+        "ns-0",
+        "pod-0",
+        "node-0",
+        "192.0.2.2".parse().unwrap(),
+        Some(("container-0", vec![2222])),
+    );
+    idx.reset_pods(vec![p]).unwrap();
+    let port2222 = lookup_rx
+        .lookup("ns-0", "pod-0", 2222)
+        .expect("pod must exist in lookups");
+    assert_eq!(
+        port2222.get().authorizations,
+        mk_default_policy(default, cluster_net)
+    );
+
+    let new_net = IpNet::from_str("198.51.100.0/24").unwrap();
+    idx.set_cluster_networks(vec![new_net]);
+    assert_eq!(
+        port2222.get().authorizations,
+        mk_default_policy(default, new_net),
+        "existing watches must observe the new networks"
+    );
+}
+
 /// Tests that pod servers are configured with defaults based on the workload-defined `DefaultPolicy`
 /// policy.
 ///
@@ -294,7 +1446,122 @@ fn default_policy_annotated() {
 }
 /// Tests that an invalid workload annotation is ignored in favor of the global default.
 #[test]
-fn default_policy_annotated_invalid() {
+fn default_policy_annotated_invalid() {
+    let cluster_net = IpNet::from_str("192.0.2.0/24").unwrap();
+    let cluster = ClusterInfo {
+        networks: vec![cluster_net],
+        control_plane_ns: "linkerd".to_string(),
+        identity_domain: "cluster.example.com".into(),
+    };
+    let pod_net = IpNet::from_str("192.0.2.2/28").unwrap();
+    let detect_timeout = time::Duration::from_secs(1);
+
+    let default = DefaultPolicy::Allow {
+        authenticated_only: false,
+        cluster_only: false,
+    };
+    let (lookup_rx, mut idx) = Index::new(cluster, default, detect_timeout);
+
+    let mut p = mk_pod(
+        "ns-0",
+        "pod-0",
+        "node-0",
+        pod_net.hosts().next().unwrap(),
+        Some(("container-0", vec![2222])),
+    );
+    p.annotations_mut()
+        .insert(DefaultPolicy::ANNOTATION.into(), "bogus".into());
+    idx.reset_pods(vec![p]).unwrap();
+
+    // Lookup port 2222 -> default config.
+    let port2222 = lookup_rx
+        .lookup("ns-0", "pod-0", 2222)
+        .expect("pod must exist in lookups");
+    assert_eq!(
+        port2222.get(),
+        InboundServer {
+            name: format!("default:{}", default),
+            authorizations: mk_default_policy(
+                DefaultPolicy::Allow {
+                    authenticated_only: false,
+                    cluster_only: false,
+                },
+                cluster_net,
+            ),
+            protocol: ProxyProtocol::Detect {
+                timeout: detect_timeout,
+            },
+        }
+    );
+}
+
+/// Tests that a namespace's annotation overrides the global default for its pods, and that the
+/// global default is restored when the annotation is removed, invalid, or the namespace is deleted.
+#[test]
+fn default_policy_namespace_annotated() {
+    let cluster_net = IpNet::from_str("192.0.2.0/24").unwrap();
+    let cluster = ClusterInfo {
+        networks: vec![cluster_net],
+        control_plane_ns: "linkerd".to_string(),
+        identity_domain: "cluster.example.com".into(),
+    };
+    let pod_net = IpNet::from_str("192.0.2.2/28").unwrap();
+    let detect_timeout = time::Duration::from_secs(1);
+    let mk_config = |default: DefaultPolicy| InboundServer {
+        name: format!("default:{}", default),
+        authorizations: mk_default_policy(default, cluster_net),
+        protocol: ProxyProtocol::Detect {
+            timeout: detect_timeout,
+        },
+    };
+
+    let global = DefaultPolicy::Allow {
+        authenticated_only: false,
+        cluster_only: false,
+    };
+    let (lookup_rx, mut idx) = Index::new(cluster, global, detect_timeout);
+
+    // The namespace is annotated before its pods are indexed.
+    idx.reset_namespaces(vec![mk_namespace("ns-0", Some("deny"))]);
+    idx.reset_pods(vec![mk_pod(
+        "ns-0",
+        "pod-0",
+        "node-0",
+        pod_net.hosts().next().unwrap(),
+        Some(("container-0", vec![2222])),
+    )])
+    .unwrap();
+    let port2222 = lookup_rx
+        .lookup("ns-0", "pod-0", 2222)
+        .expect("pod must exist in lookups");
+    assert_eq!(port2222.get(), mk_config(DefaultPolicy::Deny));
+
+    // Removing the annotation restores the global default.
+    idx.apply_namespace(mk_namespace("ns-0", None));
+    assert_eq!(port2222.get(), mk_config(global));
+
+    // Annotating an existing namespace updates its pods.
+    let authn = DefaultPolicy::Allow {
+        authenticated_only: true,
+        cluster_only: false,
+    };
+    idx.apply_namespace(mk_namespace("ns-0", Some("all-authenticated")));
+    assert_eq!(port2222.get(), mk_config(authn));
+
+    // An invalid annotation is ignored in favor of the global default.
+    idx.apply_namespace(mk_namespace("ns-0", Some("bogus")));
+    assert_eq!(port2222.get(), mk_config(global));
+
+    // Deleting the namespace restores the global default.
+    idx.apply_namespace(mk_namespace("ns-0", Some("deny")));
+    assert_eq!(port2222.get(), mk_config(DefaultPolicy::Deny));
+    idx.delete_namespace(mk_namespace("ns-0", Some("deny")));
+    assert_eq!(port2222.get(), mk_config(global));
+}
+
+/// Tests that a pod's annotation takes precedence over its namespace's annotation.
+#[test]
+fn default_policy_namespace_annotated_pod_override() {
     let cluster_net = IpNet::from_str("192.0.2.0/24").unwrap();
     let cluster = ClusterInfo {
         networks: vec![cluster_net],
@@ -303,12 +1570,11 @@ fn default_policy_annotated_invalid() {
     };
     let pod_net = IpNet::from_str("192.0.2.2/28").unwrap();
     let detect_timeout = time::Duration::from_secs(1);
-
-    let default = DefaultPolicy::Allow {
+    let global = DefaultPolicy::Allow {
         authenticated_only: false,
         cluster_only: false,
     };
-    let (lookup_rx, mut idx) = Index::new(cluster, default, detect_timeout);
+    let (lookup_rx, mut idx) = Index::new(cluster, global, detect_timeout);
 
     let mut p = mk_pod(
         "ns-0",
@@ -318,29 +1584,14 @@ fn default_policy_annotated_invalid() {
         Some(("container-0", vec![2222])),
     );
     p.annotations_mut()
-        .insert(DefaultPolicy::ANNOTATION.into(), "bogus".into());
+        .insert(DefaultPolicy::ANNOTATION.into(), global.to_string());
     idx.reset_pods(vec![p]).unwrap();
+    idx.apply_namespace(mk_namespace("ns-0", Some("deny")));
 
-    // Lookup port 2222 -> default config.
     let port2222 = lookup_rx
         .lookup("ns-0", "pod-0", 2222)
         .expect("pod must exist in lookups");
-    assert_eq!(
-        port2222.get(),
-        InboundServer {
-            name: format!("default:{}", default),
-            authorizations: mk_default_policy(
-                DefaultPolicy::Allow {
-                    authenticated_only: false,
-                    cluster_only: false,
-                },
-                cluster_net,
-            ),
-            protocol: ProxyProtocol::Detect {
-                timeout: detect_timeout,
-            },
-        }
-    );
+    assert_eq!(port2222.get().name, format!("default:{}", global));
 }
 
 #[test]
@@ -381,6 +1632,197 @@ fn opaque_annotated() {
     }
 }
 
+/// Tests that detection timeout overrides apply to ports that detect their protocol, whether or
+/// not they're selected by a server, and that other ports use the default timeout.
+#[test]
+fn detect_timeout_overrides() {
+    let cluster_net = IpNet::from_str("192.0.2.0/24").unwrap();
+    let cluster = ClusterInfo {
+        networks: vec![cluster_net],
+        control_plane_ns: "linkerd".to_string(),
+        identity_domain: "cluster.example.com".into(),
+    };
+    let pod_net = IpNet::from_str("192.0.2.2/28").unwrap();
+    let detect_timeout = time::Duration::from_secs(1);
+    let override_timeout = time::Duration::from_millis(100);
+    let default = DefaultPolicy::Deny;
+    let (lookup_rx, mut idx) = Index::new(cluster, default, detect_timeout);
+    idx.set_detect_timeout_overrides(
+        vec![(2222, override_timeout), (3333, override_timeout)]
+            .into_iter()
+            .collect(),
+    );
+
+    let mut p = mk_pod(
+        "ns-0",
+        "pod-0",
+        "node-0",
+        pod_net.hosts().next().unwrap(),
+        Some(("container-0", vec![2222, 3333, 9999])),
+    );
+    p.annotations_mut()
+        .insert("config.linkerd.io/opaque-ports".into(), "3333".into());
+    idx.reset_pods(vec![p]).unwrap();
+
+    let detect = |timeout| ProxyProtocol::Detect { timeout };
+    let protocol = |port| {
+        lookup_rx
+            .lookup("ns-0", "pod-0", port)
+            .expect("pod must exist in lookups")
+            .get()
+            .protocol
+    };
+    assert_eq!(protocol(2222), detect(override_timeout));
+    assert_eq!(protocol(9999), detect(detect_timeout));
+    // The annotation configures the protocol, so it isn't detected.
+    assert_eq!(protocol(3333), ProxyProtocol::Opaque);
+
+    // Servers that don't configure a protocol use the override for their port.
+    idx.apply_server(mk_server(
+        "ns-0",
+        "srv-2222",
+        Port::Number(2222),
+        None,
+        None,
+    ));
+    assert_eq!(protocol(2222), detect(override_timeout));
+    idx.apply_server(mk_server(
+        "ns-0",
+        "srv-9999",
+        Port::Number(9999),
+        None,
+        None,
+    ));
+    assert_eq!(protocol(9999), detect(detect_timeout));
+
+    // A server's configured protocol takes precedence over the override.
+    let mut srv = mk_server("ns-0", "srv-2222", Port::Number(2222), None, None);
+    srv.spec.proxy_protocol = Some(k8s::policy::server::ProxyProtocol::Http1);
+    idx.apply_server(srv);
+    assert_eq!(protocol(2222), ProxyProtocol::Http1);
+}
+
+#[test]
+fn meshed_workload_selector() {
+    let cluster_net = IpNet::from_str("192.0.2.0/24").unwrap();
+    let cluster = ClusterInfo {
+        networks: vec![cluster_net],
+        control_plane_ns: "linkerd".to_string(),
+        identity_domain: "cluster.example.com".into(),
+    };
+    let pod_net = IpNet::from_str("192.0.2.2/28").unwrap();
+    let detect_timeout = time::Duration::from_secs(1);
+    let (lookup_rx, mut idx) = Index::new(cluster, DefaultPolicy::Deny, detect_timeout);
+    idx.set_meshed_workload_selector("example.com/mesh=true".parse().unwrap());
+
+    let mut ips = pod_net.hosts();
+    let mut meshed = mk_pod(
+        "ns-0",
+        "pod-0",
+        "node-0",
+        ips.next().unwrap(),
+        Some(("container-0", vec![2222])),
+    );
+    meshed
+        .labels_mut()
+        .insert("example.com/mesh".into(), "true".into());
+    // Pods injected by Linkerd aren't meshed unless they match the configured selector.
+    let mut unmeshed = mk_pod(
+        "ns-0",
+        "pod-1",
+        "node-0",
+        ips.next().unwrap(),
+        Some(("container-0", vec![2222])),
+    );
+    unmeshed
+        .labels_mut()
+        .insert("linkerd.io/control-plane-ns".into(), "linkerd".into());
+    idx.reset_pods(vec![meshed.clone(), unmeshed]).unwrap();
+
+    assert!(lookup_rx.lookup("ns-0", "pod-0", 2222).is_some());
+    assert!(
+        lookup_rx.lookup("ns-0", "pod-1", 2222).is_none(),
+        "unmeshed pods must not be indexed"
+    );
+
+    // A pod that no longer matches the selector is removed.
+    meshed
+        .labels_mut()
+        .insert("example.com/mesh".into(), "false".into());
+    idx.apply_pod(meshed).unwrap();
+    assert!(lookup_rx.lookup("ns-0", "pod-0", 2222).is_none());
+}
+
+#[test]
+fn server_metrics() {
+    let cluster = ClusterInfo {
+        networks: vec![IpNet::from_str("192.0.2.0/24").unwrap()],
+        control_plane_ns: "linkerd".to_string(),
+        identity_domain: "cluster.example.com".into(),
+    };
+    let (_lookup_rx, mut idx) =
+        Index::new(cluster, DefaultPolicy::Deny, time::Duration::from_secs(1));
+    let metrics = Registry::default();
+    idx.register_metrics(&metrics);
+    idx.set_server_metrics_limit(2);
+
+    idx.apply_server(mk_server("ns-0", "srv-a", Port::Number(8080), None, None));
+    let mut opaque = mk_server("ns-0", "srv-b", Port::Number(9090), None, None);
+    opaque.spec.proxy_protocol = Some(k8s::policy::server::ProxyProtocol::Opaque);
+    idx.apply_server(opaque);
+    let client = k8s::policy::authz::Client {
+        unauthenticated: true,
+        ..Default::default()
+    };
+    idx.apply_authz(mk_authz("ns-0", "authz-0", "srv-a", client.clone()))
+        .unwrap();
+    idx.update_server_metrics();
+
+    let out = metrics.encode();
+    for sample in [
+        "index_server_authorizations{namespace=\"ns-0\",server=\"srv-a\"} 1\n",
+        "index_server_authorizations{namespace=\"ns-0\",server=\"srv-b\"} 0\n",
+        "index_server_default_protocol{namespace=\"ns-0\",server=\"srv-a\"} 1\n",
+        "index_server_default_protocol{namespace=\"ns-0\",server=\"srv-b\"} 0\n",
+    ] {
+        assert!(out.contains(sample), "{}", out);
+    }
+    assert!(!out.contains("\"other\""), "{}", out);
+
+    // Servers beyond the limit are aggregated.
+    for name in ["srv-c", "srv-d"] {
+        idx.apply_server(mk_server("ns-1", name, Port::Number(8080), None, None));
+        idx.apply_authz(mk_authz("ns-1", name, name, client.clone()))
+            .unwrap();
+    }
+    idx.update_server_metrics();
+    let out = metrics.encode();
+    for sample in [
+        "index_server_authorizations{namespace=\"other\",server=\"other\"} 2\n",
+        "index_server_default_protocol{namespace=\"other\",server=\"other\"} 2\n",
+    ] {
+        assert!(out.contains(sample), "{}", out);
+    }
+    assert!(!out.contains("srv-c"), "{}", out);
+
+    // Removed servers are no longer described.
+    idx.delete_server(mk_server("ns-0", "srv-b", Port::Number(9090), None, None))
+        .unwrap();
+    idx.update_server_metrics();
+    let out = metrics.encode();
+    assert!(!out.contains("srv-b"), "{}", out);
+    assert!(
+        out.contains("index_server_authorizations{namespace=\"ns-1\",server=\"srv-c\"} 1\n"),
+        "{}",
+        out
+    );
+    assert!(
+        out.contains("index_server_authorizations{namespace=\"other\",server=\"other\"} 1\n"),
+        "{}",
+        out
+    );
+}
+
 #[test]
 fn authenticated_annotated() {
     let cluster_net = IpNet::from_str("192.0.2.0/24").unwrap();
@@ -415,6 +1857,10 @@ fn authenticated_annotated() {
                     authenticated_only: true,
                 },
                 DefaultPolicy::Deny => DefaultPolicy::Deny,
+                DefaultPolicy::AllAuthenticatedOrProbes => DefaultPolicy::Allow {
+                    cluster_only: false,
+                    authenticated_only: true,
+                },
             };
             InboundServer {
                 name: format!("default:{}", policy),
@@ -433,10 +1879,162 @@ fn authenticated_annotated() {
     }
 }
 
+/// Tests that, when traffic is denied by default, ports without a server reject all clients except
+/// for the kubelet's probes, which are admitted from the pod's node once it's scheduled.
+#[test]
+fn default_policy_deny_permits_probes() {
+    let cluster_net = IpNet::from_str("192.0.2.0/24").unwrap();
+    let cluster = ClusterInfo {
+        networks: vec![cluster_net],
+        control_plane_ns: "linkerd".to_string(),
+        identity_domain: "cluster.example.com".into(),
+    };
+    let pod_net = IpNet::from_str("192.0.2.2/28").unwrap();
+    let detect_timeout = time::Duration::from_secs(1);
+    let (lookup_rx, mut idx) = Index::new(cluster, DefaultPolicy::Deny, detect_timeout);
+
+    let mut pod = mk_pod(
+        "ns-0",
+        "pod-0",
+        "node-0",
+        pod_net.hosts().next().unwrap(),
+        Some(("container-0", vec![2222, 3333, 9999])),
+    );
+    {
+        let container = &mut pod.spec.as_mut().unwrap().containers[0];
+        container.ports.as_mut().unwrap()[1].name = Some("ready".into());
+        container.liveness_probe = Some(k8s::api::core::v1::Probe {
+            tcp_socket: Some(k8s::api::core::v1::TCPSocketAction {
+                port: k8s::IntOrString::Int(2222),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        container.readiness_probe = Some(k8s::api::core::v1::Probe {
+            http_get: Some(k8s::api::core::v1::HTTPGetAction {
+                port: k8s::IntOrString::String("ready".into()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+    }
+    idx.apply_pod(pod.clone()).unwrap();
+
+    let deny = InboundServer {
+        name: "default:deny".into(),
+        authorizations: Default::default(),
+        protocol: ProxyProtocol::Detect {
+            timeout: detect_timeout,
+        },
+    };
+
+    // The pod hasn't been scheduled, so all ports are closed.
+    let port2222 = lookup_rx.lookup("ns-0", "pod-0", 2222).unwrap();
+    let port3333 = lookup_rx.lookup("ns-0", "pod-0", 3333).unwrap();
+    let port9999 = lookup_rx.lookup("ns-0", "pod-0", 9999).unwrap();
+    assert_eq!(port2222.get(), deny);
+    assert_eq!(port3333.get(), deny);
+    assert_eq!(port9999.get(), deny);
+
+    // Once the pod's node is known, its probed ports admit unauthenticated connections from the
+    // node; but unprobed ports remain closed.
+    pod.status.as_mut().unwrap().host_ip = Some("198.51.100.1".into());
+    idx.apply_pod(pod.clone()).unwrap();
+    let probes = InboundServer {
+        authorizations: Some((
+            "default:probes".into(),
+            ClientAuthorization {
+                authentication: ClientAuthentication::Unauthenticated,
+                networks: vec![IpNet::from_str("198.51.100.1/32").unwrap().into()],
+            },
+        ))
+        .into_iter()
+        .collect(),
+        ..deny.clone()
+    };
+    assert_eq!(port2222.get(), probes);
+    assert_eq!(port3333.get(), probes);
+    assert_eq!(port9999.get(), deny);
+
+    // A server replaces the probe policy, so probes must be authorized explicitly.
+    let srv = mk_server("ns-0", "srv-0", Port::Number(2222), None, None);
+    idx.apply_server(srv.clone());
+    assert_eq!(port2222.get().name, "srv-0");
+    assert!(port2222.get().authorizations.is_empty());
+    idx.delete_server(srv).unwrap();
+    assert_eq!(port2222.get(), probes);
+}
+
+/// Tests that the `all-authenticated-or-probes` default admits the kubelet's unauthenticated probes
+/// alongside authenticated clients, while other unauthenticated clients are denied.
+#[test]
+fn default_policy_authenticated_permits_probes() {
+    let cluster_net = IpNet::from_str("192.0.2.0/24").unwrap();
+    let cluster = ClusterInfo {
+        networks: vec![cluster_net],
+        control_plane_ns: "linkerd".to_string(),
+        identity_domain: "cluster.example.com".into(),
+    };
+    let pod_net = IpNet::from_str("192.0.2.2/28").unwrap();
+    let detect_timeout = time::Duration::from_secs(1);
+    let (lookup_rx, mut idx) = Index::new(
+        cluster,
+        DefaultPolicy::AllAuthenticatedOrProbes,
+        detect_timeout,
+    );
+
+    let mut pod = mk_pod(
+        "ns-0",
+        "pod-0",
+        "node-0",
+        pod_net.hosts().next().unwrap(),
+        Some(("container-0", vec![2222, 9999])),
+    );
+    pod.spec.as_mut().unwrap().containers[0].liveness_probe = Some(k8s::api::core::v1::Probe {
+        http_get: Some(k8s::api::core::v1::HTTPGetAction {
+            port: k8s::IntOrString::Int(2222),
+            ..Default::default()
+        }),
+        ..Default::default()
+    });
+    pod.status.as_mut().unwrap().host_ip = Some("198.51.100.1".into());
+    idx.apply_pod(pod).unwrap();
+
+    let authenticated = InboundServer {
+        name: "default:all-authenticated-or-probes".into(),
+        authorizations: mk_default_policy(DefaultPolicy::AllAuthenticatedOrProbes, cluster_net),
+        protocol: ProxyProtocol::Detect {
+            timeout: detect_timeout,
+        },
+    };
+
+    // The probed port admits unauthenticated connections from the node only.
+    let port2222 = lookup_rx.lookup("ns-0", "pod-0", 2222).unwrap();
+    let mut probes = authenticated.clone();
+    probes.authorizations.insert(
+        "default:probes".into(),
+        ClientAuthorization {
+            authentication: ClientAuthentication::Unauthenticated,
+            networks: vec![IpNet::from_str("198.51.100.1/32").unwrap().into()],
+        },
+    );
+    assert_eq!(port2222.get(), probes);
+
+    // Unprobed ports only admit authenticated clients.
+    let port9999 = lookup_rx.lookup("ns-0", "pod-0", 9999).unwrap();
+    assert_eq!(port9999.get(), authenticated);
+    assert!(port9999
+        .get()
+        .authorizations
+        .values()
+        .all(|authz| authz.authentication != ClientAuthentication::Unauthenticated));
+}
+
 // === Helpers ===
 
-const DEFAULTS: [DefaultPolicy; 5] = [
+const DEFAULTS: [DefaultPolicy; 6] = [
     DefaultPolicy::Deny,
+    DefaultPolicy::AllAuthenticatedOrProbes,
     DefaultPolicy::Allow {
         authenticated_only: true,
         cluster_only: false,
@@ -455,6 +2053,21 @@ const DEFAULTS: [DefaultPolicy; 5] = [
     },
 ];
 
+fn mk_namespace(name: impl Into<String>, default_policy: Option<&str>) -> k8s::Namespace {
+    k8s::Namespace {
+        metadata: k8s::ObjectMeta {
+            name: Some(name.into()),
+            annotations: default_policy.map(|p| {
+                Some((DefaultPolicy::ANNOTATION.to_string(), p.to_string()))
+                    .into_iter()
+                    .collect()
+            }),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
 fn mk_pod(
     ns: impl Into<String>,
     name: impl Into<String>,
@@ -497,6 +2110,38 @@ fn mk_pod(
     }
 }
 
+/// Creates a pod in `ns-0` with a single container port, optionally named.
+fn mk_named_port_pod(
+    name: impl Into<String>,
+    pod_ip: IpAddr,
+    port: u16,
+    port_name: Option<&str>,
+) -> k8s::Pod {
+    let mut pod = mk_pod(
+        "ns-0",
+        name,
+        "node-0",
+        pod_ip,
+        Some(("container-0", Some(port))),
+    );
+    let container = &mut pod.spec.as_mut().unwrap().containers[0];
+    container.ports.as_mut().unwrap()[0].name = port_name.map(Into::into);
+    pod
+}
+
+/// Creates a watch that is fed by `rx`, standing in for a watch against the API server.
+///
+/// The senders must be held for the life of the test, since watches must not terminate.
+fn mk_watch<T: Send + 'static>(rx: mpsc::UnboundedReceiver<k8s::Event<T>>) -> k8s::Watch<T> {
+    k8s::Watch::from(
+        stream::unfold(
+            rx,
+            |mut rx| async move { rx.recv().await.map(|ev| (ev, rx)) },
+        )
+        .map(Ok),
+    )
+}
+
 fn mk_server(
     ns: impl Into<String>,
     name: impl Into<String>,
@@ -524,6 +2169,20 @@ fn mk_server(
     }
 }
 
+fn mk_cluster_server(name: impl Into<String>, port: Port) -> k8s::policy::ClusterServer {
+    k8s::policy::ClusterServer {
+        metadata: k8s::ObjectMeta {
+            name: Some(name.into()),
+            ..Default::default()
+        },
+        spec: k8s::policy::ClusterServerSpec {
+            port,
+            pod_selector: Default::default(),
+            proxy_protocol: None,
+        },
+    }
+}
+
 fn mk_authz(
     ns: impl Into<String>,
     name: impl Into<String>,
@@ -549,7 +2208,7 @@ fn mk_authz(
 fn mk_default_policy(
     da: DefaultPolicy,
     cluster_net: IpNet,
-) -> HashMap<String, ClientAuthorization> {
+) -> BTreeMap<String, ClientAuthorization> {
     let all_nets = vec![Ipv4Net::default().into(), Ipv6Net::default().into()];
 
     let cluster_nets = vec![NetworkMatch::from(cluster_net)];
@@ -558,6 +2217,13 @@ fn mk_default_policy(
 
     match da {
         DefaultPolicy::Deny => None,
+        DefaultPolicy::AllAuthenticatedOrProbes => Some((
+            "default:all-authenticated-or-probes".into(),
+            ClientAuthorization {
+                authentication: authed,
+                networks: all_nets,
+            },
+        )),
         DefaultPolicy::Allow {
             authenticated_only: true,
             cluster_only: false,
@@ -602,3 +2268,150 @@ fn mk_default_policy(
     .into_iter()
     .collect()
 }
+
+#[test]
+fn tracks_resource_version_lag() {
+    let cluster = ClusterInfo {
+        networks: vec![IpNet::from_str("192.0.2.0/24").unwrap()],
+        control_plane_ns: "linkerd".to_string(),
+        identity_domain: "cluster.example.com".into(),
+    };
+    let default = DefaultPolicy::Allow {
+        authenticated_only: false,
+        cluster_only: true,
+    };
+    let (_lookup_rx, mut idx) = Index::new(cluster, default, time::Duration::from_secs(1));
+    let metrics = Registry::default();
+    idx.register_metrics(&metrics);
+
+    let mk_pod_at = |name: &str, version: &str| {
+        let mut pod = mk_pod(
+            "ns-0",
+            name,
+            "node-0",
+            "192.0.2.2".parse().unwrap(),
+            Some(("container-0", vec![2222])),
+        );
+        pod.metadata.resource_version = Some(version.to_string());
+        pod
+    };
+
+    let ev = idx.record(
+        "pods",
+        k8s::Event::Restarted(vec![mk_pod_at("pod-0", "10"), mk_pod_at("pod-1", "12")]),
+        |r| &mut r.pods,
+    );
+    idx.process("pods", |idx| match ev {
+        k8s::Event::Restarted(pods) => idx.reset_pods(pods),
+        _ => unreachable!(),
+    })
+    .unwrap();
+    let encoded = metrics.encode();
+    assert!(encoded.contains("index_resource_version_observed{kind=\"pods\"} 12"));
+    assert!(encoded.contains("index_resource_version_applied{kind=\"pods\"} 12"));
+    assert!(encoded.contains("index_resource_version_lag{kind=\"pods\"} 0"));
+
+    // While an event is being processed, its version has been observed but not applied.
+    let ev = idx.record("pods", k8s::Event::Applied(mk_pod_at("pod-2", "20")), |r| {
+        &mut r.pods
+    });
+    idx.process("pods", |idx| {
+        assert!(metrics
+            .encode()
+            .contains("index_resource_version_lag{kind=\"pods\"} 8"));
+        match ev {
+            k8s::Event::Applied(pod) => idx.apply_pod(pod),
+            _ => unreachable!(),
+        }
+    })
+    .unwrap();
+    let encoded = metrics.encode();
+    assert!(encoded.contains("index_resource_version_applied{kind=\"pods\"} 20"));
+    assert!(encoded.contains("index_resource_version_lag{kind=\"pods\"} 0"));
+
+    // Versions that aren't numeric are ignored.
+    idx.record(
+        "pods",
+        k8s::Event::Applied(mk_pod_at("pod-3", "abc")),
+        |r| &mut r.pods,
+    );
+    assert!(metrics
+        .encode()
+        .contains("index_resource_version_observed{kind=\"pods\"} 20"));
+}
+
+#[tokio::test]
+async fn resync_corrects_drift() {
+    let cluster = ClusterInfo {
+        networks: vec![IpNet::from_str("192.0.2.0/24").unwrap()],
+        control_plane_ns: "linkerd".to_string(),
+        identity_domain: "cluster.example.com".into(),
+    };
+    let default = DefaultPolicy::Allow {
+        authenticated_only: false,
+        cluster_only: true,
+    };
+    let (lookup_rx, mut idx) = Index::new(cluster, default, time::Duration::from_secs(1));
+    let metrics = Registry::default();
+    idx.register_metrics(&metrics);
+
+    let (namespaces_tx, namespaces_rx) = mpsc::unbounded_channel();
+    let (pods_tx, pods_rx) = mpsc::unbounded_channel();
+    let (servers_tx, servers_rx) = mpsc::unbounded_channel();
+    let (authzs_tx, authzs_rx) = mpsc::unbounded_channel();
+    let (ready_tx, _ready_rx) = watch::channel(false);
+    tokio::spawn(idx.run(
+        k8s::ResourceWatches {
+            namespaces_rx: mk_watch(namespaces_rx),
+            pods_rx: mk_watch(pods_rx),
+            servers_rx: mk_watch(servers_rx),
+            authorizations_rx: mk_watch(authzs_rx),
+            cluster_servers_rx: k8s::Watch::unwatched(),
+        },
+        ready_tx,
+    ));
+    namespaces_tx.send(k8s::Event::Restarted(vec![])).unwrap();
+    servers_tx.send(k8s::Event::Restarted(vec![])).unwrap();
+    authzs_tx.send(k8s::Event::Restarted(vec![])).unwrap();
+
+    let mk_pod_at = |name: &str, ip: &str| {
+        mk_pod(
+            "ns-0",
+            name,
+            "node-0",
+            ip.parse().unwrap(),
+            Some(("container-0", vec![2222])),
+        )
+    };
+    pods_tx
+        .send(k8s::Event::Restarted(vec![
+            mk_pod_at("pod-0", "192.0.2.2"),
+            mk_pod_at("pod-1", "192.0.2.3"),
+        ]))
+        .unwrap();
+    time::timeout(time::Duration::from_secs(1), async {
+        while lookup_rx.lookup("ns-0", "pod-1", 2222).is_none() {
+            time::sleep(time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("pods must be indexed");
+
+    // The deletion of pod-1 is missed, so it's still indexed, until the resync lists the current
+    // pods.
+    pods_tx
+        .send(k8s::Event::Restarted(vec![mk_pod_at("pod-0", "192.0.2.2")]))
+        .unwrap();
+
+    time::timeout(time::Duration::from_secs(1), async {
+        while lookup_rx.lookup("ns-0", "pod-1", 2222).is_some() {
+            time::sleep(time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("drifted pods must be removed");
+    assert!(lookup_rx.lookup("ns-0", "pod-0", 2222).is_some());
+    assert!(metrics
+        .encode()
+        .contains("index_resync_corrections_total{kind=\"pods\"} 1"));
+}
@@ -0,0 +1,389 @@
+//! A minimal metrics registry that renders metrics in the Prometheus text exposition format.
+//!
+//! Metrics are cheap handles to shared state, so a metric may be cloned into the components that
+//! update it while the registry holds another handle for rendering.
+
+#![deny(warnings, rust_2018_idioms)]
+#![forbid(unsafe_code)]
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicI64, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// Holds all metrics exported by the controller.
+#[derive(Clone, Default)]
+pub struct Registry(Arc<Mutex<Vec<Entry>>>);
+
+/// A metric that can be rendered by a [`Registry`].
+pub trait Metric: Send + Sync + 'static {
+    /// The Prometheus metric type, e.g. `counter`.
+    fn kind(&self) -> &'static str;
+
+    /// Writes the metric's samples. `labels` is either empty or a comma-separated list of
+    /// already-escaped `key="value"` pairs.
+    fn encode(&self, name: &str, labels: &str, out: &mut String);
+}
+
+/// A monotonically increasing count.
+#[derive(Clone, Debug, Default)]
+pub struct Counter(Arc<AtomicU64>);
+
+/// A value that may increase or decrease.
+#[derive(Clone, Debug, Default)]
+pub struct Gauge(Arc<AtomicI64>);
+
+/// Counts observations into a fixed set of buckets.
+#[derive(Clone, Debug)]
+pub struct Histogram(Arc<HistogramInner>);
+
+/// A set of metrics of the same type, distinguished by their label values.
+#[derive(Clone)]
+pub struct Family<M> {
+    labels: &'static [&'static str],
+    mk: fn() -> M,
+    metrics: Arc<Mutex<BTreeMap<Vec<String>, M>>>,
+}
+
+struct Entry {
+    name: &'static str,
+    help: &'static str,
+    metric: Box<dyn Metric>,
+}
+
+#[derive(Debug)]
+struct HistogramInner {
+    bounds: &'static [f64],
+    buckets: Vec<AtomicU64>,
+    count: AtomicU64,
+    /// The sum of all observations, stored as the bits of an `f64`.
+    sum: AtomicU64,
+}
+
+// === impl Registry ===
+
+impl Registry {
+    /// Registers a metric, returning it so that it may be updated.
+    pub fn register<M: Metric + Clone>(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        metric: M,
+    ) -> M {
+        let entry = Entry {
+            name,
+            help,
+            metric: Box::new(metric.clone()),
+        };
+        self.0
+            .lock()
+            .expect("lock must not be poisoned")
+            .push(entry);
+        metric
+    }
+
+    /// Renders all registered metrics.
+    pub fn encode(&self) -> String {
+        let mut out = String::new();
+        for Entry { name, help, metric } in self.0.lock().expect("lock must not be poisoned").iter()
+        {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, metric.kind());
+            metric.encode(name, "", &mut out);
+        }
+        out
+    }
+}
+
+impl std::fmt::Debug for Registry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let entries = self.0.lock().expect("lock must not be poisoned");
+        f.debug_list()
+            .entries(entries.iter().map(|e| e.name))
+            .finish()
+    }
+}
+
+// === impl Counter ===
+
+impl Counter {
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl Metric for Counter {
+    fn kind(&self) -> &'static str {
+        "counter"
+    }
+
+    fn encode(&self, name: &str, labels: &str, out: &mut String) {
+        write_sample(out, name, labels, self.get());
+    }
+}
+
+// === impl Gauge ===
+
+impl Gauge {
+    pub fn set(&self, v: i64) {
+        self.0.store(v, Ordering::Relaxed);
+    }
+
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn dec(&self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> i64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl Metric for Gauge {
+    fn kind(&self) -> &'static str {
+        "gauge"
+    }
+
+    fn encode(&self, name: &str, labels: &str, out: &mut String) {
+        write_sample(out, name, labels, self.get());
+    }
+}
+
+// === impl Histogram ===
+
+impl Histogram {
+    /// Creates a histogram with the given bucket upper bounds, which must be sorted.
+    pub fn new(bounds: &'static [f64]) -> Self {
+        debug_assert!(
+            bounds.windows(2).all(|w| w[0] < w[1]),
+            "bounds must be sorted"
+        );
+        Self(Arc::new(HistogramInner {
+            bounds,
+            buckets: bounds.iter().map(|_| AtomicU64::new(0)).collect(),
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(0f64.to_bits()),
+        }))
+    }
+
+    pub fn observe(&self, v: f64) {
+        let inner = &*self.0;
+        if let Some(i) = inner.bounds.iter().position(|b| v <= *b) {
+            inner.buckets[i].fetch_add(1, Ordering::Relaxed);
+        }
+        inner.count.fetch_add(1, Ordering::Relaxed);
+        let _ = inner
+            .sum
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |sum| {
+                Some((f64::from_bits(sum) + v).to_bits())
+            });
+    }
+
+    pub fn count(&self) -> u64 {
+        self.0.count.load(Ordering::Relaxed)
+    }
+}
+
+impl Metric for Histogram {
+    fn kind(&self) -> &'static str {
+        "histogram"
+    }
+
+    fn encode(&self, name: &str, labels: &str, out: &mut String) {
+        let inner = &*self.0;
+        let sep = if labels.is_empty() { "" } else { "," };
+        let bucket = format!("{}_bucket", name);
+
+        let mut cumulative = 0;
+        for (bound, n) in inner.bounds.iter().zip(&inner.buckets) {
+            cumulative += n.load(Ordering::Relaxed);
+            let labels = format!("{}{}le=\"{}\"", labels, sep, bound);
+            write_sample(out, &bucket, &labels, cumulative);
+        }
+        let count = self.count();
+        write_sample(
+            out,
+            &bucket,
+            &format!("{}{}le=\"+Inf\"", labels, sep),
+            count,
+        );
+        let sum = f64::from_bits(inner.sum.load(Ordering::Relaxed));
+        write_sample(out, &format!("{}_sum", name), labels, sum);
+        write_sample(out, &format!("{}_count", name), labels, count);
+    }
+}
+
+// === impl Family ===
+
+impl<M> std::fmt::Debug for Family<M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Family")
+            .field("labels", &self.labels)
+            .finish()
+    }
+}
+
+impl<M: Default> Family<M> {
+    pub fn new(labels: &'static [&'static str]) -> Self {
+        Self::new_with(labels, M::default)
+    }
+}
+
+impl<M> Family<M> {
+    /// Creates a family whose metrics are built by `mk`, e.g. to configure histogram buckets.
+    pub fn new_with(labels: &'static [&'static str], mk: fn() -> M) -> Self {
+        Self {
+            labels,
+            mk,
+            metrics: Default::default(),
+        }
+    }
+}
+
+impl<M: Clone> Family<M> {
+    /// Gets the metric for the given label values, which must correspond to the family's labels.
+    pub fn get(&self, values: &[&str]) -> M {
+        debug_assert_eq!(values.len(), self.labels.len(), "label values must match");
+        let key = values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+        let mut metrics = self.metrics.lock().expect("lock must not be poisoned");
+        metrics.entry(key).or_insert_with(self.mk).clone()
+    }
+
+    /// Removes the metric for the given label values so that it is no longer exported.
+    pub fn remove(&self, values: &[&str]) {
+        let key = values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+        self.metrics
+            .lock()
+            .expect("lock must not be poisoned")
+            .remove(&key);
+    }
+}
+
+impl<M: Metric + Clone> Metric for Family<M> {
+    fn kind(&self) -> &'static str {
+        (self.mk)().kind()
+    }
+
+    fn encode(&self, name: &str, labels: &str, out: &mut String) {
+        for (values, metric) in self
+            .metrics
+            .lock()
+            .expect("lock must not be poisoned")
+            .iter()
+        {
+            let mut pairs = labels.to_string();
+            for (k, v) in self.labels.iter().zip(values) {
+                if !pairs.is_empty() {
+                    pairs.push(',');
+                }
+                let _ = write!(pairs, "{}=\"{}\"", k, escape(v));
+            }
+            metric.encode(name, &pairs, out);
+        }
+    }
+}
+
+fn write_sample(out: &mut String, name: &str, labels: &str, value: impl std::fmt::Display) {
+    if labels.is_empty() {
+        let _ = writeln!(out, "{} {}", name, value);
+    } else {
+        let _ = writeln!(out, "{}{{{}}} {}", name, labels, value);
+    }
+}
+
+fn escape(v: &str) -> String {
+    v.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_counters_and_gauges() {
+        let registry = Registry::default();
+        let counter = registry.register("requests_total", "Total requests.", Counter::default());
+        let gauge = registry.register("connections", "Open connections.", Gauge::default());
+        counter.add(3);
+        gauge.inc();
+        gauge.inc();
+        gauge.dec();
+
+        assert_eq!(
+            registry.encode(),
+            "# HELP requests_total Total requests.\n\
+             # TYPE requests_total counter\n\
+             requests_total 3\n\
+             # HELP connections Open connections.\n\
+             # TYPE connections gauge\n\
+             connections 1\n"
+        );
+    }
+
+    #[test]
+    fn encodes_families() {
+        let registry = Registry::default();
+        let family = registry.register(
+            "responses_total",
+            "Total responses.",
+            Family::<Counter>::new(&["code", "path"]),
+        );
+        family.get(&["200", "/a\"b"]).inc();
+        family.get(&["200", "/a\"b"]).inc();
+        family.get(&["503", "/"]).inc();
+
+        let out = registry.encode();
+        assert!(out.contains("# TYPE responses_total counter\n"), "{}", out);
+        assert!(
+            out.contains("responses_total{code=\"200\",path=\"/a\\\"b\"} 2\n"),
+            "{}",
+            out
+        );
+        assert!(
+            out.contains("responses_total{code=\"503\",path=\"/\"} 1\n"),
+            "{}",
+            out
+        );
+
+        family.remove(&["503", "/"]);
+        assert!(!registry.encode().contains("503"));
+    }
+
+    #[test]
+    fn encodes_histograms() {
+        static BOUNDS: [f64; 2] = [0.5, 1.0];
+        let registry = Registry::default();
+        let histogram = registry.register("latency_seconds", "Latency.", Histogram::new(&BOUNDS));
+        histogram.observe(0.25);
+        histogram.observe(0.5);
+        histogram.observe(4.0);
+
+        assert_eq!(
+            registry.encode(),
+            "# HELP latency_seconds Latency.\n\
+             # TYPE latency_seconds histogram\n\
+             latency_seconds_bucket{le=\"0.5\"} 2\n\
+             latency_seconds_bucket{le=\"1\"} 2\n\
+             latency_seconds_bucket{le=\"+Inf\"} 3\n\
+             latency_seconds_sum 4.75\n\
+             latency_seconds_count 3\n"
+        );
+    }
+}
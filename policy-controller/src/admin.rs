@@ -1,29 +1,358 @@
+use crate::{build_info, k8s, tls};
+use anyhow::{Context, Result};
 use futures::future;
-use hyper::{Body, Request, Response};
-use std::net::SocketAddr;
-use tokio::sync::watch;
-use tracing::{info, instrument};
-
-#[instrument(skip(ready))]
-pub async fn serve(addr: SocketAddr, ready: watch::Receiver<bool>) -> Result<(), hyper::Error> {
-    let server =
-        hyper::server::Server::bind(&addr).serve(hyper::service::make_service_fn(move |_conn| {
-            let ready = ready.clone();
-            future::ok::<_, hyper::Error>(hyper::service::service_fn(
-                move |req: hyper::Request<hyper::Body>| match req.uri().path() {
-                    "/ready" => future::ok(handle_ready(&ready, req)),
-                    _ => future::ok::<_, hyper::Error>(
-                        hyper::Response::builder()
-                            .status(hyper::StatusCode::NOT_FOUND)
-                            .body(hyper::Body::default())
-                            .unwrap(),
-                    ),
-                },
-            ))
-        }));
-    let addr = server.local_addr();
-    info!(%addr, "HTTP admin server listening");
-    server.await
+use hyper::{server::conn::Http, service::service_fn, Body, Request, Response};
+use linkerd_policy_controller_core::{ClientAuthentication, DiscoverInboundServer};
+use linkerd_policy_controller_metrics::Registry;
+use std::{convert::Infallible, net::SocketAddr, sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, TcpStream},
+    sync::{mpsc, watch, OwnedSemaphorePermit, Semaphore},
+};
+use tokio_rustls::TlsAcceptor;
+use tracing::{debug, info, instrument, warn};
+
+/// Configures the admin server.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub addr: SocketAddr,
+
+    /// When set, `/metrics` is served only on this address and `addr` serves only health checks.
+    /// Otherwise, all endpoints are served on `addr`.
+    pub metrics_addr: Option<SocketAddr>,
+
+    /// The maximum number of concurrent connections served by each listener.
+    pub max_connections: usize,
+
+    /// Sets `SO_REUSEPORT` on the admin listeners (see [`crate::net::bind`]).
+    pub reuse_port: bool,
+
+    /// Enables the `/debug/pprof` endpoints on `addr`. When disabled, these routes don't exist.
+    pub enable_profiling: bool,
+
+    /// When set, `/debug/index` describes the index's state on `addr`. This exposes policy
+    /// details, so it's only set when debug endpoints are enabled.
+    pub index: Option<k8s::DumpHandle>,
+
+    /// When set, `/debug/policy` resolves the policy served for a pod's port on `addr`. Like the
+    /// index, this is only set when debug endpoints are enabled.
+    pub policies: Option<k8s::Reader>,
+
+    /// When set, all admin listeners serve HTTPS with this certificate. Otherwise, they serve
+    /// plaintext HTTP.
+    pub tls: Option<Arc<tls::CertResolver>>,
+}
+
+/// Determines which endpoints an admin listener serves.
+#[derive(Clone)]
+struct Admin {
+    /// Set when the listener serves `/ready`.
+    ready: Option<watch::Receiver<bool>>,
+
+    /// Set when the listener serves `/metrics`.
+    metrics: Option<Registry>,
+
+    /// Set when the listener serves `/debug/pprof` endpoints.
+    profiling: bool,
+
+    /// Set when the listener serves `/debug/index`.
+    index: Option<k8s::DumpHandle>,
+
+    /// Set when the listener serves `/debug/policy`.
+    policies: Option<k8s::Reader>,
+}
+
+/// The connections served by an admin listener.
+enum Incoming {
+    Listener(TcpListener),
+
+    /// Connections accepted on a listener that is shared with the gRPC server.
+    Shared(mpsc::Receiver<(TcpStream, SocketAddr)>),
+}
+
+/// Serves the admin server until `drain` is signaled, at which point listeners are shutdown
+/// gracefully.
+pub async fn serve(
+    config: Config,
+    metrics: Registry,
+    ready: watch::Receiver<bool>,
+    drain: drain::Watch,
+) -> Result<()> {
+    serve_with(config, None, metrics, ready, drain).await
+}
+
+/// Serves the admin server on connections accepted by a listener that is shared with the gRPC
+/// server (see [`crate::mux`]), rather than binding `config.addr`. A separate metrics listener is
+/// still bound, if one is configured.
+pub async fn serve_shared(
+    config: Config,
+    connections: mpsc::Receiver<(TcpStream, SocketAddr)>,
+    metrics: Registry,
+    ready: watch::Receiver<bool>,
+    drain: drain::Watch,
+) -> Result<()> {
+    serve_with(config, Some(connections), metrics, ready, drain).await
+}
+
+async fn serve_with(
+    config: Config,
+    shared: Option<mpsc::Receiver<(TcpStream, SocketAddr)>>,
+    metrics: Registry,
+    ready: watch::Receiver<bool>,
+    drain: drain::Watch,
+) -> Result<()> {
+    let Config {
+        addr,
+        metrics_addr,
+        max_connections,
+        reuse_port,
+        enable_profiling,
+        index,
+        policies,
+        tls,
+    } = config;
+    let tls = tls.map(tls::acceptor);
+    match metrics_addr {
+        None => {
+            let admin = Admin {
+                ready: Some(ready),
+                metrics: Some(metrics),
+                profiling: enable_profiling,
+                index,
+                policies,
+            };
+            listen(addr, reuse_port, shared, tls, admin, max_connections, drain).await
+        }
+        Some(metrics_addr) => {
+            let admin = Admin {
+                ready: Some(ready),
+                metrics: None,
+                profiling: enable_profiling,
+                index,
+                policies,
+            };
+            let metrics = Admin {
+                ready: None,
+                metrics: Some(metrics),
+                profiling: false,
+                index: None,
+                policies: None,
+            };
+            tokio::try_join!(
+                listen(
+                    addr,
+                    reuse_port,
+                    shared,
+                    tls.clone(),
+                    admin,
+                    max_connections,
+                    drain.clone()
+                ),
+                listen(
+                    metrics_addr,
+                    reuse_port,
+                    None,
+                    tls,
+                    metrics,
+                    max_connections,
+                    drain
+                ),
+            )?;
+            Ok(())
+        }
+    }
+}
+
+#[instrument(skip(reuse_port, shared, tls, admin, max_connections, drain))]
+async fn listen(
+    addr: SocketAddr,
+    reuse_port: bool,
+    shared: Option<mpsc::Receiver<(TcpStream, SocketAddr)>>,
+    tls: Option<TlsAcceptor>,
+    admin: Admin,
+    max_connections: usize,
+    drain: drain::Watch,
+) -> Result<()> {
+    let incoming = match shared {
+        Some(connections) => {
+            info!(
+                tls = tls.is_some(),
+                "HTTP admin server sharing the gRPC listener"
+            );
+            Incoming::Shared(connections)
+        }
+        None => {
+            let listener = crate::net::bind(addr, reuse_port)
+                .await
+                .with_context(|| format!("failed to bind admin server on {}", addr))?;
+            info!(
+                addr = %listener.local_addr()?,
+                tls = tls.is_some(),
+                "HTTP admin server listening"
+            );
+            Incoming::Listener(listener)
+        }
+    };
+    serve_connections(incoming, tls, admin, max_connections, drain).await;
+    Ok(())
+}
+
+/// How long a client is given to complete a TLS handshake before its connection is closed.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Accepts connections until `drain` is signaled. When `tls` is set, each connection must
+/// complete a TLS handshake, within the `HANDSHAKE_TIMEOUT`, before it is served.
+///
+/// Connections beyond `max_connections` are answered with a 503 and closed, so that a misbehaving
+/// client can't exhaust the process's file descriptors.
+async fn serve_connections(
+    incoming: impl Into<Incoming>,
+    tls: Option<TlsAcceptor>,
+    admin: Admin,
+    max_connections: usize,
+    drain: drain::Watch,
+) {
+    let mut incoming = incoming.into();
+    let connections = Arc::new(Semaphore::new(max_connections));
+    loop {
+        let (socket, client) = tokio::select! {
+            res = incoming.accept() => match res {
+                Some(Ok(conn)) => conn,
+                Some(Err(error)) => {
+                    warn!(%error, "Failed to accept connection");
+                    tokio::time::sleep(crate::net::ACCEPT_BACKOFF).await;
+                    continue;
+                }
+                None => {
+                    debug!("Shared listener closed");
+                    return;
+                }
+            },
+            _ = drain.clone().signaled() => {
+                debug!("Shutting down");
+                return;
+            }
+        };
+
+        let permit = connections.clone().try_acquire_owned().ok();
+        if permit.is_none() {
+            debug!(%client, "Too many connections");
+        }
+
+        let tls = tls.clone();
+        let admin = admin.clone();
+        let drain = drain.clone();
+        tokio::spawn(async move {
+            match tls {
+                None => serve_connection(socket, client, admin, permit, drain).await,
+                Some(tls) => {
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, tls.accept(socket)).await {
+                        Ok(Ok(socket)) => {
+                            serve_connection(socket, client, admin, permit, drain).await
+                        }
+                        Ok(Err(error)) => debug!(%error, %client, "TLS handshake failed"),
+                        Err(_) => debug!(%client, "TLS handshake timed out"),
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// How long a connection beyond the limit is given to send its request before it's closed.
+const REJECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Serves a single connection, holding `permit` until the connection completes. If no permit
+/// could be acquired, a 503 is returned and the connection is closed, or it's closed once the
+/// `REJECT_TIMEOUT` elapses if the client doesn't send a request.
+async fn serve_connection<I>(
+    io: I,
+    client: SocketAddr,
+    admin: Admin,
+    permit: Option<OwnedSemaphorePermit>,
+    drain: drain::Watch,
+) where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let permit = match permit {
+        Some(permit) => permit,
+        None => {
+            let rejected = Http::new().http1_keep_alive(false).serve_connection(
+                io,
+                service_fn(|_: Request<Body>| future::ok::<_, Infallible>(unavailable())),
+            );
+            if tokio::time::timeout(REJECT_TIMEOUT, rejected)
+                .await
+                .is_err()
+            {
+                debug!(%client, "Closing idle connection beyond the limit");
+            }
+            return;
+        }
+    };
+
+    let service = service_fn(move |req: Request<Body>| {
+        let admin = admin.clone();
+        async move { Ok::<_, Infallible>(admin.handle(req).await) }
+    });
+    let conn = Http::new().serve_connection(io, service);
+    tokio::pin!(conn);
+    let res = tokio::select! {
+        res = &mut conn => res,
+        handle = drain.signaled() => {
+            conn.as_mut().graceful_shutdown();
+            handle.release_after(conn).await
+        }
+    };
+    if let Err(error) = res {
+        debug!(%error, %client, "Connection failed");
+    }
+    drop(permit);
+}
+
+// === impl Incoming ===
+
+impl From<TcpListener> for Incoming {
+    fn from(listener: TcpListener) -> Self {
+        Self::Listener(listener)
+    }
+}
+
+impl Incoming {
+    /// Accepts the next connection, or returns `None` if a shared listener has closed.
+    async fn accept(&mut self) -> Option<std::io::Result<(TcpStream, SocketAddr)>> {
+        match self {
+            Self::Listener(listener) => Some(listener.accept().await),
+            Self::Shared(connections) => connections.recv().await.map(Ok),
+        }
+    }
+}
+
+// === impl Admin ===
+
+impl Admin {
+    async fn handle(&self, req: Request<Body>) -> Response<Body> {
+        let path = req.uri().path();
+        match (
+            path,
+            &self.ready,
+            &self.metrics,
+            &self.index,
+            &self.policies,
+        ) {
+            ("/ready", Some(ready), _, _, _) => handle_ready(ready, req),
+            ("/metrics", _, Some(metrics), _, _) => handle_metrics(metrics, req),
+            ("/debug/pprof/profile", _, _, _, _) if self.profiling => handle_profile(req).await,
+            ("/debug/index", _, _, Some(index), _) => handle_index(index, req).await,
+            ("/debug/policy", _, _, _, Some(policies)) => handle_policy(policies, req).await,
+            ("/version", _, _, _, _) => handle_version(req),
+            _ => Response::builder()
+                .status(hyper::StatusCode::NOT_FOUND)
+                .body(Body::default())
+                .unwrap(),
+        }
+    }
 }
 
 fn handle_ready(ready: &watch::Receiver<bool>, req: Request<Body>) -> Response<Body> {
@@ -37,15 +366,575 @@ fn handle_ready(ready: &watch::Receiver<bool>, req: Request<Body>) -> Response<B
                     .unwrap()
             } else {
                 Response::builder()
-                    .status(hyper::StatusCode::INTERNAL_SERVER_ERROR)
+                    .status(hyper::StatusCode::SERVICE_UNAVAILABLE)
                     .header(hyper::header::CONTENT_TYPE, "text/plain")
                     .body("not ready\n".into())
                     .unwrap()
             }
         }
-        _ => Response::builder()
-            .status(hyper::StatusCode::METHOD_NOT_ALLOWED)
-            .body(Body::default())
+        _ => method_not_allowed(),
+    }
+}
+
+fn handle_metrics(metrics: &Registry, req: Request<Body>) -> Response<Body> {
+    match *req.method() {
+        hyper::Method::GET | hyper::Method::HEAD => Response::builder()
+            .status(hyper::StatusCode::OK)
+            .header(hyper::header::CONTENT_TYPE, "text/plain; version=0.0.4")
+            .body(metrics.encode().into())
+            .unwrap(),
+        _ => method_not_allowed(),
+    }
+}
+
+/// Describes the controller's build so that operators can tell which build a pod is running.
+fn handle_version(req: Request<Body>) -> Response<Body> {
+    match *req.method() {
+        hyper::Method::GET | hyper::Method::HEAD => Response::builder()
+            .status(hyper::StatusCode::OK)
+            .header(hyper::header::CONTENT_TYPE, "application/json")
+            .body(build_info::to_json().to_string().into())
+            .unwrap(),
+        _ => method_not_allowed(),
+    }
+}
+
+async fn handle_index(index: &k8s::DumpHandle, req: Request<Body>) -> Response<Body> {
+    if req.method() != hyper::Method::GET {
+        return method_not_allowed();
+    }
+
+    match index.dump().await {
+        Some(dump) => Response::builder()
+            .status(hyper::StatusCode::OK)
+            .header(hyper::header::CONTENT_TYPE, "application/json")
+            .body(
+                serde_json::to_vec_pretty(&dump)
+                    .expect("index state must serialize")
+                    .into(),
+            )
             .unwrap(),
+        None => unavailable(),
+    }
+}
+
+/// Describes the policy served for `?namespace=NS&workload=POD&port=PORT`, i.e. the pod-port's
+/// server (or default policy) and the authorizations that apply to it.
+async fn handle_policy(policies: &k8s::Reader, req: Request<Body>) -> Response<Body> {
+    if req.method() != hyper::Method::GET {
+        return method_not_allowed();
+    }
+
+    let query = |name: &str| {
+        req.uri()
+            .query()
+            .into_iter()
+            .flat_map(|q| q.split('&'))
+            .find_map(|kv| kv.strip_prefix(name)?.strip_prefix('='))
+            .filter(|v| !v.is_empty())
+    };
+    let (ns, pod, port) = match (query("namespace"), query("workload"), query("port")) {
+        (Some(ns), Some(pod), Some(port)) => match port.parse::<u16>() {
+            Ok(port) => (ns.to_string(), pod.to_string(), port),
+            Err(_) => return bad_request(format!("invalid port: {:?}", port)),
+        },
+        _ => return bad_request("namespace, workload, and port must be set".to_string()),
+    };
+
+    let server = match policies
+        .get_inbound_server((ns.clone(), pod.clone(), port))
+        .await
+    {
+        Ok(Some(server)) => server,
+        Ok(None) => {
+            return Response::builder()
+                .status(hyper::StatusCode::NOT_FOUND)
+                .header(hyper::header::CONTENT_TYPE, "text/plain")
+                .body(format!("pod {}/{} with port {} not found\n", ns, pod, port).into())
+                .unwrap();
+        }
+        Err(error) => {
+            warn!(%error, "Failed to resolve policy");
+            return Response::builder()
+                .status(hyper::StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::default())
+                .unwrap();
+        }
+    };
+
+    let authorizations = server
+        .authorizations
+        .iter()
+        .map(|(name, authz)| {
+            let networks = authz
+                .networks
+                .iter()
+                .map(|n| {
+                    serde_json::json!({
+                        "net": n.net.to_string(),
+                        "except": n.except.iter().map(ToString::to_string).collect::<Vec<_>>(),
+                    })
+                })
+                .collect::<Vec<_>>();
+            let authentication = match authz.authentication {
+                ClientAuthentication::Unauthenticated => serde_json::json!("unauthenticated"),
+                ClientAuthentication::TlsUnauthenticated => {
+                    serde_json::json!("tls-unauthenticated")
+                }
+                ClientAuthentication::TlsAuthenticated(ref identities) => serde_json::json!({
+                    "identities": identities.iter().map(ToString::to_string).collect::<Vec<_>>(),
+                }),
+            };
+            let authz = serde_json::json!({
+                "networks": networks,
+                "authentication": authentication,
+            });
+            (name.clone(), authz)
+        })
+        .collect::<serde_json::Map<_, _>>();
+    let policy = serde_json::json!({
+        "namespace": ns,
+        "workload": pod,
+        "port": port,
+        "server": server.name,
+        "protocol": format!("{:?}", server.protocol),
+        "authorizations": authorizations,
+    });
+    Response::builder()
+        .status(hyper::StatusCode::OK)
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(
+            serde_json::to_vec_pretty(&policy)
+                .expect("policy must serialize")
+                .into(),
+        )
+        .unwrap()
+}
+
+/// Records a CPU profile for `?seconds=N` (30 by default), returning it in pprof's protobuf
+/// format.
+///
+/// Heap profiles are not currently supported, since they require allocator-level support.
+async fn handle_profile(req: Request<Body>) -> Response<Body> {
+    const FREQUENCY_HZ: i32 = 99;
+    const MAX_SECONDS: u64 = 300;
+
+    if req.method() != hyper::Method::GET {
+        return method_not_allowed();
+    }
+
+    let seconds = match req
+        .uri()
+        .query()
+        .into_iter()
+        .flat_map(|q| q.split('&'))
+        .find_map(|kv| kv.strip_prefix("seconds="))
+        .map(|s| s.parse::<u64>())
+        .unwrap_or(Ok(30))
+    {
+        Ok(s) if 0 < s && s <= MAX_SECONDS => s,
+        _ => {
+            return Response::builder()
+                .status(hyper::StatusCode::BAD_REQUEST)
+                .header(hyper::header::CONTENT_TYPE, "text/plain")
+                .body(format!("seconds must be between 1 and {}\n", MAX_SECONDS).into())
+                .unwrap();
+        }
+    };
+
+    // The profiler is driven by signals rather than by the runtime, so it's run on a blocking
+    // thread for the duration of the profile.
+    let profile = tokio::task::spawn_blocking(move || -> Result<Vec<u8>> {
+        use pprof::protos::Message;
+
+        let guard = pprof::ProfilerGuard::new(FREQUENCY_HZ)?;
+        std::thread::sleep(Duration::from_secs(seconds));
+        let profile = guard.report().build()?.pprof()?;
+        let mut buf = Vec::new();
+        profile.encode(&mut buf)?;
+        Ok(buf)
+    })
+    .await;
+
+    match profile {
+        Ok(Ok(profile)) => Response::builder()
+            .status(hyper::StatusCode::OK)
+            .header(hyper::header::CONTENT_TYPE, "application/octet-stream")
+            .body(profile.into())
+            .unwrap(),
+        Ok(Err(error)) => {
+            warn!(%error, "Failed to record CPU profile");
+            Response::builder()
+                .status(hyper::StatusCode::INTERNAL_SERVER_ERROR)
+                .header(hyper::header::CONTENT_TYPE, "text/plain")
+                .body(format!("{}\n", error).into())
+                .unwrap()
+        }
+        Err(error) => {
+            warn!(%error, "Profiling task failed");
+            Response::builder()
+                .status(hyper::StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::default())
+                .unwrap()
+        }
+    }
+}
+
+fn bad_request(message: String) -> Response<Body> {
+    Response::builder()
+        .status(hyper::StatusCode::BAD_REQUEST)
+        .header(hyper::header::CONTENT_TYPE, "text/plain")
+        .body(format!("{}\n", message).into())
+        .unwrap()
+}
+
+fn unavailable() -> Response<Body> {
+    Response::builder()
+        .status(hyper::StatusCode::SERVICE_UNAVAILABLE)
+        .body(Body::default())
+        .unwrap()
+}
+
+fn method_not_allowed() -> Response<Body> {
+    Response::builder()
+        .status(hyper::StatusCode::METHOD_NOT_ALLOWED)
+        .body(Body::default())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn get(admin: &Admin, path: &str) -> hyper::StatusCode {
+        let req = Request::get(path).body(Body::default()).unwrap();
+        admin.handle(req).await.status()
+    }
+
+    #[tokio::test]
+    async fn serves_metrics_separately() {
+        let (_ready_tx, ready) = watch::channel(true);
+        let health = Admin {
+            ready: Some(ready),
+            metrics: None,
+            profiling: false,
+            index: None,
+            policies: None,
+        };
+        assert_eq!(get(&health, "/ready").await, hyper::StatusCode::OK);
+        assert_eq!(get(&health, "/metrics").await, hyper::StatusCode::NOT_FOUND);
+
+        let metrics = Admin {
+            ready: None,
+            metrics: Some(Registry::default()),
+            profiling: false,
+            index: None,
+            policies: None,
+        };
+        assert_eq!(get(&metrics, "/ready").await, hyper::StatusCode::NOT_FOUND);
+        assert_eq!(get(&metrics, "/metrics").await, hyper::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn not_ready_until_signaled() {
+        let (ready_tx, ready) = watch::channel(false);
+        let admin = Admin {
+            ready: Some(ready),
+            metrics: Some(Registry::default()),
+            profiling: false,
+            index: None,
+            policies: None,
+        };
+        assert_eq!(
+            get(&admin, "/ready").await,
+            hyper::StatusCode::SERVICE_UNAVAILABLE
+        );
+
+        ready_tx.send(true).unwrap();
+        assert_eq!(get(&admin, "/ready").await, hyper::StatusCode::OK);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn rejects_excess_connections() {
+        use std::io::{Read, Write};
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (_ready_tx, ready) = watch::channel(true);
+        let admin = Admin {
+            ready: Some(ready),
+            metrics: None,
+            profiling: false,
+            index: None,
+            policies: None,
+        };
+        let (drain_tx, drain_rx) = drain::channel();
+        tokio::spawn(serve_connections(listener, None, admin, 1, drain_rx));
+
+        let responses = tokio::task::spawn_blocking(move || {
+            let get = |conn: &mut std::net::TcpStream| {
+                conn.write_all(b"GET /ready HTTP/1.1\r\nhost: admin\r\n\r\n")
+                    .unwrap();
+                let mut buf = [0u8; 1024];
+                let n = conn.read(&mut buf).unwrap();
+                String::from_utf8_lossy(&buf[..n]).to_string()
+            };
+
+            // The first connection is held open, so it continues to hold the only permit.
+            let mut first = std::net::TcpStream::connect(addr).unwrap();
+            let ok = get(&mut first);
+            let mut second = std::net::TcpStream::connect(addr).unwrap();
+            let rejected = get(&mut second);
+
+            // Excess connections that never send a request are closed rather than held open.
+            let mut idle = std::net::TcpStream::connect(addr).unwrap();
+            idle.set_read_timeout(Some(REJECT_TIMEOUT * 5)).unwrap();
+            let closed = idle
+                .read(&mut [0u8; 1])
+                .expect("idle connection must be closed");
+            assert_eq!(closed, 0);
+            (ok, rejected)
+        })
+        .await
+        .unwrap();
+
+        assert!(responses.0.starts_with("HTTP/1.1 200"), "{}", responses.0);
+        assert!(responses.1.starts_with("HTTP/1.1 503"), "{}", responses.1);
+        drain_tx.drain().await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn serves_tls() {
+        use std::io::{Read, Write};
+        use tokio_rustls::rustls;
+
+        let testdata = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata");
+        let cert = testdata.join("localhost.crt");
+        let certs = tls::CertResolver::load(cert.clone(), testdata.join("localhost.key")).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (_ready_tx, ready) = watch::channel(true);
+        let admin = Admin {
+            ready: Some(ready),
+            metrics: None,
+            profiling: false,
+            index: None,
+            policies: None,
+        };
+        let (drain_tx, drain_rx) = drain::channel();
+        let tls = Some(tls::acceptor(certs));
+        tokio::spawn(serve_connections(listener, tls, admin, 1, drain_rx));
+
+        let rsp = tokio::task::spawn_blocking(move || {
+            // The self-signed certificate is its own trust root.
+            let mut roots = rustls::RootCertStore::empty();
+            let pem = std::fs::read(cert).unwrap();
+            for der in rustls_pemfile::certs(&mut &pem[..]).unwrap() {
+                roots.add(&rustls::Certificate(der)).unwrap();
+            }
+            let config = rustls::ClientConfig::builder()
+                .with_safe_defaults()
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+            let conn =
+                rustls::ClientConnection::new(Arc::new(config), "localhost".try_into().unwrap())
+                    .unwrap();
+            let mut conn =
+                rustls::StreamOwned::new(conn, std::net::TcpStream::connect(addr).unwrap());
+
+            conn.write_all(b"GET /ready HTTP/1.1\r\nhost: admin\r\n\r\n")
+                .expect("TLS handshake must succeed");
+            let mut buf = [0u8; 1024];
+            let n = conn.read(&mut buf).unwrap();
+            String::from_utf8_lossy(&buf[..n]).to_string()
+        })
+        .await
+        .unwrap();
+
+        assert!(rsp.starts_with("HTTP/1.1 200"), "{}", rsp);
+        drain_tx.drain().await;
+    }
+
+    #[tokio::test]
+    async fn serves_version() {
+        let admin = Admin {
+            ready: None,
+            metrics: None,
+            profiling: false,
+            index: None,
+            policies: None,
+        };
+        let req = Request::get("/version").body(Body::default()).unwrap();
+        let rsp = admin.handle(req).await;
+        assert_eq!(rsp.status(), hyper::StatusCode::OK);
+
+        let body = hyper::body::to_bytes(rsp.into_body()).await.unwrap();
+        let info = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+        for field in ["version", "git_sha", "build_timestamp"] {
+            let value = info[field].as_str().unwrap_or_default();
+            assert!(!value.is_empty(), "{} must be set: {}", field, info);
+        }
+        assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+    }
+
+    #[tokio::test]
+    async fn debug_index_disabled() {
+        let admin = Admin {
+            ready: None,
+            metrics: None,
+            profiling: false,
+            index: None,
+            policies: None,
+        };
+        assert_eq!(
+            get(&admin, "/debug/index").await,
+            hyper::StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            get(
+                &admin,
+                "/debug/policy?namespace=ns-0&workload=web-0&port=8080"
+            )
+            .await,
+            hyper::StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn resolves_policies() {
+        let resources = crate::static_policy::parse(
+            r#"
+kind: Pod
+metadata:
+  name: web-0
+  namespace: ns-0
+  labels:
+    app: web
+spec:
+  containers:
+    - name: web
+      ports:
+        - containerPort: 8080
+        - containerPort: 9090
+---
+apiVersion: policy.linkerd.io/v1beta1
+kind: Server
+metadata:
+  name: web-http
+  namespace: ns-0
+spec:
+  podSelector:
+    matchLabels:
+      app: web
+  port: 8080
+  proxyProtocol: HTTP/1
+---
+apiVersion: policy.linkerd.io/v1beta1
+kind: ServerAuthorization
+metadata:
+  name: web-public
+  namespace: ns-0
+spec:
+  server:
+    name: web-http
+  client:
+    unauthenticated: true
+"#,
+        )
+        .unwrap();
+        let cluster = k8s::ClusterInfo {
+            networks: vec!["10.0.0.0/8".parse().unwrap()],
+            control_plane_ns: "linkerd".to_string(),
+            identity_domain: "cluster.local".to_string(),
+        };
+        let (reader, index) =
+            k8s::Index::new(cluster, k8s::DefaultPolicy::Deny, Duration::from_secs(1));
+        let (ready_tx, mut ready_rx) = watch::channel(false);
+        tokio::spawn(index.run(crate::static_policy::watches(resources), ready_tx));
+        ready_rx.changed().await.unwrap();
+
+        let admin = Admin {
+            ready: None,
+            metrics: None,
+            profiling: false,
+            index: None,
+            policies: Some(reader),
+        };
+        let policy = |port: u16| {
+            let req = Request::get(format!(
+                "/debug/policy?namespace=ns-0&workload=web-0&port={}",
+                port
+            ))
+            .body(Body::default())
+            .unwrap();
+            let admin = admin.clone();
+            async move {
+                let rsp = admin.handle(req).await;
+                assert_eq!(rsp.status(), hyper::StatusCode::OK);
+                let body = hyper::body::to_bytes(rsp.into_body()).await.unwrap();
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+            }
+        };
+
+        let selected = policy(8080).await;
+        assert_eq!(selected["server"], "web-http");
+        assert_eq!(selected["protocol"], "Http1");
+        assert_eq!(
+            selected["authorizations"]["web-public"],
+            serde_json::json!({
+                "networks": [{ "net": "10.0.0.0/8", "except": [] }],
+                "authentication": "unauthenticated",
+            })
+        );
+
+        let default = policy(9090).await;
+        assert_eq!(default["server"], "default:deny");
+        assert_eq!(default["authorizations"], serde_json::json!({}));
+
+        for (path, status) in [
+            (
+                "/debug/policy?namespace=ns-0&workload=web-1&port=8080",
+                hyper::StatusCode::NOT_FOUND,
+            ),
+            (
+                "/debug/policy?namespace=ns-0&workload=web-0&port=7070",
+                hyper::StatusCode::NOT_FOUND,
+            ),
+            (
+                "/debug/policy?namespace=ns-0&workload=web-0",
+                hyper::StatusCode::BAD_REQUEST,
+            ),
+            (
+                "/debug/policy?namespace=ns-0&workload=web-0&port=http",
+                hyper::StatusCode::BAD_REQUEST,
+            ),
+        ] {
+            assert_eq!(get(&admin, path).await, status, "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn profiling_disabled() {
+        let admin = Admin {
+            ready: None,
+            metrics: None,
+            profiling: false,
+            index: None,
+            policies: None,
+        };
+        assert_eq!(
+            get(&admin, "/debug/pprof/profile?seconds=1").await,
+            hyper::StatusCode::NOT_FOUND
+        );
+
+        let admin = Admin {
+            profiling: true,
+            ..admin
+        };
+        assert_eq!(
+            get(&admin, "/debug/pprof/profile?seconds=0").await,
+            hyper::StatusCode::BAD_REQUEST
+        );
     }
 }
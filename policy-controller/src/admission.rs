@@ -1,95 +1,801 @@
-use crate::api;
+use crate::api::{
+    self,
+    api::core::v1::{Event, EventSource, ObjectReference},
+    chrono::{DateTime, Utc},
+    labels::Selector,
+    ObjectMeta, Time,
+};
+use crate::k8s::NamespaceLabels;
 use anyhow::{anyhow, bail, Result};
-use api::policy::ServerSpec;
-use kube::{api::Api, core::DynamicObject, ResourceExt};
-use std::convert::{Infallible, TryInto};
+use api::policy::{ServerAuthorizationSpec, ServerSpec};
+use kube::{
+    api::{Api, PostParams},
+    core::DynamicObject,
+    ResourceExt,
+};
+use linkerd_policy_controller_metrics::{Counter, Family, Registry};
+use serde::de::DeserializeOwned;
+use std::{
+    collections::HashSet,
+    convert::{Infallible, TryInto},
+    num::{NonZeroU32, NonZeroU64},
+    sync::{Arc, Mutex},
+};
+use tokio::{sync::watch, time};
 use tracing::{debug, info, warn};
 use warp::{filters::BoxedFilter, http, reply, Filter};
 
-/// Builds an API handler for a validating admission webhook that examines `Server` resources.
-pub fn routes(client: kube::Client) -> BoxedFilter<(impl warp::Reply,)> {
-    warp::post()
-        .and(warp::path::end())
-        // 64KB should be more than enough for any `Server` instance we can expect.
-        .and(warp::body::content_length_limit(64 * 1024).and(warp::body::json()))
-        .and(warp::any().map(move || client.clone()))
-        .and_then(|review: Review, client| async move {
-            let req: Request = match review.try_into() {
-                Ok(req) => req,
-                Err(error) => {
-                    warn!(%error, "Invalid admission request");
-                    return ok(reply::json(&Response::invalid(error).into_review()));
-                }
-            };
-            debug!(?req);
+/// Handles admission reviews.
+#[derive(Clone)]
+pub struct Admission {
+    client: kube::Client,
+    decisions: Decisions,
 
-            let rsp = Response::from(&req);
+    /// Unset in observe-only mode, so that no events are written.
+    events: Option<Events>,
 
-            // Parse the server instance under review before doing anything with the API--i.e., if
-            // this fails we don't have to waste the API calls.
-            let (ns, name, review_spec) = match parse_server(req) {
-                Ok(s) => s,
-                Err(error) => {
-                    warn!(%error, "Failed to deserialize server from admission request");
-                    return ok(reply::json(&Response::invalid(error).into_review()));
-                }
-            };
+    /// When set, limits the rate at which reviews are processed.
+    rate_limit: Option<RateLimit>,
 
-            // Fetch a list of servers so that we can detect conflicts.
-            //
-            // TODO(ver) We already have a watch on these resources, so we could simply lookup
-            // against an index to avoid unnecessary work on the API server.
-            let api = Api::<api::policy::Server>::namespaced(client, &*ns);
-            let servers = match api.list(&Default::default()).await {
-                Ok(servers) => servers,
-                Err(error) => {
-                    warn!(%error, "Failed to list servers");
-                    return ok(http::StatusCode::INTERNAL_SERVER_ERROR);
-                }
-            };
+    scope: Scope,
 
-            // If validation fails, deny admission.
-            let rsp = match validate(&name, &review_spec, &*servers.items) {
-                Ok(()) => rsp,
-                Err(error) => {
-                    info!(%error, %ns, %name, "Denying server");
-                    rsp.deny(error)
-                }
-            };
-            debug!(?rsp);
-            ok(reply::json(&rsp.into_review()))
+    failure_policy: FailurePolicy,
+
+    /// When set, resources are reviewed as usual but are always admitted, so that the webhook's
+    /// decisions can be observed before they're enforced.
+    observe_only: bool,
+}
+
+/// Determines how a review is answered when the webhook fails to review a resource, e.g. because
+/// the API server can't be reached.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FailurePolicy {
+    /// The resource is admitted with a warning.
+    Open,
+
+    /// The resource is denied.
+    Closed,
+}
+
+/// Limits the namespaces in which resources are reviewed. Resources in other namespaces are
+/// admitted without review, so that a broadly-registered webhook can't block unrelated writes.
+///
+/// When both are set, a namespace must be listed and match the selector.
+#[derive(Clone, Debug, Default)]
+pub struct Scope {
+    /// When set, only resources in these namespaces are reviewed.
+    pub namespaces: Option<HashSet<String>>,
+
+    /// When set, only resources in namespaces with matching labels are reviewed.
+    pub namespace_selector: Option<Selector>,
+
+    /// The labels of the indexed namespaces, against which the selector is matched.
+    pub namespace_labels: NamespaceLabels,
+}
+
+/// A token bucket that admits up to a fixed number of reviews per second, allowing bursts of up to
+/// one second's worth of reviews.
+#[derive(Clone, Debug)]
+struct RateLimit(Arc<Mutex<Bucket>>);
+
+#[derive(Debug)]
+struct Bucket {
+    per_sec: f64,
+    tokens: f64,
+    refilled: time::Instant,
+}
+
+/// Records the outcome of each admission review.
+///
+/// Every decision is counted, labeled by the resource kind, the decision, and a reason code from a
+/// fixed set. Each decision is also logged at `debug` level with the resource's identity.
+#[derive(Clone, Debug)]
+struct Decisions(Family<Counter>);
+
+/// Records Kubernetes events on resources that are denied admission, so that denials are visible
+/// via `kubectl describe` and retained with the cluster's other events.
+///
+/// The webhook never mutates resources, so only denials are recorded. Events are only recorded
+/// when the controller is authorized to create them; otherwise, failures are logged.
+#[derive(Clone)]
+struct Events {
+    client: kube::Client,
+
+    /// When leader election is enabled, events are only recorded while this replica leads.
+    leader: Option<watch::Receiver<bool>>,
+}
+
+/// Identifies the resource under review.
+#[derive(Debug)]
+struct Subject {
+    kind: String,
+    ns: String,
+    name: String,
+    uid: String,
+}
+
+/// A field that failed validation, formatted like the API server's own validation errors (e.g.
+/// `spec.port: Invalid value: 0: must not be 0`) so that users can find the offending field.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct FieldError {
+    /// The JSON path of the field within the resource.
+    path: &'static str,
+    kind: FieldErrorKind,
+    detail: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum FieldErrorKind {
+    /// The field must be set.
+    Required,
+
+    /// The field is set to an invalid value, formatted as it's reported.
+    Invalid(String),
+
+    /// The field must not be set, given the resource's other fields.
+    Forbidden,
+}
+
+/// All of the fields in a resource that failed validation, reported together so that a resource
+/// can be fixed in one pass.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct FieldErrors(Vec<FieldError>);
+
+/// Builds API handlers for the admission webhooks:
+///
+/// - `/` examines `Server` resources, rejecting servers that conflict with existing servers.
+/// - `/validate` examines `Server` and `ServerAuthorization` resources, rejecting resources that
+///   are invalid on their own.
+///
+/// Each path may be registered with the API server independently. Decisions are counted in the
+/// `admission_decisions_total` metric, and denied resources are annotated with a Kubernetes event.
+///
+/// When a `rate_limit` is set, reviews beyond that many per second (across both paths) are not
+/// processed. Instead, the webhook responds that it's busy so that the request may be retried.
+/// Resources in namespaces outside of the `scope` are admitted unchanged. If a resource can't be
+/// reviewed because of an internal error, it's admitted or denied according to the
+/// `failure_policy`. Reviews larger than `max_body_bytes` are rejected with a 413 before they're
+/// read. Bodies that can't be decoded are answered with a 400 and a review describing the error,
+/// so that the API server applies the webhook's `failurePolicy`, and are counted in the
+/// `admission_decode_errors_total` metric.
+///
+/// When `observe_only` is set, decisions are counted and logged as usual, but every resource is
+/// admitted (with a warning describing any denial) and no events are recorded. When a `leader`
+/// watch is set, reviews are answered on every replica but events are only recorded by the leader.
+#[allow(clippy::too_many_arguments)]
+pub fn routes(
+    client: kube::Client,
+    metrics: &Registry,
+    rate_limit: Option<NonZeroU32>,
+    scope: Scope,
+    failure_policy: FailurePolicy,
+    max_body_bytes: NonZeroU64,
+    observe_only: bool,
+    leader: Option<watch::Receiver<bool>>,
+) -> BoxedFilter<(impl warp::Reply,)> {
+    let admission = Admission {
+        events: Some(Events {
+            client: client.clone(),
+            leader,
         })
+        .filter(|_| !observe_only),
+        client,
+        decisions: Decisions::register(metrics),
+        rate_limit: rate_limit.map(RateLimit::new),
+        scope,
+        failure_policy,
+        observe_only,
+    };
+
+    let conflicts = warp::path::end()
+        .and(review(admission.clone(), max_body_bytes))
+        .and_then(|review: Review, admission: Admission| admission.check_conflicts(review));
+
+    let validate = warp::path("validate")
+        .and(warp::path::end())
+        .and(review(admission, max_body_bytes))
+        .and_then(|review: Review, admission: Admission| admission.validate(review));
+
+    let decode_errors = metrics.register(
+        "admission_decode_errors_total",
+        "Total admission requests with bodies that could not be decoded as admission reviews.",
+        Counter::default(),
+    );
+
+    warp::post()
+        .and(conflicts.or(validate).unify())
+        .recover(move |rejection| decode_failed(rejection, decode_errors.clone()))
+        .unify()
         .with(warp::trace::request())
         .boxed()
 }
 
+/// Answers a request with a body that can't be decoded with a review describing the error. Other
+/// rejections (e.g. of oversized bodies) are passed through.
+async fn decode_failed(
+    rejection: warp::Rejection,
+    decode_errors: Counter,
+) -> Result<Box<dyn warp::Reply>, warp::Rejection> {
+    let error = match rejection.find::<warp::filters::body::BodyDeserializeError>() {
+        Some(error) => error,
+        None => return Err(rejection),
+    };
+    warn!(%error, "Failed to decode admission review");
+    decode_errors.inc();
+    let mut rsp = Response::invalid(error);
+    rsp.result.code = Some(400);
+    let review = reply::json(&rsp.into_review());
+    Ok(Box::new(reply::with_status(
+        review,
+        http::StatusCode::BAD_REQUEST,
+    )))
+}
+
+/// Extracts an admission review from the request body.
+fn review(
+    admission: Admission,
+    max_body_bytes: NonZeroU64,
+) -> impl Filter<Extract = (Review, Admission), Error = warp::Rejection> + Clone {
+    warp::body::content_length_limit(max_body_bytes.get())
+        .and(warp::body::json())
+        .and(warp::any().map(move || admission.clone()))
+}
+
 type Review = kube::core::admission::AdmissionReview<DynamicObject>;
 type Request = kube::core::admission::AdmissionRequest<DynamicObject>;
 type Response = kube::core::admission::AdmissionResponse;
 
+// === impl Admission ===
+
+impl Admission {
+    /// Denies `Server` resources that select the same pods and port as an existing `Server`.
+    async fn check_conflicts(self, review: Review) -> Result<Box<dyn warp::Reply>, Infallible> {
+        let req: Request = match review.try_into() {
+            Ok(req) => req,
+            Err(error) => {
+                warn!(%error, "Invalid admission request");
+                self.decisions
+                    .record(&Subject::unknown(), "error", "malformed_review");
+                return self.reply(Response::invalid(error));
+            }
+        };
+        debug!(?req);
+
+        let rsp = Response::from(&req);
+        let subject = Subject::from(&req);
+        if self.is_limited() {
+            self.decisions.record(&subject, "error", "rate_limited");
+            return self.reply(busy(&req));
+        }
+        if !self.in_scope(&req) {
+            self.decisions.record(&subject, "allow", "out_of_scope");
+            return self.reply(rsp);
+        }
+        if req.kind.kind != "Server" {
+            self.decisions.record(&subject, "allow", "unsupported_kind");
+            return self.reply(allow_unsupported(&req));
+        }
+
+        // Parse the server instance under review before doing anything with the API--i.e., if
+        // this fails we don't have to waste the API calls.
+        let target = event_target(&req);
+        let (ns, name, review_spec) = match parse_spec::<ServerSpec>(req) {
+            Ok(s) => s,
+            Err(error) => {
+                warn!(%error, "Failed to deserialize server from admission request");
+                self.decisions
+                    .record(&subject, "error", "malformed_resource");
+                return self.reply(Response::invalid(error));
+            }
+        };
+
+        // Fetch a list of servers so that we can detect conflicts.
+        //
+        // TODO(ver) We already have a watch on these resources, so we could simply lookup
+        // against an index to avoid unnecessary work on the API server.
+        let api = Api::<api::policy::Server>::namespaced(self.client.clone(), &ns);
+        let servers = match api.list(&Default::default()).await {
+            Ok(servers) => servers,
+            Err(error) => {
+                warn!(%error, "Failed to list servers");
+                self.decisions.record(&subject, "error", "api_error");
+                let rsp = self.failure_policy.respond(rsp, "failed to list servers");
+                return self.reply(rsp);
+            }
+        };
+
+        // If validation fails, deny admission.
+        let rsp = match check_conflicts(&name, &review_spec, &servers.items) {
+            Ok(()) => {
+                self.decisions.record(&subject, "allow", "no_conflict");
+                rsp
+            }
+            Err(error) => {
+                info!(%error, %ns, %name, "Denying server");
+                self.decisions.record(&subject, "deny", "conflict");
+                if let (Some(events), Some(target)) = (self.events.as_ref(), target) {
+                    events.denied(target, "Conflict", error.to_string());
+                }
+                rsp.deny(error)
+            }
+        };
+        debug!(?rsp);
+        self.reply(rsp)
+    }
+
+    /// Denies `Server` and `ServerAuthorization` resources with invalid specs.
+    async fn validate(self, review: Review) -> Result<Box<dyn warp::Reply>, Infallible> {
+        let req: Request = match review.try_into() {
+            Ok(req) => req,
+            Err(error) => {
+                warn!(%error, "Invalid admission request");
+                self.decisions
+                    .record(&Subject::unknown(), "error", "malformed_review");
+                return self.reply(Response::invalid(error));
+            }
+        };
+        debug!(?req);
+
+        let rsp = Response::from(&req);
+        let subject = Subject::from(&req);
+        if self.is_limited() {
+            self.decisions.record(&subject, "error", "rate_limited");
+            return self.reply(busy(&req));
+        }
+        if !self.in_scope(&req) {
+            self.decisions.record(&subject, "allow", "out_of_scope");
+            return self.reply(rsp);
+        }
+        let kind = req.kind.kind.clone();
+        let target = event_target(&req);
+        let res = match &*kind {
+            "Server" => parse_spec::<ServerSpec>(req).map(|(ns, name, spec)| {
+                let res = validate_server(&spec);
+                (ns, name, res)
+            }),
+            "ServerAuthorization" => {
+                parse_spec::<ServerAuthorizationSpec>(req).map(|(ns, name, spec)| {
+                    let res = validate_authz(&spec);
+                    (ns, name, res)
+                })
+            }
+            _ => {
+                self.decisions.record(&subject, "allow", "unsupported_kind");
+                return self.reply(allow_unsupported(&req));
+            }
+        };
+        let (ns, name, res) = match res {
+            Ok(parsed) => parsed,
+            Err(error) => {
+                warn!(%error, %kind, "Failed to deserialize resource from admission request");
+                self.decisions
+                    .record(&subject, "error", "malformed_resource");
+                return self.reply(Response::invalid(error));
+            }
+        };
+
+        let rsp = match res {
+            Ok(()) => {
+                self.decisions.record(&subject, "allow", "valid");
+                rsp
+            }
+            Err(error) => {
+                info!(%error, %kind, %ns, %name, "Denying resource");
+                self.decisions.record(&subject, "deny", "invalid_spec");
+                if let (Some(events), Some(target)) = (self.events.as_ref(), target) {
+                    events.denied(target, "Invalid", error.to_string());
+                }
+                rsp.deny(error)
+            }
+        };
+        debug!(?rsp);
+        self.reply(rsp)
+    }
+
+    /// Returns true if the resource under review is in a namespace that the webhook reviews.
+    ///
+    /// Cluster-scoped resources are always reviewed. If the namespace isn't indexed (e.g. because
+    /// it was just created), the resource is reviewed.
+    fn in_scope(&self, req: &Request) -> bool {
+        let ns = match req.namespace.as_deref() {
+            Some(ns) => ns,
+            None => return true,
+        };
+        if !self.scope.includes_name(ns) {
+            debug!(%ns, "Namespace is not reviewed");
+            return false;
+        }
+
+        let selector = match self.scope.namespace_selector.as_ref() {
+            Some(selector) => selector,
+            None => return true,
+        };
+        match self.scope.namespace_labels.get(ns) {
+            Some(labels) => {
+                let matches = selector.matches(&labels);
+                if !matches {
+                    debug!(%ns, "Namespace labels do not match the selector");
+                }
+                matches
+            }
+            None => {
+                warn!(%ns, "Namespace is not indexed; reviewing resource");
+                true
+            }
+        }
+    }
+
+    /// Answers a review for a resource. In observe-only mode, a resource that would have been
+    /// denied is admitted instead, with a warning describing the denial.
+    fn reply(&self, mut rsp: Response) -> Result<Box<dyn warp::Reply>, Infallible> {
+        if self.observe_only && !rsp.allowed {
+            // Invalid responses describe the denial in the status's reason rather than its
+            // message.
+            let message = rsp
+                .result
+                .message
+                .take()
+                .or_else(|| rsp.result.reason.take())
+                .unwrap_or_default();
+            info!(uid = %rsp.uid, %message, "Admitting a resource that would be denied");
+            rsp.allowed = true;
+            rsp.result.code = None;
+            rsp.result.reason = None;
+            rsp.warnings.get_or_insert_with(Vec::new).push(format!(
+                "the admission webhook is only observing; the resource would have been denied: {}",
+                message
+            ));
+        }
+        ok(reply::json(&rsp.into_review()))
+    }
+
+    /// Returns true if the review exceeds the rate limit, if one is set.
+    fn is_limited(&self) -> bool {
+        matches!(&self.rate_limit, Some(limit) if !limit.try_acquire())
+    }
+}
+
+// === impl FailurePolicy ===
+
+impl FailurePolicy {
+    /// Answers a review that failed with an internal error, described by `msg`.
+    fn respond(self, rsp: Response, msg: &str) -> Response {
+        match self {
+            Self::Open => {
+                let mut rsp = rsp;
+                rsp.warnings = Some(vec![format!(
+                    "the admission webhook {}; the resource was not reviewed",
+                    msg
+                )]);
+                rsp
+            }
+            Self::Closed => {
+                let mut rsp = rsp.deny(format!("the admission webhook {}", msg));
+                rsp.result.code = Some(500);
+                rsp.result.reason = Some("InternalError".to_string());
+                rsp
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for FailurePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "open" => Ok(Self::Open),
+            "closed" => Ok(Self::Closed),
+            s => bail!(
+                "invalid failure policy: {:?}; expected 'open' or 'closed'",
+                s
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for FailurePolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Open => "open".fmt(f),
+            Self::Closed => "closed".fmt(f),
+        }
+    }
+}
+
+// === impl Scope ===
+
+impl Scope {
+    fn includes_name(&self, ns: &str) -> bool {
+        match &self.namespaces {
+            Some(namespaces) => namespaces.contains(ns),
+            None => true,
+        }
+    }
+}
+
+// === impl RateLimit ===
+
+impl RateLimit {
+    fn new(per_sec: NonZeroU32) -> Self {
+        let per_sec = f64::from(per_sec.get());
+        Self(Arc::new(Mutex::new(Bucket {
+            per_sec,
+            tokens: per_sec,
+            refilled: time::Instant::now(),
+        })))
+    }
+
+    /// Takes a token from the bucket, returning false if none are available.
+    fn try_acquire(&self) -> bool {
+        let mut bucket = self.0.lock().expect("lock must not be poisoned");
+        let now = time::Instant::now();
+        let elapsed = now.saturating_duration_since(bucket.refilled).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * bucket.per_sec).min(bucket.per_sec);
+        bucket.refilled = now;
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+}
+
+// === impl Decisions ===
+
+impl Decisions {
+    fn register(metrics: &Registry) -> Self {
+        Self(metrics.register(
+            "admission_decisions_total",
+            "Total admission reviews by resource kind, decision, and reason.",
+            Family::new(&["kind", "decision", "reason"]),
+        ))
+    }
+
+    /// Counts and logs a decision. `reason` must come from a fixed set of codes so that the
+    /// metric's cardinality is bounded.
+    fn record(&self, subject: &Subject, decision: &'static str, reason: &'static str) {
+        let Subject {
+            kind,
+            ns,
+            name,
+            uid,
+        } = subject;
+        debug!(%kind, %ns, %name, %uid, %decision, %reason, "Admission decision");
+        self.0.get(&[kind.as_str(), decision, reason]).inc();
+    }
+}
+
+// === impl Events ===
+
+impl Events {
+    /// The component that reports events.
+    const COMPONENT: &'static str = "linkerd-policy-controller";
+
+    /// Records a `Warning` event on `target` in the background.
+    ///
+    /// Recording is best-effort: failures are logged and the admission response is never delayed.
+    fn denied(&self, target: ObjectReference, reason: &'static str, message: String) {
+        if matches!(&self.leader, Some(leader) if !*leader.borrow()) {
+            debug!(%reason, "Not recording admission event, since this replica isn't the leader");
+            return;
+        }
+
+        let event = denied_event(target, reason, message, Utc::now());
+        let ns = event.metadata.namespace.clone().unwrap_or_default();
+        let api = Api::<Event>::namespaced(self.client.clone(), &ns);
+        tokio::spawn(async move {
+            match api.create(&PostParams::default(), &event).await {
+                Ok(_) => debug!(%ns, %reason, "Recorded admission event"),
+                Err(error) => warn!(%error, %ns, %reason, "Failed to record admission event"),
+            }
+        });
+    }
+}
+
+/// Returns a reference to the resource under review for an event, unless the request is a dry run
+/// or the resource isn't namespaced.
+fn event_target(req: &Request) -> Option<ObjectReference> {
+    if req.dry_run {
+        return None;
+    }
+    let namespace = req.namespace.clone()?;
+    let kind = &req.kind;
+    let api_version = if kind.group.is_empty() {
+        kind.version.clone()
+    } else {
+        format!("{}/{}", kind.group, kind.version)
+    };
+    let uid = match (&req.object, &req.old_object) {
+        (Some(obj), _) | (None, Some(obj)) => obj.metadata.uid.clone(),
+        (None, None) => None,
+    };
+    Some(ObjectReference {
+        api_version: Some(api_version),
+        kind: Some(kind.kind.clone()),
+        name: Some(req.name.clone()),
+        namespace: Some(namespace),
+        uid,
+        ..Default::default()
+    })
+}
+
+/// Builds a `Warning` event describing a denial of `target`.
+fn denied_event(
+    target: ObjectReference,
+    reason: &str,
+    message: String,
+    now: DateTime<Utc>,
+) -> Event {
+    Event {
+        metadata: ObjectMeta {
+            generate_name: target.name.as_ref().map(|n| format!("{}.", n)),
+            namespace: target.namespace.clone(),
+            ..Default::default()
+        },
+        involved_object: target,
+        reason: Some(reason.to_string()),
+        message: Some(message),
+        type_: Some("Warning".to_string()),
+        count: Some(1),
+        first_timestamp: Some(Time(now)),
+        last_timestamp: Some(Time(now)),
+        source: Some(EventSource {
+            component: Some(Events::COMPONENT.to_string()),
+            host: None,
+        }),
+        reporting_component: Some(Events::COMPONENT.to_string()),
+        ..Default::default()
+    }
+}
+
+// === impl Subject ===
+
+impl Subject {
+    /// Describes a review that couldn't be decoded into a request.
+    fn unknown() -> Self {
+        Self {
+            kind: "unknown".to_string(),
+            ns: String::new(),
+            name: String::new(),
+            uid: String::new(),
+        }
+    }
+}
+
+impl From<&Request> for Subject {
+    fn from(req: &Request) -> Self {
+        Self {
+            kind: req.kind.kind.clone(),
+            ns: req.namespace.clone().unwrap_or_default(),
+            name: req.name.clone(),
+            uid: req.uid.clone(),
+        }
+    }
+}
+
+// === impl FieldError ===
+
+impl FieldError {
+    fn required(path: &'static str, detail: &str) -> Self {
+        Self {
+            path,
+            kind: FieldErrorKind::Required,
+            detail: detail.to_string(),
+        }
+    }
+
+    fn invalid(path: &'static str, value: impl std::fmt::Debug, detail: &str) -> Self {
+        Self {
+            path,
+            kind: FieldErrorKind::Invalid(format!("{:?}", value)),
+            detail: detail.to_string(),
+        }
+    }
+
+    fn forbidden(path: &'static str, detail: &str) -> Self {
+        Self {
+            path,
+            kind: FieldErrorKind::Forbidden,
+            detail: detail.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for FieldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            FieldErrorKind::Required => write!(f, "{}: Required value", self.path)?,
+            FieldErrorKind::Invalid(ref value) => {
+                write!(f, "{}: Invalid value: {}", self.path, value)?
+            }
+            FieldErrorKind::Forbidden => write!(f, "{}: Forbidden", self.path)?,
+        }
+        write!(f, ": {}", self.detail)
+    }
+}
+
+// === impl FieldErrors ===
+
+impl FieldErrors {
+    fn push(&mut self, error: FieldError) {
+        self.0.push(error);
+    }
+
+    fn into_result(self) -> Result<(), Self> {
+        if self.0.is_empty() {
+            return Ok(());
+        }
+        Err(self)
+    }
+}
+
+/// A single error is reported on its own; multiple errors are listed in brackets, as the API
+/// server aggregates them.
+impl std::fmt::Display for FieldErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let [error] = &*self.0 {
+            return error.fmt(f);
+        }
+        write!(f, "[")?;
+        for (i, error) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            error.fmt(f)?;
+        }
+        write!(f, "]")
+    }
+}
+
+impl std::error::Error for FieldErrors {}
+
 #[inline]
 fn ok(reply: impl warp::Reply + 'static) -> Result<Box<dyn warp::Reply>, Infallible> {
     Ok(Box::new(reply))
 }
 
-/// Parses a `Server` instance and it's namespace from the admission request.
-fn parse_server(req: Request) -> Result<(String, String, api::policy::ServerSpec)> {
-    let obj = req.object.ok_or_else(|| anyhow!("missing server"))?;
+/// Returns an allowed, no-op response for a resource kind the webhook doesn't handle.
+///
+/// The webhook may be registered for kinds the controller doesn't know about (e.g. by a
+/// misconfigured `ValidatingWebhookConfiguration`). Failing these reviews would block all writes
+/// to the resource, so they are admitted unchanged.
+fn allow_unsupported(req: &Request) -> Response {
+    let kind = &*req.kind.kind;
+    warn!(%kind, ns = ?req.namespace, name = %req.name, "Allowing unsupported resource kind");
+    Response::from(req)
+}
+
+/// Returns a response indicating that the webhook is too busy to review the resource.
+///
+/// The response carries a `429 Too Many Requests` status so that clients recognize the failure as
+/// transient and retry.
+fn busy(req: &Request) -> Response {
+    warn!(kind = %req.kind.kind, ns = ?req.namespace, name = %req.name, "Admission webhook is busy");
+    let mut rsp = Response::from(req).deny("the admission webhook is busy; try again later");
+    rsp.result.code = Some(429);
+    rsp.result.reason = Some("TooManyRequests".to_string());
+    rsp
+}
+
+/// Parses a resource's namespace, name, and spec from the admission request.
+fn parse_spec<T: DeserializeOwned>(req: Request) -> Result<(String, String, T)> {
+    let obj = req.object.ok_or_else(|| anyhow!("missing resource"))?;
     let ns = obj
         .namespace()
-        .ok_or_else(|| anyhow!("no 'namespace' field set on server"))?;
+        .ok_or_else(|| anyhow!("no 'namespace' field set on resource"))?;
     let name = obj.name();
     let data = obj
         .data
         .get("spec")
         .cloned()
-        .ok_or_else(|| anyhow!("no 'spec' field set on server"))?;
-    let spec = serde_json::from_value::<ServerSpec>(data)?;
+        .ok_or_else(|| anyhow!("no 'spec' field set on resource"))?;
+    let spec = serde_json::from_value::<T>(data)?;
     Ok((ns, name, spec))
 }
 
 /// Validates a new server (`review`) against existing `servers`.
-fn validate(
+fn check_conflicts(
     review_name: &str,
     review_spec: &api::policy::ServerSpec,
     servers: &[api::policy::Server],
@@ -111,3 +817,916 @@ fn validate(
 
     Ok(())
 }
+
+/// Validates a server's spec independently of other resources.
+fn validate_server(spec: &ServerSpec) -> Result<(), FieldErrors> {
+    let mut errors = FieldErrors::default();
+    match spec.port {
+        api::policy::server::Port::Number(0) => {
+            errors.push(FieldError::invalid("spec.port", 0, "must not be 0"))
+        }
+        api::policy::server::Port::Number(_) => {}
+        // A port name that can't be set on a container port can never select a pod's port.
+        api::policy::server::Port::Name(ref name) => {
+            if !is_valid_port_name(name) {
+                errors.push(FieldError::invalid(
+                    "spec.port",
+                    name,
+                    "must be a valid container port name",
+                ));
+            }
+        }
+    }
+
+    errors.into_result()
+}
+
+/// Validates an authorization's spec independently of other resources.
+fn validate_authz(spec: &ServerAuthorizationSpec) -> Result<(), FieldErrors> {
+    let mut errors = FieldErrors::default();
+
+    let selects_all = match (spec.server.name.as_ref(), spec.server.selector.as_ref()) {
+        (Some(_), None) => Some(false),
+        (None, Some(sel)) => Some(sel.is_empty()),
+        (Some(_), Some(_)) => {
+            errors.push(FieldError::forbidden(
+                "spec.server.selector",
+                "must not be set with spec.server.name",
+            ));
+            None
+        }
+        (None, None) => {
+            errors.push(FieldError::required(
+                "spec.server",
+                "must set either name or selector",
+            ));
+            None
+        }
+    };
+
+    let client = &spec.client;
+    let authorizes_all = match (client.unauthenticated, client.mesh_tls.as_ref()) {
+        (true, None) => Some(true),
+        (true, Some(_)) => {
+            errors.push(FieldError::forbidden(
+                "spec.client.meshTLS",
+                "must not be set with spec.client.unauthenticated",
+            ));
+            None
+        }
+        (false, None) => {
+            errors.push(FieldError::required(
+                "spec.client",
+                "must set either unauthenticated or meshTLS",
+            ));
+            None
+        }
+        (false, Some(mtls)) => {
+            let identities = mtls.identities.iter().flatten().collect::<Vec<_>>();
+            let has_clients =
+                !identities.is_empty() || mtls.service_accounts.iter().flatten().next().is_some();
+            if mtls.unauthenticated_tls && has_clients {
+                errors.push(FieldError::forbidden(
+                    "spec.client.meshTLS.unauthenticatedTLS",
+                    "must not be set with identities or serviceAccounts",
+                ));
+                None
+            } else if !mtls.unauthenticated_tls && !has_clients {
+                errors.push(FieldError::required(
+                    "spec.client.meshTLS",
+                    "must set either unauthenticatedTLS, identities, or serviceAccounts",
+                ));
+                None
+            } else {
+                Some(identities.into_iter().any(|id| id == "*"))
+            }
+        }
+    };
+
+    // An authorization that applies to all clients on all servers makes the default policy
+    // meaningless, which is almost certainly unintentional.
+    if selects_all == Some(true) && authorizes_all == Some(true) {
+        errors.push(FieldError::forbidden(
+            "spec.client",
+            "must not authorize all clients when spec.server.selector selects all servers",
+        ));
+    }
+
+    errors.into_result()
+}
+
+/// Checks that `name` is a valid container port name (an IANA_SVC_NAME).
+fn is_valid_port_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 15
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && name.chars().any(|c| c.is_ascii_lowercase())
+        && !name.starts_with('-')
+        && !name.ends_with('-')
+        && !name.contains("--")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{
+        labels::{Labels, Map},
+        policy::{authz, server::Port},
+    };
+
+    fn mk_server_spec(port: Port) -> ServerSpec {
+        ServerSpec {
+            pod_selector: Default::default(),
+            port,
+            proxy_protocol: None,
+        }
+    }
+
+    fn mk_authz_spec(server: authz::Server, client: authz::Client) -> ServerAuthorizationSpec {
+        ServerAuthorizationSpec { server, client }
+    }
+
+    /// Builds a review of the creation of `object`, taking the request's kind, name, and namespace
+    /// from the object itself.
+    fn mk_review(resource: &str, object: serde_json::Value, dry_run: bool) -> Review {
+        let api_version = object["apiVersion"].as_str().unwrap().to_string();
+        let (group, version) = api_version.split_once('/').unwrap_or(("", &api_version));
+        let kind = object["kind"].clone();
+        let name = object["metadata"]["name"].clone();
+        let namespace = object["metadata"]["namespace"].clone();
+        serde_json::from_value::<Review>(serde_json::json!({
+            "apiVersion": "admission.k8s.io/v1",
+            "kind": "AdmissionReview",
+            "request": {
+                "uid": "uid-0",
+                "kind": { "group": group, "version": version, "kind": kind },
+                "resource": { "group": group, "version": version, "resource": resource },
+                "name": name,
+                "namespace": namespace,
+                "operation": "CREATE",
+                "userInfo": {},
+                "dryRun": dry_run,
+                "object": object
+            }
+        }))
+        .unwrap()
+    }
+
+    /// Builds a review of the creation of server `srv-0` in `ns`.
+    fn mk_server_review(ns: &str, spec: serde_json::Value, dry_run: bool) -> Review {
+        mk_review(
+            "servers",
+            serde_json::json!({
+                "apiVersion": "policy.linkerd.io/v1beta1",
+                "kind": "Server",
+                "metadata": { "name": "srv-0", "namespace": ns },
+                "spec": spec
+            }),
+            dry_run,
+        )
+    }
+
+    /// Builds a client whose API server echoes each request's body as a created resource,
+    /// reporting the request's method, path, and body.
+    fn mk_recording_client() -> (
+        kube::Client,
+        tokio::sync::mpsc::UnboundedReceiver<(http::Method, String, hyper::body::Bytes)>,
+    ) {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let client = kube::Client::new(
+            hyper::service::service_fn(move |req: hyper::Request<hyper::Body>| {
+                let tx = tx.clone();
+                async move {
+                    let (parts, body) = req.into_parts();
+                    let body = hyper::body::to_bytes(body).await.unwrap();
+                    let _ = tx.send((parts.method, parts.uri.path().to_string(), body.clone()));
+                    Ok::<_, Infallible>(
+                        hyper::Response::builder()
+                            .status(http::StatusCode::CREATED)
+                            .body(hyper::Body::from(body))
+                            .unwrap(),
+                    )
+                }
+            }),
+            "default",
+        );
+        (client, rx)
+    }
+
+    #[test]
+    fn counts_decisions() {
+        let metrics = Registry::default();
+        let decisions = Decisions::register(&metrics);
+        let subject = Subject {
+            kind: "Server".to_string(),
+            ns: "ns-0".to_string(),
+            name: "srv-0".to_string(),
+            uid: "uid-0".to_string(),
+        };
+        decisions.record(&subject, "allow", "valid");
+        decisions.record(&subject, "allow", "valid");
+        decisions.record(&subject, "deny", "invalid_spec");
+        decisions.record(&Subject::unknown(), "error", "malformed_review");
+
+        let out = metrics.encode();
+        for sample in [
+            "admission_decisions_total{kind=\"Server\",decision=\"allow\",reason=\"valid\"} 2\n",
+            "admission_decisions_total{kind=\"Server\",decision=\"deny\",reason=\"invalid_spec\"} 1\n",
+            "admission_decisions_total{kind=\"unknown\",decision=\"error\",reason=\"malformed_review\"} 1\n",
+        ] {
+            assert!(out.contains(sample), "{}", out);
+        }
+    }
+
+    #[test]
+    fn allows_unsupported_kinds() {
+        let review = mk_review(
+            "configmaps",
+            serde_json::json!({
+                "apiVersion": "v1",
+                "kind": "ConfigMap",
+                "metadata": { "name": "cm-0", "namespace": "ns-0" },
+                "data": {}
+            }),
+            false,
+        );
+        let req: Request = review.try_into().unwrap();
+        let review = serde_json::to_value(allow_unsupported(&req).into_review()).unwrap();
+        assert_eq!(review["response"]["uid"], "uid-0");
+        assert_eq!(review["response"]["allowed"].as_bool(), Some(true));
+        assert!(review["response"]["patch"].is_null());
+    }
+
+    #[test]
+    fn allows_namespace_deny_default() {
+        let review = mk_review(
+            "namespaces",
+            serde_json::json!({
+                "apiVersion": "v1",
+                "kind": "Namespace",
+                "metadata": {
+                    "name": "ns-0",
+                    "annotations": {
+                        "config.linkerd.io/default-inbound-policy": "deny"
+                    }
+                }
+            }),
+            false,
+        );
+        let req: Request = review.try_into().unwrap();
+        let review = serde_json::to_value(allow_unsupported(&req).into_review()).unwrap();
+        assert_eq!(review["response"]["allowed"].as_bool(), Some(true));
+    }
+
+    #[test]
+    fn rate_limit_responds_busy() {
+        let limit = RateLimit::new(NonZeroU32::new(2).unwrap());
+        assert!(limit.try_acquire());
+        assert!(limit.try_acquire());
+        assert!(!limit.try_acquire(), "the limit must be saturated");
+
+        let review = mk_server_review(
+            "ns-0",
+            serde_json::json!({ "podSelector": {}, "port": 8080 }),
+            false,
+        );
+        let req: Request = review.try_into().unwrap();
+        let review = serde_json::to_value(busy(&req).into_review()).unwrap();
+        assert_eq!(review["kind"], "AdmissionReview");
+        assert_eq!(review["response"]["uid"], "uid-0");
+        assert_eq!(review["response"]["allowed"].as_bool(), Some(false));
+        assert_eq!(review["response"]["status"]["code"], 429);
+        assert_eq!(review["response"]["status"]["reason"], "TooManyRequests");
+        assert!(review["response"]["status"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("busy"));
+    }
+
+    #[test]
+    fn denied_event_references_target() {
+        let req: Request = mk_server_review(
+            "ns-0",
+            serde_json::json!({ "podSelector": {}, "port": 0 }),
+            true,
+        )
+        .try_into()
+        .unwrap();
+        assert!(
+            event_target(&req).is_none(),
+            "dry runs must not be recorded"
+        );
+
+        let req: Request = mk_server_review(
+            "ns-0",
+            serde_json::json!({ "podSelector": {}, "port": 0 }),
+            false,
+        )
+        .try_into()
+        .unwrap();
+        let target = event_target(&req).expect("target must be set");
+        assert_eq!(
+            target.api_version.as_deref(),
+            Some("policy.linkerd.io/v1beta1")
+        );
+        assert_eq!(target.kind.as_deref(), Some("Server"));
+
+        let now = Utc::now();
+        let event = denied_event(target.clone(), "Invalid", "port must not be 0".into(), now);
+        assert_eq!(event.metadata.namespace.as_deref(), Some("ns-0"));
+        assert_eq!(event.metadata.generate_name.as_deref(), Some("srv-0."));
+        assert_eq!(event.involved_object, target);
+        assert_eq!(event.reason.as_deref(), Some("Invalid"));
+        assert_eq!(event.message.as_deref(), Some("port must not be 0"));
+        assert_eq!(event.type_.as_deref(), Some("Warning"));
+        assert_eq!(event.last_timestamp, Some(Time(now)));
+    }
+
+    #[tokio::test]
+    async fn records_denial_events() {
+        let (client, mut rx) = mk_recording_client();
+
+        let req: Request = mk_server_review(
+            "ns-0",
+            serde_json::json!({ "podSelector": {}, "port": 0 }),
+            false,
+        )
+        .try_into()
+        .unwrap();
+        let (leader_tx, leader) = watch::channel(false);
+        let events = Events {
+            client,
+            leader: Some(leader),
+        };
+
+        // Only the leader records events.
+        events.denied(
+            event_target(&req).unwrap(),
+            "Invalid",
+            "port must not be 0".into(),
+        );
+        assert!(
+            time::timeout(time::Duration::from_millis(100), rx.recv())
+                .await
+                .is_err(),
+            "events must not be recorded by other replicas"
+        );
+        leader_tx.send(true).unwrap();
+        events.denied(
+            event_target(&req).unwrap(),
+            "Invalid",
+            "port must not be 0".into(),
+        );
+
+        let (method, path, body) = time::timeout(time::Duration::from_secs(1), rx.recv())
+            .await
+            .expect("event must be created")
+            .unwrap();
+        assert_eq!(method, http::Method::POST);
+        assert_eq!(path, "/api/v1/namespaces/ns-0/events");
+        let event = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+        assert_eq!(event["involvedObject"]["name"], "srv-0");
+        assert_eq!(event["reason"], "Invalid");
+        assert_eq!(event["type"], "Warning");
+    }
+
+    #[tokio::test]
+    async fn reviews_in_scope_namespaces() {
+        let mk_ns = |name: &str, labels: &[(&str, &str)]| {
+            let labels = labels
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<Map>();
+            (name.to_string(), Labels::from(labels))
+        };
+        let namespace_labels = vec![
+            mk_ns("ns-0", &[("linkerd.io/inject", "enabled")]),
+            mk_ns("ns-1", &[]),
+            mk_ns("ns-2", &[("linkerd.io/inject", "enabled")]),
+        ]
+        .into_iter()
+        .collect::<NamespaceLabels>();
+        let (client, _) = mk_recording_client();
+        let admission = Admission {
+            events: Some(Events {
+                client: client.clone(),
+                leader: None,
+            }),
+            client,
+            decisions: Decisions::register(&Registry::default()),
+            rate_limit: None,
+            scope: Scope {
+                namespaces: Some(
+                    vec!["ns-0".to_string(), "ns-1".to_string()]
+                        .into_iter()
+                        .collect(),
+                ),
+                namespace_selector: Some("linkerd.io/inject=enabled".parse().unwrap()),
+                namespace_labels: namespace_labels.clone(),
+            },
+            failure_policy: FailurePolicy::Open,
+            observe_only: false,
+        };
+
+        // Reviews an invalid server in `ns`, returning whether it was allowed.
+        let allowed = |ns: &str| {
+            let review = mk_server_review(
+                ns,
+                serde_json::json!({ "podSelector": {}, "port": 0 }),
+                true,
+            );
+            let admission = admission.clone();
+            async move {
+                let rsp = warp::Reply::into_response(admission.validate(review).await.unwrap());
+                let body = hyper::body::to_bytes(rsp.into_body()).await.unwrap();
+                let review = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+                review["response"]["allowed"].as_bool().unwrap()
+            }
+        };
+
+        assert!(
+            !allowed("ns-0").await,
+            "listed and labeled namespaces must be reviewed"
+        );
+        assert!(
+            allowed("ns-1").await,
+            "unlabeled namespaces must not be reviewed"
+        );
+        assert!(
+            allowed("ns-2").await,
+            "unlisted namespaces must not be reviewed"
+        );
+
+        // If the namespace isn't indexed, the resource is reviewed.
+        let scope = Scope {
+            namespaces: None,
+            namespace_selector: Some("linkerd.io/inject=enabled".parse().unwrap()),
+            namespace_labels,
+        };
+        let admission = Admission {
+            scope,
+            ..admission.clone()
+        };
+        let review: Request = mk_server_review(
+            "ns-unknown",
+            serde_json::json!({ "podSelector": {}, "port": 0 }),
+            true,
+        )
+        .try_into()
+        .unwrap();
+        assert!(admission.in_scope(&review));
+    }
+
+    #[tokio::test]
+    async fn internal_errors_follow_failure_policy() {
+        // Stands in for an API server that fails every request.
+        let client = kube::Client::new(
+            hyper::service::service_fn(|_: hyper::Request<hyper::Body>| async move {
+                Ok::<_, Infallible>(
+                    hyper::Response::builder()
+                        .status(http::StatusCode::INTERNAL_SERVER_ERROR)
+                        .body(hyper::Body::from(
+                            r#"{"kind":"Status","apiVersion":"v1","status":"Failure","reason":"InternalError","code":500}"#,
+                        ))
+                        .unwrap(),
+                )
+            }),
+            "default",
+        );
+        let review = || {
+            mk_server_review(
+                "ns-0",
+                serde_json::json!({ "podSelector": {}, "port": 8080 }),
+                true,
+            )
+        };
+
+        for (policy, allowed) in [(FailurePolicy::Open, true), (FailurePolicy::Closed, false)] {
+            let admission = Admission {
+                events: Some(Events {
+                    client: client.clone(),
+                    leader: None,
+                }),
+                client: client.clone(),
+                decisions: Decisions::register(&Registry::default()),
+                rate_limit: None,
+                scope: Scope::default(),
+                failure_policy: policy,
+                observe_only: false,
+            };
+            let rsp =
+                warp::Reply::into_response(admission.check_conflicts(review()).await.unwrap());
+            assert_eq!(rsp.status(), http::StatusCode::OK);
+            let body = hyper::body::to_bytes(rsp.into_body()).await.unwrap();
+            let review = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+            assert_eq!(review["response"]["allowed"], allowed, "{}", policy);
+            if allowed {
+                assert!(review["response"]["warnings"][0]
+                    .as_str()
+                    .unwrap()
+                    .contains("not reviewed"));
+            } else {
+                assert_eq!(review["response"]["status"]["code"], 500);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_oversized_reviews() {
+        // Oversized reviews are rejected before the API server is queried.
+        let client = kube::Client::new(
+            hyper::service::service_fn(|_: hyper::Request<hyper::Body>| async move {
+                Ok::<_, Infallible>(hyper::Response::new(hyper::Body::empty()))
+            }),
+            "default",
+        );
+        let routes = routes(
+            client,
+            &Registry::default(),
+            None,
+            Scope::default(),
+            FailurePolicy::Open,
+            NonZeroU64::new(1024).unwrap(),
+            false,
+            None,
+        );
+
+        for path in ["/", "/validate"] {
+            let rsp = warp::test::request()
+                .method("POST")
+                .path(path)
+                .header("content-type", "application/json")
+                .body(vec![b' '; 1025])
+                .reply(&routes)
+                .await;
+            assert_eq!(
+                rsp.status(),
+                http::StatusCode::PAYLOAD_TOO_LARGE,
+                "{}",
+                path
+            );
+        }
+
+        // Smaller bodies are read (and, here, rejected as malformed JSON).
+        let rsp = warp::test::request()
+            .method("POST")
+            .path("/validate")
+            .header("content-type", "application/json")
+            .body(vec![b' '; 1024])
+            .reply(&routes)
+            .await;
+        assert_eq!(rsp.status(), http::StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn reports_decode_errors() {
+        let client = kube::Client::new(
+            hyper::service::service_fn(|_: hyper::Request<hyper::Body>| async move {
+                Ok::<_, Infallible>(hyper::Response::new(hyper::Body::empty()))
+            }),
+            "default",
+        );
+        let metrics = Registry::default();
+        let routes = routes(
+            client,
+            &metrics,
+            None,
+            Scope::default(),
+            FailurePolicy::Open,
+            NonZeroU64::new(1024).unwrap(),
+            false,
+            None,
+        );
+
+        for (path, body) in [("/", "{\"kind\":"), ("/validate", "[]")] {
+            let rsp = warp::test::request()
+                .method("POST")
+                .path(path)
+                .header("content-type", "application/json")
+                .body(body)
+                .reply(&routes)
+                .await;
+            assert_eq!(rsp.status(), http::StatusCode::BAD_REQUEST, "{}", path);
+            let review = serde_json::from_slice::<serde_json::Value>(rsp.body()).unwrap();
+            assert_eq!(review["kind"], "AdmissionReview");
+            assert_eq!(review["response"]["allowed"].as_bool(), Some(false));
+            assert_eq!(review["response"]["status"]["code"], 400);
+        }
+        assert!(
+            metrics
+                .encode()
+                .contains("admission_decode_errors_total 2\n"),
+            "{}",
+            metrics.encode()
+        );
+
+        // Other rejections aren't decode errors.
+        let rsp = warp::test::request()
+            .method("GET")
+            .path("/validate")
+            .reply(&routes)
+            .await;
+        assert_eq!(rsp.status(), http::StatusCode::METHOD_NOT_ALLOWED);
+        assert!(metrics
+            .encode()
+            .contains("admission_decode_errors_total 2\n"));
+    }
+
+    #[tokio::test]
+    async fn observe_only_admits_without_writes() {
+        let review = mk_server_review(
+            "ns-0",
+            serde_json::json!({ "podSelector": {}, "port": 0 }),
+            false,
+        );
+
+        for observe_only in [false, true] {
+            let (client, mut rx) = mk_recording_client();
+            let routes = routes(
+                client,
+                &Registry::default(),
+                None,
+                Scope::default(),
+                FailurePolicy::Open,
+                NonZeroU64::new(1024 * 1024).unwrap(),
+                observe_only,
+                None,
+            );
+
+            let rsp = warp::test::request()
+                .method("POST")
+                .path("/validate")
+                .json(&review)
+                .reply(&routes)
+                .await;
+            assert_eq!(rsp.status(), http::StatusCode::OK);
+            let rsp = serde_json::from_slice::<serde_json::Value>(rsp.body()).unwrap();
+            assert_eq!(rsp["response"]["allowed"], observe_only);
+
+            let write = time::timeout(time::Duration::from_millis(100), rx.recv()).await;
+            if observe_only {
+                assert!(rsp["response"]["warnings"][0]
+                    .as_str()
+                    .unwrap()
+                    .contains("would have been denied"));
+                assert!(rsp["response"].get("patch").is_none(), "must not mutate");
+                assert!(write.is_err(), "must not write to the API server");
+            } else {
+                let (method, path, _) = write.expect("event must be created").unwrap();
+                assert_eq!(method, http::Method::POST);
+                assert_eq!(path, "/api/v1/namespaces/ns-0/events");
+            }
+        }
+
+        // Resources that can't be parsed are admitted as well, on both webhook paths.
+        let malformed = mk_server_review("ns-0", serde_json::json!({ "podSelector": {} }), false);
+        for path in ["/", "/validate"] {
+            for observe_only in [false, true] {
+                let (client, _) = mk_recording_client();
+                let routes = routes(
+                    client,
+                    &Registry::default(),
+                    None,
+                    Scope::default(),
+                    FailurePolicy::Open,
+                    NonZeroU64::new(1024 * 1024).unwrap(),
+                    observe_only,
+                    None,
+                );
+
+                let rsp = warp::test::request()
+                    .method("POST")
+                    .path(path)
+                    .json(&malformed)
+                    .reply(&routes)
+                    .await;
+                assert_eq!(rsp.status(), http::StatusCode::OK);
+                let rsp = serde_json::from_slice::<serde_json::Value>(rsp.body()).unwrap();
+                assert_eq!(rsp["response"]["allowed"], observe_only, "{}", path);
+                if observe_only {
+                    assert!(rsp["response"]["warnings"][0]
+                        .as_str()
+                        .unwrap()
+                        .contains("would have been denied: missing field `port`"));
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn dry_runs_have_no_side_effects() {
+        let (client, mut rx) = mk_recording_client();
+        let routes = routes(
+            client,
+            &Registry::default(),
+            None,
+            Scope::default(),
+            FailurePolicy::Open,
+            NonZeroU64::new(1024 * 1024).unwrap(),
+            false,
+            None,
+        );
+
+        let rsp = warp::test::request()
+            .method("POST")
+            .path("/validate")
+            .json(&mk_server_review(
+                "ns-0",
+                serde_json::json!({ "podSelector": {}, "port": 0 }),
+                true,
+            ))
+            .reply(&routes)
+            .await;
+
+        // The decision is the same as for any other request, but no event is recorded. The webhook
+        // never mutates resources, so there's no patch.
+        assert_eq!(rsp.status(), http::StatusCode::OK);
+        let rsp = serde_json::from_slice::<serde_json::Value>(rsp.body()).unwrap();
+        assert_eq!(rsp["response"]["uid"], "uid-0");
+        assert_eq!(rsp["response"]["allowed"].as_bool(), Some(false));
+        assert!(rsp["response"].get("patch").is_none(), "must not mutate");
+        assert!(
+            time::timeout(time::Duration::from_millis(100), rx.recv())
+                .await
+                .is_err(),
+            "must not write to the API server"
+        );
+    }
+
+    #[test]
+    fn parse_failure_policy() {
+        assert_eq!(
+            "open".parse::<FailurePolicy>().unwrap(),
+            FailurePolicy::Open
+        );
+        assert_eq!(
+            "closed".parse::<FailurePolicy>().unwrap(),
+            FailurePolicy::Closed
+        );
+        assert!("Ignore".parse::<FailurePolicy>().is_err());
+    }
+
+    #[test]
+    fn validates_server_ports() {
+        for (port, valid) in [
+            (Port::Number(8080), true),
+            (Port::Number(0), false),
+            (Port::Name("http".into()), true),
+            (Port::Name("admin-http".into()), true),
+            (Port::Name("HTTP".into()), false),
+            (Port::Name("1234".into()), false),
+            (Port::Name("-http".into()), false),
+            (Port::Name("admin--http".into()), false),
+            (Port::Name("a-very-long-port-name".into()), false),
+        ] {
+            assert_eq!(
+                validate_server(&mk_server_spec(port.clone())).is_ok(),
+                valid,
+                "{:?}",
+                port
+            );
+        }
+    }
+
+    #[test]
+    fn validates_authz_server_selection() {
+        let client = authz::Client {
+            unauthenticated: true,
+            ..Default::default()
+        };
+
+        let by_name = authz::Server {
+            name: Some("srv-0".into()),
+            selector: None,
+        };
+        assert!(validate_authz(&mk_authz_spec(by_name, client.clone())).is_ok());
+
+        let ambiguous = authz::Server {
+            name: Some("srv-0".into()),
+            selector: Some(Default::default()),
+        };
+        assert!(validate_authz(&mk_authz_spec(ambiguous, client.clone())).is_err());
+
+        assert!(validate_authz(&mk_authz_spec(Default::default(), client)).is_err());
+    }
+
+    #[test]
+    fn rejects_authz_selecting_all_servers_and_clients() {
+        let all_servers = authz::Server {
+            name: None,
+            selector: Some(Default::default()),
+        };
+
+        let unauthenticated = authz::Client {
+            unauthenticated: true,
+            ..Default::default()
+        };
+        assert!(validate_authz(&mk_authz_spec(all_servers.clone(), unauthenticated)).is_err());
+
+        let all_identities = authz::Client {
+            mesh_tls: Some(authz::MeshTls {
+                identities: Some(vec!["*".into()]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(validate_authz(&mk_authz_spec(all_servers.clone(), all_identities)).is_err());
+
+        let some_identities = authz::Client {
+            mesh_tls: Some(authz::MeshTls {
+                identities: Some(vec!["*.cluster.local".into()]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(validate_authz(&mk_authz_spec(all_servers, some_identities)).is_ok());
+    }
+
+    #[test]
+    fn rejects_authz_conflicting_clients() {
+        let server = authz::Server {
+            name: Some("srv-0".into()),
+            selector: None,
+        };
+
+        let unauthenticated_mtls = authz::Client {
+            unauthenticated: true,
+            mesh_tls: Some(authz::MeshTls {
+                unauthenticated_tls: true,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(validate_authz(&mk_authz_spec(server.clone(), unauthenticated_mtls)).is_err());
+
+        let tls_identities = authz::Client {
+            mesh_tls: Some(authz::MeshTls {
+                unauthenticated_tls: true,
+                identities: Some(vec!["foo.cluster.local".into()]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(validate_authz(&mk_authz_spec(server.clone(), tls_identities)).is_err());
+
+        assert!(validate_authz(&mk_authz_spec(server, Default::default())).is_err());
+    }
+
+    #[test]
+    fn reports_field_paths() {
+        let error = validate_server(&mk_server_spec(Port::Number(0))).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "spec.port: Invalid value: 0: must not be 0"
+        );
+
+        let error = validate_server(&mk_server_spec(Port::Name("HTTP".into()))).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "spec.port: Invalid value: \"HTTP\": must be a valid container port name"
+        );
+
+        let error =
+            validate_authz(&mk_authz_spec(Default::default(), Default::default())).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "[spec.server: Required value: must set either name or selector, \
+             spec.client: Required value: must set either unauthenticated or meshTLS]"
+        );
+
+        let ambiguous = authz::Server {
+            name: Some("srv-0".into()),
+            selector: Some(Default::default()),
+        };
+        let unauthenticated_mtls = authz::Client {
+            unauthenticated: true,
+            mesh_tls: Some(Default::default()),
+            ..Default::default()
+        };
+        let error = validate_authz(&mk_authz_spec(ambiguous, unauthenticated_mtls)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "[spec.server.selector: Forbidden: must not be set with spec.server.name, \
+             spec.client.meshTLS: Forbidden: must not be set with spec.client.unauthenticated]"
+        );
+
+        let all_servers = authz::Server {
+            name: None,
+            selector: Some(Default::default()),
+        };
+        let unauthenticated = authz::Client {
+            unauthenticated: true,
+            ..Default::default()
+        };
+        let error = validate_authz(&mk_authz_spec(all_servers, unauthenticated)).unwrap_err();
+        assert_eq!(
+            error.0,
+            vec![FieldError::forbidden(
+                "spec.client",
+                "must not authorize all clients when spec.server.selector selects all servers"
+            )]
+        );
+    }
+}
@@ -0,0 +1,373 @@
+//! A circuit breaker for the Kubernetes API client.
+//!
+//! When the API server is failing, the controller's watches and admission lookups retry, which can
+//! prolong an outage. After a number of consecutive failures, the breaker opens and requests fail
+//! immediately until a cooldown elapses. The breaker then half-opens so that a single request
+//! probes the API server: if it succeeds, the breaker closes; otherwise, it opens again.
+
+use hyper::{Request, Response, StatusCode};
+use linkerd_policy_controller_metrics::{Family, Gauge, Registry};
+use std::{
+    future::Future,
+    num::NonZeroU32,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+use tokio::time;
+use tracing::{info, warn};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Tracks the health of the API server, shared by all clones.
+///
+/// The breaker is a [`tower::Layer`] so that it can wrap the client's HTTP service.
+#[derive(Clone, Debug)]
+pub struct Breaker {
+    inner: Arc<Mutex<Inner>>,
+
+    /// The number of consecutive failures after which the breaker opens.
+    threshold: NonZeroU32,
+
+    /// The amount of time the breaker stays open before a request is permitted to probe the API
+    /// server.
+    cooldown: time::Duration,
+
+    /// Set to 1 for the breaker's current state, and 0 for its other states.
+    states: Family<Gauge>,
+}
+
+/// The state of a [`Breaker`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum State {
+    /// Requests are permitted.
+    Closed,
+
+    /// Requests fail immediately until the cooldown elapses.
+    Open { until: time::Instant },
+
+    /// A single request is permitted to probe the API server.
+    HalfOpen { probing: bool },
+}
+
+/// The error returned for requests that are not sent because the breaker is open.
+#[derive(Debug)]
+pub struct BreakerOpen(());
+
+/// Wraps a service with a [`Breaker`].
+#[derive(Clone, Debug)]
+pub struct BreakerService<S> {
+    inner: S,
+    breaker: Breaker,
+}
+
+/// Records a probe as failed if it is dropped before it completes, so that the breaker doesn't
+/// stay half-open without a probe in flight.
+#[derive(Debug)]
+struct ProbeGuard(Option<Breaker>);
+
+#[derive(Debug)]
+struct Inner {
+    state: State,
+    failures: u32,
+}
+
+// === impl Breaker ===
+
+impl Breaker {
+    pub fn new(threshold: NonZeroU32, cooldown: time::Duration, metrics: &Registry) -> Self {
+        let states = metrics.register(
+            "kube_client_breaker_state",
+            "Set to 1 for the current state of the Kubernetes API client's circuit breaker.",
+            Family::new(&["state"]),
+        );
+        let breaker = Self {
+            inner: Arc::new(Mutex::new(Inner {
+                state: State::Closed,
+                failures: 0,
+            })),
+            threshold,
+            cooldown,
+            states,
+        };
+        breaker.set_state_metric(State::Closed);
+        breaker
+    }
+
+    pub fn state(&self) -> State {
+        self.inner.lock().expect("lock must not be poisoned").state
+    }
+
+    /// Returns an error if a request may not be sent at `now`, or whether the request is a probe.
+    ///
+    /// Once an open breaker's cooldown elapses, the breaker half-opens, and this request is
+    /// permitted as a probe. Other requests fail until the probe completes.
+    fn try_acquire(&self, now: time::Instant) -> Result<bool, BreakerOpen> {
+        let mut inner = self.inner.lock().expect("lock must not be poisoned");
+        match inner.state {
+            State::Closed => Ok(false),
+            State::Open { until } if now < until => Err(BreakerOpen(())),
+            State::Open { .. } | State::HalfOpen { probing: false } => {
+                self.transition(&mut inner, State::HalfOpen { probing: true });
+                Ok(true)
+            }
+            State::HalfOpen { probing: true } => Err(BreakerOpen(())),
+        }
+    }
+
+    /// Records the outcome of a request sent at `now`.
+    fn record(&self, success: bool, now: time::Instant) {
+        let mut inner = self.inner.lock().expect("lock must not be poisoned");
+        if success {
+            inner.failures = 0;
+            if inner.state != State::Closed {
+                self.transition(&mut inner, State::Closed);
+            }
+            return;
+        }
+
+        inner.failures = inner.failures.saturating_add(1);
+        match inner.state {
+            State::Closed if inner.failures < self.threshold.get() => {}
+            // The probe failed, or too many consecutive requests failed.
+            State::Closed | State::HalfOpen { .. } => {
+                let until = now + self.cooldown;
+                self.transition(&mut inner, State::Open { until });
+            }
+            // Requests that were sent before the breaker opened don't extend the cooldown.
+            State::Open { .. } => {}
+        }
+    }
+
+    fn transition(&self, inner: &mut Inner, state: State) {
+        match state {
+            State::Open { .. } => warn!(
+                failures = inner.failures,
+                cooldown = ?self.cooldown,
+                "Kubernetes API circuit breaker opened"
+            ),
+            State::HalfOpen { .. } => info!("Kubernetes API circuit breaker half-opened"),
+            State::Closed => info!("Kubernetes API circuit breaker closed"),
+        }
+        inner.state = state;
+        self.set_state_metric(state);
+    }
+
+    fn set_state_metric(&self, state: State) {
+        for (name, value) in [
+            ("closed", state == State::Closed),
+            ("open", matches!(state, State::Open { .. })),
+            ("half_open", matches!(state, State::HalfOpen { .. })),
+        ] {
+            self.states.get(&[name]).set(value as i64);
+        }
+    }
+}
+
+impl<S> tower::Layer<S> for Breaker {
+    type Service = BreakerService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        BreakerService {
+            inner,
+            breaker: self.clone(),
+        }
+    }
+}
+
+// === impl BreakerService ===
+
+/// Server errors and throttled requests indicate that the API server is failing or overloaded.
+/// Other responses, including client errors, show that the API server is available.
+fn is_failure(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+impl<S, ReqB, RspB> tower::Service<Request<ReqB>> for BreakerService<S>
+where
+    S: tower::Service<Request<ReqB>, Response = Response<RspB>>,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+    RspB: Send + 'static,
+{
+    type Response = Response<RspB>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Response<RspB>, BoxError>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<ReqB>) -> Self::Future {
+        let probe = match self.breaker.try_acquire(time::Instant::now()) {
+            Ok(probe) => probe,
+            Err(error) => return Box::pin(futures::future::ready(Err(error.into()))),
+        };
+
+        let breaker = self.breaker.clone();
+        let mut guard = ProbeGuard(if probe { Some(breaker.clone()) } else { None });
+        let rsp = self.inner.call(req);
+        Box::pin(async move {
+            let res = rsp.await.map_err(Into::into);
+            guard.disarm();
+            let success = matches!(&res, Ok(rsp) if !is_failure(rsp.status()));
+            breaker.record(success, time::Instant::now());
+            res
+        })
+    }
+}
+
+// === impl ProbeGuard ===
+
+impl ProbeGuard {
+    /// Marks the probe as complete, so that its outcome is recorded by the caller.
+    fn disarm(&mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for ProbeGuard {
+    fn drop(&mut self) {
+        if let Some(breaker) = self.0.take() {
+            warn!("Kubernetes API circuit breaker probe was dropped");
+            breaker.record(false, time::Instant::now());
+        }
+    }
+}
+
+// === impl BreakerOpen ===
+
+impl std::fmt::Display for BreakerOpen {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        "the Kubernetes API circuit breaker is open".fmt(f)
+    }
+}
+
+impl std::error::Error for BreakerOpen {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower::{Layer, Service, ServiceExt};
+
+    fn mk_breaker(threshold: u32) -> (Breaker, Registry) {
+        let metrics = Registry::default();
+        let breaker = Breaker::new(
+            NonZeroU32::new(threshold).unwrap(),
+            time::Duration::from_secs(10),
+            &metrics,
+        );
+        (breaker, metrics)
+    }
+
+    #[test]
+    fn transitions() {
+        let (breaker, metrics) = mk_breaker(3);
+        let now = time::Instant::now();
+        assert_eq!(breaker.state(), State::Closed);
+
+        // Failures below the threshold, or interrupted by a success, don't open the breaker.
+        for _ in 0..2 {
+            breaker.try_acquire(now).unwrap();
+            breaker.record(false, now);
+        }
+        breaker.record(true, now);
+        for _ in 0..2 {
+            breaker.record(false, now);
+        }
+        assert_eq!(breaker.state(), State::Closed);
+
+        // Consecutive failures at the threshold open the breaker.
+        breaker.record(false, now);
+        let until = now + time::Duration::from_secs(10);
+        assert_eq!(breaker.state(), State::Open { until });
+        assert!(breaker.try_acquire(now).is_err());
+        assert!(metrics
+            .encode()
+            .contains("kube_client_breaker_state{state=\"open\"} 1"));
+
+        // Once the cooldown elapses, a single probe is permitted.
+        breaker.try_acquire(until).unwrap();
+        assert_eq!(breaker.state(), State::HalfOpen { probing: true });
+        assert!(breaker.try_acquire(until).is_err(), "only one probe");
+
+        // A failed probe reopens the breaker.
+        breaker.record(false, until);
+        let until = until + time::Duration::from_secs(10);
+        assert_eq!(breaker.state(), State::Open { until });
+
+        // A successful probe closes it.
+        breaker.try_acquire(until).unwrap();
+        breaker.record(true, until);
+        assert_eq!(breaker.state(), State::Closed);
+        breaker.try_acquire(until).unwrap();
+        assert!(metrics
+            .encode()
+            .contains("kube_client_breaker_state{state=\"closed\"} 1"));
+    }
+
+    #[tokio::test]
+    async fn short_circuits_failing_requests() {
+        let (breaker, _metrics) = mk_breaker(2);
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let mut svc = breaker.layer(tower::service_fn({
+            let calls = calls.clone();
+            move |_: Request<()>| {
+                calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                futures::future::ok::<_, BoxError>(
+                    Response::builder()
+                        .status(StatusCode::SERVICE_UNAVAILABLE)
+                        .body(())
+                        .unwrap(),
+                )
+            }
+        }));
+
+        for _ in 0..2 {
+            let rsp = svc.ready().await.unwrap().call(Request::new(())).await;
+            assert_eq!(rsp.unwrap().status(), StatusCode::SERVICE_UNAVAILABLE);
+        }
+        assert!(matches!(breaker.state(), State::Open { .. }));
+
+        let error = svc
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::new(()))
+            .await
+            .expect_err("request must be short-circuited");
+        assert!(error.is::<BreakerOpen>());
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn dropped_probe_reopens() {
+        let metrics = Registry::default();
+        let breaker = Breaker::new(NonZeroU32::new(1).unwrap(), time::Duration::ZERO, &metrics);
+        let mut svc = breaker.layer(tower::service_fn(|_: Request<()>| {
+            futures::future::pending::<Result<Response<()>, BoxError>>()
+        }));
+        breaker.record(false, time::Instant::now());
+        assert!(matches!(breaker.state(), State::Open { .. }));
+
+        // The probe is dropped while it's in flight, e.g. because its caller timed out.
+        let probe = svc.ready().await.unwrap().call(Request::new(()));
+        assert_eq!(breaker.state(), State::HalfOpen { probing: true });
+        drop(probe);
+        assert!(matches!(breaker.state(), State::Open { .. }));
+
+        // Once the cooldown elapses, another probe is permitted.
+        let _probe = svc.ready().await.unwrap().call(Request::new(()));
+        assert_eq!(breaker.state(), State::HalfOpen { probing: true });
+    }
+
+    #[test]
+    fn classifies_failures() {
+        assert!(is_failure(StatusCode::INTERNAL_SERVER_ERROR));
+        assert!(is_failure(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(is_failure(StatusCode::TOO_MANY_REQUESTS));
+        assert!(!is_failure(StatusCode::OK));
+        assert!(!is_failure(StatusCode::NOT_FOUND));
+        assert!(!is_failure(StatusCode::CONFLICT));
+    }
+}
@@ -0,0 +1,33 @@
+//! Describes the controller's build, as recorded by the build script.
+
+use crate::api::chrono::{SecondsFormat, TimeZone, Utc};
+
+/// The controller's crate version.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The commit from which the controller was built, or `unknown`.
+pub const GIT_SHA: &str = env!("LINKERD_POLICY_BUILD_GIT_SHA");
+
+/// The time of the build, in seconds since the Unix epoch.
+const BUILD_TIMESTAMP: &str = env!("LINKERD_POLICY_BUILD_TIMESTAMP");
+
+/// Identifies the build in a single string, e.g. for response headers.
+pub fn version() -> String {
+    format!("{}+{}", VERSION, GIT_SHA)
+}
+
+/// Describes the build as JSON.
+pub fn to_json() -> serde_json::Value {
+    serde_json::json!({
+        "version": VERSION,
+        "git_sha": GIT_SHA,
+        "build_timestamp": build_timestamp(),
+    })
+}
+
+/// Formats the build timestamp as an RFC 3339 date.
+fn build_timestamp() -> String {
+    let secs = BUILD_TIMESTAMP.parse::<i64>().unwrap_or_default();
+    Utc.timestamp(secs, 0)
+        .to_rfc3339_opts(SecondsFormat::Secs, true)
+}
@@ -0,0 +1,187 @@
+//! Loads the servers' TLS credentials and checks that they're trusted.
+
+use anyhow::{bail, Context, Result};
+use linkerd_policy_controller::tls;
+use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+use tracing::{debug, warn};
+
+/// Warns if the admission webhook's `caBundle` doesn't include the CA that issued the admission
+/// server's certificate, in which case the API server can't connect to the webhook.
+///
+/// This check is advisory, so failures (e.g. when the controller isn't authorized to read the
+/// webhook configuration) are logged and ignored.
+pub(crate) async fn check_webhook_ca(
+    client: kube::Client,
+    name: String,
+    certs: Arc<tls::CertResolver>,
+) {
+    let api =
+        kube::Api::<linkerd_policy_controller::api::ValidatingWebhookConfiguration>::all(client);
+    let config = match api.get(&name).await {
+        Ok(config) => config,
+        Err(error) => {
+            warn!(%error, config = %name, "Failed to get webhook configuration; skipping the webhook check");
+            return;
+        }
+    };
+
+    let chain = certs.certificates();
+    for webhook in config.webhooks.into_iter().flatten() {
+        let bundle = match webhook.client_config.ca_bundle {
+            Some(bundle) => bundle,
+            None => {
+                warn!(webhook = %webhook.name, "Webhook does not configure a caBundle");
+                continue;
+            }
+        };
+        match tls::bundle_trusts(&bundle.0, &chain) {
+            Ok(true) => {
+                debug!(webhook = %webhook.name, "Webhook caBundle includes the admission server's CA")
+            }
+            Ok(false) => warn!(
+                webhook = %webhook.name,
+                "Webhook caBundle does not include the CA that issued the admission server's certificate; admission requests will fail"
+            ),
+            Err(error) => {
+                warn!(%error, webhook = %webhook.name, "Failed to parse webhook caBundle")
+            }
+        }
+    }
+}
+
+/// Reads and validates the gRPC server's TLS credentials, if they're configured.
+pub(crate) fn load_grpc_tls(
+    cert: Option<PathBuf>,
+    key: Option<PathBuf>,
+    client_ca: Option<PathBuf>,
+) -> Result<Option<linkerd_policy_controller_grpc::TlsConfig>> {
+    let read = |path: PathBuf| {
+        std::fs::read(&path).with_context(|| format!("failed to read {}", path.display()))
+    };
+    match (cert, key) {
+        (Some(cert), Some(key)) => {
+            let tls = linkerd_policy_controller_grpc::TlsConfig {
+                cert: read(cert)?,
+                key: read(key)?,
+                client_ca: client_ca.map(read).transpose()?,
+            };
+            tls.validate().context("invalid gRPC TLS credentials")?;
+            Ok(Some(tls))
+        }
+        (None, None) if client_ca.is_none() => Ok(None),
+        (None, None) => bail!("--grpc-tls-client-ca requires --grpc-tls-cert and --grpc-tls-key"),
+        _ => bail!("--grpc-tls-cert and --grpc-tls-key must be set together"),
+    }
+}
+
+/// Loads the admission server's TLS certificate, unless the admission server is disabled.
+///
+/// When the admission server is disabled, the certificate files are not read.
+pub(crate) fn load_admission_certs(
+    addr: Option<SocketAddr>,
+    cert: PathBuf,
+    key: PathBuf,
+) -> Result<Option<(SocketAddr, Arc<tls::CertResolver>)>> {
+    match addr {
+        None => Ok(None),
+        Some(addr) => {
+            let certs = tls::CertResolver::load(cert, key)
+                .context("failed to load admission TLS certificate")?;
+            Ok(Some((addr, certs)))
+        }
+    }
+}
+
+/// Loads the admin server's TLS certificate, if one is configured.
+pub(crate) fn load_admin_certs(
+    cert: Option<PathBuf>,
+    key: Option<PathBuf>,
+) -> Result<Option<Arc<tls::CertResolver>>> {
+    match (cert, key) {
+        (Some(cert), Some(key)) => {
+            let certs = tls::CertResolver::load(cert, key)
+                .context("failed to load admin TLS certificate")?;
+            Ok(Some(certs))
+        }
+        (None, None) => Ok(None),
+        _ => bail!("--admin-tls-cert and --admin-tls-key must be set together"),
+    }
+}
+
+/// Warns if none of the trust anchors at `path` has the identity domain as its trust domain. This
+/// is advisory, so failures to read the trust anchors are also only logged.
+pub(crate) fn check_trust_anchors(path: &std::path::Path, identity_domain: &str) -> bool {
+    let domains = match std::fs::read(path)
+        .with_context(|| format!("failed to read {}", path.display()))
+        .and_then(|pem| tls::trust_domains(&pem))
+    {
+        Ok(domains) => domains,
+        Err(error) => {
+            warn!(%error, "Failed to check trust anchors");
+            return false;
+        }
+    };
+
+    if domains.iter().any(|d| d == identity_domain) {
+        debug!(%identity_domain, "Trust anchors match the identity domain");
+        return true;
+    }
+    warn!(
+        %identity_domain,
+        trust_domains = ?domains,
+        "No trust anchor matches the identity domain; client identities may not be authorized"
+    );
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_trust_anchors() {
+        let path =
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/trust-anchors.crt");
+        assert!(check_trust_anchors(&path, "cluster.local"));
+        assert!(!check_trust_anchors(&path, "example.com"));
+        assert!(!check_trust_anchors(
+            std::path::Path::new("/nonexistent/ca.crt"),
+            "cluster.local"
+        ));
+    }
+
+    #[test]
+    fn admin_tls_requires_cert_and_key() {
+        assert!(load_admin_certs(None, None).unwrap().is_none());
+
+        let testdata = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata");
+        let cert = || Some(testdata.join("localhost.crt"));
+        let key = || Some(testdata.join("localhost.key"));
+        assert!(load_admin_certs(cert(), None).is_err());
+        assert!(load_admin_certs(None, key()).is_err());
+        assert!(load_admin_certs(cert(), key()).unwrap().is_some());
+    }
+
+    #[test]
+    fn grpc_tls_requires_cert_and_key() {
+        assert!(load_grpc_tls(None, None, None).unwrap().is_none());
+
+        let path = || Some(PathBuf::from("/nonexistent/tls.pem"));
+        assert!(load_grpc_tls(path(), None, None).is_err());
+        assert!(load_grpc_tls(None, path(), None).is_err());
+        assert!(load_grpc_tls(None, None, path()).is_err());
+        assert!(
+            load_grpc_tls(path(), path(), None).is_err(),
+            "missing files must fail"
+        );
+
+        let testdata = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata");
+        let cert = || Some(testdata.join("localhost.crt"));
+        let key = || Some(testdata.join("localhost.key"));
+        assert!(load_grpc_tls(cert(), key(), None).unwrap().is_some());
+        assert!(
+            load_grpc_tls(cert(), cert(), None).is_err(),
+            "invalid keys must fail"
+        );
+    }
+}
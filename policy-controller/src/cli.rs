@@ -0,0 +1,1814 @@
+//! Parses and validates the controller's command-line arguments.
+
+use anyhow::{bail, Context, Error, Result};
+use linkerd_policy_controller::{admission, k8s::DefaultPolicy, tls};
+use linkerd_policy_controller_core::IpNet;
+use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+use structopt::StructOpt;
+use tokio::{sync::watch, time};
+use tracing::{debug, error, info};
+use tracing_subscriber::EnvFilter;
+
+// Switches (`bool` fields) have no environment variables: clap only reads the environment for
+// arguments that take a value, so supporting one would require spelling the switch as, e.g.,
+// `--check=true`.
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "policy",
+    about = "A policy resource prototype",
+    after_help = "Each option may also be set by the environment variable listed with it. \
+                  Command-line flags take precedence over environment variables. Switches that \
+                  take no value, like `--check`, may only be set on the command line."
+)]
+pub(crate) struct Args {
+    /// A tracing filter directive (in `RUST_LOG` syntax) that applies to both log formats.
+    #[structopt(
+        parse(try_from_str),
+        long,
+        default_value = "linkerd=info,warn",
+        env = "LINKERD_POLICY_CONTROLLER_LOG"
+    )]
+    pub(crate) log_level: EnvFilter,
+
+    /// Either `plain` or `json`.
+    ///
+    /// JSON logs include the fields of all active spans so that, e.g., the gRPC server's `addr` is
+    /// emitted as a structured key.
+    #[structopt(long, default_value = "plain", env = "LINKERD_POLICY_LOG_FORMAT")]
+    pub(crate) log_format: LogFormat,
+
+    /// When set, spans are exported to this OpenTelemetry collector's OTLP/gRPC endpoint (e.g.
+    /// `http://collector.linkerd-jaeger:4317`).
+    #[structopt(long, env = "LINKERD_POLICY_TRACE_COLLECTOR")]
+    pub(crate) trace_collector: Option<String>,
+
+    /// The fraction of traces, between 0 and 1, that are exported when a trace collector is set.
+    #[structopt(
+        long,
+        default_value = "1.0",
+        parse(try_from_str = parse_sample_rate),
+        env = "LINKERD_POLICY_TRACE_SAMPLE_RATE"
+    )]
+    pub(crate) trace_sample_rate: f64,
+
+    /// The address on which the admin server listens. On dual-stack clusters, `[::]:8080` accepts
+    /// both IPv4 and IPv6 connections.
+    #[structopt(
+        long,
+        default_value = "0.0.0.0:8080",
+        env = "LINKERD_POLICY_ADMIN_ADDR"
+    )]
+    pub(crate) admin_addr: SocketAddr,
+
+    /// The maximum number of concurrent connections served by each admin listener. Additional
+    /// connections are rejected with a 503.
+    #[structopt(
+        long,
+        default_value = "128",
+        env = "LINKERD_POLICY_ADMIN_MAX_CONNECTIONS"
+    )]
+    pub(crate) admin_max_connections: usize,
+
+    /// When set, `/metrics` is served on this address instead of the admin address.
+    #[structopt(long, env = "LINKERD_POLICY_METRICS_ADDR")]
+    pub(crate) metrics_addr: Option<SocketAddr>,
+
+    /// The path to a PEM-encoded certificate for the admin server. When set with
+    /// `--admin-tls-key`, the admin and metrics listeners only accept HTTPS connections.
+    #[structopt(long, env = "LINKERD_POLICY_ADMIN_TLS_CERT")]
+    pub(crate) admin_tls_cert: Option<PathBuf>,
+
+    /// The path to the admin server's PEM-encoded private key.
+    #[structopt(long, env = "LINKERD_POLICY_ADMIN_TLS_KEY")]
+    pub(crate) admin_tls_key: Option<PathBuf>,
+
+    /// Serves CPU profiles at `/debug/pprof/profile` on the admin address.
+    ///
+    /// This should only be enabled while debugging, since profiles expose internal details and are
+    /// expensive to record.
+    #[structopt(long)]
+    pub(crate) enable_profiling: bool,
+
+    /// Serves a JSON description of the controller's index at `/debug/index`, and of the policy
+    /// served for a pod's port at `/debug/policy`, on the admin address.
+    ///
+    /// This should only be enabled while debugging, since it exposes policy details.
+    #[structopt(long)]
+    pub(crate) enable_debug_endpoints: bool,
+
+    /// Delays each discovery response by this amount, to test how proxies behave when the
+    /// controller is slow. Requires `--enable-debug-endpoints`.
+    ///
+    /// This must never be enabled in production.
+    #[structopt(
+        long,
+        parse(try_from_str = parse_nonzero_duration),
+        requires = "enable-debug-endpoints",
+        env = "LINKERD_POLICY_DEBUG_INJECT_LATENCY"
+    )]
+    pub(crate) debug_inject_latency: Option<time::Duration>,
+
+    /// The address on which the gRPC server listens: either a socket address or, to serve on a
+    /// Unix domain socket, `unix:` followed by the socket's path. On dual-stack clusters,
+    /// `[::]:8090` accepts both IPv4 and IPv6 connections.
+    #[structopt(long, default_value = "0.0.0.0:8090", env = "LINKERD_POLICY_GRPC_ADDR")]
+    pub(crate) grpc_addr: GrpcAddr,
+
+    /// Serves the admin server on the gRPC address instead of `--admin-addr`, so that only one port
+    /// is exposed. HTTP/2 connections are served by the gRPC server and all other connections are
+    /// served by the admin server.
+    ///
+    /// Connections are dispatched before any TLS handshake, so neither server may use TLS.
+    #[structopt(long, conflicts_with_all = &["admin-tls-cert", "grpc-tls-cert"])]
+    pub(crate) single_port: bool,
+
+    /// Sets `SO_REUSEPORT` on the admin, gRPC, and admission listeners, so that several controller
+    /// processes may listen on the same addresses with the kernel balancing connections across
+    /// them. Listeners always set `SO_REUSEADDR`.
+    #[structopt(long)]
+    pub(crate) reuse_port: bool,
+
+    /// When set, the gRPC server sends HTTP/2 keepalive pings to clients at this interval.
+    ///
+    /// Proxies already ping the controller on their own interval, so this only needs to be set to
+    /// detect clients that vanish without closing their connections. It should not be shorter than
+    /// the proxies' interval, since clients may treat excessive pings as abuse.
+    #[structopt(
+        long,
+        parse(try_from_str = parse_nonzero_duration),
+        env = "LINKERD_POLICY_GRPC_KEEPALIVE_INTERVAL"
+    )]
+    pub(crate) grpc_keepalive_interval: Option<time::Duration>,
+
+    /// The amount of time to wait for a gRPC keepalive ping to be acknowledged before closing the
+    /// connection.
+    #[structopt(
+        long,
+        default_value = "20s",
+        parse(try_from_str = parse_nonzero_duration),
+        env = "LINKERD_POLICY_GRPC_KEEPALIVE_TIMEOUT"
+    )]
+    pub(crate) grpc_keepalive_timeout: time::Duration,
+
+    /// The amount of time gRPC clients have to begin the HTTP/2 handshake before their connections
+    /// are closed.
+    #[structopt(
+        long,
+        default_value = "10s",
+        parse(try_from_str = parse_nonzero_duration),
+        env = "LINKERD_POLICY_GRPC_CONNECT_TIMEOUT"
+    )]
+    pub(crate) grpc_connect_timeout: time::Duration,
+
+    /// When set, gRPC connections are closed gracefully once they've been open this long, so that
+    /// proxies reconnect and their connections are rebalanced across replicas (e.g. after a
+    /// rollout).
+    #[structopt(
+        long,
+        parse(try_from_str = parse_nonzero_duration),
+        env = "LINKERD_POLICY_GRPC_MAX_CONNECTION_AGE"
+    )]
+    pub(crate) grpc_max_connection_age: Option<time::Duration>,
+
+    /// gRPC connections that remain open this long after their maximum age (e.g. because a watch
+    /// is still streaming) are closed forcibly. Defaults to 1m.
+    #[structopt(
+        long,
+        parse(try_from_str = parse_nonzero_duration),
+        requires = "grpc-max-connection-age",
+        env = "LINKERD_POLICY_GRPC_MAX_CONNECTION_AGE_GRACE"
+    )]
+    pub(crate) grpc_max_connection_age_grace: Option<time::Duration>,
+
+    /// When set, limits the number of concurrent HTTP/2 streams on each gRPC connection.
+    #[structopt(long, env = "LINKERD_POLICY_GRPC_MAX_CONCURRENT_STREAMS")]
+    pub(crate) grpc_max_concurrent_streams: Option<u32>,
+
+    /// When set, the initial HTTP/2 flow-control window, in bytes, of each gRPC stream.
+    #[structopt(
+        long,
+        parse(try_from_str = parse_window_size),
+        env = "LINKERD_POLICY_GRPC_INITIAL_STREAM_WINDOW"
+    )]
+    pub(crate) grpc_initial_stream_window: Option<u32>,
+
+    /// When set, the initial HTTP/2 flow-control window, in bytes, of each gRPC connection.
+    #[structopt(
+        long,
+        parse(try_from_str = parse_window_size),
+        env = "LINKERD_POLICY_GRPC_INITIAL_CONNECTION_WINDOW"
+    )]
+    pub(crate) grpc_initial_connection_window: Option<u32>,
+
+    /// When set, limits the number of gRPC lookups processed concurrently. Additional requests
+    /// wait for capacity rather than failing. Watches only hold capacity while they're
+    /// established, so that long-lived watches don't exhaust it.
+    #[structopt(long, env = "LINKERD_POLICY_GRPC_MAX_CONCURRENT_REQUESTS")]
+    pub(crate) grpc_max_concurrent_requests: Option<std::num::NonZeroUsize>,
+
+    /// Records each discovery response--the client's identity, the discovered workload, and the
+    /// server and authorizations that apply--as a line of JSON in the audit log.
+    #[structopt(long)]
+    pub(crate) enable_audit_log: bool,
+
+    /// The file to which audit records are appended. When unset, records are written to stdout,
+    /// interleaved with logs.
+    #[structopt(
+        long,
+        requires = "enable-audit-log",
+        env = "LINKERD_POLICY_AUDIT_LOG_PATH"
+    )]
+    pub(crate) audit_log_path: Option<PathBuf>,
+
+    /// Updates to a watched port that occur within this window after an update is sent are
+    /// collapsed into a single update (e.g. `50ms`). `0s` sends every update.
+    #[structopt(
+        long,
+        default_value = "50ms",
+        parse(try_from_str = parse_duration),
+        env = "LINKERD_POLICY_INDEX_UPDATE_DEBOUNCE"
+    )]
+    pub(crate) index_update_debounce: time::Duration,
+
+    /// The maximum number of servers described individually by the per-server metrics (e.g.
+    /// `index_server_authorizations`). Servers beyond this limit are described in aggregate, with
+    /// `other` labels, so that large clusters don't overwhelm Prometheus.
+    #[structopt(
+        long,
+        default_value = "1000",
+        env = "LINKERD_POLICY_INDEX_SERVER_METRICS_LIMIT"
+    )]
+    pub(crate) index_server_metrics_limit: usize,
+
+    /// How long watch events may fail to be processed, without any event being processed
+    /// successfully, before the controller is reported as not ready (e.g. `5s`). `0s` reports the
+    /// controller as not ready as soon as an event fails.
+    #[structopt(
+        long,
+        default_value = "5s",
+        parse(try_from_str = parse_duration),
+        env = "LINKERD_POLICY_UNREADY_GRACE_PERIOD"
+    )]
+    pub(crate) unready_grace_period: time::Duration,
+
+    /// The interval at which all resources are re-listed, correcting any drift from missed watch
+    /// events (e.g. `10m`). Each interval is jittered by up to 10% so that replicas don't re-list
+    /// in lockstep. `0s` disables periodic resyncs.
+    #[structopt(
+        long,
+        default_value = "10m",
+        parse(try_from_str = parse_duration),
+        env = "LINKERD_POLICY_RESYNC_INTERVAL"
+    )]
+    pub(crate) resync_interval: time::Duration,
+
+    /// When set, the indexed resources are written to this file on shutdown and, on startup, read
+    /// from it to serve policies while the index syncs with the API server.
+    #[structopt(long, env = "LINKERD_POLICY_INDEX_SNAPSHOT_PATH")]
+    pub(crate) index_snapshot_path: Option<PathBuf>,
+
+    /// When set, policies are served for the `Namespace`, `Pod`, `Server`, `ServerAuthorization`,
+    /// and `ClusterServer` resources in this YAML file rather than for resources watched from the
+    /// API server, e.g. for hermetic tests. The file is read once, at startup.
+    ///
+    /// No Kubernetes client is created, so the identity domain must be set, and the admission
+    /// controller, leader election, handoff coordination, and pod network check are disabled.
+    #[structopt(
+        long,
+        requires = "identity-domain",
+        conflicts_with_all = &["enable-leader-election", "coordinate-handoff"],
+        env = "LINKERD_POLICY_STATIC_POLICY_FILE"
+    )]
+    pub(crate) static_policy_file: Option<PathBuf>,
+
+    /// The path to a PEM-encoded certificate for the gRPC server. When set with `--grpc-tls-key`,
+    /// the gRPC server only accepts TLS connections.
+    #[structopt(long, env = "LINKERD_POLICY_GRPC_TLS_CERT")]
+    pub(crate) grpc_tls_cert: Option<PathBuf>,
+
+    /// The path to the gRPC server's PEM-encoded private key.
+    #[structopt(long, env = "LINKERD_POLICY_GRPC_TLS_KEY")]
+    pub(crate) grpc_tls_key: Option<PathBuf>,
+
+    /// The path to PEM-encoded roots used to verify gRPC clients. When set, clients must present
+    /// a certificate issued by one of these roots.
+    #[structopt(long, env = "LINKERD_POLICY_GRPC_TLS_CLIENT_CA")]
+    pub(crate) grpc_tls_client_ca: Option<PathBuf>,
+
+    /// Denies discovery requests for workloads outside of the namespace of the client's mTLS
+    /// identity. Requires `--grpc-tls-client-ca`.
+    #[structopt(long, requires = "grpc-tls-client-ca")]
+    pub(crate) grpc_enforce_client_identity: bool,
+
+    /// Encodes equivalent discovery responses identically and sets each lookup's content hash on
+    /// its `l5d-policy-hash` header, so that responses may be cached by their contents.
+    #[structopt(long)]
+    pub(crate) grpc_stable_encoding: bool,
+
+    #[structopt(long, env = "LINKERD_POLICY_ADMISSION_ADDR")]
+    pub(crate) admission_addr: Option<SocketAddr>,
+
+    /// Disables the admission server, even if an `--admission-addr` is set.
+    ///
+    /// The admission server's TLS certificate and key need not exist when it is disabled.
+    #[structopt(long)]
+    pub(crate) disable_admission: bool,
+
+    /// The path to the admission server's PEM-encoded TLS certificate.
+    #[structopt(
+        long,
+        default_value = "/var/run/linkerd/tls/tls.crt",
+        env = "LINKERD_POLICY_ADMISSION_TLS_CERT"
+    )]
+    pub(crate) admission_tls_cert: PathBuf,
+
+    /// The path to the admission server's PEM-encoded TLS private key.
+    #[structopt(
+        long,
+        default_value = "/var/run/linkerd/tls/tls.key",
+        env = "LINKERD_POLICY_ADMISSION_TLS_KEY"
+    )]
+    pub(crate) admission_tls_key: PathBuf,
+
+    /// The minimum TLS version accepted by the admission server: `1.2` or `1.3`.
+    ///
+    /// Requiring TLS 1.3 is only possible when the Kubernetes API server supports it, since the API
+    /// server must complete a handshake to send admission reviews.
+    #[structopt(
+        long,
+        default_value = "1.2",
+        env = "LINKERD_POLICY_ADMISSION_MIN_TLS_VERSION"
+    )]
+    pub(crate) admission_min_tls_version: tls::TlsVersion,
+
+    /// When set, limits the number of admission reviews processed each second. Reviews beyond the
+    /// limit receive a response indicating that the webhook is busy.
+    #[structopt(long, env = "LINKERD_POLICY_ADMISSION_RATE_LIMIT")]
+    pub(crate) admission_rate_limit: Option<std::num::NonZeroU32>,
+
+    /// A comma-separated list of namespaces in which resources are reviewed. Resources in other
+    /// namespaces are admitted without review.
+    #[structopt(
+        long,
+        parse(try_from_str = parse_namespaces),
+        env = "LINKERD_POLICY_ADMISSION_NAMESPACES"
+    )]
+    pub(crate) admission_namespaces: Option<std::collections::HashSet<String>>,
+
+    /// A label selector for namespaces in which resources are reviewed. Resources in other
+    /// namespaces are admitted without review.
+    ///
+    /// Namespaces' labels are read from the index, so this can't be set with `--namespace`, which
+    /// disables the namespace watch.
+    #[structopt(
+        long,
+        conflicts_with = "namespace",
+        parse(try_from_str = parse_label_selector),
+        env = "LINKERD_POLICY_ADMISSION_NAMESPACE_SELECTOR"
+    )]
+    pub(crate) admission_namespace_selector: Option<String>,
+
+    /// Determines how admission reviews are answered when a resource can't be reviewed because of
+    /// an internal error: `open` admits the resource with a warning, and `closed` denies it.
+    #[structopt(
+        long,
+        default_value = "open",
+        env = "LINKERD_POLICY_ADMISSION_FAILURE_POLICY"
+    )]
+    pub(crate) admission_failure_policy: admission::FailurePolicy,
+
+    /// The maximum size, in bytes, of an admission review. Larger reviews are rejected with a
+    /// `413 Payload Too Large` before they're read.
+    #[structopt(
+        long,
+        default_value = "1048576",
+        env = "LINKERD_POLICY_ADMISSION_MAX_BODY_BYTES"
+    )]
+    pub(crate) admission_max_body_bytes: std::num::NonZeroU64,
+
+    /// Reviews resources without enforcing decisions: every resource is admitted, with a warning
+    /// describing any denial, and nothing is written to the Kubernetes API. Policy discovery is
+    /// unaffected.
+    ///
+    /// This is intended for observing the controller in a new cluster before enforcing
+    /// policies. Leader election writes Leases, so it can't be enabled in this mode.
+    #[structopt(long, conflicts_with = "enable-leader-election")]
+    pub(crate) observe_only: bool,
+
+    /// Enables leader election so that only one replica records admission events. All replicas
+    /// answer admission reviews and serve policy discovery.
+    ///
+    /// The controller must be authorized to get, create, and update Leases in the lease namespace.
+    #[structopt(long)]
+    pub(crate) enable_leader_election: bool,
+
+    /// The namespace of the leader election and handoff Leases. Defaults to the control plane
+    /// namespace.
+    #[structopt(long, env = "LINKERD_POLICY_LEASE_NAMESPACE")]
+    pub(crate) lease_namespace: Option<String>,
+
+    /// The name of the leader election Lease.
+    #[structopt(
+        long,
+        default_value = "linkerd-policy-controller",
+        env = "LINKERD_POLICY_LEASE_NAME"
+    )]
+    pub(crate) lease_name: String,
+
+    /// The amount of time other replicas wait after the leader last renewed its lease before
+    /// taking it over.
+    #[structopt(
+        long,
+        default_value = "15s",
+        parse(try_from_str = parse_nonzero_duration),
+        env = "LINKERD_POLICY_LEASE_DURATION"
+    )]
+    pub(crate) lease_duration: time::Duration,
+
+    /// The amount of time the leader continues to lead without renewing its lease. Must be less
+    /// than the lease duration.
+    #[structopt(
+        long,
+        default_value = "10s",
+        parse(try_from_str = parse_nonzero_duration),
+        env = "LINKERD_POLICY_LEASE_RENEW_DEADLINE"
+    )]
+    pub(crate) lease_renew_deadline: time::Duration,
+
+    /// The interval at which the lease is renewed or, by other replicas, checked. Must be less
+    /// than the renew deadline.
+    #[structopt(
+        long,
+        default_value = "2s",
+        parse(try_from_str = parse_nonzero_duration),
+        env = "LINKERD_POLICY_LEASE_RETRY_PERIOD"
+    )]
+    pub(crate) lease_retry_period: time::Duration,
+
+    /// Coordinates handoffs between replicas during rollouts: each replica announces when it's
+    /// ready, and a replica that's shutting down continues serving until another replica has
+    /// announced that it's ready, for at most the shutdown grace period.
+    ///
+    /// The controller must be authorized to get, create, and update Leases in the lease namespace.
+    #[structopt(long)]
+    pub(crate) coordinate_handoff: bool,
+
+    /// The name of the Lease on which replicas announce that they're ready.
+    #[structopt(
+        long,
+        default_value = "linkerd-policy-controller-handoff",
+        env = "LINKERD_POLICY_HANDOFF_LEASE_NAME"
+    )]
+    pub(crate) handoff_lease_name: String,
+
+    /// Network CIDRs of pod IPs.
+    ///
+    /// IPv4 and IPv6 networks may be mixed. The default includes all private IPv4 networks and,
+    /// when the controller detects that it is running on a dual-stack network, the IPv6 unique
+    /// local address range.
+    #[structopt(long, env = "LINKERD_POLICY_CLUSTER_NETWORKS")]
+    pub(crate) cluster_networks: Option<IpNets>,
+
+    /// A file containing newline- or comma-separated network CIDRs of pod IPs, used instead of
+    /// `--cluster-networks`.
+    ///
+    /// The file is watched for changes so that networks can be updated without restarting the
+    /// controller. Invalid updates are logged and ignored.
+    #[structopt(
+        long,
+        conflicts_with = "cluster-networks",
+        env = "LINKERD_POLICY_CLUSTER_NETWORKS_FILE"
+    )]
+    pub(crate) cluster_networks_file: Option<PathBuf>,
+
+    /// Skips the startup check that warns when the cluster networks don't cover the pod CIDRs
+    /// assigned to nodes, e.g. when the controller isn't authorized to list nodes.
+    #[structopt(long)]
+    pub(crate) skip_network_check: bool,
+
+    /// Reports the controller as not ready while any indexed pod's IP is outside of the cluster
+    /// networks. Otherwise, uncovered networks are only logged.
+    #[structopt(long)]
+    pub(crate) strict_network_check: bool,
+
+    /// Omits IPv4 networks from the authorizations served to proxies, e.g. on an IPv6-only
+    /// cluster. This applies to the cluster networks and to networks set on authorizations.
+    #[structopt(long, conflicts_with = "disable-ipv6")]
+    pub(crate) disable_ipv4: bool,
+
+    /// Omits IPv6 networks from the authorizations served to proxies, e.g. on an IPv4-only
+    /// cluster. This applies to the cluster networks and to networks set on authorizations.
+    #[structopt(long)]
+    pub(crate) disable_ipv6: bool,
+
+    /// The name of the `ValidatingWebhookConfiguration` that registers the admission server.
+    #[structopt(
+        long,
+        default_value = "linkerd-policy-validator-webhook-config",
+        env = "LINKERD_POLICY_ADMISSION_WEBHOOK_CONFIG"
+    )]
+    pub(crate) admission_webhook_config: String,
+
+    /// Skips the startup check that warns when the admission webhook's `caBundle` doesn't include
+    /// the CA that issued the admission server's certificate, e.g. when the controller isn't
+    /// authorized to read webhook configurations.
+    #[structopt(long)]
+    pub(crate) skip_webhook_check: bool,
+
+    /// Verifies that the controller is authorized to list and watch the resources it indexes, and
+    /// exits. The controller exits unsuccessfully if any permissions are missing.
+    ///
+    /// Otherwise, missing permissions are logged at startup but don't prevent the controller from
+    /// starting.
+    #[structopt(long, conflicts_with = "static-policy-file")]
+    pub(crate) verify_rbac: bool,
+
+    /// The maximum number of attempts to initialize a Kubernetes client before exiting.
+    #[structopt(
+        long,
+        default_value = "5",
+        env = "LINKERD_POLICY_KUBE_CLIENT_RETRY_MAX"
+    )]
+    pub(crate) kube_client_retry_max: u32,
+
+    /// The initial delay between attempts to initialize a Kubernetes client. The delay doubles
+    /// (with jitter) after each failed attempt.
+    #[structopt(
+        long,
+        default_value = "1s",
+        parse(try_from_str = parse_nonzero_duration),
+        env = "LINKERD_POLICY_KUBE_CLIENT_RETRY_BASE_DELAY"
+    )]
+    pub(crate) kube_client_retry_base_delay: time::Duration,
+
+    /// The number of consecutive failed Kubernetes API requests after which requests fail
+    /// immediately, without being sent, until the `--kube-breaker-cooldown` elapses.
+    #[structopt(
+        long,
+        default_value = "5",
+        env = "LINKERD_POLICY_KUBE_BREAKER_THRESHOLD"
+    )]
+    pub(crate) kube_breaker_threshold: std::num::NonZeroU32,
+
+    /// The amount of time that Kubernetes API requests fail immediately once the
+    /// `--kube-breaker-threshold` is reached. A single request is then sent to check whether the
+    /// API server has recovered.
+    #[structopt(
+        long,
+        default_value = "10s",
+        parse(try_from_str = parse_nonzero_duration),
+        env = "LINKERD_POLICY_KUBE_BREAKER_COOLDOWN"
+    )]
+    pub(crate) kube_breaker_cooldown: time::Duration,
+
+    /// The mesh identity trust domain, used to build the identities of authorized clients.
+    ///
+    /// When unset, the trust domain is read from the control plane's `linkerd-config` ConfigMap or,
+    /// failing that, derived from the pod's DNS search domains. If neither is available,
+    /// `cluster.local` is used.
+    #[structopt(
+        long,
+        parse(try_from_str = parse_identity_domain),
+        env = "LINKERD_POLICY_IDENTITY_DOMAIN"
+    )]
+    pub(crate) identity_domain: Option<String>,
+
+    /// The path to the mesh's PEM-encoded trust anchors. When set, a warning is logged at startup
+    /// if no trust anchor's trust domain matches the identity domain, since clients' identities
+    /// would then not match those issued by the mesh.
+    #[structopt(long, env = "LINKERD_POLICY_TRUST_ANCHORS_PATH")]
+    pub(crate) trust_anchors_path: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        default_value = "all-unauthenticated",
+        env = "LINKERD_POLICY_DEFAULT_POLICY"
+    )]
+    pub(crate) default_policy: DefaultPolicy,
+
+    #[structopt(
+        long,
+        default_value = "linkerd",
+        env = "LINKERD_POLICY_CONTROL_PLANE_NAMESPACE"
+    )]
+    pub(crate) control_plane_namespace: String,
+
+    /// When set, only resources in this namespace are indexed so that the controller may run
+    /// with namespace-scoped RBAC. By default, resources are indexed in all namespaces.
+    ///
+    /// Namespaces can't be watched with namespace-scoped RBAC, so namespace default-policy
+    /// annotations are ignored when this is set.
+    #[structopt(long, env = "LINKERD_POLICY_NAMESPACE")]
+    pub(crate) namespace: Option<String>,
+
+    /// When set, only `Server` and `ServerAuthorization` resources matching this label selector
+    /// (e.g. `policy.example.com/scope=meshed`) are watched and indexed.
+    #[structopt(
+        long,
+        parse(try_from_str = parse_label_selector),
+        env = "LINKERD_POLICY_WATCH_LABEL_SELECTOR"
+    )]
+    pub(crate) watch_label_selector: Option<String>,
+
+    /// A label selector for the pods that are meshed, and so may discover their policies. Other
+    /// pods are neither watched nor indexed, including those read from a static policy file.
+    #[structopt(
+        long,
+        default_value = "linkerd.io/control-plane-ns",
+        parse(try_from_str = parse_label_selector),
+        env = "LINKERD_POLICY_MESHED_WORKLOAD_SELECTOR"
+    )]
+    pub(crate) meshed_workload_selector: String,
+
+    /// The comma-separated policy resource kinds to watch, which must include `Server`. When
+    /// `ServerAuthorization` is omitted, servers have no authorizations. `ClusterServer`s are only
+    /// watched when listed (and when the controller isn't limited to a namespace).
+    #[structopt(
+        long,
+        default_value = "Server,ServerAuthorization",
+        env = "LINKERD_POLICY_WATCH_KINDS"
+    )]
+    pub(crate) watch_kinds: linkerd_policy_controller::api::WatchKinds,
+
+    /// The amount of time proxies wait to detect a connection's protocol (e.g. `10s`, `500ms`).
+    #[structopt(
+        long,
+        default_value = "10s",
+        parse(try_from_str = parse_nonzero_duration),
+        env = "LINKERD_POLICY_DETECT_TIMEOUT"
+    )]
+    pub(crate) detect_timeout: time::Duration,
+
+    /// Overrides `--detect-timeout` for specific ports, as a comma-separated list of
+    /// `PORT=DURATION` pairs (e.g. `5432=100ms,8080=30s`).
+    ///
+    /// Overrides only apply to ports whose protocol is detected. Ports are matched by number, so
+    /// servers that select ports by name use the default timeout.
+    #[structopt(
+        long,
+        parse(try_from_str = parse_detect_timeout_overrides),
+        env = "LINKERD_POLICY_DETECT_TIMEOUT_OVERRIDES"
+    )]
+    pub(crate) detect_timeout_overrides: Option<std::collections::HashMap<u16, time::Duration>>,
+
+    /// The maximum amount of time to wait for clients to disconnect once shutdown begins.
+    ///
+    /// This should be less than the pod's termination grace period so that the controller exits
+    /// on its own before it's killed.
+    #[structopt(
+        long,
+        default_value = "30s",
+        parse(try_from_str = parse_duration),
+        env = "LINKERD_POLICY_SHUTDOWN_GRACE_PERIOD"
+    )]
+    pub(crate) shutdown_grace_period: time::Duration,
+
+    /// Drains the gRPC server before the admission server on shutdown, so that no new proxies
+    /// discover policies while admission reviews are still being served. The admission server
+    /// drains once the other servers have drained or the shutdown grace period elapses.
+    ///
+    /// By default, all servers drain together.
+    #[structopt(long)]
+    pub(crate) shutdown_drain_grpc_first: bool,
+
+    /// The number of threads that run the controller's tasks.
+    ///
+    /// Defaults to the number of CPUs allowed by the container's cgroup CPU quota (rounded up) or,
+    /// if no quota is set, the number of CPUs on the node.
+    #[structopt(long, env = "LINKERD_POLICY_RUNTIME_WORKER_THREADS")]
+    pub(crate) runtime_worker_threads: Option<std::num::NonZeroUsize>,
+
+    /// Validates the configuration and prints it, exiting without connecting to Kubernetes or
+    /// binding any ports.
+    #[structopt(long)]
+    pub(crate) check: bool,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum LogFormat {
+    Json,
+    Plain,
+}
+
+#[derive(Debug)]
+pub(crate) struct IpNets(pub(crate) Vec<IpNet>);
+
+/// The gRPC server's address.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum GrpcAddr {
+    Tcp(SocketAddr),
+
+    /// The path of a Unix domain socket.
+    Unix(PathBuf),
+}
+
+// === impl IpNets ===
+
+impl IpNets {
+    const DEFAULT_V4: &'static str = "10.0.0.0/8,100.64.0.0/10,172.16.0.0/12,192.168.0.0/16";
+    const DEFAULT_V6: &'static str = "fd00::/8";
+
+    /// Returns the default cluster networks for this host, including the IPv6 unique local address
+    /// range when the host has a globally-scoped IPv6 address.
+    pub(crate) fn host_default() -> Self {
+        let nets = if Self::is_dual_stack() {
+            debug!("Detected a dual-stack network; including IPv6 default networks");
+            format!("{},{}", Self::DEFAULT_V4, Self::DEFAULT_V6)
+        } else {
+            Self::DEFAULT_V4.to_string()
+        };
+        nets.parse().expect("default networks must be valid")
+    }
+
+    /// Loads networks from a file, where networks are separated by newlines or commas.
+    pub(crate) fn load(path: &std::path::Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Self::parse_file(&contents)
+            .with_context(|| format!("invalid networks in {}", path.display()))
+    }
+
+    fn parse_file(contents: &str) -> Result<Self> {
+        let nets = contents
+            .lines()
+            .map(|l| l.trim().trim_matches(','))
+            .filter(|l| !l.is_empty())
+            .collect::<Vec<_>>();
+        if nets.is_empty() {
+            bail!("no networks are listed");
+        }
+        nets.join(",").parse()
+    }
+
+    /// Polls the networks file, publishing its networks when it's modified.
+    ///
+    /// If the file can't be loaded (e.g. because it's malformed or only partially written), an
+    /// error is logged and the previously-loaded networks remain in use.
+    pub(crate) async fn reload_on_change(
+        path: PathBuf,
+        networks: watch::Sender<Arc<[IpNet]>>,
+        interval: time::Duration,
+    ) {
+        let mtime = || std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+        let mut loaded = mtime();
+        loop {
+            time::sleep(interval).await;
+
+            let modified = mtime();
+            if modified == loaded {
+                continue;
+            }
+
+            match Self::load(&path) {
+                Ok(Self(nets)) => {
+                    loaded = modified;
+                    if *networks.borrow().as_ref() != nets[..] {
+                        info!(path = %path.display(), networks = %Self(nets.clone()), "Reloaded cluster networks");
+                        if networks.send(nets.into()).is_err() {
+                            return;
+                        }
+                    }
+                }
+                // Continue using the prior networks.
+                Err(error) => error!(%error, "Failed to reload cluster networks"),
+            }
+        }
+    }
+
+    /// Checks whether the host has a global (i.e. not loopback or link-local) IPv6 address.
+    ///
+    /// Each line of `/proc/net/if_inet6` describes an address as: the address, the interface
+    /// index, the prefix length, the scope, the flags, and the interface name. A scope of `00`
+    /// indicates a global address (which includes unique local addresses).
+    fn is_dual_stack() -> bool {
+        match std::fs::read_to_string("/proc/net/if_inet6") {
+            Ok(addrs) => addrs
+                .lines()
+                .any(|l| l.split_whitespace().nth(3) == Some("00")),
+            Err(_) => false,
+        }
+    }
+}
+
+impl std::fmt::Display for IpNets {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut nets = self.0.iter();
+        if let Some(net) = nets.next() {
+            net.fmt(f)?;
+        }
+        for net in nets {
+            write!(f, ",{}", net)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for IpNets {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut nets = Vec::<IpNet>::new();
+        for (i, n) in s.split(',').enumerate() {
+            let net = n
+                .trim()
+                .parse::<IpNet>()
+                .with_context(|| format!("invalid network at index {}: {:?}", i, n))?;
+            // Exact duplicates are harmless, so they're silently dropped.
+            if !nets.contains(&net) {
+                nets.push(net);
+            }
+        }
+
+        // Networks that overlap are almost certainly a misconfiguration, so we fail loudly rather
+        // than guess at which network was intended. Since CIDRs can't partially overlap, it's
+        // sufficient to check whether either network contains the other.
+        for (i, a) in nets.iter().enumerate() {
+            for b in &nets[i + 1..] {
+                if a.contains(b) || b.contains(a) {
+                    bail!("networks {} and {} overlap", a, b);
+                }
+            }
+        }
+
+        Ok(Self(nets))
+    }
+}
+
+// === impl GrpcAddr ===
+
+impl GrpcAddr {
+    const UNIX_PREFIX: &'static str = "unix:";
+
+    pub(crate) fn tcp(&self) -> Option<SocketAddr> {
+        match self {
+            Self::Tcp(addr) => Some(*addr),
+            Self::Unix(_) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for GrpcAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Tcp(addr) => addr.fmt(f),
+            Self::Unix(path) => write!(f, "{}{}", Self::UNIX_PREFIX, path.display()),
+        }
+    }
+}
+
+impl std::str::FromStr for GrpcAddr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.strip_prefix(Self::UNIX_PREFIX) {
+            Some("") => bail!("socket path must not be empty"),
+            Some(path) => Ok(Self::Unix(path.into())),
+            None => s
+                .parse()
+                .map(Self::Tcp)
+                .with_context(|| format!("invalid address: {:?}", s)),
+        }
+    }
+}
+
+/// Returns the number of CPUs allowed by the container's cgroup CPU quota or, if no quota is set,
+/// the number of CPUs on the node.
+pub(crate) fn default_worker_threads() -> usize {
+    let cpus = num_cpus::get();
+    cgroup_cpu_limit().map_or(cpus, |limit| limit.min(cpus))
+}
+
+/// Reads the cgroup's CPU quota, as a number of CPUs rounded up, supporting both cgroup v2 and v1.
+fn cgroup_cpu_limit() -> Option<usize> {
+    if let Ok(max) = std::fs::read_to_string("/sys/fs/cgroup/cpu.max") {
+        return parse_cpu_max(&max);
+    }
+
+    let read = |name: &str| {
+        let path = std::path::Path::new("/sys/fs/cgroup/cpu").join(name);
+        std::fs::read_to_string(path)
+            .ok()?
+            .trim()
+            .parse::<i64>()
+            .ok()
+    };
+    cpu_limit(read("cpu.cfs_quota_us")?, read("cpu.cfs_period_us")?)
+}
+
+/// Parses a cgroup v2 `cpu.max` file, formatted as `$MAX $PERIOD`, where `$MAX` may be `max` when
+/// there's no quota.
+fn parse_cpu_max(s: &str) -> Option<usize> {
+    let mut parts = s.split_whitespace();
+    let quota = parts.next()?;
+    let period = parts.next().map_or(Some(100_000), |p| p.parse().ok())?;
+    if quota == "max" {
+        return None;
+    }
+    cpu_limit(quota.parse().ok()?, period)
+}
+
+/// Converts a CFS quota and period (in microseconds) to a number of CPUs, rounded up. Quotas that
+/// aren't positive (e.g. cgroup v1's `-1`) indicate that there's no limit.
+fn cpu_limit(quota: i64, period: i64) -> Option<usize> {
+    if quota <= 0 || period <= 0 {
+        return None;
+    }
+    usize::try_from((quota + period - 1) / period).ok()
+}
+
+/// Parses a duration like `10s`, `500ms`, or `1m30s`.
+///
+/// Supported units are `ms`, `s`, `m`, and `h`.
+fn parse_duration(s: &str) -> Result<time::Duration> {
+    let mut rest = s.trim();
+    if rest.is_empty() {
+        bail!("duration must not be empty");
+    }
+
+    let mut duration = time::Duration::ZERO;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        if digits == 0 {
+            bail!("invalid duration: {:?}", s);
+        }
+        let n = rest[..digits]
+            .parse::<u64>()
+            .with_context(|| format!("invalid duration: {:?}", s))?;
+        rest = &rest[digits..];
+
+        let unit = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let part = match &rest[..unit] {
+            "ms" => Some(time::Duration::from_millis(n)),
+            "s" => Some(time::Duration::from_secs(n)),
+            "m" => n.checked_mul(60).map(time::Duration::from_secs),
+            "h" => n.checked_mul(60 * 60).map(time::Duration::from_secs),
+            "" => bail!("duration must specify a unit: {:?}", s),
+            unit => bail!("invalid duration unit {:?} in {:?}", unit, s),
+        };
+        duration = part
+            .and_then(|part| duration.checked_add(part))
+            .ok_or_else(|| anyhow::anyhow!("duration is too large: {:?}", s))?;
+        rest = &rest[unit..];
+    }
+
+    Ok(duration)
+}
+
+/// Parses a duration, requiring that it be greater than zero.
+fn parse_nonzero_duration(s: &str) -> Result<time::Duration> {
+    let duration = parse_duration(s)?;
+    if duration == time::Duration::ZERO {
+        bail!("duration must be greater than zero");
+    }
+    Ok(duration)
+}
+
+/// Parses a comma-separated list of `PORT=DURATION` pairs.
+fn parse_detect_timeout_overrides(
+    s: &str,
+) -> Result<std::collections::HashMap<u16, time::Duration>> {
+    let mut overrides = std::collections::HashMap::new();
+    for pair in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (port, timeout) = pair
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("invalid port timeout: {:?}", pair))?;
+        let port = port
+            .trim()
+            .parse::<u16>()
+            .with_context(|| format!("invalid port: {:?}", port))?;
+        if port == 0 {
+            bail!("port must not be 0");
+        }
+        if overrides
+            .insert(port, parse_nonzero_duration(timeout)?)
+            .is_some()
+        {
+            bail!("port {} is overridden more than once", port);
+        }
+    }
+    if overrides.is_empty() {
+        bail!("at least one port timeout must be set");
+    }
+    Ok(overrides)
+}
+
+/// Validates a label selector, so that a malformed selector fails at startup rather than when the
+/// resources are watched.
+fn parse_label_selector(s: &str) -> Result<String> {
+    if s.trim().is_empty() {
+        bail!("label selector must not be empty");
+    }
+    s.parse::<linkerd_policy_controller::api::labels::Selector>()?;
+    Ok(s.to_string())
+}
+
+/// Parses a comma-separated list of namespace names.
+fn parse_namespaces(s: &str) -> Result<std::collections::HashSet<String>> {
+    let namespaces = s
+        .split(',')
+        .map(str::trim)
+        .filter(|ns| !ns.is_empty())
+        .map(|ns| {
+            let valid = ns.len() <= 63
+                && ns
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+                && !ns.starts_with('-')
+                && !ns.ends_with('-');
+            if !valid {
+                bail!("invalid namespace: {:?}", ns);
+            }
+            Ok(ns.to_string())
+        })
+        .collect::<Result<std::collections::HashSet<_>>>()?;
+    if namespaces.is_empty() {
+        bail!("at least one namespace must be set");
+    }
+    Ok(namespaces)
+}
+
+/// Parses an HTTP/2 flow-control window size, which must be between 1 byte and 2^31-1 bytes.
+fn parse_window_size(s: &str) -> Result<u32> {
+    const MAX: u32 = (1 << 31) - 1;
+    let size = s
+        .parse::<u32>()
+        .with_context(|| format!("invalid window size: {:?}", s))?;
+    if size == 0 || size > MAX {
+        bail!("window size must be between 1 and {}: {}", MAX, size);
+    }
+    Ok(size)
+}
+
+/// Ensures that no two servers bind the same port, ignoring unset addresses.
+pub(crate) fn check_addrs(addrs: &[(&str, Option<SocketAddr>)]) -> Result<()> {
+    let addrs = addrs
+        .iter()
+        .filter_map(|(name, addr)| addr.map(|a| (*name, a)))
+        .collect::<Vec<_>>();
+    for (i, (a_name, a)) in addrs.iter().enumerate() {
+        for (b_name, b) in &addrs[i + 1..] {
+            let overlap = a.ip() == b.ip() || a.ip().is_unspecified() || b.ip().is_unspecified();
+            if a.port() != 0 && a.port() == b.port() && overlap {
+                bail!(
+                    "{} address {} conflicts with {} address {}",
+                    a_name,
+                    a,
+                    b_name,
+                    b
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Parses a DNS domain name (e.g. `cluster.local`), normalizing it to lowercase.
+pub(crate) fn parse_identity_domain(s: &str) -> Result<String> {
+    if s.is_empty() {
+        bail!("identity domain must not be empty");
+    }
+    if s.len() > 253 {
+        bail!("identity domain must not exceed 253 characters");
+    }
+    if s.starts_with('.') || s.ends_with('.') {
+        bail!("identity domain must not begin or end with a dot: {:?}", s);
+    }
+    for label in s.split('.') {
+        if label.is_empty() {
+            bail!("identity domain must not contain empty labels: {:?}", s);
+        }
+        if label.len() > 63 {
+            bail!(
+                "identity domain labels must not exceed 63 characters: {:?}",
+                label
+            );
+        }
+        if !label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            bail!("identity domain labels must be alphanumeric: {:?}", label);
+        }
+        if label.starts_with('-') || label.ends_with('-') {
+            bail!(
+                "identity domain labels must not begin or end with a hyphen: {:?}",
+                label
+            );
+        }
+    }
+    Ok(s.to_ascii_lowercase())
+}
+
+fn parse_sample_rate(s: &str) -> Result<f64> {
+    let rate = s
+        .parse::<f64>()
+        .with_context(|| format!("invalid sample rate: {:?}", s))?;
+    if !(0.0..=1.0).contains(&rate) {
+        bail!("sample rate must be between 0 and 1: {}", rate);
+    }
+    Ok(rate)
+}
+
+// === impl LogFormat ===
+
+impl std::str::FromStr for LogFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s == "json" {
+            Ok(Self::Json)
+        } else if s == "plain" {
+            Ok(Self::Plain)
+        } else {
+            bail!("invalid log format: {}", s)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::certs::load_admission_certs;
+
+    /// Held by tests while they read or modify the process's environment.
+    static ENV: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    /// Parses command-line arguments, falling back to the environment like `Args::from_args`.
+    fn parse<I>(args: I) -> structopt::clap::Result<Args>
+    where
+        I: IntoIterator,
+        I::Item: Into<std::ffi::OsString> + Clone,
+    {
+        let _env = ENV.blocking_lock();
+        Args::from_iter_safe(args)
+    }
+
+    #[test]
+    fn resync_interval() {
+        let args = parse(&["policy"]).unwrap();
+        assert_eq!(args.resync_interval, time::Duration::from_secs(600));
+        let args = parse(&["policy", "--resync-interval=0s"]).unwrap();
+        assert!(args.resync_interval.is_zero());
+    }
+
+    #[test]
+    fn index_server_metrics_limit() {
+        let args = parse(&["policy"]).unwrap();
+        assert_eq!(args.index_server_metrics_limit, 1000);
+        let args = parse(&["policy", "--index-server-metrics-limit=0"]).unwrap();
+        assert_eq!(args.index_server_metrics_limit, 0);
+        assert!(parse(&["policy", "--index-server-metrics-limit=-1"]).is_err());
+    }
+
+    #[test]
+    fn unready_grace_period() {
+        let args = parse(&["policy"]).unwrap();
+        assert_eq!(
+            args.unready_grace_period,
+            linkerd_policy_controller::k8s::Index::DEFAULT_UNREADY_GRACE_PERIOD
+        );
+        let args = parse(&["policy", "--unready-grace-period=0s"]).unwrap();
+        assert!(args.unready_grace_period.is_zero());
+        assert!(parse(&["policy", "--unready-grace-period=soon"]).is_err());
+    }
+
+    #[test]
+    fn parse_ipnets_dual_stack() {
+        let IpNets(nets) = "10.0.0.0/8, fd00::/8,192.168.0.0/16".parse().unwrap();
+        assert_eq!(
+            nets,
+            vec![
+                "10.0.0.0/8".parse::<IpNet>().unwrap(),
+                "fd00::/8".parse().unwrap(),
+                "192.168.0.0/16".parse().unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_ipnets_invalid() {
+        let error = "10.0.0.0/8,fd00::/8,bogus"
+            .parse::<IpNets>()
+            .expect_err("invalid network must not parse");
+        assert!(
+            error.to_string().contains("index 2"),
+            "error must name the invalid index: {}",
+            error
+        );
+    }
+
+    #[test]
+    fn parse_ipnets_file() {
+        let IpNets(nets) =
+            IpNets::parse_file("10.0.0.0/8\n\n  fd00::/8,\n192.168.0.0/16,172.16.0.0/12\n")
+                .unwrap();
+        assert_eq!(
+            nets,
+            vec![
+                "10.0.0.0/8".parse::<IpNet>().unwrap(),
+                "fd00::/8".parse().unwrap(),
+                "192.168.0.0/16".parse().unwrap(),
+                "172.16.0.0/12".parse().unwrap(),
+            ]
+        );
+
+        assert!(
+            IpNets::parse_file("").is_err(),
+            "empty files must be rejected"
+        );
+        assert!(IpNets::parse_file("10.0.0.0/8\nbogus\n").is_err());
+
+        assert!(
+            parse(&[
+                "policy",
+                "--cluster-networks=10.0.0.0/8",
+                "--cluster-networks-file=/etc/networks",
+            ])
+            .is_err(),
+            "networks must not be set inline and from a file"
+        );
+    }
+
+    #[test]
+    fn parse_grpc_addrs() {
+        assert_eq!(
+            "0.0.0.0:8090".parse::<GrpcAddr>().unwrap(),
+            GrpcAddr::Tcp(([0, 0, 0, 0], 8090).into())
+        );
+        let unix = "unix:/var/run/policy.sock".parse::<GrpcAddr>().unwrap();
+        assert_eq!(unix, GrpcAddr::Unix("/var/run/policy.sock".into()));
+        assert_eq!(unix.to_string(), "unix:/var/run/policy.sock");
+        assert_eq!(unix.tcp(), None);
+
+        for s in ["unix:", "/var/run/policy.sock", "localhost"] {
+            assert!(s.parse::<GrpcAddr>().is_err(), "{:?} must not parse", s);
+        }
+    }
+
+    #[test]
+    fn parse_ipnets_dedup() {
+        let IpNets(nets) = "10.0.0.0/8,fd00::/8,10.0.0.0/8".parse().unwrap();
+        assert_eq!(
+            nets,
+            vec![
+                "10.0.0.0/8".parse::<IpNet>().unwrap(),
+                "fd00::/8".parse().unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_ipnets_overlapping() {
+        for (nets, msg) in [
+            ("10.0.0.0/8,10.1.0.0/16", "contained ipv4"),
+            ("fd00::/16,fd00::/8", "contained ipv6"),
+        ] {
+            assert!(nets.parse::<IpNets>().is_err(), "{}", msg);
+        }
+    }
+
+    #[test]
+    fn parse_durations() {
+        for (s, expected) in [
+            ("10s", time::Duration::from_secs(10)),
+            ("500ms", time::Duration::from_millis(500)),
+            ("2m", time::Duration::from_secs(120)),
+            ("1h", time::Duration::from_secs(3600)),
+            ("1m30s", time::Duration::from_secs(90)),
+            ("0s", time::Duration::ZERO),
+        ] {
+            assert_eq!(parse_duration(s).unwrap(), expected, "{}", s);
+        }
+
+        for s in [
+            "",
+            "10",
+            "s",
+            "-1s",
+            "1.5s",
+            "10d",
+            "18446744073709551615h",
+            "18446744073709551615s1s",
+        ] {
+            assert!(parse_duration(s).is_err(), "{:?} must not parse", s);
+        }
+    }
+
+    #[test]
+    fn parse_detect_timeout() {
+        assert_eq!(
+            parse_nonzero_duration("10s").unwrap(),
+            time::Duration::from_secs(10)
+        );
+        assert!(parse_nonzero_duration("0s").is_err());
+        assert!(parse_nonzero_duration("0ms").is_err());
+        assert!(parse_nonzero_duration("-10s").is_err());
+
+        assert!(parse(&["policy", "--detect-timeout=0s"]).is_err());
+        let args = parse(&["policy", "--detect-timeout=500ms"]).unwrap();
+        assert_eq!(args.detect_timeout, time::Duration::from_millis(500));
+        assert_eq!(args.detect_timeout_overrides, None);
+    }
+
+    #[test]
+    fn detect_timeout_overrides() {
+        let args = parse(&["policy", "--detect-timeout-overrides=5432=100ms, 8080=30s"]).unwrap();
+        assert_eq!(
+            args.detect_timeout_overrides,
+            Some(
+                vec![
+                    (5432, time::Duration::from_millis(100)),
+                    (8080, time::Duration::from_secs(30)),
+                ]
+                .into_iter()
+                .collect()
+            )
+        );
+
+        for s in [
+            "",
+            "5432",
+            "5432=",
+            "0=1s",
+            "http=1s",
+            "5432=0s",
+            "5432=1s,5432=2s",
+        ] {
+            assert!(
+                parse_detect_timeout_overrides(s).is_err(),
+                "{:?} must not parse",
+                s
+            );
+        }
+    }
+
+    #[test]
+    fn parse_label_selectors() {
+        for s in [
+            "app=web",
+            "tier in (a, b),!legacy",
+            "example.com/scope!=unmeshed",
+        ] {
+            assert_eq!(parse_label_selector(s).unwrap(), s);
+        }
+        for s in ["", " ", "app in (a", "=web", "app=web,"] {
+            assert!(parse_label_selector(s).is_err(), "{:?} must not parse", s);
+        }
+    }
+
+    #[test]
+    fn meshed_workload_selector() {
+        let args = parse(&["policy"]).unwrap();
+        assert_eq!(
+            args.meshed_workload_selector,
+            linkerd_policy_controller::api::DEFAULT_MESHED_WORKLOAD_SELECTOR
+        );
+        let args = parse(&["policy", "--meshed-workload-selector=example.com/mesh=true"]).unwrap();
+        assert_eq!(args.meshed_workload_selector, "example.com/mesh=true");
+        assert!(parse(&["policy", "--meshed-workload-selector=mesh in ("]).is_err());
+    }
+
+    #[test]
+    fn parse_namespace_lists() {
+        let namespaces = parse_namespaces("emojivoto, booksapp,emojivoto").unwrap();
+        assert_eq!(namespaces.len(), 2);
+        assert!(namespaces.contains("emojivoto") && namespaces.contains("booksapp"));
+
+        for s in ["", ",", "Emojivoto", "-ns", "ns_0", "a.b"] {
+            assert!(parse_namespaces(s).is_err(), "{:?} must not parse", s);
+        }
+    }
+
+    #[test]
+    fn parse_window_sizes() {
+        assert_eq!(parse_window_size("65535").unwrap(), 65_535);
+        assert_eq!(parse_window_size("2147483647").unwrap(), 2_147_483_647);
+        for s in ["", "0", "-1", "2147483648", "4294967296", "1MB"] {
+            assert!(parse_window_size(s).is_err(), "{:?} must not parse", s);
+        }
+
+        let args = parse(&["policy"]).unwrap();
+        assert_eq!(args.grpc_initial_stream_window, None);
+        assert_eq!(args.grpc_initial_connection_window, None);
+        assert!(parse(&["policy", "--grpc-initial-stream-window=0"]).is_err());
+    }
+
+    #[test]
+    fn parse_args_from_env() {
+        // The environment is shared by all tests, so it's only modified while no other test is
+        // parsing arguments, and it's restored before anything can panic.
+        let _env = ENV.blocking_lock();
+        let prior = std::env::var_os("LINKERD_POLICY_IDENTITY_DOMAIN");
+        std::env::set_var("LINKERD_POLICY_IDENTITY_DOMAIN", "env.example.com");
+        let from_env = Args::from_iter_safe(&["policy"]);
+        let from_flag = Args::from_iter_safe(&["policy", "--identity-domain=flag.example.com"]);
+        match prior {
+            Some(prior) => std::env::set_var("LINKERD_POLICY_IDENTITY_DOMAIN", prior),
+            None => std::env::remove_var("LINKERD_POLICY_IDENTITY_DOMAIN"),
+        }
+
+        assert_eq!(
+            from_env.unwrap().identity_domain.as_deref(),
+            Some("env.example.com")
+        );
+        assert_eq!(
+            from_flag.unwrap().identity_domain.as_deref(),
+            Some("flag.example.com"),
+            "flags must take precedence over the environment"
+        );
+    }
+
+    #[test]
+    fn parse_sample_rates() {
+        for (s, rate) in [("0", 0.0), ("0.25", 0.25), ("1.0", 1.0)] {
+            assert!(
+                (parse_sample_rate(s).unwrap() - rate).abs() < f64::EPSILON,
+                "{}",
+                s
+            );
+        }
+        for s in ["", "-0.1", "1.5", "NaN", "half"] {
+            assert!(parse_sample_rate(s).is_err(), "{:?} must not parse", s);
+        }
+
+        let args = parse(&["policy"]).unwrap();
+        assert!(args.trace_collector.is_none());
+        assert!((args.trace_sample_rate - 1.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn parse_log_format() {
+        assert!(matches!("plain".parse::<LogFormat>(), Ok(LogFormat::Plain)));
+        assert!(matches!("json".parse::<LogFormat>(), Ok(LogFormat::Json)));
+        assert!("yaml".parse::<LogFormat>().is_err());
+    }
+
+    #[test]
+    fn display_ipnets() {
+        let nets = "10.0.0.0/8,fd00::/8".parse::<IpNets>().unwrap();
+        assert_eq!(nets.to_string(), "10.0.0.0/8,fd00::/8");
+        assert_eq!(nets.to_string().parse::<IpNets>().unwrap().0, nets.0);
+    }
+
+    #[test]
+    fn parse_ipnets_defaults() {
+        assert!(IpNets::DEFAULT_V4.parse::<IpNets>().is_ok());
+        assert!(format!("{},{}", IpNets::DEFAULT_V4, IpNets::DEFAULT_V6)
+            .parse::<IpNets>()
+            .is_ok());
+    }
+
+    #[test]
+    fn parse_cgroup_cpu_limits() {
+        assert_eq!(parse_cpu_max("max 100000\n"), None);
+        assert_eq!(parse_cpu_max("100000 100000\n"), Some(1));
+        assert_eq!(parse_cpu_max("150000 100000\n"), Some(2));
+        assert_eq!(parse_cpu_max("50000 100000\n"), Some(1));
+        assert_eq!(parse_cpu_max("400000\n"), Some(4));
+        assert_eq!(parse_cpu_max(""), None);
+        assert_eq!(parse_cpu_max("bogus 100000"), None);
+
+        // cgroup v1 sets a quota of -1 when there's no limit.
+        assert_eq!(cpu_limit(-1, 100_000), None);
+        assert_eq!(cpu_limit(250_000, 100_000), Some(3));
+        assert_eq!(cpu_limit(100_000, 0), None);
+    }
+
+    #[test]
+    fn runtime_worker_threads_must_be_positive() {
+        let args = parse(&["policy"]).unwrap();
+        assert_eq!(args.runtime_worker_threads, None);
+
+        let args = parse(&["policy", "--runtime-worker-threads=2"]).unwrap();
+        assert_eq!(args.runtime_worker_threads.map(|n| n.get()), Some(2));
+
+        assert!(parse(&["policy", "--runtime-worker-threads=0"]).is_err());
+    }
+
+    #[test]
+    fn grpc_max_concurrent_requests_must_be_positive() {
+        let args = parse(&["policy", "--grpc-max-concurrent-requests=8"]).unwrap();
+        assert_eq!(args.grpc_max_concurrent_requests.map(|n| n.get()), Some(8));
+
+        assert!(parse(&["policy", "--grpc-max-concurrent-requests=0"]).is_err());
+    }
+
+    #[test]
+    fn disable_one_address_family() {
+        let args = parse(&["policy"]).unwrap();
+        assert!(!args.disable_ipv4 && !args.disable_ipv6);
+
+        let args = parse(&["policy", "--disable-ipv6"]).unwrap();
+        assert!(!args.disable_ipv4 && args.disable_ipv6);
+
+        assert!(
+            parse(&["policy", "--disable-ipv4", "--disable-ipv6"]).is_err(),
+            "both families must not be disabled"
+        );
+    }
+
+    #[test]
+    fn audit_log_path_requires_audit_log() {
+        let args = parse(&["policy", "--enable-audit-log"]).unwrap();
+        assert!(args.enable_audit_log);
+        assert_eq!(args.audit_log_path, None);
+
+        assert!(parse(&["policy", "--audit-log-path=/var/log/audit.json"]).is_err());
+        let args = parse(&[
+            "policy",
+            "--enable-audit-log",
+            "--audit-log-path=/var/log/audit.json",
+        ])
+        .unwrap();
+        assert_eq!(
+            args.audit_log_path,
+            Some(PathBuf::from("/var/log/audit.json"))
+        );
+    }
+
+    #[test]
+    fn single_port_conflicts_with_tls() {
+        let args = parse(&["policy"]).unwrap();
+        assert!(!args.single_port);
+        let args = parse(&["policy", "--single-port"]).unwrap();
+        assert!(args.single_port);
+
+        for tls in ["--admin-tls-cert=admin.pem", "--grpc-tls-cert=grpc.pem"] {
+            assert!(parse(&["policy", "--single-port", tls]).is_err(), "{}", tls);
+        }
+    }
+
+    #[test]
+    fn grpc_max_connection_age() {
+        let args = parse(&["policy"]).unwrap();
+        assert_eq!(args.grpc_max_connection_age, None);
+        assert_eq!(args.grpc_max_connection_age_grace, None);
+
+        assert!(
+            parse(&["policy", "--grpc-max-connection-age-grace=10s"]).is_err(),
+            "a grace period requires a maximum age"
+        );
+        assert!(parse(&["policy", "--grpc-max-connection-age=0s"]).is_err());
+        let args = parse(&[
+            "policy",
+            "--grpc-max-connection-age=30m",
+            "--grpc-max-connection-age-grace=10s",
+        ])
+        .unwrap();
+        assert_eq!(
+            args.grpc_max_connection_age,
+            Some(time::Duration::from_secs(30 * 60))
+        );
+        assert_eq!(
+            args.grpc_max_connection_age_grace,
+            Some(time::Duration::from_secs(10))
+        );
+    }
+
+    #[test]
+    fn grpc_enforce_client_identity_requires_client_ca() {
+        let args = parse(&["policy"]).unwrap();
+        assert!(!args.grpc_enforce_client_identity);
+
+        assert!(
+            parse(&["policy", "--grpc-enforce-client-identity"]).is_err(),
+            "client identities must be verified"
+        );
+        let args = parse(&[
+            "policy",
+            "--grpc-enforce-client-identity",
+            "--grpc-tls-client-ca=ca.pem",
+        ])
+        .unwrap();
+        assert!(args.grpc_enforce_client_identity);
+    }
+
+    #[test]
+    fn admission_min_tls_version() {
+        let args = parse(&["policy"]).unwrap();
+        assert_eq!(args.admission_min_tls_version, tls::TlsVersion::V1_2);
+
+        let args = parse(&["policy", "--admission-min-tls-version=1.3"]).unwrap();
+        assert_eq!(args.admission_min_tls_version, tls::TlsVersion::V1_3);
+
+        assert!(parse(&["policy", "--admission-min-tls-version=1.1"]).is_err());
+    }
+
+    #[test]
+    fn admission_failure_policy() {
+        let args = parse(&["policy"]).unwrap();
+        assert_eq!(
+            args.admission_failure_policy,
+            admission::FailurePolicy::Open
+        );
+
+        let args = parse(&["policy", "--admission-failure-policy=closed"]).unwrap();
+        assert_eq!(
+            args.admission_failure_policy,
+            admission::FailurePolicy::Closed
+        );
+
+        assert!(parse(&["policy", "--admission-failure-policy=fail"]).is_err());
+    }
+
+    #[test]
+    fn observe_only_conflicts_with_leader_election() {
+        let args = parse(&["policy"]).unwrap();
+        assert!(!args.observe_only);
+        let args = parse(&["policy", "--observe-only"]).unwrap();
+        assert!(args.observe_only);
+        assert!(parse(&["policy", "--observe-only", "--enable-leader-election"]).is_err());
+    }
+
+    #[test]
+    fn admission_namespace_selector_conflicts_with_namespace() {
+        let selector = "--admission-namespace-selector=linkerd.io/inject=enabled";
+        let args = parse(&["policy", selector]).unwrap();
+        assert_eq!(
+            args.admission_namespace_selector.as_deref(),
+            Some("linkerd.io/inject=enabled")
+        );
+        assert!(parse(&["policy", selector, "--namespace=ns-0"]).is_err());
+    }
+
+    #[test]
+    fn debug_inject_latency_requires_debug_endpoints() {
+        let args = parse(&["policy"]).unwrap();
+        assert_eq!(args.debug_inject_latency, None);
+
+        assert!(parse(&["policy", "--debug-inject-latency=1s"]).is_err());
+        let args = parse(&[
+            "policy",
+            "--enable-debug-endpoints",
+            "--debug-inject-latency=1s",
+        ])
+        .unwrap();
+        assert_eq!(
+            args.debug_inject_latency,
+            Some(time::Duration::from_secs(1))
+        );
+    }
+
+    #[test]
+    fn verify_rbac() {
+        let args = parse(&["policy"]).unwrap();
+        assert!(!args.verify_rbac);
+        let args = parse(&["policy", "--verify-rbac"]).unwrap();
+        assert!(args.verify_rbac);
+        assert!(
+            parse(&[
+                "policy",
+                "--verify-rbac",
+                "--static-policy-file=policy.yaml",
+                "--identity-domain=cluster.local",
+            ])
+            .is_err(),
+            "static policies aren't watched"
+        );
+    }
+
+    #[test]
+    fn static_policy_file_requires_identity_domain() {
+        assert!(parse(&["policy", "--static-policy-file=policy.yaml"]).is_err());
+        assert!(parse(&[
+            "policy",
+            "--static-policy-file=policy.yaml",
+            "--identity-domain=cluster.local",
+            "--enable-leader-election",
+        ])
+        .is_err());
+
+        let args = parse(&[
+            "policy",
+            "--static-policy-file=policy.yaml",
+            "--identity-domain=cluster.local",
+        ])
+        .unwrap();
+        assert_eq!(args.static_policy_file, Some(PathBuf::from("policy.yaml")));
+    }
+
+    #[test]
+    fn watch_kinds() {
+        let args = parse(&["policy"]).unwrap();
+        assert!(args.watch_kinds.server_authorizations);
+
+        assert!(!args.watch_kinds.cluster_servers);
+
+        let args = parse(&["policy", "--watch-kinds=Server"]).unwrap();
+        assert!(!args.watch_kinds.server_authorizations);
+
+        let args = parse(&["policy", "--watch-kinds=Server,ClusterServer"]).unwrap();
+        assert!(args.watch_kinds.cluster_servers);
+
+        assert!(parse(&["policy", "--watch-kinds=ServerAuthorization"]).is_err());
+        assert!(parse(&["policy", "--watch-kinds=Server,HTTPRoute"]).is_err());
+    }
+
+    #[test]
+    fn admission_max_body_bytes() {
+        let args = parse(&["policy"]).unwrap();
+        assert_eq!(args.admission_max_body_bytes.get(), 1024 * 1024);
+
+        let args = parse(&["policy", "--admission-max-body-bytes=65536"]).unwrap();
+        assert_eq!(args.admission_max_body_bytes.get(), 65_536);
+
+        assert!(parse(&["policy", "--admission-max-body-bytes=0"]).is_err());
+    }
+
+    #[test]
+    fn admission_rate_limit_must_be_positive() {
+        let args = parse(&["policy"]).unwrap();
+        assert_eq!(args.admission_rate_limit, None);
+
+        let args = parse(&["policy", "--admission-rate-limit=50"]).unwrap();
+        assert_eq!(args.admission_rate_limit.map(|l| l.get()), Some(50));
+
+        for invalid in ["0", "-1", "1.5"] {
+            let arg = format!("--admission-rate-limit={}", invalid);
+            assert!(
+                parse(&["policy", arg.as_str()]).is_err(),
+                "{:?} must be rejected",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn admission_disabled_without_certs() {
+        let args = parse(&[
+            "policy",
+            "--admission-addr=127.0.0.1:9443",
+            "--admission-tls-cert=/nonexistent/tls.crt",
+            "--admission-tls-key=/nonexistent/tls.key",
+            "--disable-admission",
+        ])
+        .unwrap();
+        assert!(args.disable_admission);
+
+        let addr = args.admission_addr.filter(|_| !args.disable_admission);
+        let certs = load_admission_certs(
+            addr,
+            args.admission_tls_cert.clone(),
+            args.admission_tls_key.clone(),
+        )
+        .expect("startup must not require certificates when admission is disabled");
+        assert!(certs.is_none());
+
+        assert!(
+            load_admission_certs(
+                args.admission_addr,
+                args.admission_tls_cert,
+                args.admission_tls_key
+            )
+            .is_err(),
+            "enabled admission must require certificates"
+        );
+    }
+
+    #[test]
+    fn parse_identity_domains() {
+        for (s, expected) in [
+            ("cluster.local", "cluster.local"),
+            ("Cluster.Example.COM", "cluster.example.com"),
+            ("my-cluster.example.com", "my-cluster.example.com"),
+            ("local", "local"),
+        ] {
+            assert_eq!(parse_identity_domain(s).unwrap(), expected, "{}", s);
+        }
+
+        for s in [
+            "",
+            "cluster..local",
+            ".cluster.local",
+            "cluster.local.",
+            "cluster_local",
+            "cluster.local/ns",
+            "-cluster.local",
+            "cluster-.local",
+            "cluster .local",
+        ] {
+            assert!(parse_identity_domain(s).is_err(), "{:?} must not parse", s);
+        }
+        assert!(parse_identity_domain(&format!("{}.local", "a".repeat(64))).is_err());
+
+        assert!(parse(&["policy", "--identity-domain=cluster..local"]).is_err());
+    }
+
+    #[test]
+    fn check_conflicting_addrs() {
+        let addr = |s: &str| Some(s.parse::<SocketAddr>().unwrap());
+        assert!(check_addrs(&[
+            ("admin", addr("0.0.0.0:8080")),
+            ("metrics", None),
+            ("gRPC", addr("0.0.0.0:8090")),
+            ("admission", addr("127.0.0.1:9443")),
+        ])
+        .is_ok());
+        assert!(check_addrs(&[("a", addr("0.0.0.0:0")), ("b", addr("0.0.0.0:0"))]).is_ok());
+        assert!(check_addrs(&[("a", addr("127.0.0.1:80")), ("b", addr("127.0.0.2:80"))]).is_ok());
+
+        assert!(check_addrs(&[("a", addr("0.0.0.0:8080")), ("b", addr("0.0.0.0:8080"))]).is_err());
+        assert!(check_addrs(&[("a", addr("0.0.0.0:8080")), ("b", addr("10.0.0.1:8080"))]).is_err());
+    }
+}
@@ -0,0 +1,109 @@
+//! Coordinates replicas with leases: leader election, which limits event recording to a single
+//! replica, and handoffs, which keep a replica serving until its successor is ready.
+
+use crate::{cli::Args, Failure};
+use anyhow::{Context, Result};
+use linkerd_policy_controller::{
+    api::chrono::{DateTime, Utc},
+    handoff, lease,
+};
+use tokio::sync::watch;
+use tracing::{info_span, Instrument};
+
+/// A replica's announcement that it's ready to take over from its predecessor.
+pub(crate) struct Handoff {
+    client: kube::Client,
+    config: handoff::Config,
+    announced: watch::Receiver<Option<DateTime<Utc>>>,
+}
+
+/// Builds the leader election and handoff configurations, if they're enabled.
+///
+/// Both leases are held in the lease namespace, by an identity that's unique to this replica.
+pub(crate) fn configs(args: &Args) -> Result<(Option<lease::Config>, Option<handoff::Config>)> {
+    let namespace = args
+        .lease_namespace
+        .clone()
+        .unwrap_or_else(|| args.control_plane_namespace.clone());
+    let identity = std::env::var("HOSTNAME")
+        .unwrap_or_else(|_| format!("policy-{:016x}", rand::random::<u64>()));
+
+    let leader_election = if args.enable_leader_election {
+        let config = lease::Config {
+            namespace: namespace.clone(),
+            name: args.lease_name.clone(),
+            identity: identity.clone(),
+            lease_duration: args.lease_duration,
+            renew_deadline: args.lease_renew_deadline,
+            retry_period: args.lease_retry_period,
+        };
+        config.validate().context(Failure::Config)?;
+        Some(config)
+    } else {
+        None
+    };
+    let handoff = if args.coordinate_handoff {
+        Some(handoff::Config {
+            namespace,
+            name: args.handoff_lease_name.clone(),
+            identity,
+            retry_period: args.lease_retry_period,
+        })
+    } else {
+        None
+    };
+    Ok((leader_election, handoff))
+}
+
+/// Spawns a task that participates in leader election until `drain` is signaled, returning a
+/// receiver that's set while this replica is the leader.
+pub(crate) fn elect_leader(
+    client: kube::Client,
+    config: lease::Config,
+    drain: drain::Watch,
+) -> watch::Receiver<bool> {
+    let (leader_tx, leader_rx) = watch::channel(false);
+    tokio::spawn(lease::run(client, config, leader_tx, drain).instrument(info_span!("lease")));
+    leader_rx
+}
+
+// === impl Handoff ===
+
+impl Handoff {
+    /// Spawns a task that announces that this replica is ready, so that its predecessor may drain,
+    /// once `ready` is set. The time of the announcement is recorded so that this replica can
+    /// recognize its own successor.
+    pub(crate) fn announce(
+        client: kube::Client,
+        config: handoff::Config,
+        ready: watch::Receiver<bool>,
+    ) -> Self {
+        let (announced_tx, announced) = watch::channel(None);
+        tokio::spawn({
+            let client = client.clone();
+            let config = config.clone();
+            async move {
+                let _ = announced_tx.send(handoff::announce(client, &config, ready).await);
+            }
+            .instrument(info_span!("handoff"))
+        });
+        Self {
+            client,
+            config,
+            announced,
+        }
+    }
+
+    /// Completes once a successor has announced that it's ready.
+    ///
+    /// A replica that never announced it was ready has no clients to hand off, so this completes
+    /// immediately.
+    pub(crate) async fn successor(self) {
+        let since = *self.announced.borrow();
+        if let Some(since) = since {
+            handoff::successor(self.client, self.config, since)
+                .instrument(info_span!("handoff"))
+                .await;
+        }
+    }
+}
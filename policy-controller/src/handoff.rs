@@ -0,0 +1,164 @@
+//! Coordinates the handoff of policy discovery between replicas during rollouts.
+//!
+//! Replicas announce that they're ready by writing their identity and the time into a shared
+//! `coordination.k8s.io/v1` Lease once their index has synced. When a replica shuts down, it waits
+//! for a successor (i.e. another replica that announced its readiness after this one) before it
+//! drains, so that proxies can always reach a ready replica.
+
+use crate::api::{
+    chrono::{DateTime, Utc},
+    Lease, LeaseSpec, MicroTime, ObjectMeta,
+};
+use anyhow::Result;
+use kube::api::{Api, PostParams};
+use tokio::{sync::watch, time};
+use tracing::{debug, info, warn};
+
+/// Configures handoff coordination.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub namespace: String,
+    pub name: String,
+
+    /// Identifies this replica in its announcements.
+    pub identity: String,
+
+    /// The interval at which failed announcements are retried and, while shutting down, the lease
+    /// is checked for a successor.
+    pub retry_period: time::Duration,
+}
+
+// === impl Config ===
+
+impl Config {
+    fn spec(&self, now: DateTime<Utc>) -> LeaseSpec {
+        LeaseSpec {
+            holder_identity: Some(self.identity.clone()),
+            renew_time: Some(MicroTime(now)),
+            ..Default::default()
+        }
+    }
+
+    /// Determines whether the lease was last announced by another replica after `since`.
+    fn is_succeeded(&self, spec: &LeaseSpec, since: DateTime<Utc>) -> bool {
+        match (spec.holder_identity.as_deref(), spec.renew_time.as_ref()) {
+            (Some(holder), Some(MicroTime(announced))) => {
+                !holder.is_empty() && holder != self.identity && *announced > since
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Announces this replica's readiness once `ready` is set, returning the time of the
+/// announcement. Failed announcements are retried, so this only completes once the lease is
+/// written or `ready`'s sender is dropped.
+pub async fn announce(
+    client: kube::Client,
+    config: &Config,
+    mut ready: watch::Receiver<bool>,
+) -> Option<DateTime<Utc>> {
+    while !*ready.borrow() {
+        ready.changed().await.ok()?;
+    }
+
+    let api = Api::<Lease>::namespaced(client, &config.namespace);
+    loop {
+        let now = Utc::now();
+        match write(&api, config, now).await {
+            Ok(()) => {
+                info!(lease = %config.name, identity = %config.identity, "Announced readiness");
+                return Some(now);
+            }
+            Err(error) => warn!(%error, lease = %config.name, "Failed to announce readiness"),
+        }
+        time::sleep(config.retry_period).await;
+    }
+}
+
+/// Completes once another replica has announced its readiness after `since`.
+pub async fn successor(client: kube::Client, config: Config, since: DateTime<Utc>) {
+    let api = Api::<Lease>::namespaced(client, &config.namespace);
+    loop {
+        match api.get(&config.name).await {
+            Ok(lease) => {
+                let spec = lease.spec.unwrap_or_default();
+                if config.is_succeeded(&spec, since) {
+                    info!(successor = ?spec.holder_identity, "Successor is ready");
+                    return;
+                }
+                debug!("Waiting for a successor");
+            }
+            Err(error) => warn!(%error, lease = %config.name, "Failed to check for a successor"),
+        }
+        time::sleep(config.retry_period).await;
+    }
+}
+
+/// Writes this replica's announcement, creating the lease if it doesn't exist.
+async fn write(api: &Api<Lease>, config: &Config, now: DateTime<Utc>) -> Result<()> {
+    let metadata = match api.get(&config.name).await {
+        Ok(lease) => lease.metadata,
+        Err(kube::Error::Api(rsp)) if rsp.code == 404 => {
+            let lease = Lease {
+                metadata: ObjectMeta {
+                    name: Some(config.name.clone()),
+                    namespace: Some(config.namespace.clone()),
+                    ..Default::default()
+                },
+                spec: Some(config.spec(now)),
+            };
+            api.create(&PostParams::default(), &lease).await?;
+            return Ok(());
+        }
+        Err(error) => return Err(error.into()),
+    };
+
+    // The lease's metadata retains its resource version, so the write fails (and is retried) if
+    // another replica announced itself since the lease was read.
+    let lease = Lease {
+        metadata,
+        spec: Some(config.spec(now)),
+    };
+    api.replace(&config.name, &PostParams::default(), &lease)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::chrono;
+
+    fn mk_config(identity: &str) -> Config {
+        Config {
+            namespace: "linkerd".to_string(),
+            name: "policy-handoff".to_string(),
+            identity: identity.to_string(),
+            retry_period: time::Duration::from_secs(1),
+        }
+    }
+
+    #[test]
+    fn detects_successors() {
+        let announced = Utc::now();
+        let old = mk_config("old");
+        let new = mk_config("new");
+
+        let own = old.spec(announced);
+        assert!(
+            !old.is_succeeded(&own, announced),
+            "a replica can't succeed itself"
+        );
+
+        let stale = new.spec(announced - chrono::Duration::seconds(1));
+        assert!(
+            !old.is_succeeded(&stale, announced),
+            "a replica that was ready before this one isn't a successor"
+        );
+
+        let successor = new.spec(announced + chrono::Duration::seconds(1));
+        assert!(old.is_succeeded(&successor, announced));
+        assert!(!old.is_succeeded(&LeaseSpec::default(), announced));
+    }
+}
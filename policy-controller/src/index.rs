@@ -0,0 +1,70 @@
+//! Persists and refreshes the resource index.
+
+use std::sync::Arc;
+use tokio::time;
+use tracing::{debug, info, warn};
+
+/// Writes a snapshot of the index to `path` so that it can seed the index when the controller
+/// restarts. Failures are logged, since the snapshot is only an optimization.
+pub(crate) async fn write_snapshot(
+    path: &std::path::Path,
+    handle: linkerd_policy_controller::k8s::SnapshotHandle,
+) {
+    let snapshot = match handle.snapshot().await {
+        Some(snapshot) => snapshot,
+        None => {
+            warn!("Index stopped before it could be snapshotted");
+            return;
+        }
+    };
+    match snapshot.write(path) {
+        Ok(()) => info!(path = %path.display(), resources = snapshot.len(), "Wrote index snapshot"),
+        Err(error) => warn!(?error, "Failed to write index snapshot"),
+    }
+}
+
+/// Resyncs the index after each jittered `interval`.
+pub(crate) async fn resync_periodically(
+    interval: time::Duration,
+    resync: Arc<tokio::sync::Notify>,
+) {
+    loop {
+        time::sleep(jitter(interval)).await;
+        debug!("Resyncing resources");
+        resync.notify_one();
+    }
+}
+
+/// Randomly scales `interval` by a factor between 0.9 and 1.1.
+fn jitter(interval: time::Duration) -> time::Duration {
+    interval.mul_f64(0.9 + 0.2 * rand::random::<f64>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn resyncs_periodically() {
+        let resync = Arc::new(tokio::sync::Notify::new());
+        tokio::spawn(resync_periodically(
+            time::Duration::from_millis(10),
+            resync.clone(),
+        ));
+        for _ in 0..2 {
+            time::timeout(time::Duration::from_secs(1), resync.notified())
+                .await
+                .expect("index must be resynced");
+        }
+
+        for _ in 0..100 {
+            let interval = jitter(time::Duration::from_secs(600));
+            assert!(
+                time::Duration::from_secs(540) <= interval
+                    && interval <= time::Duration::from_secs(660),
+                "{:?}",
+                interval
+            );
+        }
+    }
+}
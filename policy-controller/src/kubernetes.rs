@@ -0,0 +1,315 @@
+//! Connects to the Kubernetes API and checks the cluster's configuration against the controller's.
+
+use crate::{cli::parse_identity_domain, Failure};
+use anyhow::{Context, Error, Result};
+use linkerd_policy_controller::{breaker, rbac};
+use linkerd_policy_controller_core::IpNet;
+use tokio::time;
+use tracing::{debug, error, info, warn};
+
+/// Warns when the pod CIDRs assigned to nodes aren't covered by the cluster networks, since traffic
+/// from uncovered pods would be treated as coming from outside the cluster.
+///
+/// This check is advisory, so failures to list nodes are logged and otherwise ignored.
+pub(crate) async fn check_pod_networks(client: kube::Client, cluster_networks: &[IpNet]) {
+    let api = kube::Api::<linkerd_policy_controller::api::Node>::all(client);
+    let nodes = match api.list(&Default::default()).await {
+        Ok(nodes) => nodes,
+        Err(error) => {
+            warn!(%error, "Failed to list nodes; skipping the pod network check");
+            return;
+        }
+    };
+
+    let pod_cidrs = nodes.items.into_iter().flat_map(|node| {
+        let spec = node.spec.unwrap_or_default();
+        let cidrs = spec.pod_cidrs.unwrap_or_default();
+        if cidrs.is_empty() {
+            spec.pod_cidr.into_iter().collect()
+        } else {
+            cidrs
+        }
+    });
+    let mut nets = Vec::new();
+    for cidr in pod_cidrs {
+        match cidr.parse::<IpNet>() {
+            Ok(net) => nets.push(net),
+            Err(error) => warn!(%error, %cidr, "Ignoring invalid node pod CIDR"),
+        }
+    }
+
+    let uncovered = uncovered_networks(nets, cluster_networks);
+    if uncovered.is_empty() {
+        debug!("Cluster networks cover all node pod CIDRs");
+    }
+    for net in uncovered {
+        warn!(pod_cidr = %net, "Cluster networks do not cover a node's pod CIDR");
+    }
+}
+
+/// Verifies that the controller has all of `permissions`, failing if any are missing.
+pub(crate) async fn verify_permissions(
+    client: kube::Client,
+    permissions: &[rbac::Permission],
+) -> Result<()> {
+    let missing = rbac::missing(client, permissions)
+        .await
+        .context(Failure::Kubernetes)?;
+    if missing.is_empty() {
+        info!(permissions = permissions.len(), "Verified RBAC permissions");
+        return Ok(());
+    }
+    for permission in &missing {
+        error!(%permission, "Missing RBAC permission");
+    }
+    Err(anyhow::anyhow!(
+        "missing {} of {} RBAC permissions",
+        missing.len(),
+        permissions.len()
+    ))
+    .context(Failure::Kubernetes)
+}
+
+/// Warns when the controller lacks any of `permissions`, since its watches would fail.
+///
+/// This check is advisory, so failures to review permissions are logged and otherwise ignored.
+pub(crate) async fn check_permissions(client: kube::Client, permissions: &[rbac::Permission]) {
+    match rbac::missing(client, permissions).await {
+        Ok(missing) => {
+            for permission in missing {
+                warn!(%permission, "Missing RBAC permission; watches will fail");
+            }
+        }
+        Err(error) => warn!(%error, "Failed to review RBAC permissions; skipping the RBAC check"),
+    }
+}
+
+/// Returns the distinct networks in `nets` that are not contained by any of `cluster_networks`.
+fn uncovered_networks(
+    nets: impl IntoIterator<Item = IpNet>,
+    cluster_networks: &[IpNet],
+) -> Vec<IpNet> {
+    let mut uncovered = Vec::new();
+    for net in nets {
+        if !cluster_networks.iter().any(|c| c.contains(&net)) && !uncovered.contains(&net) {
+            uncovered.push(net);
+        }
+    }
+    uncovered
+}
+
+/// Discovers the mesh identity trust domain from the control plane's configuration, falling back
+/// to the cluster's DNS domain.
+pub(crate) async fn discover_identity_domain(
+    client: kube::Client,
+    control_plane_ns: &str,
+) -> String {
+    let api = kube::Api::<linkerd_policy_controller::api::api::core::v1::ConfigMap>::namespaced(
+        client,
+        control_plane_ns,
+    );
+    let config = match api.get("linkerd-config").await {
+        Ok(cm) => cm
+            .data
+            .and_then(|mut data| data.remove("values"))
+            .ok_or_else(|| anyhow::anyhow!("linkerd-config has no values")),
+        Err(error) => Err(error).context("failed to read linkerd-config"),
+    };
+    let resolv_conf = std::fs::read_to_string("/etc/resolv.conf").ok();
+    choose_identity_domain(config, resolv_conf.as_deref())
+}
+
+/// Chooses the identity domain from the control plane's configuration values, parsing the cluster
+/// domain from `resolv.conf` if the configuration can't be used.
+fn choose_identity_domain(config_values: Result<String>, resolv_conf: Option<&str>) -> String {
+    const DEFAULT: &str = "cluster.local";
+
+    let configured = config_values.and_then(|values| {
+        let values = serde_yaml::from_str::<serde_yaml::Value>(&values)
+            .context("invalid linkerd-config values")?;
+        let domain = values
+            .get("identityTrustDomain")
+            .and_then(|d| d.as_str())
+            .ok_or_else(|| anyhow::anyhow!("identityTrustDomain is not set"))?;
+        parse_identity_domain(domain)
+    });
+    let error = match configured {
+        Ok(domain) => return domain,
+        Err(error) => error,
+    };
+    debug!(%error, "Failed to read the identity domain from linkerd-config");
+
+    // Pods' search domains include `<ns>.svc.<cluster-domain>`, from which we can recover the
+    // cluster domain (which is the default trust domain).
+    let domain = resolv_conf.and_then(|conf| {
+        conf.lines()
+            .filter_map(|l| l.strip_prefix("search "))
+            .flat_map(|l| l.split_whitespace())
+            .find_map(|d| d.split_once(".svc.").map(|(_, domain)| domain))
+            .and_then(|d| parse_identity_domain(d.trim_end_matches('.')).ok())
+    });
+    match domain {
+        Some(domain) => domain,
+        None => {
+            warn!(%error, "Failed to discover the identity domain; using {}", DEFAULT);
+            DEFAULT.to_string()
+        }
+    }
+}
+
+/// Initializes a Kubernetes client, retrying failures with a jittered exponential backoff so that
+/// the controller tolerates the API server being briefly unavailable.
+///
+/// Building a client doesn't contact the API server, so each attempt probes it by fetching its
+/// version.
+pub(crate) async fn kube_client(
+    max_attempts: u32,
+    base_delay: time::Duration,
+    breaker: breaker::Breaker,
+) -> Result<kube::Client> {
+    let mut attempt = 1;
+    loop {
+        let probed = async {
+            let client = try_kube_client(breaker.clone()).await?;
+            let version = client
+                .apiserver_version()
+                .await
+                .context("failed to reach the API server")?;
+            debug!(version = %version.git_version, "Connected to the API server");
+            Ok::<_, Error>(client)
+        };
+        match probed.await {
+            Ok(client) => return Ok(client),
+            Err(error) if attempt < max_attempts => {
+                let delay = backoff(base_delay, attempt);
+                warn!(%error, attempt, ?delay, "Failed to initialize kubernetes client");
+                time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(error) => return Err(error).context("failed to initialize kubernetes client"),
+        }
+    }
+}
+
+/// Builds a client like `kube::Client::try_default`, with requests passing through `breaker`.
+///
+/// kube can't add a layer to its default client, so this builds the same stack--with the config's
+/// connect and read timeouts, its base URI and authentication layers, and request tracing--and
+/// wraps it with the breaker.
+async fn try_kube_client(breaker: breaker::Breaker) -> Result<kube::Client> {
+    use kube::client::ConfigExt;
+
+    let config = kube::Config::infer().await?;
+    #[cfg(feature = "rustls")]
+    let https = config.rustls_https_connector()?;
+    #[cfg(not(feature = "rustls"))]
+    let https = config.native_tls_https_connector()?;
+    let mut connector = hyper_timeout::TimeoutConnector::new(https);
+    connector.set_connect_timeout(config.timeout);
+    connector.set_read_timeout(config.timeout);
+
+    let trace = tower_http::trace::TraceLayer::new_for_http().make_span_with(
+        |req: &hyper::Request<hyper::Body>| {
+            tracing::debug_span!("HTTP", http.method = %req.method(), http.url = %req.uri())
+        },
+    );
+    let service = tower::ServiceBuilder::new()
+        .layer(breaker)
+        .layer(config.base_uri_layer())
+        .option_layer(config.auth_layer()?)
+        .layer(trace)
+        .service(hyper::Client::builder().build(connector));
+    Ok(kube::Client::new(service, config.default_namespace))
+}
+
+/// Returns the delay before retrying after the given (1-indexed) attempt.
+///
+/// The delay is between half and all of `base * 2^(attempt - 1)`, capped at 30s, so that replicas
+/// don't retry in lockstep.
+fn backoff(base: time::Duration, attempt: u32) -> time::Duration {
+    const MAX: time::Duration = time::Duration::from_secs(30);
+    let exp = 2u32.saturating_pow(attempt.saturating_sub(1));
+    let ceiling = base.saturating_mul(exp).min(MAX);
+    ceiling / 2 + (ceiling / 2).mul_f64(rand::random::<f64>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::IpNets;
+
+    #[test]
+    fn detects_uncovered_pod_networks() {
+        let IpNets(cluster) = "10.0.0.0/8,fd00::/8".parse().unwrap();
+        let nets = [
+            "10.1.0.0/24",
+            "192.168.0.0/24",
+            "fd00:1::/64",
+            "192.168.0.0/24",
+        ]
+        .iter()
+        .map(|n| n.parse::<IpNet>().unwrap());
+        assert_eq!(
+            uncovered_networks(nets, &cluster),
+            vec!["192.168.0.0/24".parse::<IpNet>().unwrap()]
+        );
+
+        // A pod network is only covered if it's entirely contained by a cluster network.
+        let wide = Some("10.0.0.0/7".parse::<IpNet>().unwrap());
+        assert_eq!(uncovered_networks(wide, &cluster).len(), 1);
+    }
+
+    #[test]
+    fn backoff_is_bounded() {
+        let base = time::Duration::from_millis(100);
+        for attempt in 1..=4 {
+            let ceiling = base * 2u32.pow(attempt - 1);
+            for _ in 0..100 {
+                let delay = backoff(base, attempt);
+                assert!(delay >= ceiling / 2, "{:?} < {:?}", delay, ceiling / 2);
+                assert!(delay <= ceiling, "{:?} > {:?}", delay, ceiling);
+            }
+        }
+
+        assert!(backoff(base, 100) <= time::Duration::from_secs(30));
+    }
+
+    #[test]
+    fn discovers_identity_domain() {
+        let resolv_conf = "nameserver 10.96.0.10\n\
+                           search linkerd.svc.k8s.example.com svc.k8s.example.com\n\
+                           options ndots:5\n";
+
+        // The control plane's configuration takes precedence.
+        let values = Ok("identityTrustDomain: Mesh.Example.COM\nclusterDomain: x\n".to_string());
+        assert_eq!(
+            choose_identity_domain(values, Some(resolv_conf)),
+            "mesh.example.com"
+        );
+
+        // Otherwise, the cluster domain is recovered from the DNS search domains.
+        for values in [
+            Err(anyhow::anyhow!("forbidden")),
+            Ok("clusterDomain: foo\n".to_string()),
+            Ok("identityTrustDomain: bad..domain\n".to_string()),
+        ] {
+            assert_eq!(
+                choose_identity_domain(values, Some(resolv_conf)),
+                "k8s.example.com"
+            );
+        }
+
+        // If discovery fails entirely, the default is used.
+        assert_eq!(
+            choose_identity_domain(Err(anyhow::anyhow!("forbidden")), None),
+            "cluster.local"
+        );
+        assert_eq!(
+            choose_identity_domain(
+                Err(anyhow::anyhow!("forbidden")),
+                Some("search example.com\n")
+            ),
+            "cluster.local"
+        );
+    }
+}
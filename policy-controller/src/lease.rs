@@ -0,0 +1,322 @@
+//! Leader election backed by a `coordination.k8s.io/v1` Lease.
+//!
+//! The lease is claimed by writing this replica's identity into the lease's `holderIdentity`. Writes
+//! include the lease's resource version, so when multiple replicas race to claim a lease, only one
+//! write succeeds. The holder renews the lease periodically; other replicas may claim the lease once
+//! its holder has failed to renew it for the lease duration.
+
+use crate::api::{
+    chrono::{self, DateTime, Utc},
+    Lease, LeaseSpec, MicroTime, ObjectMeta,
+};
+use anyhow::{bail, Result};
+use kube::api::{Api, PostParams};
+use std::convert::TryInto;
+use tokio::{sync::watch, time};
+use tracing::{debug, info, warn};
+
+/// Configures leader election.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub namespace: String,
+    pub name: String,
+
+    /// Identifies this replica as the lease's holder.
+    pub identity: String,
+
+    /// The amount of time other replicas wait after the lease was last renewed before claiming it.
+    pub lease_duration: time::Duration,
+
+    /// The amount of time the leader keeps leading without successfully renewing its lease.
+    ///
+    /// This must be less than the lease duration so that a leader stops leading before another
+    /// replica may claim the lease.
+    pub renew_deadline: time::Duration,
+
+    /// The interval at which the lease is renewed by the leader or checked by other replicas.
+    pub retry_period: time::Duration,
+}
+
+// === impl Config ===
+
+impl Config {
+    /// Checks that the election's timings are consistent.
+    pub fn validate(&self) -> Result<()> {
+        if self.renew_deadline >= self.lease_duration {
+            bail!("the lease renew deadline must be less than the lease duration");
+        }
+        if self.retry_period >= self.renew_deadline {
+            bail!("the lease retry period must be less than the renew deadline");
+        }
+        Ok(())
+    }
+
+    fn spec(&self, now: DateTime<Utc>, prior: Option<&LeaseSpec>) -> LeaseSpec {
+        let held = matches!(prior, Some(s) if self.holds(s));
+        let transitions = prior.and_then(|s| s.lease_transitions).unwrap_or(0);
+        LeaseSpec {
+            holder_identity: Some(self.identity.clone()),
+            acquire_time: match prior {
+                Some(s) if held => s.acquire_time.clone(),
+                _ => Some(MicroTime(now)),
+            },
+            renew_time: Some(MicroTime(now)),
+            lease_duration_seconds: Some(
+                self.lease_duration.as_secs().try_into().unwrap_or(i32::MAX),
+            ),
+            lease_transitions: Some(if held { transitions } else { transitions + 1 }),
+        }
+    }
+
+    fn holds(&self, spec: &LeaseSpec) -> bool {
+        spec.holder_identity.as_deref() == Some(&*self.identity)
+    }
+
+    /// Determines whether this replica may claim a lease described by `spec` at `now`.
+    fn can_claim(&self, spec: &LeaseSpec, now: DateTime<Utc>) -> bool {
+        match spec.holder_identity.as_deref() {
+            None | Some("") => true,
+            Some(holder) if holder == self.identity => true,
+            Some(_) => match (spec.renew_time.as_ref(), spec.lease_duration_seconds) {
+                (Some(MicroTime(renewed)), Some(secs)) => {
+                    *renewed + chrono::Duration::seconds(secs.into()) < now
+                }
+                // A lease without a renewal time or duration can't be considered held.
+                _ => true,
+            },
+        }
+    }
+}
+
+/// Participates in leader election until `drain` is signaled, publishing whether this replica is
+/// the leader on `leader`.
+///
+/// When the drain is signaled, the lease is released so that another replica may lead immediately.
+pub async fn run(
+    client: kube::Client,
+    config: Config,
+    leader: watch::Sender<bool>,
+    drain: drain::Watch,
+) {
+    let api = Api::<Lease>::namespaced(client, &config.namespace);
+    let mut renewed = None;
+    loop {
+        // A claim that doesn't complete by the renew deadline (e.g. because the API server is
+        // unresponsive) is abandoned, so that the leader steps down before its lease expires.
+        let deadline = renewed.unwrap_or_else(time::Instant::now) + config.renew_deadline;
+        match time::timeout_at(deadline, claim(&api, &config, Utc::now())).await {
+            Ok(Ok(true)) => renewed = Some(time::Instant::now()),
+            Ok(Ok(false)) => renewed = None,
+            Ok(Err(error)) => warn!(%error, lease = %config.name, "Failed to update lease"),
+            Err(_) => {
+                warn!(lease = %config.name, timeout = ?config.renew_deadline, "Timed out updating lease");
+                renewed = None;
+            }
+        }
+
+        let leading = matches!(renewed, Some(t) if t.elapsed() < config.renew_deadline);
+        if *leader.borrow() != leading {
+            if leading {
+                info!(lease = %config.name, identity = %config.identity, "Became the leader");
+            } else {
+                info!(lease = %config.name, "Stopped leading");
+            }
+            let _ = leader.send(leading);
+        }
+
+        tokio::select! {
+            _ = time::sleep(config.retry_period) => {}
+            _ = drain.clone().signaled() => {
+                let _ = leader.send(false);
+                if leading {
+                    release(&api, &config).await;
+                }
+                return;
+            }
+        }
+    }
+}
+
+/// Attempts to create, renew, or take over the lease, returning whether this replica holds it.
+async fn claim(api: &Api<Lease>, config: &Config, now: DateTime<Utc>) -> Result<bool> {
+    let lease = match api.get(&config.name).await {
+        Ok(lease) => lease,
+        Err(kube::Error::Api(rsp)) if rsp.code == 404 => {
+            let lease = Lease {
+                metadata: ObjectMeta {
+                    name: Some(config.name.clone()),
+                    namespace: Some(config.namespace.clone()),
+                    ..Default::default()
+                },
+                spec: Some(config.spec(now, None)),
+            };
+            return conflict_is_lost(api.create(&PostParams::default(), &lease).await);
+        }
+        Err(error) => return Err(error.into()),
+    };
+
+    let prior = lease.spec.unwrap_or_default();
+    if !config.can_claim(&prior, now) {
+        debug!(holder = ?prior.holder_identity, "Lease is held by another replica");
+        return Ok(false);
+    }
+
+    // The lease's metadata retains its resource version, so the write fails if another replica
+    // updated the lease since it was read.
+    let lease = Lease {
+        metadata: lease.metadata,
+        spec: Some(config.spec(now, Some(&prior))),
+    };
+    conflict_is_lost(
+        api.replace(&config.name, &PostParams::default(), &lease)
+            .await,
+    )
+}
+
+/// Treats a conflicting write as a lost election.
+fn conflict_is_lost(res: kube::Result<Lease>) -> Result<bool> {
+    match res {
+        Ok(_) => Ok(true),
+        Err(kube::Error::Api(rsp)) if rsp.code == 409 => Ok(false),
+        Err(error) => Err(error.into()),
+    }
+}
+
+async fn release(api: &Api<Lease>, config: &Config) {
+    let lease = match api.get(&config.name).await {
+        Ok(lease) => lease,
+        Err(error) => {
+            warn!(%error, "Failed to release lease");
+            return;
+        }
+    };
+    let mut spec = lease.spec.unwrap_or_default();
+    if !config.holds(&spec) {
+        return;
+    }
+    spec.holder_identity = None;
+    let lease = Lease {
+        metadata: lease.metadata,
+        spec: Some(spec),
+    };
+    match api
+        .replace(&config.name, &PostParams::default(), &lease)
+        .await
+    {
+        Ok(_) => debug!("Released lease"),
+        Err(error) => warn!(%error, "Failed to release lease"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mk_config(identity: &str) -> Config {
+        Config {
+            namespace: "linkerd".to_string(),
+            name: "policy".to_string(),
+            identity: identity.to_string(),
+            lease_duration: time::Duration::from_secs(15),
+            renew_deadline: time::Duration::from_secs(10),
+            retry_period: time::Duration::from_secs(2),
+        }
+    }
+
+    #[test]
+    fn claims_expired_leases() {
+        let now = Utc::now();
+        let a = mk_config("a");
+        let b = mk_config("b");
+
+        let held = a.spec(now, None);
+        assert_eq!(held.lease_transitions, Some(1));
+        assert!(a.can_claim(&held, now), "the holder may renew its lease");
+        assert!(!b.can_claim(&held, now), "a held lease must not be claimed");
+        assert!(!b.can_claim(&held, now + chrono::Duration::seconds(15)));
+        assert!(b.can_claim(&held, now + chrono::Duration::seconds(16)));
+
+        let released = LeaseSpec {
+            holder_identity: None,
+            ..held.clone()
+        };
+        assert!(
+            b.can_claim(&released, now),
+            "a released lease may be claimed"
+        );
+
+        // Renewing a lease retains its acquisition time, while taking it over counts a transition.
+        let later = now + chrono::Duration::seconds(5);
+        let renewed = a.spec(later, Some(&held));
+        assert_eq!(renewed.acquire_time, held.acquire_time);
+        assert_eq!(renewed.renew_time, Some(MicroTime(later)));
+        assert_eq!(renewed.lease_transitions, Some(1));
+        let taken = b.spec(later, Some(&held));
+        assert_eq!(taken.acquire_time, Some(MicroTime(later)));
+        assert_eq!(taken.lease_transitions, Some(2));
+    }
+
+    #[tokio::test]
+    async fn steps_down_when_renewals_hang() {
+        // Stands in for an API server that creates the lease and then stops responding.
+        let requests = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let client = kube::Client::new(
+            hyper::service::service_fn(move |_: hyper::Request<hyper::Body>| {
+                let n = requests.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                async move {
+                    let (status, body) = match n {
+                        0 => (
+                            404,
+                            r#"{"kind":"Status","apiVersion":"v1","status":"Failure","reason":"NotFound","code":404}"#,
+                        ),
+                        1 => (
+                            201,
+                            r#"{"kind":"Lease","apiVersion":"coordination.k8s.io/v1","metadata":{"name":"policy","namespace":"linkerd"}}"#,
+                        ),
+                        _ => futures::future::pending().await,
+                    };
+                    Ok::<_, std::convert::Infallible>(
+                        hyper::Response::builder()
+                            .status(status)
+                            .body(hyper::Body::from(body))
+                            .unwrap(),
+                    )
+                }
+            }),
+            "default",
+        );
+        let config = Config {
+            lease_duration: time::Duration::from_millis(300),
+            renew_deadline: time::Duration::from_millis(200),
+            retry_period: time::Duration::from_millis(50),
+            ..mk_config("a")
+        };
+        let (leader_tx, mut leader_rx) = watch::channel(false);
+        let (_drain_tx, drain_rx) = drain::channel();
+        tokio::spawn(run(client, config, leader_tx, drain_rx));
+
+        time::timeout(time::Duration::from_secs(1), leader_rx.changed())
+            .await
+            .expect("must become the leader")
+            .unwrap();
+        assert!(*leader_rx.borrow());
+        time::timeout(time::Duration::from_secs(1), leader_rx.changed())
+            .await
+            .expect("must stop leading once renewals exceed the deadline")
+            .unwrap();
+        assert!(!*leader_rx.borrow());
+    }
+
+    #[test]
+    fn validates_timings() {
+        assert!(mk_config("a").validate().is_ok());
+
+        let mut config = mk_config("a");
+        config.renew_deadline = config.lease_duration;
+        assert!(config.validate().is_err());
+
+        let mut config = mk_config("a");
+        config.retry_period = config.renew_deadline;
+        assert!(config.validate().is_err());
+    }
+}
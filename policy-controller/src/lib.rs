@@ -3,6 +3,15 @@
 
 pub mod admin;
 pub mod admission;
+pub mod breaker;
+pub mod build_info;
+pub mod handoff;
+pub mod lease;
+pub mod mux;
+pub mod net;
+pub mod rbac;
+pub mod static_policy;
+pub mod tls;
 
 pub use linkerd_policy_controller_grpc as grpc;
 pub use linkerd_policy_controller_k8s_api as api;
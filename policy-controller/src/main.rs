@@ -1,196 +1,805 @@
 #![deny(warnings, rust_2018_idioms)]
 #![forbid(unsafe_code)]
 
-use anyhow::{bail, Context, Error, Result};
-use futures::{future, prelude::*};
-use linkerd_policy_controller::k8s::DefaultPolicy;
-use linkerd_policy_controller::{admin, admission};
-use linkerd_policy_controller_core::IpNet;
-use std::net::SocketAddr;
+mod certs;
+mod cli;
+mod coordination;
+mod index;
+mod kubernetes;
+mod server;
+mod signals;
+
+use self::cli::{Args, IpNets, LogFormat};
+use anyhow::{Context, Error, Result};
+use linkerd_policy_controller::{
+    admin, admission, breaker, build_info, mux, rbac, static_policy, tls,
+};
+use linkerd_policy_controller_metrics::{Counter, Family, Gauge, Registry};
+use std::sync::Arc;
 use structopt::StructOpt;
-use tokio::{sync::watch, time};
-use tracing::{debug, info, info_span, instrument, Instrument};
+use tokio::sync::watch;
+use tracing::{debug, error, info, info_span, warn, Instrument};
 use tracing_subscriber::{fmt::format, prelude::*, EnvFilter};
 
 #[cfg(all(target_os = "linux", target_arch = "x86_64", target_env = "gnu"))]
 #[global_allocator]
 static GLOBAL: jemallocator::Jemalloc = jemallocator::Jemalloc;
 
-#[derive(Debug, StructOpt)]
-#[structopt(name = "policy", about = "A policy resource prototype")]
-struct Args {
-    #[structopt(
-        parse(try_from_str),
-        long,
-        default_value = "linkerd=info,warn",
-        env = "LINKERD_POLICY_CONTROLLER_LOG"
-    )]
-    log_level: EnvFilter,
-
-    #[structopt(long, default_value = "plain")]
-    log_format: LogFormat,
-
-    #[structopt(long, default_value = "0.0.0.0:8080")]
-    admin_addr: SocketAddr,
-
-    #[structopt(long, default_value = "0.0.0.0:8090")]
-    grpc_addr: SocketAddr,
-
-    #[structopt(long)]
-    admission_addr: Option<SocketAddr>,
-
-    /// Network CIDRs of pod IPs.
-    ///
-    /// The default includes all private networks.
-    #[structopt(
-        long,
-        default_value = "10.0.0.0/8,100.64.0.0/10,172.16.0.0/12,192.168.0.0/16"
-    )]
-    cluster_networks: IpNets,
-
-    #[structopt(long, default_value = "cluster.local")]
-    identity_domain: String,
-
-    #[structopt(long, default_value = "all-unauthenticated")]
-    default_policy: DefaultPolicy,
-
-    #[structopt(long, default_value = "linkerd")]
-    control_plane_namespace: String,
+/// Categorizes startup failures so that orchestrators can distinguish them by exit code.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Failure {
+    /// The controller's configuration is invalid. Restarting won't help.
+    Config,
+
+    /// The Kubernetes API couldn't be reached or refused to serve the controller's requests.
+    Kubernetes,
+
+    /// A server's port couldn't be bound.
+    Bind,
+}
+
+fn main() {
+    let res = parse_args().and_then(|args| {
+        // The runtime is sized before it's started, so it's built explicitly rather than with
+        // `#[tokio::main]`.
+        let worker_threads = args
+            .runtime_worker_threads
+            .map_or_else(cli::default_worker_threads, |n| n.get());
+        let tracing = args.trace_collector.is_some();
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(worker_threads)
+            .enable_all()
+            .build()
+            .context("failed to build runtime")?;
+        let res = runtime.block_on(run(args, worker_threads));
+
+        // Flush any spans that haven't yet been exported. This blocks until the exporter, which
+        // runs on the runtime, completes, so it can't be done from within one of the runtime's
+        // tasks.
+        if tracing {
+            opentelemetry::global::shutdown_tracer_provider();
+        }
+        res
+    });
+    if let Err(error) = res {
+        eprintln!("Error: {:?}", error);
+        std::process::exit(Failure::exit_code(&error));
+    }
 }
 
-#[derive(Clone, Debug)]
-enum LogFormat {
-    Json,
-    Plain,
+fn parse_args() -> Result<Args> {
+    match Args::from_args_safe() {
+        Ok(args) => Ok(args),
+        // Help and version output is not an error.
+        Err(error) if !error.use_stderr() => error.exit(),
+        Err(error) => Err(error).context(Failure::Config),
+    }
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
+async fn run(args: Args, worker_threads: usize) -> Result<()> {
+    let (leader_election, handoff_config) = coordination::configs(&args)?;
     let Args {
         admin_addr,
+        admin_max_connections,
+        metrics_addr,
+        admin_tls_cert,
+        admin_tls_key,
+        enable_profiling,
+        enable_debug_endpoints,
+        debug_inject_latency,
         grpc_addr,
+        single_port,
+        reuse_port,
+        grpc_keepalive_interval,
+        grpc_keepalive_timeout,
+        grpc_connect_timeout,
+        grpc_max_connection_age,
+        grpc_max_connection_age_grace,
+        grpc_max_concurrent_streams,
+        grpc_initial_stream_window,
+        grpc_initial_connection_window,
+        grpc_max_concurrent_requests,
+        enable_audit_log,
+        audit_log_path,
+        index_update_debounce,
+        index_server_metrics_limit,
+        unready_grace_period,
+        resync_interval,
+        index_snapshot_path,
+        static_policy_file,
+        grpc_tls_cert,
+        grpc_tls_key,
+        grpc_tls_client_ca,
+        grpc_enforce_client_identity,
+        grpc_stable_encoding,
         admission_addr,
+        disable_admission,
+        admission_tls_cert,
+        admission_tls_key,
+        admission_min_tls_version,
+        admission_rate_limit,
+        admission_namespaces,
+        admission_namespace_selector,
+        admission_failure_policy,
+        admission_max_body_bytes,
+        observe_only,
+        enable_leader_election: _,
+        lease_namespace: _,
+        lease_name: _,
+        lease_duration: _,
+        lease_renew_deadline: _,
+        lease_retry_period: _,
+        coordinate_handoff: _,
+        handoff_lease_name: _,
         identity_domain,
-        cluster_networks: IpNets(cluster_networks),
+        trust_anchors_path,
+        cluster_networks,
+        cluster_networks_file,
+        skip_network_check,
+        strict_network_check,
+        disable_ipv4,
+        disable_ipv6,
+        admission_webhook_config,
+        skip_webhook_check,
+        verify_rbac,
         default_policy,
+        kube_client_retry_max,
+        kube_client_retry_base_delay,
+        kube_breaker_threshold,
+        kube_breaker_cooldown,
         log_level,
         log_format,
+        trace_collector,
+        trace_sample_rate,
         control_plane_namespace,
-    } = Args::from_args();
+        namespace,
+        watch_label_selector,
+        meshed_workload_selector,
+        watch_kinds,
+        detect_timeout,
+        detect_timeout_overrides,
+        shutdown_grace_period,
+        shutdown_drain_grpc_first,
+        runtime_worker_threads: _,
+        check,
+    } = args;
+
+    let admission_addr =
+        admission_addr.filter(|_| !disable_admission && static_policy_file.is_none());
+    let admin_addr = if single_port {
+        grpc_addr
+            .tcp()
+            .ok_or_else(|| anyhow::anyhow!("--single-port requires a TCP gRPC address"))
+            .context(Failure::Config)?
+    } else {
+        admin_addr
+    };
+    let cluster_networks = match cluster_networks_file.as_ref() {
+        Some(path) => IpNets::load(path).context(Failure::Config)?,
+        None => cluster_networks.unwrap_or_else(IpNets::host_default),
+    };
+    cli::check_addrs(&[
+        ("admin", Some(admin_addr).filter(|_| !single_port)),
+        ("metrics", metrics_addr),
+        ("gRPC", grpc_addr.tcp()),
+        ("admission", admission_addr),
+    ])
+    .context(Failure::Config)?;
+
+    // At most one family may be disabled, which is enforced when the args are parsed.
+    let omit_family = match (disable_ipv4, disable_ipv6) {
+        (true, _) => Some(linkerd_policy_controller_grpc::IpFamily::V4),
+        (false, true) => Some(linkerd_policy_controller_grpc::IpFamily::V6),
+        (false, false) => None,
+    };
 
-    log_init(log_level, log_format)?;
+    // The effective configuration, printed by `--check` and logged on SIGHUP.
+    let mut config = vec![("admin_addr", admin_addr.to_string())];
+    if let Some(addr) = metrics_addr {
+        config.push(("metrics_addr", addr.to_string()));
+    }
+    config.push((
+        "admin_tls_cert",
+        admin_tls_cert
+            .as_ref()
+            .map_or_else(|| "disabled".to_string(), |p| p.display().to_string()),
+    ));
+    config.push(("grpc_addr", grpc_addr.to_string()));
+    config.push(("single_port", single_port.to_string()));
+    config.push(("reuse_port", reuse_port.to_string()));
+    config.push((
+        "admission_addr",
+        admission_addr.map_or_else(|| "disabled".to_string(), |a| a.to_string()),
+    ));
+    config.push((
+        "admission_min_tls_version",
+        admission_min_tls_version.to_string(),
+    ));
+    config.push((
+        "admission_failure_policy",
+        admission_failure_policy.to_string(),
+    ));
+    config.push((
+        "admission_max_body_bytes",
+        admission_max_body_bytes.to_string(),
+    ));
+    config.push(("observe_only", observe_only.to_string()));
+    config.push((
+        "audit_log",
+        match (enable_audit_log, audit_log_path.as_ref()) {
+            (false, _) => "disabled".to_string(),
+            (true, None) => "stdout".to_string(),
+            (true, Some(path)) => path.display().to_string(),
+        },
+    ));
+    if let Some(limit) = admission_rate_limit {
+        config.push(("admission_rate_limit", format!("{}/s", limit)));
+    }
+    if let Some(namespaces) = admission_namespaces.as_ref() {
+        let mut namespaces = namespaces.iter().cloned().collect::<Vec<_>>();
+        namespaces.sort();
+        config.push(("admission_namespaces", namespaces.join(",")));
+    }
+    if let Some(selector) = admission_namespace_selector.as_ref() {
+        config.push(("admission_namespace_selector", selector.clone()));
+    }
+    config.push((
+        "leader_election",
+        leader_election.as_ref().map_or_else(
+            || "disabled".to_string(),
+            |l| format!("{}/{}", l.namespace, l.name),
+        ),
+    ));
+    config.push((
+        "handoff",
+        handoff_config.as_ref().map_or_else(
+            || "disabled".to_string(),
+            |h| format!("{}/{}", h.namespace, h.name),
+        ),
+    ));
+    config.push((
+        "trace_collector",
+        trace_collector
+            .clone()
+            .unwrap_or_else(|| "disabled".to_string()),
+    ));
+    config.extend(vec![
+        ("cluster_networks", cluster_networks.to_string()),
+        (
+            "cluster_networks_file",
+            cluster_networks_file
+                .as_ref()
+                .map_or_else(|| "disabled".to_string(), |p| p.display().to_string()),
+        ),
+        (
+            "grpc_enforce_client_identity",
+            grpc_enforce_client_identity.to_string(),
+        ),
+        ("grpc_stable_encoding", grpc_stable_encoding.to_string()),
+        (
+            "grpc_max_connection_age",
+            grpc_max_connection_age.map_or_else(|| "disabled".to_string(), |a| format!("{:?}", a)),
+        ),
+        (
+            "grpc_max_connection_age_grace",
+            format!(
+                "{:?}",
+                grpc_max_connection_age_grace.unwrap_or(server::GRPC_MAX_CONNECTION_AGE_GRACE)
+            ),
+        ),
+        (
+            "debug_inject_latency",
+            debug_inject_latency.map_or_else(|| "disabled".to_string(), |l| format!("{:?}", l)),
+        ),
+        ("skip_network_check", skip_network_check.to_string()),
+        ("strict_network_check", strict_network_check.to_string()),
+        ("verify_rbac", verify_rbac.to_string()),
+        (
+            "omit_family",
+            omit_family.map_or_else(|| "none".to_string(), |f| format!("{:?}", f)),
+        ),
+        (
+            "webhook_check",
+            if skip_webhook_check {
+                "disabled".to_string()
+            } else {
+                admission_webhook_config.clone()
+            },
+        ),
+        (
+            "identity_domain",
+            identity_domain
+                .clone()
+                .unwrap_or_else(|| "<discovered>".to_string()),
+        ),
+        (
+            "trust_anchors_path",
+            trust_anchors_path
+                .as_ref()
+                .map_or_else(|| "disabled".to_string(), |p| p.display().to_string()),
+        ),
+        ("default_policy", default_policy.to_string()),
+        ("control_plane_namespace", control_plane_namespace.clone()),
+        ("namespace", namespace.as_deref().unwrap_or("*").to_string()),
+        (
+            "watch_label_selector",
+            watch_label_selector
+                .clone()
+                .unwrap_or_else(|| "<none>".to_string()),
+        ),
+        ("meshed_workload_selector", meshed_workload_selector.clone()),
+        ("watch_kinds", watch_kinds.to_string()),
+        ("detect_timeout", format!("{:?}", detect_timeout)),
+        (
+            "detect_timeout_overrides",
+            detect_timeout_overrides.as_ref().map_or_else(
+                || "disabled".to_string(),
+                |overrides| {
+                    let mut overrides = overrides.iter().collect::<Vec<_>>();
+                    overrides.sort();
+                    overrides
+                        .into_iter()
+                        .map(|(port, timeout)| format!("{}={:?}", port, timeout))
+                        .collect::<Vec<_>>()
+                        .join(",")
+                },
+            ),
+        ),
+        (
+            "index_update_debounce",
+            format!("{:?}", index_update_debounce),
+        ),
+        (
+            "index_server_metrics_limit",
+            index_server_metrics_limit.to_string(),
+        ),
+        (
+            "unready_grace_period",
+            format!("{:?}", unready_grace_period),
+        ),
+        (
+            "resync_interval",
+            Some(resync_interval)
+                .filter(|i| !i.is_zero())
+                .map_or_else(|| "disabled".to_string(), |i| format!("{:?}", i)),
+        ),
+        (
+            "index_snapshot_path",
+            index_snapshot_path
+                .as_ref()
+                .map_or_else(|| "disabled".to_string(), |p| p.display().to_string()),
+        ),
+        (
+            "static_policy_file",
+            static_policy_file
+                .as_ref()
+                .map_or_else(|| "disabled".to_string(), |p| p.display().to_string()),
+        ),
+        (
+            "shutdown_grace_period",
+            format!("{:?}", shutdown_grace_period),
+        ),
+        (
+            "shutdown_drain_grpc_first",
+            shutdown_drain_grpc_first.to_string(),
+        ),
+        (
+            "kube_breaker",
+            format!(
+                "{} failures, {:?} cooldown",
+                kube_breaker_threshold, kube_breaker_cooldown
+            ),
+        ),
+        ("runtime_worker_threads", worker_threads.to_string()),
+    ]);
+
+    if check {
+        for (name, value) in &config {
+            println!("{}: {}", name, value);
+        }
+        return Ok(());
+    }
+
+    let tracer = match trace_collector.as_deref() {
+        Some(endpoint) => Some(trace_init(endpoint, trace_sample_rate).context(Failure::Config)?),
+        None => None,
+    };
+    log_init(log_level, log_format, tracer).context(Failure::Config)?;
+
+    info!(
+        version = %build_info::version(),
+        %admin_addr,
+        metrics_addr = %metrics_addr.map_or_else(|| admin_addr.to_string(), |a| a.to_string()),
+        %grpc_addr,
+        admission_addr = %admission_addr.map_or_else(|| "disabled".to_string(), |a| a.to_string()),
+        %cluster_networks,
+        cluster_networks.count = cluster_networks.0.len(),
+        identity_domain = identity_domain.as_deref().unwrap_or("<discovered>"),
+        %default_policy,
+        namespace = namespace.as_deref().unwrap_or("*"),
+        %worker_threads,
+        "Starting policy controller"
+    );
+    let IpNets(cluster_networks) = cluster_networks;
 
     let (drain_tx, drain_rx) = drain::channel();
 
-    // Load a Kubernetes client from the environment (check for in-cluster configuration first).
+    // The admission server shares the other servers' drain unless it's drained after them.
+    let (admission_drain_tx, admission_drain_rx) = if shutdown_drain_grpc_first {
+        let (tx, rx) = drain::channel();
+        (Some(tx), rx)
+    } else {
+        (None, drain_rx.clone())
+    };
+
+    // Metrics are registered by each component and served by the admin server.
+    let metrics = Registry::default();
+
+    // Load a Kubernetes client from the environment (check for in-cluster configuration first) and
+    // watch its resources, unless the resources are read from a static file.
     //
     // TODO support --kubeconfig and --context command-line arguments.
-    let client = kube::Client::try_default()
-        .await
-        .context("failed to initialize kubernetes client")?;
+    let (client, watches) = match static_policy_file.as_deref() {
+        Some(path) => {
+            let resources = static_policy::load(path).context(Failure::Config)?;
+            info!(path = %path.display(), resources = resources.len(), "Serving static policies");
+            (None, static_policy::watches(resources))
+        }
+        None => {
+            let breaker =
+                breaker::Breaker::new(kube_breaker_threshold, kube_breaker_cooldown, &metrics);
+            let client = kubernetes::kube_client(
+                kube_client_retry_max,
+                kube_client_retry_base_delay,
+                breaker,
+            )
+            .await
+            .context(Failure::Kubernetes)?;
+            let permissions = rbac::required(namespace.as_deref(), watch_kinds);
+            if verify_rbac {
+                return kubernetes::verify_permissions(client, &permissions).await;
+            }
+            kubernetes::check_permissions(client.clone(), &permissions).await;
+            let watches = linkerd_policy_controller::api::ResourceWatches::new(
+                client.clone(),
+                namespace.as_deref(),
+                watch_label_selector.as_deref(),
+                &meshed_workload_selector,
+                watch_kinds,
+            );
+            (Some(client), watches)
+        }
+    };
 
-    // Spawn an admin server, failing readiness checks until the index is updated.
+    // Discover the identity domain unless it was set explicitly.
+    let identity_domain = match (identity_domain, client.as_ref()) {
+        (Some(domain), _) => domain,
+        (None, None) => unreachable!("static policies require an identity domain"),
+        (None, Some(client)) => {
+            let domain =
+                kubernetes::discover_identity_domain(client.clone(), &control_plane_namespace)
+                    .await;
+            info!(%domain, "Discovered identity domain");
+            if let Some((_, value)) = config.iter_mut().find(|(n, _)| *n == "identity_domain") {
+                *value = domain.clone();
+            }
+            domain
+        }
+    };
+
+    if let Some(path) = trust_anchors_path.as_deref() {
+        certs::check_trust_anchors(path, &identity_domain);
+    }
+
+    if let Some(client) = client.as_ref().filter(|_| !skip_network_check) {
+        kubernetes::check_pod_networks(client.clone(), &cluster_networks).await;
+    }
+
+    // Readiness checks fail until the index is updated.
     let (ready_tx, ready_rx) = watch::channel(false);
-    tokio::spawn(admin::serve(admin_addr, ready_rx));
+
+    // The cluster networks are shared by the index and the gRPC server. They only change if they're
+    // reloaded from a file.
+    let (networks_tx, networks_rx) = watch::channel(Arc::from(cluster_networks.clone()));
 
     // Index cluster resources, returning a handle that supports lookups for the gRPC server.
-    let handle = {
-        const DETECT_TIMEOUT: time::Duration = time::Duration::from_secs(10);
+    let (handle, resync, dumps, namespace_labels, snapshots, index_task) = {
         let cluster = linkerd_policy_controller::k8s::ClusterInfo {
             networks: cluster_networks.clone(),
             identity_domain,
             control_plane_ns: control_plane_namespace,
         };
-        let (handle, index) =
-            linkerd_policy_controller::k8s::Index::new(cluster, default_policy, DETECT_TIMEOUT);
+        let (handle, mut index) =
+            linkerd_policy_controller::k8s::Index::new(cluster, default_policy, detect_timeout);
+        index.register_metrics(&metrics);
+        index.set_server_metrics_limit(index_server_metrics_limit);
+        index.set_unready_grace_period(unready_grace_period);
+        index.set_strict_network_check(strict_network_check);
+        index.set_meshed_workload_selector(
+            meshed_workload_selector.parse().context(Failure::Config)?,
+        );
+        if let Some(overrides) = detect_timeout_overrides {
+            index.set_detect_timeout_overrides(overrides);
+        }
 
-        tokio::spawn(index.run(client.clone(), ready_tx));
-        handle
-    };
+        // Seed the index from the prior snapshot so that policies can be served while the watches
+        // sync. The snapshot is not required, so a missing or invalid snapshot is ignored.
+        let snapshots = index_snapshot_path.map(|path| {
+            match linkerd_policy_controller::k8s::Snapshot::load(&path) {
+                Ok(Some(snapshot)) => {
+                    let handle = index.snapshot_handle();
+                    index.seed(snapshot);
+                    (path, handle)
+                }
+                Ok(None) => {
+                    debug!(path = %path.display(), "No index snapshot");
+                    (path, index.snapshot_handle())
+                }
+                Err(error) => {
+                    warn!(?error, "Failed to load index snapshot");
+                    (path, index.snapshot_handle())
+                }
+            }
+        });
+
+        // Update the cluster networks as the file changes.
+        if let Some(path) = cluster_networks_file {
+            index.watch_cluster_networks(networks_rx.clone());
+            tokio::spawn(
+                IpNets::reload_on_change(path, networks_tx, tls::CertResolver::RELOAD_INTERVAL)
+                    .instrument(info_span!("cluster_networks")),
+            );
+        }
 
-    // Run the gRPC server, serving results by looking up against the index handle.
-    tokio::spawn(grpc(grpc_addr, cluster_networks, handle, drain_rx.clone()));
+        let resync = index.resync_handle();
+        let dumps = index.dump_handle();
+        let namespace_labels = index.namespace_labels_handle();
+        let task = tokio::spawn(index.run(watches, ready_tx));
+        (handle, resync, dumps, namespace_labels, snapshots, task)
+    };
 
-    // Run the admission controller
-    if let Some(bind_addr) = admission_addr {
-        let (listen_addr, serve) = warp::serve(admission::routes(client))
-            .tls()
-            .cert_path("/var/run/linkerd/tls/tls.crt")
-            .key_path("/var/run/linkerd/tls/tls.key")
-            .bind_with_graceful_shutdown(bind_addr, async move {
-                let _ = drain_rx.signaled().await;
-            });
-        info!(addr = %listen_addr, "Admission controller server listening");
-        tokio::spawn(serve.instrument(info_span!("admission")));
+    // Spawn an admin server.
+    let admin_tls =
+        certs::load_admin_certs(admin_tls_cert, admin_tls_key).context(Failure::Config)?;
+    if let Some(certs) = admin_tls.clone() {
+        tokio::spawn(
+            certs
+                .reload_on_change(tls::CertResolver::RELOAD_INTERVAL)
+                .instrument(info_span!("admin")),
+        );
     }
+    let admin = admin::Config {
+        addr: admin_addr,
+        metrics_addr,
+        max_connections: admin_max_connections,
+        reuse_port,
+        enable_profiling,
+        index: Some(dumps).filter(|_| enable_debug_endpoints),
+        policies: Some(handle.clone()).filter(|_| enable_debug_endpoints),
+        tls: admin_tls,
+    };
 
-    // Block the main thread on the shutdown signal. Once it fires, wait for the background tasks to
-    // complete before exiting.
-    shutdown(drain_tx).await;
+    // On a single port, the admin server serves the connections that aren't HTTP/2.
+    let (grpc_listener, admin_connections) = if single_port {
+        let detect_timeouts = metrics.register(
+            "single_port_detect_timeouts_total",
+            "Total connections closed before their protocol could be detected.",
+            Counter::default(),
+        );
+        let mux::Split { admin, grpc } = mux::split(
+            server::bind("gRPC", admin_addr, reuse_port).await?,
+            grpc_connect_timeout,
+            detect_timeouts,
+        );
+        (grpc.into(), Some(admin))
+    } else {
+        (server::bind_grpc(&grpc_addr, reuse_port).await?, None)
+    };
+    let admin_task = match admin_connections {
+        Some(connections) => tokio::spawn(admin::serve_shared(
+            admin,
+            connections,
+            metrics.clone(),
+            ready_rx.clone(),
+            drain_rx.clone(),
+        )),
+        None => tokio::spawn(admin::serve(
+            admin,
+            metrics.clone(),
+            ready_rx.clone(),
+            drain_rx.clone(),
+        )),
+    };
 
-    Ok(())
-}
+    // Periodically re-list all resources to recover from missed events.
+    if !resync_interval.is_zero() {
+        tokio::spawn(index::resync_periodically(resync_interval, resync.clone()));
+    }
 
-#[derive(Debug)]
-struct IpNets(Vec<IpNet>);
+    // On SIGHUP, log the effective configuration and re-list all resources.
+    tokio::spawn(signals::sighup(config, resync));
 
-impl std::str::FromStr for IpNets {
-    type Err = anyhow::Error;
-    fn from_str(s: &str) -> Result<Self> {
-        s.split(',')
-            .map(|n| n.parse().map_err(Into::into))
-            .collect::<Result<Vec<IpNet>>>()
-            .map(Self)
+    // Run the gRPC server, serving results by looking up against the index handle.
+    let grpc_connections = metrics.register(
+        "grpc_connections",
+        "The number of open gRPC connections.",
+        Gauge::default(),
+    );
+    let audit = if enable_audit_log {
+        let dropped = metrics.register(
+            "grpc_audit_records_dropped_total",
+            "Total audit records dropped because the audit log could not keep up.",
+            Counter::default(),
+        );
+        let (log, records) =
+            linkerd_policy_controller_grpc::audit::channel(server::AUDIT_LOG_CAPACITY, dropped);
+        let sink = server::open_audit_log(audit_log_path.as_deref())
+            .await
+            .context(Failure::Config)?;
+        tokio::spawn(
+            async move {
+                if let Err(error) =
+                    linkerd_policy_controller_grpc::audit::write(records, sink).await
+                {
+                    error!(%error, "Failed to write audit log");
+                }
+            }
+            .instrument(info_span!("audit")),
+        );
+        Some(log)
+    } else {
+        None
+    };
+    let grpc_config = linkerd_policy_controller_grpc::ServerConfig {
+        keepalive_interval: grpc_keepalive_interval,
+        keepalive_timeout: Some(grpc_keepalive_timeout),
+        connect_timeout: Some(grpc_connect_timeout),
+        max_connection_age: grpc_max_connection_age,
+        max_connection_age_grace: Some(
+            grpc_max_connection_age_grace.unwrap_or(server::GRPC_MAX_CONNECTION_AGE_GRACE),
+        ),
+        tls: certs::load_grpc_tls(grpc_tls_cert, grpc_tls_key, grpc_tls_client_ca)
+            .context(Failure::Config)?,
+        max_concurrent_streams: grpc_max_concurrent_streams,
+        initial_stream_window_size: grpc_initial_stream_window,
+        initial_connection_window_size: grpc_initial_connection_window,
+        max_concurrent_requests: grpc_max_concurrent_requests,
+        update_debounce: index_update_debounce,
+        version: Some(build_info::version()),
+        connections: grpc_connections.clone(),
+        omit_family,
+        enforce_client_identity: grpc_enforce_client_identity,
+        audit,
+        stable_encoding: grpc_stable_encoding,
+        inject_latency: debug_inject_latency,
+    };
+    if let Some(latency) = debug_inject_latency {
+        warn!(
+            ?latency,
+            "Delaying all discovery responses; this must not be enabled in production"
+        );
     }
-}
+    // When handoffs are coordinated, this replica announces that it's ready so that its
+    // predecessor may drain, and records when it did so that it can recognize its own successor.
+    let handoff = handoff_config
+        .zip(client.clone())
+        .map(|(config, client)| coordination::Handoff::announce(client, config, ready_rx.clone()));
 
-#[instrument(skip(handle, drain))]
-async fn grpc(
-    addr: SocketAddr,
-    cluster_networks: Vec<IpNet>,
-    handle: linkerd_policy_controller_k8s_index::Reader,
-    drain: drain::Watch,
-) -> Result<()> {
-    let server =
-        linkerd_policy_controller_grpc::Server::new(handle, cluster_networks, drain.clone());
-    let (close_tx, close_rx) = tokio::sync::oneshot::channel();
-    tokio::pin! {
-        let srv = server.serve(addr, close_rx.map(|_| {}));
-    }
-    info!(%addr, "gRPC server listening");
-    tokio::select! {
-        res = (&mut srv) => res?,
-        handle = drain.signaled() => {
-            let _ = close_tx.send(());
-            handle.release_after(srv).await?
+    let grpc_task = tokio::spawn(server::grpc(
+        grpc_addr,
+        grpc_listener,
+        grpc_config,
+        networks_rx,
+        handle,
+        ready_rx,
+        drain_rx.clone(),
+    ));
+
+    // Run the admission controller
+    let admission =
+        certs::load_admission_certs(admission_addr, admission_tls_cert, admission_tls_key)
+            .context(Failure::Config)?;
+    if let (Some((bind_addr, certs)), Some(client)) = (admission, client) {
+        // The serving certificate is reloaded as it's rotated on disk.
+        tokio::spawn(
+            certs
+                .clone()
+                .reload_on_change(tls::CertResolver::RELOAD_INTERVAL)
+                .instrument(info_span!("admission")),
+        );
+
+        if !skip_webhook_check {
+            tokio::spawn(
+                certs::check_webhook_ca(client.clone(), admission_webhook_config, certs.clone())
+                    .instrument(info_span!("webhook_check")),
+            );
         }
+
+        let scope = admission::Scope {
+            namespaces: admission_namespaces,
+            namespace_selector: admission_namespace_selector
+                .as_deref()
+                .map(str::parse)
+                .transpose()
+                .context(Failure::Config)?,
+            namespace_labels,
+        };
+        // Every replica answers admission reviews, since the webhook's Service routes reviews to
+        // all ready replicas, but only the leader records events.
+        let leader = leader_election.map(|election| {
+            coordination::elect_leader(client.clone(), election, admission_drain_rx.clone())
+        });
+        let service = warp::service(admission::routes(
+            client,
+            &metrics,
+            admission_rate_limit,
+            scope,
+            admission_failure_policy,
+            admission_max_body_bytes,
+            observe_only,
+            leader,
+        ));
+        let acceptor = tls::acceptor_with_min_version(certs, admission_min_tls_version);
+        let listener = server::bind("admission", bind_addr, reuse_port).await?;
+        info!(addr = %listener.local_addr()?, "Admission controller server listening");
+        tokio::spawn(
+            tls::serve(listener, acceptor, service, admission_drain_rx.clone())
+                .instrument(info_span!("admission")),
+        );
     }
-    Ok(())
-}
 
-async fn shutdown(drain: drain::Signal) {
-    tokio::select! {
-        _ = tokio::signal::ctrl_c() => {
-            debug!("Received ctrl-c");
-        },
-        _ = sigterm() => {
-            debug!("Received SIGTERM");
+    let shutdowns = metrics.register(
+        "controller_shutdowns_total",
+        "Total shutdowns initiated by a signal, by the signal received.",
+        Family::new(&["reason"]),
+    );
+
+    let successor = async move {
+        if let Some(handoff) = handoff {
+            handoff.successor().await;
         }
-    }
-    info!("Shutting down");
-    drain.drain().await;
-}
+    };
+    // Each server holds its own drain handle, so draining completes once they've all released them.
+    drop((drain_rx, admission_drain_rx));
 
-async fn sigterm() {
-    use tokio::signal::unix::{signal, SignalKind};
-    match signal(SignalKind::terminate()) {
-        Ok(mut term) => term.recv().await,
-        _ => future::pending().await,
+    // Block the main thread on the shutdown signal. Once it fires, wait for the background tasks to
+    // complete before exiting. The indexer only completes if it can't watch resources, in which
+    // case the controller can't serve policy and must exit. The admin server only fails if it can't
+    // bind its listeners, and the gRPC server only fails if it can't serve its listener.
+    let shutting_down = signals::shutdown(
+        signals::shutdown_signal(),
+        successor,
+        drain_tx,
+        admission_drain_tx,
+        shutdown_grace_period,
+        grpc_connections,
+        shutdowns,
+    );
+    let res = tokio::select! {
+        _ = shutting_down => {
+            if let Some((path, handle)) = snapshots {
+                index::write_snapshot(&path, handle).await;
+            }
+            Ok(())
+        }
+        res = index_task => match res {
+            Ok(res) => res.context("indexer failed").context(Failure::Kubernetes),
+            Err(error) => Err(error).context("indexer panicked"),
+        },
+        Ok(Err(error)) = admin_task => Err(error.context(Failure::Bind)),
+        Ok(Err(error)) = grpc_task => Err(error.context("gRPC server failed")),
     };
+    res
 }
 
-fn log_init(filter: EnvFilter, format: LogFormat) -> Result<()> {
-    let registry = tracing_subscriber::registry().with(filter);
+fn log_init(
+    filter: EnvFilter,
+    format: LogFormat,
+    tracer: Option<opentelemetry::sdk::trace::Tracer>,
+) -> Result<()> {
+    let registry = tracing_subscriber::registry()
+        .with(filter)
+        .with(tracer.map(|t| tracing_opentelemetry::layer().with_tracer(t)));
 
     match format {
         LogFormat::Plain => registry.with(tracing_subscriber::fmt::layer()).try_init()?,
@@ -217,18 +826,77 @@ fn log_init(filter: EnvFilter, format: LogFormat) -> Result<()> {
     Ok(())
 }
 
-// === impl LogFormat ===
+/// Builds a tracer that exports spans to an OTLP collector in batches.
+fn trace_init(endpoint: &str, sample_rate: f64) -> Result<opentelemetry::sdk::trace::Tracer> {
+    use opentelemetry::sdk::{
+        trace::{self, Sampler},
+        Resource,
+    };
+    use opentelemetry_otlp::WithExportConfig;
+
+    let config = trace::config()
+        .with_sampler(Sampler::TraceIdRatioBased(sample_rate))
+        .with_resource(Resource::new(vec![opentelemetry::KeyValue::new(
+            "service.name",
+            "linkerd-policy-controller",
+        )]));
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(config)
+        .install_batch(opentelemetry::runtime::Tokio)
+        .with_context(|| format!("failed to initialize a trace exporter for {}", endpoint))?;
+    Ok(tracer)
+}
 
-impl std::str::FromStr for LogFormat {
-    type Err = Error;
+// === impl Failure ===
+
+impl Failure {
+    /// Returns the process exit code for `error`, based on the category it was tagged with.
+    /// Uncategorized errors exit with 1.
+    fn exit_code(error: &Error) -> i32 {
+        match error.downcast_ref::<Self>() {
+            None => 1,
+            Some(Self::Config) => 2,
+            Some(Self::Kubernetes) => 3,
+            Some(Self::Bind) => 4,
+        }
+    }
+}
 
-    fn from_str(s: &str) -> Result<Self> {
-        if s == "json" {
-            Ok(Self::Json)
-        } else if s == "plain" {
-            Ok(Self::Plain)
-        } else {
-            bail!("invalid log format: {}", s)
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Config => "invalid configuration".fmt(f),
+            Self::Kubernetes => "failed to communicate with the Kubernetes API".fmt(f),
+            Self::Bind => "failed to bind a port".fmt(f),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes() {
+        let fail = |category: Failure| {
+            Err::<(), _>(anyhow::anyhow!("boom"))
+                .context(category)
+                .context("while starting")
+                .unwrap_err()
+        };
+        assert_eq!(Failure::exit_code(&fail(Failure::Config)), 2);
+        assert_eq!(Failure::exit_code(&fail(Failure::Kubernetes)), 3);
+        assert_eq!(Failure::exit_code(&fail(Failure::Bind)), 4);
+        assert_eq!(Failure::exit_code(&anyhow::anyhow!("boom")), 1);
+
+        // The original message is retained.
+        let error = fail(Failure::Config);
+        assert!(format!("{:?}", error).contains("boom"), "{:?}", error);
+    }
+}
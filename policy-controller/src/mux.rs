@@ -0,0 +1,236 @@
+//! Serves the admin and gRPC servers on a single port.
+//!
+//! Some network policies only permit a single port to be exposed. A shared listener inspects the
+//! first bytes of each connection: connections that begin with the HTTP/2 connection preface are
+//! dispatched to the gRPC server, and all others are dispatched to the admin server. The bytes are
+//! peeked rather than read, so each server reads its connections from the start.
+//!
+//! Protocols are detected on plaintext connections, so neither server may be configured with TLS.
+//!
+//! This is the only protocol detection the controller performs; proxies detect the protocols of
+//! their own inbound connections and report their own detection timeouts.
+
+use linkerd_policy_controller_metrics::Counter;
+use std::{io, net::SocketAddr};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    time,
+};
+use tracing::{debug, warn, Instrument};
+
+/// The bytes with which every HTTP/2 connection begins.
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// The number of detected connections that may be waiting to be served by each server.
+const CAPACITY: usize = 128;
+
+/// How long to wait for more bytes when a client has only sent part of the preface.
+const PEEK_INTERVAL: time::Duration = time::Duration::from_millis(10);
+
+/// Connections accepted by a shared listener, by the server that serves them.
+#[derive(Debug)]
+pub struct Split {
+    pub admin: mpsc::Receiver<(TcpStream, SocketAddr)>,
+    pub grpc: mpsc::Receiver<TcpStream>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Protocol {
+    Http1,
+    Http2,
+}
+
+/// Accepts connections from `listener`, dispatching each by its protocol.
+///
+/// Connections that don't send enough bytes to detect their protocol within `detect_timeout` are
+/// closed and counted by `timeouts`.
+pub fn split(listener: TcpListener, detect_timeout: time::Duration, timeouts: Counter) -> Split {
+    let (admin_tx, admin) = mpsc::channel(CAPACITY);
+    let (grpc_tx, grpc) = mpsc::channel(CAPACITY);
+    tokio::spawn(accept(listener, detect_timeout, timeouts, admin_tx, grpc_tx).in_current_span());
+    Split { admin, grpc }
+}
+
+async fn accept(
+    listener: TcpListener,
+    detect_timeout: time::Duration,
+    timeouts: Counter,
+    admin: mpsc::Sender<(TcpStream, SocketAddr)>,
+    grpc: mpsc::Sender<TcpStream>,
+) {
+    loop {
+        let (socket, client) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(error) => {
+                warn!(%error, "Failed to accept connection");
+                time::sleep(crate::net::ACCEPT_BACKOFF).await;
+                continue;
+            }
+        };
+
+        // Detect each connection on its own task so that a slow client doesn't delay others. If a
+        // server has stopped, its connections are dropped.
+        let admin = admin.clone();
+        let grpc = grpc.clone();
+        let timeouts = timeouts.clone();
+        tokio::spawn(async move {
+            match time::timeout(detect_timeout, detect(&socket)).await {
+                Ok(Ok(Protocol::Http2)) => {
+                    let _ = grpc.send(socket).await;
+                }
+                Ok(Ok(Protocol::Http1)) => {
+                    let _ = admin.send((socket, client)).await;
+                }
+                Ok(Err(error)) => debug!(%error, %client, "Failed to detect protocol"),
+                Err(_) => {
+                    timeouts.inc();
+                    debug!(%client, timeout = ?detect_timeout, "Protocol detection timed out");
+                }
+            }
+        });
+    }
+}
+
+/// Detects a connection's protocol by peeking at the bytes it has sent.
+async fn detect(socket: &TcpStream) -> io::Result<Protocol> {
+    let mut buf = [0u8; PREFACE.len()];
+    loop {
+        let n = socket.peek(&mut buf).await?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        if buf[..n] != PREFACE[..n] {
+            return Ok(Protocol::Http1);
+        }
+        if n == PREFACE.len() {
+            return Ok(Protocol::Http2);
+        }
+
+        // Peeking returns immediately while any bytes are buffered, so wait for the client to send
+        // the rest of the preface.
+        time::sleep(PEEK_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        admin, grpc,
+        k8s::{ClusterInfo, DefaultPolicy, Index},
+    };
+    use hyper::{Body, Client, Request, StatusCode};
+    use linkerd_policy_controller_metrics::Registry;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        sync::watch,
+    };
+
+    #[tokio::test]
+    async fn detects_protocols() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        // The preface may arrive across several segments.
+        client.write_all(&PREFACE[..3]).await.unwrap();
+        let detected = tokio::spawn(async move { detect(&server).await.unwrap() });
+        time::sleep(PEEK_INTERVAL).await;
+        client.write_all(&PREFACE[3..]).await.unwrap();
+        assert_eq!(detected.await.unwrap(), Protocol::Http2);
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        client.write_all(b"POST / HTTP/1.1\r\n").await.unwrap();
+        assert_eq!(detect(&server).await.unwrap(), Protocol::Http1);
+    }
+
+    #[tokio::test]
+    async fn counts_detect_timeouts() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let timeouts = Counter::default();
+        let _split = split(listener, time::Duration::from_millis(10), timeouts.clone());
+
+        // The client never sends any bytes, so its connection is closed once the timeout fires.
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut buf = [0u8; 1];
+        let n = time::timeout(time::Duration::from_secs(1), client.read(&mut buf))
+            .await
+            .expect("connection must be closed")
+            .unwrap_or(0);
+        assert_eq!(n, 0);
+        assert_eq!(timeouts.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn serves_admin_and_grpc() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let Split {
+            admin: admin_rx,
+            grpc: grpc_rx,
+        } = split(listener, time::Duration::from_secs(1), Counter::default());
+
+        let (_drain_tx, drain_rx) = drain::channel();
+        let (_ready_tx, ready_rx) = watch::channel(true);
+        let config = admin::Config {
+            addr,
+            metrics_addr: None,
+            max_connections: 10,
+            reuse_port: false,
+            enable_profiling: false,
+            index: None,
+            policies: None,
+            tls: None,
+        };
+        tokio::spawn(admin::serve_shared(
+            config,
+            admin_rx,
+            Registry::default(),
+            ready_rx.clone(),
+            drain_rx.clone(),
+        ));
+
+        let cluster = ClusterInfo {
+            networks: vec![],
+            control_plane_ns: "linkerd".to_string(),
+            identity_domain: "cluster.local".to_string(),
+        };
+        let default = DefaultPolicy::Deny;
+        let (reader, _index) = Index::new(cluster, default, time::Duration::from_secs(1));
+        let server = grpc::Server::new(reader, vec![], drain_rx);
+        tokio::spawn(server.serve(
+            grpc_rx,
+            Default::default(),
+            ready_rx,
+            futures::future::pending(),
+        ));
+
+        let rsp = Client::new()
+            .get(format!("http://{}/ready", addr).parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(rsp.status(), StatusCode::OK);
+
+        // Check the health of the server as a whole, encoded as an empty gRPC message.
+        let req = Request::post(format!("http://{}/grpc.health.v1.Health/Check", addr))
+            .header(hyper::header::CONTENT_TYPE, "application/grpc")
+            .header("te", "trailers")
+            .body(Body::from(vec![0u8; 5]))
+            .unwrap();
+        let rsp = Client::builder()
+            .http2_only(true)
+            .build_http()
+            .request(req)
+            .await
+            .unwrap();
+        assert_eq!(rsp.status(), StatusCode::OK);
+        assert_eq!(
+            rsp.headers()[hyper::header::CONTENT_TYPE],
+            "application/grpc"
+        );
+    }
+}
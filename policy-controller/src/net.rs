@@ -0,0 +1,98 @@
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+
+/// The maximum number of pending connections on a listener.
+const BACKLOG: i32 = 1024;
+
+/// How long to wait before accepting another connection after a listener fails, e.g. because the
+/// process is out of file descriptors. Such failures usually persist for a while, so servers don't
+/// spin on them.
+pub(crate) const ACCEPT_BACKOFF: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Binds a TCP listener on `addr`.
+///
+/// A listener on the unspecified IPv6 address (i.e. `[::]`) also accepts IPv4 connections, so that
+/// a single listener serves both families on dual-stack clusters. This doesn't depend on the host's
+/// `net.ipv6.bindv6only` setting.
+///
+/// Listeners set `SO_REUSEADDR` so that a restarted controller can rebind its addresses while the
+/// prior process's connections linger in `TIME_WAIT`. When `reuse_port` is set, listeners also set
+/// `SO_REUSEPORT`, so that several processes may bind the same address and have the kernel balance
+/// connections across them.
+pub async fn bind(addr: SocketAddr, reuse_port: bool) -> std::io::Result<TcpListener> {
+    let socket = socket2::Socket::new(
+        socket2::Domain::for_address(addr),
+        socket2::Type::STREAM,
+        Some(socket2::Protocol::TCP),
+    )?;
+    if addr.is_ipv6() && addr.ip().is_unspecified() {
+        socket.set_only_v6(false)?;
+    }
+    socket.set_reuse_address(true)?;
+    if reuse_port {
+        socket.set_reuse_port(true)?;
+    }
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(BACKLOG)?;
+    TcpListener::from_std(socket.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpStream;
+
+    #[tokio::test]
+    async fn dual_stack_accepts_both_families() {
+        let listener = bind("[::]:0".parse().unwrap(), false).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        for ip in ["127.0.0.1", "::1"] {
+            let addr = SocketAddr::new(ip.parse().unwrap(), port);
+            let _conn = TcpStream::connect(addr)
+                .await
+                .unwrap_or_else(|error| panic!("failed to connect to {}: {}", addr, error));
+            listener
+                .accept()
+                .await
+                .expect("connection must be accepted");
+        }
+    }
+
+    #[tokio::test]
+    async fn binds_specific_addresses() {
+        let listener = bind("127.0.0.1:0".parse().unwrap(), false).await.unwrap();
+        assert!(listener.local_addr().unwrap().ip().is_loopback());
+    }
+
+    #[tokio::test]
+    async fn rebinds_immediately() {
+        let listener = bind("127.0.0.1:0".parse().unwrap(), false).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // The server closes its end of the connection first, leaving it in TIME_WAIT.
+        let client = TcpStream::connect(addr).await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        drop(server);
+        drop(listener);
+        drop(client);
+
+        bind(addr, false)
+            .await
+            .expect("must rebind while a connection is in TIME_WAIT");
+    }
+
+    #[tokio::test]
+    async fn reuse_port_shares_addresses() {
+        let listener = bind("127.0.0.1:0".parse().unwrap(), true).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        assert!(
+            bind(addr, false).await.is_err(),
+            "listeners that don't reuse the port must not share it"
+        );
+        bind(addr, true)
+            .await
+            .expect("listeners that reuse the port must share it");
+    }
+}
@@ -0,0 +1,183 @@
+//! Verifies that the controller is authorized to watch the resources it indexes.
+//!
+//! Missing RBAC permissions otherwise only surface once the watches fail with `Forbidden` errors,
+//! after the controller has started. Each permission is checked with a `SelfSubjectAccessReview`,
+//! which reports whether the controller's own credentials authorize a request.
+
+use crate::api::{
+    ResourceAttributes, SelfSubjectAccessReview, SelfSubjectAccessReviewSpec, WatchKinds,
+};
+use anyhow::Result;
+use kube::api::{Api, PostParams};
+
+/// The API group of the policy resources.
+const POLICY_GROUP: &str = "policy.linkerd.io";
+
+/// A request that the controller must be authorized to make.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Permission {
+    pub verb: &'static str,
+
+    /// The resource's API group, which is empty for core resources.
+    pub group: &'static str,
+
+    pub resource: &'static str,
+
+    /// When unset, the permission applies to all namespaces.
+    pub namespace: Option<String>,
+}
+
+/// Returns the permissions required to watch the given kinds of resources in `namespace` or, if
+/// no namespace is provided, in all namespaces.
+pub fn required(namespace: Option<&str>, kinds: WatchKinds) -> Vec<Permission> {
+    // Cluster-scoped resources aren't watched when the controller is limited to a namespace.
+    let mut resources = Vec::new();
+    if namespace.is_none() {
+        resources.push(("", "namespaces"));
+    }
+    resources.push(("", "pods"));
+    resources.push((POLICY_GROUP, "servers"));
+    if kinds.server_authorizations {
+        resources.push((POLICY_GROUP, "serverauthorizations"));
+    }
+    if namespace.is_none() && kinds.cluster_servers {
+        resources.push((POLICY_GROUP, "clusterservers"));
+    }
+
+    resources
+        .into_iter()
+        .flat_map(|(group, resource)| {
+            ["list", "watch"].into_iter().map(move |verb| Permission {
+                verb,
+                group,
+                resource,
+                namespace: namespace.map(ToString::to_string),
+            })
+        })
+        .collect()
+}
+
+/// Returns the permissions that the client is not authorized to use.
+pub async fn missing(client: kube::Client, permissions: &[Permission]) -> Result<Vec<Permission>> {
+    let api = Api::<SelfSubjectAccessReview>::all(client);
+    let mut missing = Vec::new();
+    for permission in permissions {
+        let review = api
+            .create(&PostParams::default(), &permission.review())
+            .await?;
+        if !matches!(&review.status, Some(s) if s.allowed) {
+            missing.push(permission.clone());
+        }
+    }
+    Ok(missing)
+}
+
+// === impl Permission ===
+
+impl Permission {
+    fn review(&self) -> SelfSubjectAccessReview {
+        SelfSubjectAccessReview {
+            spec: SelfSubjectAccessReviewSpec {
+                resource_attributes: Some(ResourceAttributes {
+                    verb: Some(self.verb.to_string()),
+                    group: Some(self.group.to_string()),
+                    resource: Some(self.resource.to_string()),
+                    namespace: self.namespace.clone(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+}
+
+impl std::fmt::Display for Permission {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.verb, self.resource)?;
+        if !self.group.is_empty() {
+            write!(f, ".{}", self.group)?;
+        }
+        match self.namespace.as_deref() {
+            Some(ns) => write!(f, " in namespace {}", ns),
+            None => write!(f, " in all namespaces"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+
+    #[test]
+    fn requires_watched_kinds() {
+        let kinds = WatchKinds {
+            server_authorizations: false,
+            cluster_servers: true,
+        };
+        let resources = required(None, kinds)
+            .into_iter()
+            .filter(|p| p.verb == "watch")
+            .map(|p| p.resource)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            resources,
+            ["namespaces", "pods", "servers", "clusterservers"]
+        );
+
+        // Cluster-scoped resources aren't watched within a namespace.
+        let permissions = required(Some("ns-0"), WatchKinds::default());
+        let resources = permissions
+            .iter()
+            .filter(|p| p.verb == "list")
+            .map(|p| p.resource)
+            .collect::<Vec<_>>();
+        assert_eq!(resources, ["pods", "servers", "serverauthorizations"]);
+        assert!(permissions
+            .iter()
+            .all(|p| p.namespace.as_deref() == Some("ns-0")));
+        assert_eq!(
+            permissions[2].to_string(),
+            "list servers.policy.linkerd.io in namespace ns-0"
+        );
+    }
+
+    #[tokio::test]
+    async fn detects_missing_permissions() {
+        // Stands in for the API server, which only permits pods and namespaces to be listed.
+        let client = kube::Client::new(
+            hyper::service::service_fn(|req: hyper::Request<hyper::Body>| async move {
+                assert_eq!(
+                    req.uri().path(),
+                    "/apis/authorization.k8s.io/v1/selfsubjectaccessreviews"
+                );
+                let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                let mut review = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+                let attrs = &review["spec"]["resourceAttributes"];
+                let allowed = attrs["verb"] == "list"
+                    && (attrs["resource"] == "pods" || attrs["resource"] == "namespaces");
+                review["status"] = serde_json::json!({ "allowed": allowed });
+                Ok::<_, Infallible>(hyper::Response::new(hyper::Body::from(
+                    serde_json::to_vec(&review).unwrap(),
+                )))
+            }),
+            "default",
+        );
+
+        let permissions = required(None, WatchKinds::default());
+        let missing = missing(client, &permissions).await.unwrap();
+        let missing = missing.iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(
+            missing,
+            [
+                "watch namespaces in all namespaces",
+                "watch pods in all namespaces",
+                "list servers.policy.linkerd.io in all namespaces",
+                "watch servers.policy.linkerd.io in all namespaces",
+                "list serverauthorizations.policy.linkerd.io in all namespaces",
+                "watch serverauthorizations.policy.linkerd.io in all namespaces",
+            ]
+        );
+    }
+}
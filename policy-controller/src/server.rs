@@ -0,0 +1,149 @@
+//! Binds the servers' listeners and runs the gRPC server.
+
+use crate::{cli::GrpcAddr, Failure};
+use anyhow::{bail, Context, Result};
+use futures::prelude::*;
+use linkerd_policy_controller::net;
+use linkerd_policy_controller_core::IpNet;
+use std::{net::SocketAddr, sync::Arc};
+use tokio::{sync::watch, time};
+use tracing::{debug, info, instrument, warn};
+
+#[instrument(skip(listener, config, handle, ready, drain))]
+pub(crate) async fn grpc(
+    addr: GrpcAddr,
+    listener: linkerd_policy_controller_grpc::Listener,
+    config: linkerd_policy_controller_grpc::ServerConfig,
+    cluster_networks: watch::Receiver<Arc<[IpNet]>>,
+    handle: linkerd_policy_controller_k8s_index::Reader,
+    ready: watch::Receiver<bool>,
+    drain: drain::Watch,
+) -> Result<()> {
+    let server = linkerd_policy_controller_grpc::Server::new(handle, vec![], drain.clone())
+        .with_cluster_networks(cluster_networks);
+    let (close_tx, close_rx) = tokio::sync::oneshot::channel();
+    tokio::pin! {
+        let srv = server.serve(listener, config, ready, close_rx.map(|_| {}));
+    }
+    info!(%addr, "gRPC server listening");
+    let res = tokio::select! {
+        res = (&mut srv) => res,
+        handle = drain.signaled() => {
+            let _ = close_tx.send(());
+            handle.release_after(srv).await
+        }
+    };
+
+    // Don't leave a stale socket behind.
+    if let GrpcAddr::Unix(path) = &addr {
+        if let Err(error) = std::fs::remove_file(path) {
+            warn!(%error, path = %path.display(), "Failed to remove socket");
+        }
+    }
+    res?;
+    Ok(())
+}
+
+/// Binds a server's listener, categorizing failures as `Failure::Bind`.
+pub(crate) async fn bind(
+    name: &str,
+    addr: SocketAddr,
+    reuse_port: bool,
+) -> Result<tokio::net::TcpListener> {
+    net::bind(addr, reuse_port)
+        .await
+        .with_context(|| format!("failed to bind {} server on {}", name, addr))
+        .context(Failure::Bind)
+}
+
+/// Binds the gRPC server's listener, categorizing failures as `Failure::Bind`.
+///
+/// A stale Unix domain socket (e.g. left by a controller that didn't shut down cleanly) is removed
+/// before binding.
+pub(crate) async fn bind_grpc(
+    addr: &GrpcAddr,
+    reuse_port: bool,
+) -> Result<linkerd_policy_controller_grpc::Listener> {
+    match addr {
+        GrpcAddr::Tcp(addr) => Ok(bind("gRPC", *addr, reuse_port).await?.into()),
+        GrpcAddr::Unix(path) => {
+            let listener = remove_stale_socket(path)
+                .and_then(|()| {
+                    tokio::net::UnixListener::bind(path).with_context(|| {
+                        format!("failed to bind gRPC server on {}", path.display())
+                    })
+                })
+                .context(Failure::Bind)?;
+            Ok(listener.into())
+        }
+    }
+}
+
+/// How long aged gRPC connections may remain open, unless `--grpc-max-connection-age-grace` is
+/// set. Watches never complete on their own, so without a limit, connections with active watches
+/// would never be closed.
+pub(crate) const GRPC_MAX_CONNECTION_AGE_GRACE: time::Duration = time::Duration::from_secs(60);
+
+/// The number of audit records buffered while they're written. Records beyond this are dropped.
+pub(crate) const AUDIT_LOG_CAPACITY: usize = 10_000;
+
+/// Opens the sink to which audit records are written: the file at `path`, which is appended to, or
+/// stdout.
+pub(crate) async fn open_audit_log(
+    path: Option<&std::path::Path>,
+) -> Result<std::pin::Pin<Box<dyn tokio::io::AsyncWrite + Send>>> {
+    match path {
+        Some(path) => {
+            let file = tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .await
+                .with_context(|| format!("failed to open {}", path.display()))?;
+            Ok(Box::pin(file))
+        }
+        None => Ok(Box::pin(tokio::io::stdout())),
+    }
+}
+
+/// Removes the socket at `path`, if one exists. Files that aren't sockets are never removed.
+fn remove_stale_socket(path: &std::path::Path) -> Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => {
+            std::fs::remove_file(path)
+                .with_context(|| format!("failed to remove stale socket {}", path.display()))?;
+            debug!(path = %path.display(), "Removed stale socket");
+            Ok(())
+        }
+        Ok(_) => bail!("{} exists and is not a socket", path.display()),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(error).with_context(|| format!("failed to inspect {}", path.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removes_stale_sockets() {
+        let dir = std::env::temp_dir().join(format!("policy-{:016x}", rand::random::<u64>()));
+        std::fs::create_dir(&dir).unwrap();
+
+        // A socket that's no longer bound is left behind when its listener is dropped.
+        let socket = dir.join("grpc.sock");
+        drop(std::os::unix::net::UnixListener::bind(&socket).unwrap());
+        remove_stale_socket(&socket).unwrap();
+        assert!(!socket.exists());
+        remove_stale_socket(&socket).expect("missing sockets must be ignored");
+
+        let file = dir.join("file");
+        std::fs::write(&file, b"").unwrap();
+        assert!(remove_stale_socket(&file).is_err());
+        assert!(file.exists(), "regular files must not be removed");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
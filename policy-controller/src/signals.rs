@@ -0,0 +1,231 @@
+//! Handles the signals that shut down the controller or resync its index.
+
+use futures::{future, prelude::*};
+use linkerd_policy_controller_metrics::{Counter, Family, Gauge};
+use std::sync::Arc;
+use tokio::time;
+use tracing::{debug, info, warn};
+
+/// The signal that initiated shutdown.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum ShutdownReason {
+    CtrlC,
+    Sigterm,
+}
+
+impl ShutdownReason {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::CtrlC => "ctrl_c",
+            Self::Sigterm => "sigterm",
+        }
+    }
+}
+
+/// Completes with the first shutdown signal received.
+pub(crate) async fn shutdown_signal() -> ShutdownReason {
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => ShutdownReason::CtrlC,
+        _ = sigterm() => ShutdownReason::Sigterm,
+    }
+}
+
+/// Drains the controller's servers once a shutdown `signal` is received, waiting at most
+/// `grace_period` for clients to disconnect.
+///
+/// Draining begins once the `successor` future completes, so that a replica can continue serving
+/// until another is ready. The time spent waiting for a successor counts against the grace period.
+/// When an `admission_drain` is set, it's only signaled once the other servers have drained (or
+/// the grace period has elapsed).
+///
+/// The reason for the shutdown is logged and counted before draining, so that operator-initiated
+/// restarts can be distinguished from crashes. While draining, the number of open gRPC
+/// connections is logged periodically, so that a slow drain can be distinguished from a client
+/// that never disconnects.
+pub(crate) async fn shutdown(
+    signal: impl Future<Output = ShutdownReason>,
+    successor: impl Future<Output = ()>,
+    drain: drain::Signal,
+    admission_drain: Option<drain::Signal>,
+    grace_period: time::Duration,
+    grpc_connections: Gauge,
+    shutdowns: Family<Counter>,
+) -> ShutdownReason {
+    const PROGRESS_INTERVAL: time::Duration = time::Duration::from_secs(5);
+
+    let reason = signal.await;
+    shutdowns.get(&[reason.as_str()]).inc();
+    info!(
+        reason = reason.as_str(),
+        grpc_connections = grpc_connections.get(),
+        "Shutting down"
+    );
+
+    let deadline = time::Instant::now() + grace_period;
+    if time::timeout_at(deadline, successor).await.is_err() {
+        warn!(waited = ?grace_period, "No successor became ready");
+    }
+
+    let drained = time::timeout_at(deadline, drain.drain());
+    tokio::pin!(drained);
+    let mut progress =
+        time::interval_at(time::Instant::now() + PROGRESS_INTERVAL, PROGRESS_INTERVAL);
+    loop {
+        tokio::select! {
+            res = &mut drained => {
+                if res.is_err() {
+                    warn!(
+                        waited = ?grace_period,
+                        grpc_connections = grpc_connections.get(),
+                        "Shutdown grace period elapsed"
+                    );
+                }
+                break;
+            }
+            _ = progress.tick() => {
+                info!(grpc_connections = grpc_connections.get(), "Waiting for connections to close");
+            }
+        }
+    }
+
+    if let Some(admission) = admission_drain {
+        debug!("Draining the admission server");
+        if time::timeout_at(deadline, admission.drain()).await.is_err() {
+            warn!(waited = ?grace_period, "Admission server did not drain");
+        }
+    }
+    reason
+}
+
+async fn sigterm() {
+    use tokio::signal::unix::{signal, SignalKind};
+    match signal(SignalKind::terminate()) {
+        Ok(mut term) => term.recv().await,
+        _ => future::pending().await,
+    };
+}
+
+/// Handles SIGHUP by logging the effective configuration and resyncing the index. Unlike SIGTERM,
+/// SIGHUP does not initiate shutdown.
+pub(crate) async fn sighup(config: Vec<(&'static str, String)>, resync: Arc<tokio::sync::Notify>) {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut hup = match signal(SignalKind::hangup()) {
+        Ok(hup) => hup,
+        Err(error) => {
+            warn!(%error, "Failed to register a SIGHUP handler");
+            return;
+        }
+    };
+
+    while hup.recv().await.is_some() {
+        let config = config
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join(" ");
+        info!(%config, "Received SIGHUP; resyncing resources");
+        resync.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linkerd_policy_controller_metrics::Registry;
+
+    #[tokio::test]
+    async fn counts_shutdown_reasons() {
+        let metrics = Registry::default();
+        let shutdowns = metrics.register(
+            "controller_shutdowns_total",
+            "Total shutdowns.",
+            Family::new(&["reason"]),
+        );
+        // No connections are open, so draining completes immediately.
+        let (drain_tx, _) = drain::channel();
+        let reason = shutdown(
+            future::ready(ShutdownReason::Sigterm),
+            future::ready(()),
+            drain_tx,
+            None,
+            time::Duration::from_secs(1),
+            Gauge::default(),
+            shutdowns,
+        )
+        .await;
+        assert_eq!(reason, ShutdownReason::Sigterm);
+        assert!(metrics
+            .encode()
+            .contains("controller_shutdowns_total{reason=\"sigterm\"} 1"));
+    }
+
+    #[tokio::test]
+    async fn drains_after_successor_is_ready() {
+        let (drain_tx, drain_rx) = drain::channel();
+        let (successor_tx, successor_rx) = tokio::sync::oneshot::channel::<()>();
+        let shutdown = tokio::spawn(shutdown(
+            future::ready(ShutdownReason::Sigterm),
+            successor_rx.map(|_| ()),
+            drain_tx,
+            None,
+            time::Duration::from_secs(10),
+            Gauge::default(),
+            Family::new(&["reason"]),
+        ));
+
+        // The old replica keeps serving until its successor reports that it's ready.
+        let signaled = drain_rx.signaled();
+        tokio::pin!(signaled);
+        assert!(
+            time::timeout(time::Duration::from_millis(100), &mut signaled)
+                .await
+                .is_err(),
+            "must not drain before a successor is ready"
+        );
+
+        successor_tx.send(()).unwrap();
+        time::timeout(time::Duration::from_secs(1), signaled)
+            .await
+            .expect("must drain once a successor is ready")
+            .release_after(future::ready(()))
+            .await;
+        shutdown.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn drains_grpc_before_admission() {
+        let (drain_tx, drain_rx) = drain::channel();
+        let (admission_tx, admission_rx) = drain::channel();
+        let shutdown = tokio::spawn(shutdown(
+            future::ready(ShutdownReason::Sigterm),
+            future::ready(()),
+            drain_tx,
+            Some(admission_tx),
+            time::Duration::from_secs(10),
+            Gauge::default(),
+            Family::new(&["reason"]),
+        ));
+
+        let grpc = time::timeout(time::Duration::from_secs(1), drain_rx.signaled())
+            .await
+            .expect("gRPC must drain first");
+
+        // The admission server keeps serving while gRPC connections remain open.
+        let admission = admission_rx.signaled();
+        tokio::pin!(admission);
+        assert!(
+            time::timeout(time::Duration::from_millis(100), &mut admission)
+                .await
+                .is_err(),
+            "must not drain admission before gRPC has drained"
+        );
+
+        grpc.release_after(future::ready(())).await;
+        time::timeout(time::Duration::from_secs(1), admission)
+            .await
+            .expect("must drain admission once gRPC has drained")
+            .release_after(future::ready(()))
+            .await;
+        shutdown.await.unwrap();
+    }
+}
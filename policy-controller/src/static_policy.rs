@@ -0,0 +1,203 @@
+//! Loads a static set of resources from a file so that policies can be served without a Kubernetes
+//! API server, e.g. in hermetic tests.
+//!
+//! The file holds YAML documents describing `Namespace`, `Pod`, `Server`, `ServerAuthorization`,
+//! and `ClusterServer` resources. The resources are listed once, when the index starts, and are
+//! never updated.
+
+use crate::{
+    api::{ResourceWatches, Watch},
+    k8s::Snapshot,
+};
+use anyhow::{bail, Context, Result};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::path::Path;
+
+/// The namespace of namespaced resources that don't set one, as when they're applied with kubectl.
+const DEFAULT_NAMESPACE: &str = "default";
+
+/// Reads the resources in the file at `path`.
+pub fn load(path: &Path) -> Result<Snapshot> {
+    let yaml = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    parse(&yaml).with_context(|| format!("failed to parse {}", path.display()))
+}
+
+/// Parses resources from a stream of YAML documents.
+pub fn parse(yaml: &str) -> Result<Snapshot> {
+    let mut resources = Snapshot::default();
+    for doc in serde_yaml::Deserializer::from_str(yaml) {
+        let value = serde_yaml::Value::deserialize(doc)?;
+        // Empty documents (e.g. following a trailing `---`) are ignored.
+        if value.is_null() {
+            continue;
+        }
+
+        let kind = value
+            .get("kind")
+            .and_then(serde_yaml::Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+        match kind.as_str() {
+            "Namespace" => resources.namespaces.push(resource(&kind, value, false)?),
+            "Pod" => resources.pods.push(resource(&kind, value, true)?),
+            "Server" => resources.servers.push(resource(&kind, value, true)?),
+            "ServerAuthorization" => resources.authorizations.push(resource(&kind, value, true)?),
+            "ClusterServer" => resources
+                .cluster_servers
+                .push(resource(&kind, value, false)?),
+            "" => bail!("resource must have a kind"),
+            kind => bail!("unsupported kind {:?}", kind),
+        }
+    }
+    Ok(resources)
+}
+
+/// Serves the resources from watches that list them once and are never updated.
+pub fn watches(resources: Snapshot) -> ResourceWatches {
+    let Snapshot {
+        namespaces,
+        pods,
+        servers,
+        authorizations,
+        cluster_servers,
+    } = resources;
+    ResourceWatches {
+        namespaces_rx: Watch::listed(namespaces),
+        pods_rx: Watch::listed(pods),
+        servers_rx: Watch::listed(servers),
+        authorizations_rx: Watch::listed(authorizations),
+        cluster_servers_rx: Watch::listed(cluster_servers),
+    }
+}
+
+/// Decodes a resource, which must be named. Namespaced resources that don't set a namespace are
+/// placed in the default namespace.
+fn resource<K>(kind: &str, value: serde_yaml::Value, namespaced: bool) -> Result<K>
+where
+    K: kube::Resource + DeserializeOwned,
+{
+    let mut resource =
+        serde_yaml::from_value::<K>(value).with_context(|| format!("invalid {}", kind))?;
+    let meta = resource.meta_mut();
+    if meta.name.is_none() {
+        bail!("{} must have a name", kind);
+    }
+    if namespaced {
+        meta.namespace
+            .get_or_insert_with(|| DEFAULT_NAMESPACE.to_string());
+    }
+    Ok(resource)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::k8s::{ClusterInfo, DefaultPolicy, Index};
+    use linkerd_policy_controller_core::{DiscoverInboundServer, ProxyProtocol};
+    use tokio::{sync::watch, time};
+
+    const POLICY: &str = r#"
+apiVersion: v1
+kind: Pod
+metadata:
+  name: web-0
+  namespace: ns-0
+  labels:
+    app: web
+spec:
+  containers:
+    - name: web
+      ports:
+        - containerPort: 8080
+---
+apiVersion: policy.linkerd.io/v1beta1
+kind: Server
+metadata:
+  name: web-http
+  namespace: ns-0
+spec:
+  podSelector:
+    matchLabels:
+      app: web
+  port: 8080
+  proxyProtocol: HTTP/1
+---
+apiVersion: policy.linkerd.io/v1beta1
+kind: ServerAuthorization
+metadata:
+  name: web-public
+  namespace: ns-0
+spec:
+  server:
+    name: web-http
+  client:
+    unauthenticated: true
+---
+"#;
+
+    #[tokio::test]
+    async fn serves_static_policies() {
+        let path =
+            std::env::temp_dir().join(format!("policy-static-{}-serves.yaml", std::process::id()));
+        std::fs::write(&path, POLICY).unwrap();
+        let resources = load(&path);
+        std::fs::remove_file(&path).unwrap();
+        let resources = resources.unwrap();
+        assert_eq!(resources.len(), 3);
+
+        let cluster = ClusterInfo {
+            networks: vec![],
+            control_plane_ns: "linkerd".to_string(),
+            identity_domain: "cluster.local".to_string(),
+        };
+        let (reader, index) =
+            Index::new(cluster, DefaultPolicy::Deny, time::Duration::from_secs(1));
+        let (ready_tx, mut ready_rx) = watch::channel(false);
+        tokio::spawn(index.run(watches(resources), ready_tx));
+        time::timeout(time::Duration::from_secs(1), ready_rx.changed())
+            .await
+            .expect("index must become ready once the resources are listed")
+            .unwrap();
+
+        let srv = reader
+            .get_inbound_server(("ns-0".to_string(), "web-0".to_string(), 8080))
+            .await
+            .unwrap()
+            .expect("port must be discovered");
+        assert_eq!(srv.name, "web-http");
+        assert_eq!(srv.protocol, ProxyProtocol::Http1);
+        assert!(srv.authorizations.contains_key("web-public"));
+
+        let unknown = reader
+            .get_inbound_server(("ns-0".to_string(), "web-1".to_string(), 8080))
+            .await
+            .unwrap();
+        assert!(unknown.is_none());
+    }
+
+    #[test]
+    fn defaults_namespaces() {
+        let resources = parse(
+            "kind: Pod\nmetadata:\n  name: web-0\n---\nkind: Namespace\nmetadata:\n  name: ns-0\n",
+        )
+        .unwrap();
+        assert_eq!(
+            resources.pods[0].metadata.namespace.as_deref(),
+            Some(DEFAULT_NAMESPACE)
+        );
+        assert_eq!(resources.namespaces[0].metadata.namespace, None);
+    }
+
+    #[test]
+    fn rejects_invalid_resources() {
+        for invalid in [
+            "kind: Service\nmetadata:\n  name: web\n",
+            "metadata:\n  name: web\n",
+            "kind: Server\nmetadata:\n  namespace: ns-0\nspec:\n  podSelector: {}\n  port: 8080\n",
+            "kind: Server\nmetadata:\n  name: web\n",
+        ] {
+            assert!(parse(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+}
@@ -0,0 +1,506 @@
+use anyhow::{anyhow, bail, Context, Result};
+use hyper::{server::conn::Http, Body, Request, Response};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::SystemTime,
+};
+use tokio::{net::TcpListener, time};
+use tokio_rustls::{
+    rustls::{
+        self,
+        server::{ClientHello, ResolvesServerCert},
+        sign::CertifiedKey,
+    },
+    TlsAcceptor,
+};
+use tracing::{debug, error, info, warn};
+use x509_parser::{certificate::X509Certificate, traits::FromDer};
+
+/// Resolves a server's certificate from PEM files on disk.
+///
+/// The certificate is reloaded as the files change so that rotated certificates are served
+/// without restarting the controller.
+pub struct CertResolver {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+
+    /// Handshakes clone the current key, so replacing it never disrupts an in-flight handshake.
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+// === impl CertResolver ===
+
+impl CertResolver {
+    /// How frequently the certificate files are checked for changes.
+    pub const RELOAD_INTERVAL: time::Duration = time::Duration::from_secs(10);
+
+    /// Loads a certificate and key, failing if either file can't be read or parsed.
+    pub fn load(cert_path: PathBuf, key_path: PathBuf) -> Result<Arc<Self>> {
+        let key = load_certified_key(&cert_path, &key_path)?;
+        Ok(Arc::new(Self {
+            cert_path,
+            key_path,
+            current: RwLock::new(Arc::new(key)),
+        }))
+    }
+
+    /// Polls the certificate and key files, reloading them when they're modified.
+    ///
+    /// If the new files can't be loaded (e.g. because the PEM is malformed or a rotation is only
+    /// partially written), an error is logged and the previously-loaded certificate continues to
+    /// be served.
+    pub async fn reload_on_change(self: Arc<Self>, interval: time::Duration) {
+        let mut loaded = self.modified();
+        loop {
+            time::sleep(interval).await;
+
+            let modified = self.modified();
+            if modified == loaded {
+                continue;
+            }
+
+            match load_certified_key(&self.cert_path, &self.key_path) {
+                Ok(key) => {
+                    *self.current.write().expect("lock must not be poisoned") = Arc::new(key);
+                    loaded = modified;
+                    info!(cert = %self.cert_path.display(), "Reloaded TLS certificate");
+                }
+                // Continue serving the prior certificate, retrying until the files can be loaded
+                // even if they aren't modified again.
+                Err(error) => error!(%error, "Failed to reload TLS certificate"),
+            }
+        }
+    }
+
+    /// Returns the DER-encoded certificate chain that is currently served.
+    pub fn certificates(&self) -> Vec<rustls::Certificate> {
+        self.current
+            .read()
+            .expect("lock must not be poisoned")
+            .cert
+            .clone()
+    }
+
+    fn modified(&self) -> (Option<SystemTime>, Option<SystemTime>) {
+        let modified = |p: &Path| fs::metadata(p).and_then(|m| m.modified()).ok();
+        (modified(&self.cert_path), modified(&self.key_path))
+    }
+}
+
+impl std::fmt::Debug for CertResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CertResolver")
+            .field("cert_path", &self.cert_path)
+            .field("key_path", &self.key_path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(
+            self.current
+                .read()
+                .expect("lock must not be poisoned")
+                .clone(),
+        )
+    }
+}
+
+/// The minimum TLS protocol version that a server accepts.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TlsVersion {
+    /// Accepts TLS 1.2 and TLS 1.3.
+    V1_2,
+
+    /// Accepts only TLS 1.3.
+    V1_3,
+}
+
+// === impl TlsVersion ===
+
+impl TlsVersion {
+    fn protocol_versions(self) -> &'static [&'static rustls::SupportedProtocolVersion] {
+        static TLS13: &[&rustls::SupportedProtocolVersion] = &[&rustls::version::TLS13];
+        match self {
+            Self::V1_2 => rustls::ALL_VERSIONS,
+            Self::V1_3 => TLS13,
+        }
+    }
+}
+
+impl std::str::FromStr for TlsVersion {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "1.2" => Ok(Self::V1_2),
+            "1.3" => Ok(Self::V1_3),
+            _ => bail!("TLS version must be 1.2 or 1.3: {:?}", s),
+        }
+    }
+}
+
+impl std::fmt::Display for TlsVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::V1_2 => "1.2".fmt(f),
+            Self::V1_3 => "1.3".fmt(f),
+        }
+    }
+}
+
+/// Builds an acceptor that serves the resolver's current certificate, negotiating HTTP/2 or
+/// HTTP/1.1 via ALPN.
+pub fn acceptor(certs: Arc<CertResolver>) -> TlsAcceptor {
+    acceptor_with_min_version(certs, TlsVersion::V1_2)
+}
+
+/// Builds an acceptor like [`acceptor`] that refuses handshakes for protocol versions older than
+/// `min_version`.
+pub fn acceptor_with_min_version(certs: Arc<CertResolver>, min_version: TlsVersion) -> TlsAcceptor {
+    let mut config = rustls::ServerConfig::builder()
+        .with_safe_default_cipher_suites()
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(min_version.protocol_versions())
+        .expect("default cipher suites must support all protocol versions")
+        .with_no_client_auth()
+        .with_cert_resolver(certs);
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    TlsAcceptor::from(Arc::new(config))
+}
+
+/// Serves `service` over TLS on `listener` until `drain` is signaled.
+///
+/// Once the drain is signaled, no new connections are accepted and existing connections are
+/// shutdown gracefully.
+pub async fn serve<S>(listener: TcpListener, acceptor: TlsAcceptor, service: S, drain: drain::Watch)
+where
+    S: hyper::service::Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    S::Future: Send + 'static,
+{
+    loop {
+        let (socket, client) = tokio::select! {
+            res = listener.accept() => match res {
+                Ok(conn) => conn,
+                Err(error) => {
+                    warn!(%error, "Failed to accept connection");
+                    time::sleep(crate::net::ACCEPT_BACKOFF).await;
+                    continue;
+                }
+            },
+            _ = drain.clone().signaled() => {
+                debug!("Shutting down");
+                return;
+            }
+        };
+
+        let acceptor = acceptor.clone();
+        let service = service.clone();
+        let drain = drain.clone();
+        tokio::spawn(async move {
+            let tls = match acceptor.accept(socket).await {
+                Ok(tls) => tls,
+                Err(error) => {
+                    debug!(%error, %client, "TLS handshake failed");
+                    return;
+                }
+            };
+
+            let conn = Http::new().serve_connection(tls, service);
+            tokio::pin!(conn);
+            let res = tokio::select! {
+                res = &mut conn => res,
+                handle = drain.signaled() => {
+                    conn.as_mut().graceful_shutdown();
+                    handle.release_after(conn).await
+                }
+            };
+            if let Err(error) = res {
+                debug!(%error, %client, "Connection failed");
+            }
+        });
+    }
+}
+
+/// Returns true if `bundle`, a set of PEM-encoded CA certificates, includes one of the
+/// certificates in `chain` or the issuer of one of them.
+///
+/// Issuers are matched by name, without verifying signatures, so this is only suitable for
+/// detecting misconfiguration.
+pub fn bundle_trusts(bundle: &[u8], chain: &[rustls::Certificate]) -> Result<bool> {
+    let cas = rustls_pemfile::certs(&mut &*bundle).context("failed to parse CA bundle")?;
+    if cas.is_empty() {
+        bail!("no certificates found in CA bundle");
+    }
+
+    Ok(chain.iter().any(|rustls::Certificate(cert)| {
+        let issuer = names(cert).map(|(issuer, _)| issuer);
+        cas.iter().any(|ca| {
+            ca == cert || (issuer.is_some() && names(ca).map(|(_, subject)| subject) == issuer)
+        })
+    }))
+}
+
+/// Returns the trust domains of `bundle`, a set of PEM-encoded trust anchors, from each anchor's
+/// common name: a Linkerd trust anchor is named `root.linkerd.<trust-domain>`, so its trust domain
+/// is the part of the name after `linkerd`, or the whole name if it has no such label.
+pub fn trust_domains(bundle: &[u8]) -> Result<Vec<String>> {
+    let cas = rustls_pemfile::certs(&mut &*bundle).context("failed to parse trust anchors")?;
+    if cas.is_empty() {
+        bail!("no certificates found in trust anchors");
+    }
+
+    Ok(cas
+        .iter()
+        .filter_map(|ca| common_name(ca))
+        .map(|cn| {
+            let cn = cn.to_ascii_lowercase();
+            match cn.split_once(".linkerd.") {
+                Some((_, domain)) => domain.to_string(),
+                None => cn,
+            }
+        })
+        .collect())
+}
+
+/// Returns the common name of an X.509 certificate's subject, if it has one.
+fn common_name(cert: &[u8]) -> Option<&str> {
+    let (_, cert) = X509Certificate::from_der(cert).ok()?;
+    let subject = cert.tbs_certificate.subject;
+    let cn = subject.iter_common_name().next()?;
+    cn.as_str().ok()
+}
+
+/// Returns the DER-encoded issuer and subject names of an X.509 certificate.
+fn names(cert: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (_, cert) = X509Certificate::from_der(cert).ok()?;
+    let tbs = cert.tbs_certificate;
+    Some((tbs.issuer.as_raw(), tbs.subject.as_raw()))
+}
+
+fn load_certified_key(cert_path: &Path, key_path: &Path) -> Result<CertifiedKey> {
+    let certs = rustls_pemfile::certs(&mut open(cert_path)?)
+        .with_context(|| format!("failed to parse {}", cert_path.display()))?
+        .into_iter()
+        .map(rustls::Certificate)
+        .collect::<Vec<_>>();
+    if certs.is_empty() {
+        bail!("no certificates found in {}", cert_path.display());
+    }
+
+    let key = {
+        let mut pem = open(key_path)?;
+        loop {
+            match rustls_pemfile::read_one(&mut pem)
+                .with_context(|| format!("failed to parse {}", key_path.display()))?
+            {
+                Some(rustls_pemfile::Item::PKCS8Key(key))
+                | Some(rustls_pemfile::Item::RSAKey(key)) => break rustls::PrivateKey(key),
+                Some(_) => continue,
+                None => bail!("no private key found in {}", key_path.display()),
+            }
+        }
+    };
+    let key = rustls::sign::any_supported_type(&key)
+        .map_err(|_| anyhow!("unsupported private key in {}", key_path.display()))?;
+
+    Ok(CertifiedKey::new(certs, key))
+}
+
+fn open(path: &Path) -> Result<io::BufReader<fs::File>> {
+    let f = fs::File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    Ok(io::BufReader::new(f))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testdata(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata")
+            .join(name)
+    }
+
+    fn load(name: &str) -> Vec<rustls::Certificate> {
+        rustls_pemfile::certs(&mut open(&testdata(name)).unwrap())
+            .unwrap()
+            .into_iter()
+            .map(rustls::Certificate)
+            .collect()
+    }
+
+    fn pem(name: &str) -> Vec<u8> {
+        fs::read(testdata(name)).unwrap()
+    }
+
+    #[test]
+    fn parses_names() {
+        let leaf = load("webhook.crt");
+        let ca = load("webhook-ca.crt");
+        let (issuer, subject) = names(&leaf[0].0).expect("certificate must parse");
+        assert_ne!(issuer, subject);
+        let (ca_issuer, ca_subject) = names(&ca[0].0).expect("certificate must parse");
+        assert_eq!(issuer, ca_subject);
+        assert_eq!(ca_issuer, ca_subject, "the CA must be self-signed");
+
+        assert!(names(b"").is_none());
+        assert!(names(&leaf[0].0[..32]).is_none(), "truncated");
+        assert!(
+            names(&[0x30, 0x84, 0xff, 0xff, 0xff, 0xff]).is_none(),
+            "the length must not exceed the input"
+        );
+        assert!(names(b"not a certificate").is_none());
+    }
+
+    #[test]
+    fn bundle_trusts_issuer() {
+        let leaf = load("webhook.crt");
+        assert!(bundle_trusts(&pem("webhook-ca.crt"), &leaf).unwrap());
+        assert!(!bundle_trusts(&pem("localhost.crt"), &leaf).unwrap());
+
+        // A bundle may include several CAs, e.g. during a rotation.
+        let mut bundle = pem("localhost.crt");
+        bundle.extend(pem("webhook-ca.crt"));
+        assert!(bundle_trusts(&bundle, &leaf).unwrap());
+
+        // A self-signed certificate may be its own CA.
+        assert!(bundle_trusts(&pem("localhost.crt"), &load("localhost.crt")).unwrap());
+
+        assert!(bundle_trusts(b"", &leaf).is_err());
+    }
+
+    fn served(certs: &CertResolver) -> Vec<rustls::Certificate> {
+        certs.current.read().unwrap().cert.clone()
+    }
+
+    #[tokio::test]
+    async fn reloads_changed_certificates() {
+        let dir = std::env::temp_dir().join(format!("policy-{}", std::process::id()));
+        fs::create_dir(&dir).unwrap();
+        let (cert_path, key_path) = (dir.join("tls.crt"), dir.join("tls.key"));
+        // Sets modification times explicitly so that the test doesn't depend on the filesystem's
+        // timestamp granularity.
+        let write = |cert: &[u8], key: &[u8], mtime: i64| {
+            let mtime = filetime::FileTime::from_unix_time(mtime, 0);
+            fs::write(&cert_path, cert).unwrap();
+            filetime::set_file_mtime(&cert_path, mtime).unwrap();
+            fs::write(&key_path, key).unwrap();
+            filetime::set_file_mtime(&key_path, mtime).unwrap();
+        };
+
+        write(&pem("localhost.crt"), &pem("localhost.key"), 1_000);
+        let certs = CertResolver::load(cert_path.clone(), key_path.clone()).unwrap();
+        let reload = tokio::spawn(
+            certs
+                .clone()
+                .reload_on_change(time::Duration::from_millis(10)),
+        );
+        let reloaded = |expected: Vec<rustls::Certificate>| {
+            let certs = certs.clone();
+            time::timeout(time::Duration::from_secs(1), async move {
+                while served(&certs) != expected {
+                    time::sleep(time::Duration::from_millis(10)).await;
+                }
+            })
+        };
+
+        // Let the task note the files' original modification times before they're replaced.
+        time::sleep(time::Duration::from_millis(50)).await;
+        write(&pem("rotated.crt"), &pem("rotated.key"), 2_000);
+        reloaded(load("rotated.crt"))
+            .await
+            .expect("the rotated certificate must be served");
+
+        // A partially-written certificate can't be loaded, so the prior certificate is still
+        // served.
+        let rotated = pem("localhost.crt");
+        write(&rotated[..rotated.len() / 2], &pem("localhost.key"), 3_000);
+        time::sleep(time::Duration::from_millis(100)).await;
+        assert_eq!(served(&certs), load("rotated.crt"));
+
+        // Once the rotation completes, the new certificate is served, even though the files'
+        // modification times are unchanged since the failed load.
+        write(&rotated, &pem("localhost.key"), 3_000);
+        reloaded(load("localhost.crt"))
+            .await
+            .expect("the completed rotation must be served");
+
+        reload.abort();
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reads_trust_domains() {
+        // A Linkerd trust anchor, named root.linkerd.cluster.local.
+        let domains = trust_domains(&pem("trust-anchors.crt")).unwrap();
+        assert_eq!(domains, vec!["cluster.local"]);
+        assert!(domains.iter().all(|d| d != "example.com"));
+
+        // Other names are treated as trust domains.
+        assert_eq!(
+            trust_domains(&pem("webhook-ca.crt")).unwrap(),
+            vec!["webhook-ca"]
+        );
+
+        assert!(trust_domains(b"").is_err());
+    }
+
+    #[test]
+    fn parse_tls_version() {
+        assert_eq!("1.2".parse::<TlsVersion>().unwrap(), TlsVersion::V1_2);
+        assert_eq!("1.3".parse::<TlsVersion>().unwrap(), TlsVersion::V1_3);
+        for invalid in ["", "1", "1.1", "1.4", "TLSv1.3"] {
+            assert!(invalid.parse::<TlsVersion>().is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[tokio::test]
+    async fn min_version_refuses_older_handshakes() {
+        let certs =
+            CertResolver::load(testdata("localhost.crt"), testdata("localhost.key")).unwrap();
+        let acceptor = acceptor_with_min_version(certs, TlsVersion::V1_3);
+
+        let mut roots = rustls::RootCertStore::empty();
+        for cert in load("localhost.crt") {
+            roots.add(&cert).unwrap();
+        }
+        let connect = |version: &'static rustls::SupportedProtocolVersion| {
+            let config = rustls::ClientConfig::builder()
+                .with_safe_default_cipher_suites()
+                .with_safe_default_kx_groups()
+                .with_protocol_versions(&[version])
+                .unwrap()
+                .with_root_certificates(roots.clone())
+                .with_no_client_auth();
+            let acceptor = acceptor.clone();
+            async move {
+                let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+                let addr = listener.local_addr().unwrap();
+                let server = tokio::spawn(async move {
+                    let (socket, _) = listener.accept().await.unwrap();
+                    acceptor.accept(socket).await.map(|_| ())
+                });
+
+                let socket = tokio::net::TcpStream::connect(addr).await.unwrap();
+                let client = tokio_rustls::TlsConnector::from(Arc::new(config))
+                    .connect("localhost".try_into().unwrap(), socket)
+                    .await
+                    .map(|_| ());
+                (client, server.await.unwrap())
+            }
+        };
+
+        let (client, server) = connect(&rustls::version::TLS12).await;
+        assert!(client.is_err(), "TLS 1.2 client must be refused");
+        assert!(server.is_err(), "TLS 1.2 handshake must be refused");
+
+        let (client, server) = connect(&rustls::version::TLS13).await;
+        client.expect("TLS 1.3 client must connect");
+        server.expect("TLS 1.3 handshake must succeed");
+    }
+}